@@ -0,0 +1,12 @@
+#![no_main]
+
+use codecrafters_sqlite::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+// Decodes a varint and a record. The first byte picks the text encoding.
+fuzz_target!(|data: &[u8]| {
+    let _ = fuzz::decode_varint(data);
+    if let Some((&encoding, payload)) = data.split_first() {
+        let _ = fuzz::decode_record(payload, u32::from(encoding % 3 + 1));
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use codecrafters_sqlite::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|sql: &str| {
+    fuzz::parse_sql(sql);
+});
@@ -0,0 +1,308 @@
+use anyhow::{Result, bail};
+use std::collections::HashSet;
+
+use crate::expr::{self, Expr};
+use crate::record::ColType;
+
+// NAMES are the aggregate functions, min and max only with a single argument.
+pub const NAMES: [&str; 7] = ["count", "sum", "total", "avg", "min", "max", "group_concat"];
+
+// Aggregate is an aggregate function call together with its FILTER clause,
+// evaluated once over every row the select reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggregateFunction,
+    // DISTINCT: a value equal to one already folded in is left out
+    pub distinct: bool,
+    // FILTER (WHERE ...): rows it doesn't hold for are left out
+    pub filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateFunction {
+    // the rows, or with an argument the rows where it isn't NULL
+    Count(Option<Box<Expr>>),
+    // the sum of the non-NULL values, an integer while they all are
+    Sum(Box<Expr>),
+    // the sum as a REAL, 0.0 when there's nothing to add
+    Total(Box<Expr>),
+    Avg(Box<Expr>),
+    Min(Box<Expr>),
+    Max(Box<Expr>),
+    // the non-NULL values as text, each after the separator its row gives,
+    // a comma by default
+    GroupConcat(Box<Expr>, Option<Box<Expr>>),
+}
+
+// is_aggregate tells a call of an aggregate from one of a scalar function:
+// min and max with several arguments are scalar.
+pub fn is_aggregate(name: &str, args: usize) -> bool {
+    NAMES.contains(&name) && (args == 1 || !matches!(name, "min" | "max"))
+}
+
+impl AggregateFunction {
+    pub fn new(name: &str, args: Vec<Expr>) -> Result<Self, String> {
+        let mut args = args.into_iter().map(Box::new);
+        match (name, args.next(), args.next()) {
+            // count() counts rows like count(*)
+            ("count", None, None) => Ok(AggregateFunction::Count(None)),
+            ("count", Some(arg), None) => Ok(AggregateFunction::Count(
+                (*arg != Expr::Column("*".to_string())).then_some(arg),
+            )),
+            ("sum", Some(arg), None) => Ok(AggregateFunction::Sum(arg)),
+            ("total", Some(arg), None) => Ok(AggregateFunction::Total(arg)),
+            ("avg", Some(arg), None) => Ok(AggregateFunction::Avg(arg)),
+            ("min", Some(arg), None) => Ok(AggregateFunction::Min(arg)),
+            ("max", Some(arg), None) => Ok(AggregateFunction::Max(arg)),
+            ("group_concat", Some(arg), sep) if args.next().is_none() => {
+                Ok(AggregateFunction::GroupConcat(arg, sep))
+            }
+            _ => Err(format!("wrong number of arguments to function {name}()")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count(_) => "count",
+            AggregateFunction::Sum(_) => "sum",
+            AggregateFunction::Total(_) => "total",
+            AggregateFunction::Avg(_) => "avg",
+            AggregateFunction::Min(_) => "min",
+            AggregateFunction::Max(_) => "max",
+            AggregateFunction::GroupConcat(..) => "group_concat",
+        }
+    }
+
+    // arg is the aggregated expression, None for count(*).
+    pub fn arg(&self) -> Option<&Expr> {
+        match self {
+            AggregateFunction::Count(arg) => arg.as_deref(),
+            AggregateFunction::Sum(arg)
+            | AggregateFunction::Total(arg)
+            | AggregateFunction::Avg(arg)
+            | AggregateFunction::Min(arg)
+            | AggregateFunction::Max(arg)
+            | AggregateFunction::GroupConcat(arg, _) => Some(arg),
+        }
+    }
+
+    // args are the arguments of the call, none for count(*).
+    pub fn args(&self) -> Vec<&Expr> {
+        match self {
+            AggregateFunction::GroupConcat(arg, sep) => {
+                std::iter::once(&**arg).chain(sep.as_deref()).collect()
+            }
+            _ => self.arg().into_iter().collect(),
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            AggregateFunction::Count(arg) => arg.as_deref_mut().into_iter().collect(),
+            AggregateFunction::Sum(arg)
+            | AggregateFunction::Total(arg)
+            | AggregateFunction::Avg(arg)
+            | AggregateFunction::Min(arg)
+            | AggregateFunction::Max(arg) => vec![&mut **arg],
+            AggregateFunction::GroupConcat(arg, sep) => std::iter::once(&mut **arg)
+                .chain(sep.as_deref_mut())
+                .collect(),
+        }
+    }
+}
+
+impl Aggregate {
+    // evaluate folds `n` buffered rows into the function's value, `eval`
+    // evaluates an expression on one of them. Rows the FILTER condition is
+    // false or NULL for don't count, and neither do NULL arguments, nor with
+    // DISTINCT the repeats of a value.
+    pub fn evaluate(
+        &self,
+        n: usize,
+        eval: &dyn Fn(&Expr, usize) -> Result<ColType>,
+    ) -> Result<ColType> {
+        let mut values = Vec::new();
+        let mut separators = Vec::new();
+        let mut seen = HashSet::new();
+        for i in 0..n {
+            if let Some(filter) = &self.filter
+                && !expr::truthy(&eval(filter, i)?)
+            {
+                continue;
+            }
+            let v = match self.func.arg() {
+                Some(arg) => eval(arg, i)?,
+                None => ColType::Integer(1),
+            };
+            if matches!(v, ColType::Null) || (self.distinct && !seen.insert(v.clone())) {
+                continue;
+            }
+            if let AggregateFunction::GroupConcat(_, sep) = &self.func {
+                let sep = match sep {
+                    Some(sep) => eval(sep, i)?.to_string(),
+                    None => ",".to_string(),
+                };
+                separators.push(sep);
+            }
+            values.push(v);
+        }
+        Ok(match &self.func {
+            AggregateFunction::Count(_) => ColType::Integer(values.len() as i64),
+            AggregateFunction::Sum(_) if values.is_empty() => ColType::Null,
+            AggregateFunction::Sum(_) => sum(&values)?,
+            AggregateFunction::Total(_) => ColType::Float(total(&values)),
+            AggregateFunction::Avg(_) if values.is_empty() => ColType::Null,
+            AggregateFunction::Avg(_) => ColType::Float(total(&values) / values.len() as f64),
+            AggregateFunction::Min(_) => values.into_iter().min().unwrap_or(ColType::Null),
+            AggregateFunction::Max(_) => values.into_iter().max().unwrap_or(ColType::Null),
+            AggregateFunction::GroupConcat(..) if values.is_empty() => ColType::Null,
+            AggregateFunction::GroupConcat(..) => {
+                let mut text = String::new();
+                for (i, (v, sep)) in values.iter().zip(&separators).enumerate() {
+                    // the first value has nothing before it to separate
+                    if i > 0 {
+                        text += sep;
+                    }
+                    text += &v.to_string();
+                }
+                ColType::Text(text)
+            }
+        })
+    }
+}
+
+impl Aggregate {
+    // extreme_row is the first of `n` buffered rows holding the value of a
+    // min() or max(), whose bare columns sqlite3 takes from that row. None for
+    // other functions and when no row counts.
+    pub fn extreme_row(
+        &self,
+        n: usize,
+        eval: &dyn Fn(&Expr, usize) -> Result<ColType>,
+    ) -> Result<Option<usize>> {
+        let (arg, max) = match &self.func {
+            AggregateFunction::Min(arg) => (arg, false),
+            AggregateFunction::Max(arg) => (arg, true),
+            _ => return Ok(None),
+        };
+        let mut best: Option<(usize, ColType)> = None;
+        for i in 0..n {
+            if let Some(filter) = &self.filter
+                && !expr::truthy(&eval(filter, i)?)
+            {
+                continue;
+            }
+            let v = eval(arg, i)?;
+            if matches!(v, ColType::Null) {
+                continue;
+            }
+            let better = best
+                .as_ref()
+                .is_none_or(|(_, b)| if max { v > *b } else { v < *b });
+            if better {
+                best = Some((i, v));
+            }
+        }
+        Ok(best.map(|(i, _)| i))
+    }
+}
+
+// sum adds integers exactly, failing on overflow like sqlite, and switches to
+// REAL arithmetic at the first value that isn't an integer.
+fn sum(values: &[ColType]) -> Result<ColType> {
+    let mut acc = 0i64;
+    for v in values {
+        // text counts as an integer only when all of it is one
+        let int = match v {
+            ColType::Integer(i) => Some(*i),
+            ColType::Text(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        let Some(i) = int else {
+            return Ok(ColType::Float(total(values)));
+        };
+        match acc.checked_add(i) {
+            Some(s) => acc = s,
+            None => bail!("integer overflow"),
+        }
+    }
+    Ok(ColType::Integer(acc))
+}
+
+fn total(values: &[ColType]) -> f64 {
+    values
+        .iter()
+        .map(|v| match expr::to_number(v) {
+            ColType::Integer(i) => i as f64,
+            ColType::Float(f) => f,
+            _ => 0.0,
+        })
+        // not sum(), which starts from -0.0
+        .fold(0.0, |acc, f| acc + f)
+}
+
+#[test]
+fn test_aggregates() {
+    // (dept, salary, active) rows, one salary unknown
+    let rows = [
+        ("eng", Some(100), 1),
+        ("eng", Some(200), 0),
+        ("ops", None, 1),
+        ("ops", Some(50), 1),
+    ];
+    let eval = |e: &Expr, i: usize| {
+        let (dept, salary, active) = rows[i];
+        e.eval(&|name| match name {
+            "dept" => Some(ColType::Text(dept.to_string())),
+            "salary" => Some(salary.map_or(ColType::Null, ColType::Integer)),
+            "active" => Some(ColType::Integer(active)),
+            _ => None,
+        })
+    };
+    let run = |sql: &str| {
+        let e = expr::parse_expr(sql).unwrap();
+        e.aggregates()[0]
+            .evaluate(rows.len(), &eval)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(run("count(*)"), "4");
+    assert_eq!(run("count(salary)"), "3");
+    assert_eq!(run("count(*) filter (where active = 1)"), "3");
+    assert_eq!(run("count(*) filter (where salary > 60)"), "2");
+    assert_eq!(run("sum(salary)"), "350");
+    assert_eq!(run("sum(salary) filter (where dept = 'ops')"), "50");
+    assert_eq!(run("sum(salary) filter (where dept = 'hr')"), "");
+    assert_eq!(run("total(salary) filter (where dept = 'hr')"), "0.0");
+    assert_eq!(run("avg(salary) filter (where active)"), "75.0");
+    assert_eq!(run("min(dept) filter (where salary is null)"), "ops");
+    assert_eq!(run("max(salary) filter (where active = 1)"), "100");
+    assert_eq!(run("group_concat(salary)"), "100,200,50");
+    assert_eq!(
+        run("group_concat(dept, '; ') filter (where active)"),
+        "eng; ops; ops"
+    );
+    assert_eq!(run("group_concat(salary, dept)"), "100eng200ops50");
+    assert_eq!(run("group_concat(salary) filter (where dept = 'hr')"), "");
+    assert_eq!(run("count(distinct dept)"), "2");
+    assert_eq!(
+        run("count(distinct active) filter (where dept = 'ops')"),
+        "1"
+    );
+    assert_eq!(run("group_concat(distinct dept)"), "eng,ops");
+    assert_eq!(run("sum(all salary)"), "350");
+    let extreme = |sql: &str| {
+        let e = expr::parse_expr(sql).unwrap();
+        e.aggregates()[0].extreme_row(rows.len(), &eval).unwrap()
+    };
+    assert_eq!(extreme("max(salary)"), Some(1));
+    assert_eq!(extreme("min(dept)"), Some(0));
+    assert_eq!(extreme("min(salary) filter (where dept = 'eng')"), Some(0));
+    assert_eq!(extreme("max(salary) filter (where dept = 'hr')"), None);
+    assert_eq!(extreme("count(*)"), None);
+    assert!(expr::parse_expr("sum(salary, 1)").is_err());
+    assert!(expr::parse_expr("count(distinct *)").is_err());
+    assert!(expr::parse_expr("group_concat(distinct dept, ';')").is_err());
+    assert!(expr::parse_expr("count(distinct dept) over ()").is_err());
+    assert!(expr::parse_expr("length(dept) filter (where active)").is_err());
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Deref;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_stream::Stream;
+
+use crate::error::SqliteError;
+use crate::exec::Row;
+use crate::header::{DatabaseHeader, HEADER_SIZE};
+use crate::journal::{self, JOURNAL_HEADER_SIZE};
+use crate::wal::{self, WalIndex};
+use crate::{Database, uri};
+
+/// A [`Database`] for async code, opened with `tokio::fs` so no read blocks a
+/// runtime thread.
+///
+/// Opening reads the whole file into memory, and every statement then reads
+/// its pages from there: once [`AsyncDatabase::open`] is done there's no more
+/// I/O, and the synchronous [`Database`] methods, reached through `Deref`,
+/// don't block either.
+#[derive(Debug)]
+pub struct AsyncDatabase {
+    db: Database,
+}
+
+impl AsyncDatabase {
+    /// Opens the database at `path`, a file name or a `file:` URI, with the
+    /// same check for a hot journal, and warning for a WAL, as
+    /// [`Database::open`].
+    pub async fn open(path: &str) -> Result<AsyncDatabase> {
+        let db_path = uri::DbPath::parse(path)?;
+        let mut file = tokio::fs::File::open(&db_path.path).await?;
+        if !db_path.immutable {
+            check_hot_journal(&db_path.path).await?;
+            check_wal(&db_path.path).await?;
+        }
+        // the header says whether it's a database at all before the rest is read
+        let mut header = [0; HEADER_SIZE];
+        file.seek(SeekFrom::Start(0)).await?;
+        if file.read_exact(&mut header).await.is_err() {
+            anyhow::bail!(SqliteError::NotADatabase);
+        }
+        DatabaseHeader::parse(&header)?;
+        let mut image = header.to_vec();
+        file.read_to_end(&mut image).await?;
+        let mut db = Database::from_bytes(image)?;
+        db.read_only = db_path.read_only();
+        Ok(AsyncDatabase { db })
+    }
+
+    /// Runs `sql` and streams its rows, as [`Database::query`] iterates them.
+    /// A statement that fails to prepare yields its error as the only item.
+    pub fn query(&self, sql: &str) -> impl Stream<Item = Result<Row>> + '_ {
+        let (rows, err) = match self.db.query(sql) {
+            Ok(rows) => (Some(rows), None),
+            Err(e) => (None, Some(Err(e))),
+        };
+        tokio_stream::iter(err.into_iter().chain(rows.into_iter().flatten()))
+    }
+}
+
+impl Deref for AsyncDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+// check_hot_journal is journal::check_hot_journal reading with tokio.
+async fn check_hot_journal(db_path: &str) -> Result<()> {
+    let path = journal::journal_path(db_path);
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut header = [0; JOURNAL_HEADER_SIZE];
+    if file.read_exact(&mut header).await.is_err() {
+        return Ok(());
+    }
+    match journal::parse_journal_header(&header, file.metadata().await?.len()) {
+        Some(header) => Err(journal::hot_journal(path, &header).into()),
+        None => Ok(()),
+    }
+}
+
+// check_wal is wal::check_wal reading with tokio.
+async fn check_wal(db_path: &str) -> Result<()> {
+    let shm = match tokio::fs::read(format!("{db_path}-shm")).await {
+        Ok(shm) => shm,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    wal::warn_uncheckpointed(db_path, WalIndex::parse(&shm)?.as_ref());
+    Ok(())
+}
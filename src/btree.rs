@@ -0,0 +1,1104 @@
+use anyhow::{Result, bail};
+use log::trace;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::error::SqliteError;
+use crate::pager::{Page, Pager};
+use crate::record::{ColType, Collation, Value, col_value, decode_varint, serial_type_size};
+
+// OnColumn receives the values of the cells a b-tree walk decodes.
+pub trait OnColumn {
+    fn on_col(&mut self, cur_type: u8, row: usize, col: usize, v: &ColType, rowid: i64);
+    fn on_row(&mut self, cur_type: u8, rowid: i64);
+    fn finalize(&mut self);
+}
+
+// IndexSearch is what an index search looks for, in an index b-tree that
+// sorts each key column in descending order where `desc` says so.
+pub struct IndexSearch {
+    pub target: IndexTarget,
+    pub desc: Vec<bool>,
+}
+
+// IndexTarget is the keys an index search finds.
+#[derive(Debug)]
+pub enum IndexTarget {
+    // the keys that start with these values, one for each leading column
+    Key(Vec<ColType>),
+    // the text keys that start with the literal prefix of a LIKE pattern, or
+    // with `blob` the blobs that do
+    Prefix { prefix: String, blob: bool },
+}
+
+impl fmt::Display for IndexSearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.target {
+            IndexTarget::Key(values) => {
+                let values = values.iter().map(ColType::to_string).collect::<Vec<_>>();
+                write!(f, "{}", values.join(", "))
+            }
+            IndexTarget::Prefix { prefix, .. } => write!(f, "{prefix}%"),
+        }
+    }
+}
+
+// scan_btree sometimes returns the found rowids, when the page type is leaf index (0x0a)
+// bad abstractions, but we are just demonstrating...
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(page_idx = p.number - 1, cell_count = p.cell_offsets.len())
+    )
+)]
+pub fn scan_btree(
+    p: &Page,
+    state: &mut dyn OnColumn,
+    pager: &Pager,
+    search: Option<&IndexSearch>,
+    rowid: Option<usize>,
+) -> Result<Vec<usize>> {
+    scan_level(p, state, pager, search, rowid, &[p.number])
+}
+
+// scan_level is scan_btree below the root, with the pages on the way down
+// to `p` in `path`: each interior cell is a 4-byte left child before its
+// key, the page header's right child follows the last cell, and a child
+// that's already on the path makes the b-tree a loop.
+fn scan_level(
+    p: &Page,
+    state: &mut dyn OnColumn,
+    pager: &Pager,
+    search: Option<&IndexSearch>,
+    rowid: Option<usize>,
+    path: &[usize],
+) -> Result<Vec<usize>> {
+    let cell_offsets = &p.cell_offsets;
+    let child = |next: usize| -> Result<(Page, Vec<usize>)> {
+        if path.contains(&next) || path.len() >= MAX_DEPTH {
+            bail!(too_deep(path[0]));
+        }
+        let mut path = path.to_vec();
+        path.push(next);
+        Ok((pager.page(next - 1, false)?, path))
+    };
+
+    // an empty table or index is a leaf root without any cell,
+    // there is nothing to scan or search
+    if cell_offsets.is_empty() && (p.page_type == 0x0d || p.page_type == 0x0a) {
+        state.finalize();
+        return Ok(Vec::new());
+    }
+
+    if p.page_type == 0x0d || p.page_type == 0x05 {
+        // table nodes
+        if rowid.is_none() {
+            // preorder traversal for full scan
+            for (ic, offset) in cell_offsets.iter().enumerate() {
+                let (key, left) = parse_one_cell(ic, *offset, p, state, pager)?;
+                let key = if let ColType::Integer(r) = key { r } else { -1 };
+                state.on_row(p.page_type, key);
+                if left > 0 {
+                    assert!(p.page_type == 0x02 || p.page_type == 0x05);
+                    // only for interior nodes
+                    let (left_page, path) = child(left)?;
+                    scan_level(&left_page, state, pager, search, rowid, &path)?;
+                }
+            }
+            if p.page_type == 0x05 || p.page_type == 0x02 {
+                let (right_page, path) = child(p.right.unwrap() as usize)?;
+                scan_level(&right_page, state, pager, search, rowid, &path)?;
+            }
+            state.finalize();
+        } else {
+            let Some(rowid) = rowid else { unreachable!() };
+            let target = rowid;
+            if p.page_type == 0x05 {
+                // interior
+                if cell_offsets.is_empty() {
+                    bail!(empty_interior(p));
+                }
+                let mut l = 0;
+                let mut r = cell_offsets.len() - 1;
+                while l < r {
+                    let m = l + (r - l) / 2;
+                    let (key, left) = parse_one_cell(m, cell_offsets[m], p, state, pager)?;
+                    let key: usize = key.try_into()?;
+                    trace!("searching table 0x05 by rowid: {rowid} vs {key}, left:{left}");
+                    // find the min key that greater than or (equal to) target
+                    // 1 2 3 5 5 5 6 8
+                    //      4^
+                    if key < target {
+                        l = m + 1;
+                    } else {
+                        r = m;
+                    }
+                }
+                assert_eq!(l, r);
+                // NOTE: we may want avoid the potential re-parse.
+                let (key, left) = parse_one_cell(l, cell_offsets[l], p, state, pager)?;
+                let key: usize = key.try_into()?;
+                state.on_row(p.page_type, key as i64);
+                let next = if target > key {
+                    trace!(
+                        "l: {}, len: {}, target {} > {}",
+                        l,
+                        cell_offsets.len(),
+                        target,
+                        key,
+                    );
+                    p.right.unwrap() as usize
+                } else {
+                    trace!(
+                        "l: {}, len: {}, target {} <= {}",
+                        l,
+                        cell_offsets.len(),
+                        target,
+                        key
+                    );
+                    left
+                };
+                pager.descend(p.number, &key, &target, next);
+                let (next_page, path) = child(next)?;
+                return scan_level(&next_page, state, pager, search, Some(rowid), &path);
+            } else {
+                // leaf 0x0d
+                let mut l = 0;
+                let mut r = cell_offsets.len() - 1;
+                // for dup, find from the "smallest"
+                // 1 2 3 4 5 5 5 5 6
+                while l < r {
+                    let m = l + (r - l) / 2;
+                    let (key, _) = parse_one_cell(m, cell_offsets[m], p, state, pager)?;
+                    let key: usize = key.try_into()?;
+                    trace!("searching table leaf 0x0d by target: {target} vs {key}");
+                    if key < target {
+                        l = m + 1;
+                    } else {
+                        r = m;
+                    }
+                }
+                assert_eq!(l, r);
+                while l < cell_offsets.len() {
+                    let (rowid, _) = parse_one_cell(l, cell_offsets[l], p, state, pager)?;
+                    let key: usize = rowid.try_into()?;
+                    state.on_row(p.page_type, key as i64);
+                    if key == target {
+                        trace!("post searching table leaf 0x0d by target: {target} vs {key}");
+                        l += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    } else if p.page_type == 0x02 {
+        // interior index
+        // binary search
+        let target = index_target(p, search)?;
+        // v = condition.value
+        // (key, left)
+        // v(target) <= key (left)
+        let mut l = 0;
+        let mut r = cell_offsets.len() - 1;
+        while l < r {
+            let m = l + (r - l) / 2;
+            let (key, _, left) = index_cell(p, m, pager)?;
+            trace!(
+                "searching index 0x02 by target: {target} vs {}, left:{left}",
+                key[0]
+            );
+            // find the min key that greater than or (equal to) target
+            // 1 2 3 5 5 5 6 8
+            //      4^
+            if index_before(&key, target) {
+                l = m + 1;
+            } else {
+                r = m;
+            }
+        }
+        assert_eq!(l, r);
+        // NOTE: we may want avoid the potential re-parse.
+        let (key, mut entry_rowid, left) = index_cell(p, l, pager)?;
+        if index_before(&key, target) {
+            let next = p.right.unwrap() as usize;
+            trace!(
+                "l: {}, len: {}, target {} > {}",
+                l,
+                cell_offsets.len(),
+                target,
+                key[0],
+            );
+            pager.descend(p.number, &key[0], target, next);
+            let (next_page, path) = child(next)?;
+            return scan_level(&next_page, state, pager, search, rowid, &path);
+        }
+        trace!(
+            "l: {}, len: {}, target {} <= {}",
+            l,
+            cell_offsets.len(),
+            target,
+            key[0]
+        );
+        pager.descend(p.number, &key[0], target, left);
+        let (next_page, path) = child(left)?;
+        let mut rowids = scan_level(&next_page, state, pager, search, rowid, &path)?;
+        // an interior cell holds an entry of the index too, and the keys
+        // equal to the target go on through the cells that hold it and the
+        // children between them
+        let mut key = key;
+        while index_match(&key, target) {
+            rowids.push(entry_rowid);
+            l += 1;
+            let next = if l < cell_offsets.len() {
+                let left;
+                (key, entry_rowid, left) = index_cell(p, l, pager)?;
+                left
+            } else {
+                p.right.unwrap() as usize
+            };
+            let (next_page, path) = child(next)?;
+            rowids.extend(scan_level(&next_page, state, pager, search, rowid, &path)?);
+            if l == cell_offsets.len() {
+                break;
+            }
+        }
+        return Ok(rowids);
+    } else if p.page_type == 0xa {
+        let target = index_target(p, search)?;
+        // leaf index node
+        let mut l = 0;
+        let mut r = cell_offsets.len() - 1;
+        // for dup, find from the "smallest"
+        // 1 2 3 4 5 5 5 5 6
+        while l < r {
+            let m = l + (r - l) / 2;
+            let (key, ..) = index_cell(p, m, pager)?;
+            trace!("searching index 0x0a by target: {target} vs {}", key[0]);
+            if index_before(&key, target) {
+                l = m + 1;
+            } else {
+                r = m;
+            }
+        }
+        let mut rowids = vec![];
+        while l < cell_offsets.len() {
+            let (key, rowid, _) = index_cell(p, l, pager)?;
+            if index_match(&key, target) {
+                l += 1;
+                trace!("find one: {}, rowid: {rowid} for target {target}", key[0]);
+                rowids.push(rowid);
+            } else {
+                break;
+            }
+        }
+        return Ok(rowids);
+    } else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "page {} is not a b-tree page",
+            p.number
+        )));
+    }
+
+    Ok(Vec::default())
+}
+
+// index_side places an index key against the keys a search looks for, in
+// the order of the index: Less before them all, Equal for one of them and
+// Greater after them all. A LIKE prefix is matched by the text that starts
+// with it, which sorts before all blobs, or with `blob` by the blobs that do.
+fn index_side(key: &[ColType], search: &IndexSearch) -> Ordering {
+    let (prefix, blob) = match &search.target {
+        IndexTarget::Key(target) => return compare_keys_in_order(key, target, &search.desc),
+        IndexTarget::Prefix { prefix, blob } => (prefix, *blob),
+    };
+    let side = match (blob, &key[0]) {
+        (false, ColType::Text(s)) if s.starts_with(prefix.as_str()) => Ordering::Equal,
+        (false, ColType::Text(s)) => s.as_str().cmp(prefix),
+        (false, ColType::Blob(_)) => Ordering::Greater,
+        (true, ColType::Blob(b)) if b.starts_with(prefix.as_bytes()) => Ordering::Equal,
+        (true, ColType::Blob(b)) => b[..].cmp(prefix.as_bytes()),
+        _ => Ordering::Less,
+    };
+    match search.desc.first() {
+        Some(true) => side.reverse(),
+        _ => side,
+    }
+}
+
+// index_before tells whether an index key comes before the keys the search
+// looks for in the order of the index.
+fn index_before(key: &[ColType], search: &IndexSearch) -> bool {
+    index_side(key, search).is_lt()
+}
+
+// index_match tells whether an index key is one the search looks for.
+fn index_match(key: &[ColType], search: &IndexSearch) -> bool {
+    index_side(key, search).is_eq()
+}
+
+// index_target is the search an index page is read for. Only a damaged
+// table b-tree leads a scan to an index page.
+fn index_target<'s>(p: &Page, search: Option<&'s IndexSearch>) -> Result<&'s IndexSearch> {
+    match search {
+        Some(search) if !p.cell_offsets.is_empty() => Ok(search),
+        Some(_) => bail!(empty_interior(p)),
+        None => bail!(SqliteError::CorruptDatabase(format!(
+            "page {} is an index page inside a table b-tree",
+            p.number
+        ))),
+    }
+}
+
+// a search can't choose a child of an interior page that has no cell
+fn empty_interior(p: &Page) -> SqliteError {
+    SqliteError::CorruptDatabase(format!("interior page {} has no cells", p.number))
+}
+
+// left_child reads the page number of the left child an interior cell at
+// `offset` starts with.
+fn left_child(p: &Page, offset: usize) -> Result<usize> {
+    let child = p
+        .page
+        .get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+    match child {
+        Some(child) if child > 0 => Ok(child as usize),
+        _ => bail!(SqliteError::CorruptDatabase(format!(
+            "cell at offset {offset} of page {} has no valid child page",
+            p.number
+        ))),
+    }
+}
+
+// walk_cells decodes every cell of the page `number` without following
+// any child, for the fuzz targets. Returns the number of cells.
+#[cfg(feature = "fuzz")]
+pub fn walk_cells(pager: &Pager, number: usize) -> Result<usize> {
+    let p = pager.page(number - 1, false)?;
+    p.free_bytes();
+    for (i, &offset) in p.cell_offsets.iter().enumerate() {
+        parse_one_cell(i, offset, &p, &mut Record::default(), pager)?;
+    }
+    Ok(p.cell_offsets.len())
+}
+
+/// Orders two index keys the way an index b-tree sorts them: column by
+/// column, the first that differs decides, each compared as sqlite3 does,
+/// NULL below numbers below text below blobs, integers and reals by value.
+/// When one key is a prefix of the other they're equal, so a shorter key
+/// finds every entry that starts with it.
+pub fn compare_index_keys(a: &[ColType], b: &[ColType]) -> Ordering {
+    compare_keys_in_order(a, b, &[])
+}
+
+// compare_keys_in_order is compare_index_keys for an index that keeps the
+// columns `desc` says so in descending order, which turns their comparison
+// around.
+fn compare_keys_in_order(a: &[ColType], b: &[ColType], desc: &[bool]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .zip(desc.iter().chain(std::iter::repeat(&false)))
+        .map(|((a, b), desc)| match a.sqlite_cmp(b, Collation::Binary) {
+            o if *desc => o.reverse(),
+            o => o,
+        })
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+// index_cell decodes cell `i` of an index page into its key, the values
+// before the rowid that ends every entry, the rowid, and the left child of
+// an interior cell, 0 on a leaf.
+fn index_cell(p: &Page, i: usize, pager: &Pager) -> Result<(Vec<ColType>, usize, usize)> {
+    pager.cell_decoded();
+    let offset = p.cell_offsets[i] as usize;
+    let db = pager.dbinfo();
+    let index_local = ((db.page_size as usize - 12) * 64 / 255) - 23;
+    let (left, start) = match p.page_type {
+        0x02 => (left_child(p, offset)?, offset + 4),
+        _ => (0, offset),
+    };
+    let Some(buf) = p.page.get(start..) else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "cell offset {offset} is past the end of page {}",
+            p.number
+        )));
+    };
+    let (size, n) = decode_varint(buf)?;
+    let payload = cell_payload(&buf[n..], size, index_local, p.number, pager)?;
+    let mut key = record_values(&payload, db.text_encoding, pager.trims_nul(), p.number)?;
+    match key.pop() {
+        Some(ColType::Integer(rowid)) if rowid >= 0 && !key.is_empty() => {
+            Ok((key, rowid as usize, left))
+        }
+        _ => bail!(SqliteError::CorruptDatabase(format!(
+            "index entry on page {} has a non-integer rowid",
+            p.number
+        ))),
+    }
+}
+
+// -> key/rowid
+// -> the left_pointer
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(p, state, pager), fields(page_idx = p.number - 1))
+)]
+fn parse_one_cell(
+    ic: usize,
+    offset: u16,
+    p: &Page,
+    state: &mut dyn OnColumn,
+    pager: &Pager,
+) -> Result<(ColType, usize)> {
+    pager.cell_decoded();
+    let db = pager.dbinfo();
+    let mut res = ColType::Null;
+    let mut left: usize = 0;
+
+    let u = db.page_size as usize;
+    // X of the file format: the most payload a table leaf cell keeps on its
+    // page, index cells keep less
+    let table_local = u - 35;
+    let index_local = ((u - 12) * 64 / 255) - 23;
+    let Some(buf) = p.page.get(offset as usize..) else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "cell offset {offset} is past the end of page {}",
+            p.number
+        )));
+    };
+    let mut i = 0;
+    if p.page_type == 0x0d {
+        let (size, j1) = decode_varint(buf)?;
+        i += j1;
+        let (rowid, j2) = decode_varint(&buf[i..])?;
+        i += j2;
+
+        let payload = cell_payload(&buf[i..], size, table_local, p.number, pager)?;
+        for (f, v) in record_values(&payload, db.text_encoding, pager.trims_nul(), p.number)?
+            .iter()
+            .enumerate()
+        {
+            state.on_col(p.page_type, ic, f, v, rowid);
+        }
+        res = ColType::Integer(rowid);
+    } else if p.page_type == 0x05 {
+        left = left_child(p, offset as usize)?;
+        i += 4;
+        let (rowid, _) = decode_varint(&buf[i..])?;
+        res = ColType::Integer(rowid);
+    } else if p.page_type == 0x02 {
+        let lefta = left_child(p, offset as usize)?;
+        i += 4;
+        let (size, j1) = decode_varint(&buf[i..])?;
+        i += j1;
+
+        let payload = cell_payload(&buf[i..], size, index_local, p.number, pager)?;
+        for (f, v) in record_values(&payload, db.text_encoding, pager.trims_nul(), p.number)?
+            .into_iter()
+            .enumerate()
+        {
+            trace!("page type 0x02: {f}, value: {v}");
+            state.on_col(p.page_type, ic, f, &v, -1);
+            if f == 0 {
+                // the leading key column, the rowid comes last
+                res = v;
+            }
+        }
+        left = lefta
+    } else if p.page_type == 0x0a {
+        // payload size
+        let (size, j1) = decode_varint(buf)?;
+        i += j1;
+
+        let payload = cell_payload(&buf[i..], size, index_local, p.number, pager)?;
+        let mut rowid = 0;
+        let values = record_values(&payload, db.text_encoding, pager.trims_nul(), p.number)?;
+        let last = values.len().saturating_sub(1);
+        for (f, v) in values.into_iter().enumerate() {
+            trace!("page_type: 0x0a: {f}, value:{v}");
+            state.on_col(p.page_type, ic, f, &v, -1);
+            if f == last {
+                rowid = match v {
+                    ColType::Integer(vv) => vv as usize,
+                    _ => bail!(SqliteError::CorruptDatabase(format!(
+                        "index entry on page {} has a non-integer rowid",
+                        p.number
+                    ))),
+                };
+            } else if f == 0 {
+                res = v;
+            }
+        }
+        left = rowid;
+    } else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "page {} has invalid type 0x{:02x}",
+            p.number, p.page_type
+        )));
+    }
+
+    Ok((res, left))
+}
+
+// MAX_PAYLOAD is sqlite3's default SQLITE_MAX_LENGTH.
+const MAX_PAYLOAD: usize = 1_000_000_000;
+
+// cell_payload returns the `size` bytes of payload a cell declares, from
+// `local` on its page and, past `max_local` bytes, the overflow chain.
+// A size the page or the chain can't back is corruption.
+fn cell_payload<'a>(
+    local: &'a [u8],
+    size: i64,
+    max_local: usize,
+    page: usize,
+    pager: &Pager,
+) -> Result<Cow<'a, [u8]>> {
+    let corrupt =
+        |what: String| SqliteError::CorruptDatabase(format!("cell on page {page} {what}"));
+    let Ok(size) = usize::try_from(size) else {
+        bail!(corrupt(format!("has a negative payload size {size}")));
+    };
+    // sqlite3 never stores a value, let alone a record, bigger than its
+    // default SQLITE_MAX_LENGTH, and an overflow chain looping back on
+    // itself would otherwise be followed until memory runs out
+    if size > MAX_PAYLOAD {
+        bail!(corrupt(format!("declares a {size} byte payload")));
+    }
+    if size <= max_local {
+        return match local.get(..size) {
+            Some(payload) => Ok(Cow::Borrowed(payload)),
+            None => bail!(corrupt(format!(
+                "declares {size} payload bytes, past the end of the page"
+            ))),
+        };
+    }
+    let on_page = local_size(size, max_local, pager.dbinfo().page_size as usize);
+    let Some(first) = local.get(..on_page + 4) else {
+        bail!(corrupt("runs past the end of the page".to_string()));
+    };
+    let mut payload = first[..on_page].to_vec();
+    let mut next = u32::from_be_bytes(first[on_page..].try_into().unwrap());
+    while payload.len() < size {
+        if next == 0 {
+            bail!(corrupt(format!(
+                "declares {size} payload bytes, its overflow pages hold {}",
+                payload.len()
+            )));
+        }
+        let op = pager.page(next as usize - 1, true)?;
+        payload.extend(&op.page[4..]);
+        next = u32::from_be_bytes(op.page[..4].try_into().unwrap());
+    }
+    payload.truncate(size);
+    Ok(Cow::Owned(payload))
+}
+
+// local_size is how many bytes of a `size` byte payload its cell keeps on
+// the page: all of them up to `max_local`, X of the file format, else the
+// first K bytes if that's at most X, else only M.
+pub(crate) fn local_size(size: usize, max_local: usize, page_size: usize) -> usize {
+    if size <= max_local {
+        return size;
+    }
+    let u = page_size;
+    let m = ((u - 12) * 32 / 255) - 23;
+    let k = m + (size - m) % (u - 4);
+    if k <= max_local { k } else { m }
+}
+
+// record_values decodes a record, which must fill its cell's payload exactly
+// like sqlite3 checks: a header or body running past the payload, or ending
+// short of it, means the declared size is wrong.
+pub(crate) fn record_values(
+    payload: &[u8],
+    text_encoding: u32,
+    trim_nul: bool,
+    page: usize,
+) -> Result<Vec<ColType>> {
+    let corrupt = |what: String| {
+        SqliteError::CorruptDatabase(format!(
+            "record on page {page} {what}, its cell declares {} bytes",
+            payload.len()
+        ))
+    };
+    let (header_size, mut i) = decode_varint(payload)?;
+    let header_size = match usize::try_from(header_size) {
+        Ok(h) if h >= i && h <= payload.len() => h,
+        _ => bail!(corrupt(format!("has a {header_size} byte header"))),
+    };
+    let mut serials = Vec::new();
+    while i < header_size {
+        let (serial_type, j) = decode_varint(&payload[i..header_size])?;
+        i += j;
+        serials.push(serial_type);
+    }
+    #[cfg(feature = "tracing")]
+    tracing::trace!(page, serial_types = ?serials, "record header");
+
+    let mut values = Vec::with_capacity(serials.len());
+    let mut end = header_size;
+    for t in serials {
+        let size = serial_type_size(t)?;
+        if end + size > payload.len() {
+            bail!(corrupt("runs past its payload".to_string()));
+        }
+        values.push(col_value(t, payload, end, text_encoding, trim_nul)?);
+        end += size;
+    }
+    if end != payload.len() {
+        bail!(corrupt(format!("takes {end} bytes")));
+    }
+    Ok(values)
+}
+
+/// How full the pages of a table or index b-tree are, as `.pagestats` shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeStats {
+    /// The number of levels, 1 for a tree whose root is a leaf.
+    pub depth: usize,
+    /// Every leaf page, in key order.
+    pub leaves: Vec<LeafStats>,
+}
+
+/// One leaf page of a b-tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafStats {
+    /// The page number, from 1.
+    pub page: usize,
+    /// How many cells it holds.
+    pub cells: usize,
+    /// The bytes no cell uses: unallocated, in freeblocks or fragmented.
+    pub free_bytes: usize,
+}
+
+/// A row of a table b-tree whose rowid isn't above the one of the row
+/// before it, which [`Database::check_rowids`](crate::Database::check_rowids)
+/// reports. sqlite3 keeps rowids strictly increasing across every leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowidViolation {
+    /// The leaf page holding the row, from 1.
+    pub page: usize,
+    /// The row's cell on that page, from 0.
+    pub cell: usize,
+    /// The row's rowid.
+    pub rowid: i64,
+    /// The rowid of the row before it, on the same leaf or an earlier one.
+    pub previous: i64,
+}
+
+// check_rowids reads the rowid of every cell of the table b-tree rooted at
+// `rootpage`, leaf after leaf, and reports each one not above the last.
+pub fn check_rowids(pager: &Pager, rootpage: usize) -> Result<Vec<RowidViolation>> {
+    let mut violations = Vec::new();
+    let mut previous = None;
+    for leaf in tree_stats(pager, rootpage)?.leaves {
+        let p = pager.page(leaf.page - 1, false)?;
+        if p.page_type != 0x0d {
+            bail!(SqliteError::CorruptDatabase(format!(
+                "page {} is not a table b-tree page",
+                p.number
+            )));
+        }
+        for cell in 0..p.cell_offsets.len() {
+            let rowid = cell_rowid(&p, cell, pager)?;
+            if let Some(previous) = previous.filter(|&prev| rowid <= prev) {
+                violations.push(RowidViolation {
+                    page: p.number,
+                    cell,
+                    rowid,
+                    previous,
+                });
+            }
+            previous = Some(rowid);
+        }
+    }
+    Ok(violations)
+}
+
+// tree_stats walks the whole b-tree rooted at `rootpage`, reading every page
+// but none of the overflow pages and without decoding a cell.
+pub fn tree_stats(pager: &Pager, rootpage: usize) -> Result<TreeStats> {
+    let mut stats = TreeStats {
+        depth: 0,
+        leaves: Vec::new(),
+    };
+    visit_pages(pager, rootpage, rootpage, 1, &mut stats)?;
+    Ok(stats)
+}
+
+fn visit_pages(
+    pager: &Pager,
+    rootpage: usize,
+    page: usize,
+    level: usize,
+    stats: &mut TreeStats,
+) -> Result<()> {
+    if level > MAX_DEPTH {
+        bail!(too_deep(rootpage));
+    }
+    let p = pager.page(page - 1, false)?;
+    if matches!(p.page_type, 0x0d | 0x0a) {
+        stats.depth = stats.depth.max(level);
+        stats.leaves.push(LeafStats {
+            page,
+            cells: p.cell_offsets.len(),
+            free_bytes: p.free_bytes(),
+        });
+        return Ok(());
+    }
+    // an interior cell starts with the page number of its left child
+    for &offset in &p.cell_offsets {
+        let left = left_child(&p, offset as usize)?;
+        visit_pages(pager, rootpage, left, level + 1, stats)?;
+    }
+    visit_pages(pager, rootpage, p.right.unwrap() as usize, level + 1, stats)
+}
+
+// leftmost_depth counts the levels of the b-tree rooted at `rootpage` by
+// following first children down to a leaf, a page per level. Every leaf of a
+// b-tree is as deep, so that's the depth of the whole tree.
+pub fn leftmost_depth(pager: &Pager, rootpage: usize) -> Result<usize> {
+    let mut page = rootpage;
+    let mut depth = 1;
+    loop {
+        let p = pager.page(page - 1, false)?;
+        if matches!(p.page_type, 0x0d | 0x0a) {
+            return Ok(depth);
+        }
+        page = match p.cell_offsets.first() {
+            Some(&offset) => left_child(&p, offset as usize)?,
+            None => p.right.unwrap() as usize,
+        };
+        depth += 1;
+        if depth > MAX_DEPTH {
+            bail!(too_deep(rootpage));
+        }
+    }
+}
+
+// index_entries calls `visit` with the record of every entry of the index
+// b-tree rooted at `rootpage`, in key order: an interior cell's entry sorts
+// after the whole subtree of its left child.
+pub fn index_entries(
+    pager: &Pager,
+    rootpage: usize,
+    visit: &mut dyn FnMut(Vec<ColType>) -> Result<()>,
+) -> Result<()> {
+    visit_entries(pager, rootpage, rootpage, 1, visit)
+}
+
+fn visit_entries(
+    pager: &Pager,
+    rootpage: usize,
+    page: usize,
+    level: usize,
+    visit: &mut dyn FnMut(Vec<ColType>) -> Result<()>,
+) -> Result<()> {
+    if level > MAX_DEPTH {
+        bail!(too_deep(rootpage));
+    }
+    let p = pager.page(page - 1, false)?;
+    if !matches!(p.page_type, 0x02 | 0x0a) {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "page {page} of the index rooted at page {rootpage} is not an index page"
+        )));
+    }
+    let db = pager.dbinfo();
+    let max_local = ((db.page_size as usize - 12) * 64 / 255) - 23;
+    for &offset in &p.cell_offsets {
+        // an interior cell starts with its left child, then both kinds hold
+        // the payload size and the payload
+        let mut start = offset as usize;
+        if p.page_type == 0x02 {
+            let left = left_child(&p, start)?;
+            visit_entries(pager, rootpage, left, level + 1, visit)?;
+            start += 4;
+        }
+        let Some(buf) = p.page.get(start..) else {
+            bail!(SqliteError::CorruptDatabase(format!(
+                "cell offset {offset} is past the end of page {page}"
+            )));
+        };
+        let (size, n) = decode_varint(buf)?;
+        let payload = cell_payload(&buf[n..], size, max_local, page, pager)?;
+        visit(record_values(
+            &payload,
+            db.text_encoding,
+            pager.trims_nul(),
+            page,
+        )?)?;
+    }
+    if p.page_type == 0x02 {
+        visit_entries(pager, rootpage, p.right.unwrap() as usize, level + 1, visit)?;
+    }
+    Ok(())
+}
+
+pub struct IndexCol;
+
+impl OnColumn for IndexCol {
+    fn on_col(&mut self, _cur_type: u8, row: usize, col: usize, v: &ColType, _rowid: i64) {
+        trace!("on_col {row}, {col}, {v}");
+    }
+
+    fn on_row(&mut self, _cur_type: u8, _: i64) {
+        trace!("on_row");
+    }
+
+    fn finalize(&mut self) {}
+}
+
+/// A cursor over the rows of a table b-tree in rowid order, made by
+/// [`Database::scan_table`](crate::Database::scan_table). Pages are read
+/// only when the walk reaches them, so a caller that stops early never reads
+/// the rest of the tree.
+///
+/// Each row comes as its rowid and the values its record holds, without
+/// going through SQL: a column that aliases the rowid is NULL as stored, and
+/// a record written before columns were added has fewer values.
+pub struct TableCursor<'p> {
+    pager: &'p Pager,
+    // the pages from the root down to the current leaf, each with the index of
+    // the next cell (leaf) or child (interior) to visit
+    stack: Vec<(Page<'p>, usize)>,
+    rootpage: usize,
+    started: bool,
+    // the rowid last yielded, which debug builds check the next one is above
+    last: Option<i64>,
+}
+
+impl fmt::Debug for TableCursor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableCursor")
+            .field("rootpage", &self.rootpage)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'p> TableCursor<'p> {
+    // new positions the cursor before the first record, nothing is read yet.
+    pub(crate) fn new(pager: &'p Pager, rootpage: usize) -> Self {
+        TableCursor {
+            pager,
+            stack: Vec::new(),
+            rootpage,
+            started: false,
+            last: None,
+        }
+    }
+
+    /// Positions the cursor at the first row whose rowid is `rowid` or more,
+    /// reading only the pages on the way down to its leaf. Past the last row
+    /// the cursor yields nothing.
+    pub fn seek(&mut self, rowid: i64) -> Result<()> {
+        self.stack.clear();
+        self.started = true;
+        self.last = None;
+        let mut page = self.pager.page(self.rootpage - 1, false)?;
+        loop {
+            // the first cell whose key is at least rowid: an interior key is the
+            // largest rowid of the child to its left
+            let cells = page.cell_offsets.len();
+            let (mut l, mut r) = (0, cells);
+            while l < r {
+                let m = l + (r - l) / 2;
+                if cell_rowid(&page, m, self.pager)? < rowid {
+                    l = m + 1;
+                } else {
+                    r = m;
+                }
+            }
+            match page.page_type {
+                0x0d => {
+                    self.stack.push((page, l));
+                    return Ok(());
+                }
+                0x05 => {
+                    let child = if l < cells {
+                        left_child(&page, page.cell_offsets[l] as usize)?
+                    } else {
+                        page.right.unwrap() as usize
+                    };
+                    if cells > 0 {
+                        let key = cell_rowid(&page, l.min(cells - 1), self.pager)?;
+                        self.pager.descend(page.number, &key, &rowid, child);
+                    }
+                    self.stack.push((page, l + 1));
+                    check_depth(&self.stack)?;
+                    page = self.pager.page(child - 1, false)?;
+                }
+                _ => bail!(SqliteError::CorruptDatabase(format!(
+                    "page {} is not a table b-tree page",
+                    page.number
+                ))),
+            }
+        }
+    }
+
+    // next_record returns the next rowid and record, None past the last one.
+    pub(crate) fn next_record(&mut self) -> Result<Option<(i64, Vec<ColType>)>> {
+        if !self.started {
+            self.started = true;
+            self.stack
+                .push((self.pager.page(self.rootpage - 1, false)?, 0));
+        }
+        loop {
+            let Some((p, next)) = self.stack.last_mut() else {
+                return Ok(None);
+            };
+            let cells = p.cell_offsets.len();
+            let child = match p.page_type {
+                0x0d if *next < cells => {
+                    let mut record = Record::default();
+                    let (rowid, _) =
+                        parse_one_cell(*next, p.cell_offsets[*next], p, &mut record, self.pager)?;
+                    *next += 1;
+                    let rowid = rowid.try_into()?;
+                    // out of order rows mean a corrupt file or a bug in the walk
+                    if cfg!(debug_assertions)
+                        && let Some(last) = self.last.filter(|&last| rowid <= last)
+                    {
+                        bail!(SqliteError::CorruptDatabase(format!(
+                            "rowid {rowid} on page {} follows rowid {last}",
+                            p.number
+                        )));
+                    }
+                    self.last = Some(rowid);
+                    return Ok(Some((rowid, record.0)));
+                }
+                0x05 if *next < cells => {
+                    let offset = p.cell_offsets[*next] as usize;
+                    *next += 1;
+                    left_child(p, offset)?
+                }
+                0x05 if *next == cells => {
+                    *next += 1;
+                    p.right.unwrap() as usize
+                }
+                0x0d | 0x05 => {
+                    self.stack.pop();
+                    continue;
+                }
+                _ => bail!(SqliteError::CorruptDatabase(format!(
+                    "page {} is not a table b-tree page",
+                    p.number
+                ))),
+            };
+            check_depth(&self.stack)?;
+            let child = self.pager.page(child - 1, false)?;
+            self.stack.push((child, 0));
+        }
+    }
+}
+
+/// Yields each row's rowid and values. After an error it yields nothing
+/// more.
+impl Iterator for TableCursor<'_> {
+    type Item = Result<(i64, Vec<Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_record().transpose();
+        if let Some(Err(_)) = next {
+            self.stack.clear();
+        }
+        next
+    }
+}
+
+// leaf_for reads the leaf of the table rooted at `rootpage` that holds
+// `rowid`, or would hold it: the rightmost one for a rowid past the last.
+pub(crate) fn leaf_for(pager: &Pager, rootpage: usize, rowid: i64) -> Result<Page<'_>> {
+    let mut cursor = TableCursor::new(pager, rootpage);
+    cursor.seek(rowid)?;
+    // a seek that succeeds always ends on a leaf
+    Ok(cursor.stack.pop().unwrap().0)
+}
+
+// last_rowid is the largest rowid of the table rooted at `rootpage`, 0 when
+// it has no rows.
+pub(crate) fn last_rowid(pager: &Pager, rootpage: usize) -> Result<i64> {
+    let leaf = leaf_for(pager, rootpage, i64::MAX)?;
+    match leaf.cell_offsets.len() {
+        0 => Ok(0),
+        n => cell_rowid(&leaf, n - 1, pager),
+    }
+}
+
+// table_row decodes cell `i` of a table leaf page into its rowid and values.
+pub(crate) fn table_row(p: &Page, i: usize, pager: &Pager) -> Result<(i64, Vec<ColType>)> {
+    let mut record = Record::default();
+    let (rowid, _) = parse_one_cell(i, p.cell_offsets[i], p, &mut record, pager)?;
+    Ok((rowid.try_into()?, record.0))
+}
+
+// children lists the child pages of an interior page, the right child last.
+pub(crate) fn children(p: &Page) -> Result<Vec<usize>> {
+    let mut children = p
+        .cell_offsets
+        .iter()
+        .map(|&offset| left_child(p, offset as usize))
+        .collect::<Result<Vec<_>>>()?;
+    children.extend(p.right.map(|r| r as usize));
+    Ok(children)
+}
+
+// cell_rowid reads the key of cell `i` of a table page without decoding its
+// record: the rowid of a leaf cell, the largest rowid to its left for an
+// interior one.
+fn cell_rowid(p: &Page, i: usize, pager: &Pager) -> Result<i64> {
+    pager.cell_decoded();
+    let offset = p.cell_offsets[i] as usize;
+    let buf = match p.page_type {
+        0x0d => p.page.get(offset..),
+        _ => p.page.get(offset + 4..),
+    };
+    let Some(buf) = buf else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "cell offset {offset} is past the end of page {}",
+            p.number
+        )));
+    };
+    match p.page_type {
+        0x0d => {
+            let (_, n) = decode_varint(buf)?;
+            Ok(decode_varint(&buf[n..])?.0)
+        }
+        _ => Ok(decode_varint(buf)?.0),
+    }
+}
+
+// MAX_DEPTH is how deep a b-tree can be, as in sqlite3: a cursor going
+// deeper is following a page that's its own descendant.
+const MAX_DEPTH: usize = 20;
+
+fn check_depth(stack: &[(Page, usize)]) -> Result<()> {
+    if stack.len() >= MAX_DEPTH {
+        bail!(too_deep(stack[0].0.number));
+    }
+    Ok(())
+}
+
+fn too_deep(rootpage: usize) -> SqliteError {
+    SqliteError::CorruptDatabase(format!(
+        "b-tree rooted at page {rootpage} is more than {MAX_DEPTH} levels deep"
+    ))
+}
+
+// Record collects the values of one table leaf cell.
+#[derive(Default)]
+struct Record(Vec<ColType>);
+
+impl OnColumn for Record {
+    fn on_col(&mut self, _cur_type: u8, _row: usize, _col: usize, v: &ColType, _rowid: i64) {
+        self.0.push(v.clone());
+    }
+
+    fn on_row(&mut self, _cur_type: u8, _: i64) {}
+
+    fn finalize(&mut self) {}
+}
@@ -0,0 +1,87 @@
+use thiserror::Error;
+
+/// Categorizes failures, each category exits the binary with its own code.
+#[derive(Debug, Error)]
+pub enum SqliteError {
+    /// A command or its arguments were used wrongly, or a statement can't
+    /// run in the current state, e.g. COMMIT without a transaction.
+    #[error("{0}")]
+    Usage(String),
+    /// The file doesn't start with the SQLite header.
+    #[error("file is not a database")]
+    NotADatabase,
+    /// The file is a database, but a page or record in it is damaged.
+    #[error("database disk image is malformed: {0}")]
+    CorruptDatabase(String),
+    /// The SQL doesn't parse, or names something in a way it can't.
+    #[error("{0}")]
+    SqlParse(String),
+    /// A statement names a table the schema doesn't have.
+    #[error("no such table: {0}")]
+    NoSuchTable(String),
+    /// A statement names a column its table doesn't have.
+    #[error("no such column: {0}")]
+    NoSuchColumn(String),
+    /// The file or statement uses something that isn't implemented.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    /// A value was read as a Rust type it doesn't convert to.
+    #[error("column {column} is {actual}, not {expected}")]
+    WrongType {
+        /// The type asked for.
+        expected: &'static str,
+        /// The SQL type of the value.
+        actual: &'static str,
+        /// The column the value came from.
+        column: String,
+    },
+    /// A STRICT table holds a value of another type than its column
+    /// declares, which sqlite3 never stores.
+    #[error("{table}.{column} is a STRICT {declared} column but holds a {stored} value")]
+    StrictTypeViolation {
+        /// The table holding the value.
+        table: String,
+        /// The column holding the value.
+        column: String,
+        /// The column's declared type.
+        declared: String,
+        /// The SQL type of the value.
+        stored: &'static str,
+    },
+    /// A rollback journal next to the database holds pages of a transaction
+    /// that didn't finish, so the file may be half written.
+    #[error(
+        "hot journal {path} found ({pages} pages saved): the database is mid-transaction, \
+         open it once with sqlite3 to roll it back, read it rolled back in memory, or open \
+         it immutable to read the possibly inconsistent file as is"
+    )]
+    HotJournal {
+        /// The journal's path.
+        path: String,
+        /// How many pages the journal saved.
+        pages: u32,
+    },
+    /// A write would break a NOT NULL, UNIQUE or type constraint.
+    #[error("{0}")]
+    Constraint(String),
+}
+
+impl SqliteError {
+    /// The process exit status for this category, 1 is left for I/O and other
+    /// uncategorized failures.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            SqliteError::Usage(_) => 2,
+            SqliteError::NotADatabase => 3,
+            SqliteError::CorruptDatabase(_) => 4,
+            SqliteError::SqlParse(_) => 5,
+            SqliteError::NoSuchTable(_) => 6,
+            SqliteError::NoSuchColumn(_) => 7,
+            SqliteError::Unsupported(_) => 8,
+            SqliteError::HotJournal { .. } => 9,
+            SqliteError::WrongType { .. } => 10,
+            SqliteError::StrictTypeViolation { .. } => 11,
+            SqliteError::Constraint(_) => 12,
+        }
+    }
+}
@@ -0,0 +1,1983 @@
+use anyhow::{Context, Result, bail};
+use log::{debug, trace};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::aggregate::AggregateFunction;
+use crate::btree::{
+    self, IndexCol, IndexTarget, OnColumn, RowidViolation, TableCursor, scan_btree,
+};
+use crate::error::SqliteError;
+use crate::expr::{self, Expr};
+use crate::pager::Pager;
+use crate::parser;
+use crate::record::{ColType, FromValue, Value};
+use crate::rtree::{self, RtreeCursor, RtreeScan};
+use crate::schema::{
+    Create, Tables, UniqueViolation, column_affinity, column_index, is_rowid_alias,
+};
+#[cfg(feature = "serde")]
+use crate::ser::RowMap;
+use crate::write;
+
+#[derive(Debug, Clone)]
+pub enum SelectBy {
+    Scan,
+    RowIds(Vec<usize>),
+    // rowids already in ORDER BY order, so plain rows need no sorting
+    Sorted(Vec<usize>),
+}
+
+// the query side of Tables, the schema itself is read in schema.rs
+impl Tables {
+    fn select_rowids_by_index(
+        &self,
+        pager: &Pager,
+        index_name: &String,
+        target: IndexTarget,
+    ) -> Result<Vec<usize>> {
+        let index_rootpage = self
+            .pos
+            .get(index_name)
+            .with_context(|| format!("no such index: {index_name}"))?;
+        let p = pager
+            .page(index_rootpage - 1, false)
+            .with_context(|| format!("cannot parse page {index_rootpage} for {index_name}"))?;
+        let search = btree::IndexSearch {
+            target,
+            desc: self.index_desc(index_name),
+        };
+        scan_btree(&p, &mut IndexCol, pager, Some(&search), None)
+    }
+
+    // tree_depth is the number of levels of `table`'s b-tree, 1 when the
+    // root is a leaf, reading one page per level.
+    pub fn tree_depth(&self, pager: &Pager, table: &str) -> Result<usize> {
+        let Some(&rootpage) = self.pos.get(table) else {
+            bail!(SqliteError::NoSuchTable(table.to_string()));
+        };
+        btree::leftmost_depth(pager, rootpage)
+    }
+
+    // scan_table opens a cursor on the rows of the rowid table `table`.
+    pub fn scan_table<'p>(&self, pager: &'p Pager, table: &str) -> Result<TableCursor<'p>> {
+        match self.content.get(table) {
+            Some(Create::Table(t)) if t.without_rowid => bail!(SqliteError::Unsupported(format!(
+                "{table} is a WITHOUT ROWID table"
+            ))),
+            Some(Create::Table(t)) if self.pos[table] == 0 => bail!(no_content(t)),
+            Some(Create::Table(_)) => Ok(TableCursor::new(pager, self.pos[table])),
+            _ => bail!(SqliteError::NoSuchTable(table.to_string())),
+        }
+    }
+
+    // check_rowids walks the leaves of the rowid table `table` checking that
+    // rowids only go up.
+    pub fn check_rowids(&self, pager: &Pager, table: &str) -> Result<Vec<RowidViolation>> {
+        match self.content.get(table) {
+            Some(Create::Table(t)) if t.without_rowid => bail!(SqliteError::Unsupported(format!(
+                "{table} is a WITHOUT ROWID table"
+            ))),
+            Some(Create::Table(t)) if self.pos[table] == 0 => bail!(no_content(t)),
+            Some(Create::Table(_)) => btree::check_rowids(pager, self.pos[table]),
+            _ => bail!(SqliteError::NoSuchTable(table.to_string())),
+        }
+    }
+
+    // check_unique walks every UNIQUE index of the rowid table `table` and
+    // pairs up the entries with equal keys, the first of a run with each of
+    // the others. Keys holding a NULL never conflict.
+    pub fn check_unique(&self, pager: &Pager, table: &str) -> Result<Vec<UniqueViolation>> {
+        let Some(info) = self
+            .info
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(table))
+        else {
+            bail!(SqliteError::NoSuchTable(table.to_string()));
+        };
+        if info.without_rowid {
+            bail!(SqliteError::Unsupported(format!(
+                "{table} is a WITHOUT ROWID table"
+            )));
+        }
+        let mut violations = Vec::new();
+        for index in info.indexes.iter().filter(|i| i.unique) {
+            // each entry is the key followed by the rowid
+            let mut first: Option<(Vec<ColType>, i64)> = None;
+            btree::index_entries(pager, index.rootpage, &mut |mut entry| {
+                let rowid = match entry.pop() {
+                    Some(ColType::Integer(rowid)) => rowid,
+                    _ => bail!(SqliteError::CorruptDatabase(format!(
+                        "entry of index {} has no integer rowid",
+                        index.name
+                    ))),
+                };
+                match &first {
+                    Some((key, other)) if *key == entry => violations.push(UniqueViolation {
+                        index_name: index.name.clone(),
+                        key_value: entry,
+                        rowids: [*other, rowid],
+                    }),
+                    _ if entry.iter().any(|v| matches!(v, ColType::Null)) => first = None,
+                    _ => first = Some((entry, rowid)),
+                }
+                Ok(())
+            })?;
+        }
+        Ok(violations)
+    }
+
+    // plan decides how a select reaches its rows: a rowid seek when the only
+    // condition is an equality on the rowid, an index search when the WHERE
+    // clause sets the leading columns of an index equal to values or the only
+    // condition is a LIKE prefix, a walk of an index when its order is the one
+    // ORDER BY asks for, and a full scan otherwise.
+    fn plan(&self, select: &parser::SelectStmt) -> Result<Plan> {
+        let table = &select.table;
+        if select.args.is_some() {
+            if self.functions.get(table).is_none() {
+                bail!(SqliteError::NoSuchTable(table.clone()));
+            }
+            return Ok(Plan {
+                table: table.clone(),
+                access: Access::Function,
+            });
+        }
+        let t = match self.content.get(table) {
+            Some(Create::Table(t)) => t,
+            _ => bail!(SqliteError::NoSuchTable(table.clone())),
+        };
+        if self.pos[table] == 0 {
+            bail!(no_content(t));
+        }
+        let access = match select.conditions.as_slice() {
+            [cond] if cond.op == "=" => {
+                let is_rowid = ["rowid", "_rowid_", "oid"]
+                    .iter()
+                    .any(|r| cond.column.eq_ignore_ascii_case(r))
+                    || t.rowid_column() == Some(cond.column.as_str());
+                // a placeholder's value is only known when the statement runs
+                let seekable = cond.value.parse::<usize>().is_ok() || cond.value.starts_with('?');
+                match is_rowid && seekable {
+                    true => Access::Rowid,
+                    false => self.index_search(t, select).unwrap_or(Access::Scan),
+                }
+            }
+            // a LIKE pattern that starts with text matches only keys that
+            // start with it, a range of the index. LIKE ignores the case of
+            // ASCII letters where the index doesn't, so like sqlite3 with a
+            // BINARY index, only a prefix without any can seek, and only on
+            // a TEXT column, whose index holds numbers as text too
+            [cond] if cond.op == "like" => {
+                let prefix = match &select.filter {
+                    Some(Expr::Call { name, args }) if name == "like" => match &args[..] {
+                        [Expr::Literal(expr::Literal::Text(p)), Expr::Column(_)] => {
+                            expr::like_prefix(p)
+                        }
+                        _ => "",
+                    },
+                    _ => "",
+                };
+                let seekable = !prefix.is_empty()
+                    && !prefix.bytes().any(|b| b.is_ascii_alphabetic())
+                    && column_index(&t.columns, &cond.column)
+                        .is_some_and(|i| t.columns[i].text_affinity());
+                match self.usable_index(select) {
+                    Some((column, index)) if seekable && *column == cond.column => {
+                        Access::IndexRange {
+                            name: index.clone(),
+                            column: column.clone(),
+                            prefix: prefix.to_string(),
+                        }
+                    }
+                    _ => Access::Scan,
+                }
+            }
+            _ => self.index_search(t, select).unwrap_or(Access::Scan),
+        };
+        let access = match (access, &t.fts5, &t.rtree) {
+            (_, _, Some(_)) => Access::Rtree(rtree::index(t, select.filter.as_ref())),
+            (Access::Scan, Some(_), _) => Access::FullText(fts5_index(t, select.filter.as_ref())),
+            (Access::Scan, None, None) => self.index_order(select).unwrap_or(Access::Scan),
+            (access, ..) => access,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(table_name = %table, ?access, conditions = select.conditions.len(), "planned");
+        Ok(Plan {
+            table: table.clone(),
+            access,
+        })
+    }
+
+    // index_search searches the usable index for the keys that start with the
+    // values the WHERE clause sets its leading columns equal to, as many
+    // columns in a row as it sets that sort by BINARY. The other terms filter
+    // what it finds, a comparison with another column among them.
+    fn index_search(
+        &self,
+        t: &parser::CreateTableStmt,
+        select: &parser::SelectStmt,
+    ) -> Option<Access> {
+        let (_, name) = self.usable_index(select)?;
+        let Some(Create::Index(index)) = self.content.get(name) else {
+            return None;
+        };
+        let columns = index
+            .columns
+            .iter()
+            .enumerate()
+            .map_while(|(k, column)| {
+                let bound = select.conditions.iter().any(|c| binds(c, column, t));
+                (bound && index.sorts_binary(k, t)).then(|| column.clone())
+            })
+            .collect::<Vec<_>>();
+        (!columns.is_empty()).then(|| Access::Index {
+            name: name.clone(),
+            columns,
+        })
+    }
+
+    // usable_index is the index of the select's table, the column it's on
+    // and its name, unless it's a partial index whose predicate the WHERE
+    // clause doesn't imply: it would miss rows the query returns. Nor is an
+    // index whose collation isn't BINARY, which sorts keys the comparisons
+    // here would look for elsewhere, as with COLLATE NOCASE.
+    fn usable_index(&self, select: &parser::SelectStmt) -> Option<&(String, String)> {
+        let Some(Create::Table(t)) = self.content.get(&select.table) else {
+            return None;
+        };
+        self.indexes.get(&select.table).filter(|(_, name)| {
+            matches!(self.content.get(name), Some(Create::Index(i))
+                if i.covers(&select.conditions) && i.sorts_binary(0, t))
+        })
+    }
+
+    // index_order walks the index of the only ORDER BY term, a column, when
+    // the index sorts it as ORDER BY does: read forward when both are DESC or
+    // neither is, backward otherwise. A result column named like the term but
+    // computed otherwise would be the sort key instead.
+    fn index_order(&self, select: &parser::SelectStmt) -> Option<Access> {
+        let [(term, desc)] = &select.order_by[..] else {
+            return None;
+        };
+        let (column, index) = self.usable_index(select)?;
+        let aliased = select.columns.iter().any(|c| {
+            matches!(parser::split_alias(c), (sql, Some(alias))
+                if alias.eq_ignore_ascii_case(term) && !sql.trim().eq_ignore_ascii_case(term))
+        });
+        if !term.eq_ignore_ascii_case(column) || aliased || !select.group_by.is_empty() {
+            return None;
+        }
+        Some(Access::IndexOrder {
+            name: index.clone(),
+            desc: *desc,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(table_name = %select.table))
+    )]
+    pub fn select(
+        &self,
+        pager: &Pager,
+        select: &parser::SelectStmt,
+        select_by: SelectBy,
+        params: &[ColType],
+    ) -> Result<ResultSet> {
+        self.compile(select, params)?.run(pager, select_by)
+    }
+
+    // compile checks a select against its table and gets everything ready to
+    // take records, without reading any. `params` are bound to the
+    // placeholders of its expressions.
+    fn compile(&self, select: &parser::SelectStmt, params: &[ColType]) -> Result<Query> {
+        let table = &select.table;
+        let function_table;
+        let (t, source) = match &select.args {
+            Some(args) => {
+                let (columns, rows) = self.call_function(table, args, params)?;
+                function_table = columns;
+                (&function_table, Source::Function(rows))
+            }
+            None => match (self.content.get(table), self.pos.get(table)) {
+                (Some(Create::Table(t)), Some(rootpage)) => (t, Source::Table(*rootpage)),
+                _ => bail!(SqliteError::NoSuchTable(table.clone())),
+            },
+        };
+        if t.without_rowid {
+            bail!(SqliteError::Unsupported(format!(
+                "WITHOUT ROWID table {table}"
+            )));
+        }
+        let mut exprs = Vec::new();
+        let mut names = Vec::new();
+        for col in &select.columns {
+            if col == "*" {
+                let columns = t.declared_columns();
+                exprs.extend(columns.iter().map(|c| Expr::Column(c.name.clone())));
+                names.extend(columns.iter().map(|c| c.name.clone()));
+            } else {
+                let (sql, alias) = parser::split_alias(col);
+                let mut e = expr::parse_expr(sql).map_err(SqliteError::SqlParse)?;
+                e.bind(params)?;
+                // sqlite3 names a bare rowid after the column aliasing it
+                let name = match (alias, &e, t.rowid_column()) {
+                    (Some(alias), _, _) => alias,
+                    (None, Expr::Column(c), Some(rowid)) if is_rowid_alias(c) => rowid.to_string(),
+                    _ => col.clone(),
+                };
+                exprs.push(e);
+                names.push(name);
+            }
+        }
+        if let Some(w) = select.filter.iter().flat_map(Expr::windows).next() {
+            bail!(SqliteError::SqlParse(format!(
+                "misuse of window function {}()",
+                w.func.name()
+            )));
+        }
+        if let Some(a) = select.filter.iter().flat_map(Expr::aggregates).next() {
+            bail!(SqliteError::SqlParse(format!(
+                "misuse of aggregate function {}()",
+                a.func.name()
+            )));
+        }
+        // GROUP BY terms name a column of the table, or else a result column by
+        // its alias or position
+        let mut group_by = Vec::new();
+        for (n, term) in select.group_by.iter().enumerate() {
+            let e = match term.parse::<usize>() {
+                Ok(k) if (1..=names.len()).contains(&k) => exprs[k - 1].clone(),
+                Ok(_) => bail!(SqliteError::SqlParse(format!(
+                    "{} GROUP BY term out of range - should be between 1 and {}",
+                    ordinal(n + 1),
+                    names.len()
+                ))),
+                Err(_) => {
+                    let is_column =
+                        column_index(&t.columns, term).is_some() || is_rowid_alias(term);
+                    match names.iter().position(|c| c.eq_ignore_ascii_case(term)) {
+                        Some(i) if !is_column => exprs[i].clone(),
+                        _ => {
+                            let mut e = expr::parse_expr(term).map_err(SqliteError::SqlParse)?;
+                            e.bind(params)?;
+                            e
+                        }
+                    }
+                }
+            };
+            if !e.aggregates().is_empty() {
+                bail!(SqliteError::SqlParse(
+                    "aggregate functions are not allowed in the GROUP BY clause".to_string()
+                ));
+            }
+            group_by.push(e);
+        }
+        // ORDER BY terms that aren't a result column are evaluated as hidden
+        // columns after the select list, and dropped once the rows are sorted
+        let mut keys = Vec::new();
+        for (n, (term, desc)) in select.order_by.iter().enumerate() {
+            let i = match term.parse::<usize>() {
+                Ok(k) if (1..=names.len()).contains(&k) => k - 1,
+                Ok(_) => bail!(SqliteError::SqlParse(format!(
+                    "{} ORDER BY term out of range - should be between 1 and {}",
+                    ordinal(n + 1),
+                    names.len()
+                ))),
+                Err(_) => match names.iter().position(|c| c.eq_ignore_ascii_case(term)) {
+                    Some(i) => i,
+                    None => {
+                        let mut e = expr::parse_expr(term).map_err(SqliteError::SqlParse)?;
+                        e.bind(params)?;
+                        exprs.push(e);
+                        exprs.len() - 1
+                    }
+                },
+            };
+            keys.push((i, *desc));
+        }
+        let mut filter = select.filter.clone();
+        if let Some(f) = &mut filter {
+            f.bind(params)?;
+        }
+        let indexed = t.fts5.as_ref().map(|f| f.indexed.as_slice());
+        for e in exprs.iter_mut().chain(&mut filter) {
+            e.resolve_match(&t.table, indexed)?;
+        }
+        for e in exprs.iter_mut().chain(&mut filter).chain(&mut group_by) {
+            e.resolve_affinity(&|name| column_affinity(t, name));
+        }
+        let source = match (&t.rtree, source) {
+            (Some(_), Source::Table(rowid_root)) => {
+                let node = format!("{table}_node");
+                let Some(&node_root) = self.pos.get(&node) else {
+                    bail!(SqliteError::NoSuchTable(node));
+                };
+                Source::Rtree(RtreeScan::new(t, node_root, rowid_root, filter.as_ref())?)
+            }
+            (_, source) => source,
+        };
+        // unknown columns fail before any row is read, even on an empty table
+        for e in exprs.iter().chain(&filter).chain(&group_by) {
+            for col in e.columns() {
+                if column_index(&t.columns, col).is_none() && !is_rowid_alias(col) {
+                    bail!(SqliteError::NoSuchColumn(col.to_string()));
+                }
+            }
+        }
+        let windows = exprs.iter().any(|e| !e.windows().is_empty());
+        let aggregate = exprs.iter().any(|e| !e.aggregates().is_empty()) || !group_by.is_empty();
+        if windows && aggregate {
+            bail!(SqliteError::Unsupported(
+                "aggregate and window functions in the same select".to_string()
+            ));
+        }
+        let collector = RowCollector {
+            table: t.table.clone(),
+            strict: t.strict,
+            schema: t.columns.clone(),
+            rowid_column: t.rowid_column().and_then(|c| column_index(&t.columns, c)),
+            exprs,
+            filter,
+            group_by,
+            target: None,
+            values: Vec::new(),
+            error: None,
+            raw: windows || aggregate,
+            aggregate,
+            rows: Vec::new(),
+        };
+        Ok(Query {
+            source,
+            collector,
+            names,
+            keys,
+        })
+    }
+
+    // call_function calls the table-valued function `name` with `args`,
+    // returning its rows and a table describing their columns.
+    fn call_function(
+        &self,
+        name: &str,
+        args: &[Expr],
+        params: &[ColType],
+    ) -> Result<(parser::CreateTableStmt, Box<dyn Iterator<Item = Row>>)> {
+        let Some(function) = self.functions.get(name) else {
+            bail!(SqliteError::NoSuchTable(name.to_string()));
+        };
+        let mut values = Vec::new();
+        for arg in args {
+            let mut arg = arg.clone();
+            arg.bind(params)?;
+            values.push(arg.eval(&|_| None)?);
+        }
+        let columns = function
+            .columns()
+            .into_iter()
+            .map(|name| parser::ColumnDef {
+                name,
+                ty: None,
+                primary_key: false,
+                not_null: false,
+                default: None,
+                collation: None,
+            })
+            .collect();
+        let table = parser::CreateTableStmt {
+            table: function.name().to_string(),
+            columns,
+            without_rowid: false,
+            strict: false,
+            autoincrement: false,
+            fts5: None,
+            rtree: None,
+        };
+        Ok((table, function.call(&values)?))
+    }
+}
+
+// no_content is the error for an FTS5 table whose text is kept elsewhere,
+// or not at all.
+fn no_content(t: &parser::CreateTableStmt) -> SqliteError {
+    SqliteError::Unsupported(format!(
+        "FTS5 table {} without a content table of its own",
+        t.table
+    ))
+}
+
+// Source is where a query's records come from.
+enum Source {
+    // the b-tree of a table, by its root page
+    Table(usize),
+    // the rows of a table-valued function, numbered from rowid 1
+    Function(Box<dyn Iterator<Item = Row>>),
+    // the leaves of an rtree
+    Rtree(RtreeScan),
+}
+
+// Query is a select compiled against its table.
+struct Query {
+    source: Source,
+    collector: RowCollector,
+    names: Vec<String>,
+    keys: Vec<(usize, bool)>, // ORDER BY as (column, descending)
+}
+
+impl Query {
+    // streams tells whether rows can come out as records are read: nothing
+    // has to see every row first.
+    fn streams(&self) -> bool {
+        !self.collector.raw && self.keys.is_empty()
+    }
+
+    fn run(self, pager: &Pager, select_by: SelectBy) -> Result<ResultSet> {
+        let Query {
+            source,
+            collector: mut cp,
+            names,
+            keys,
+        } = self;
+        let raw = cp.raw;
+        let sorted = matches!(select_by, SelectBy::Sorted(_)) && !raw;
+        match (source, select_by) {
+            (Source::Table(rootpage), SelectBy::Scan) => {
+                let p = pager.page(rootpage - 1, false)?;
+                scan_btree(&p, &mut cp, pager, None, None)?;
+            }
+            (Source::Table(rootpage), SelectBy::RowIds(rowids) | SelectBy::Sorted(rowids)) => {
+                let p = pager.page(rootpage - 1, false)?;
+                for rowid in rowids {
+                    debug!("looking up rowid {rowid}");
+                    cp.target = Some(rowid);
+                    scan_btree(&p, &mut cp, pager, None, Some(rowid))?;
+                }
+            }
+            (Source::Rtree(scan), _) => {
+                let mut cursor = RtreeCursor::new(pager, scan);
+                while let Some((rowid, record)) = cursor.next_record()? {
+                    cp.values = record;
+                    if let Some(row) = cp.take_record(rowid)? {
+                        cp.rows.push(row);
+                    }
+                }
+            }
+            // a function's rows are never planned to be looked up by rowid
+            (Source::Function(rows), _) => {
+                for (i, row) in rows.enumerate() {
+                    cp.values = row.into_values();
+                    if let Some(row) = cp.take_record(i as i64 + 1)? {
+                        cp.rows.push(row);
+                    }
+                }
+            }
+        }
+        if let Some(e) = cp.error {
+            return Err(e);
+        }
+        pager.rows_matched(cp.rows.len());
+        let mut rows = match (raw, cp.aggregate) {
+            (false, _) => cp.rows,
+            (true, false) => cp.apply_windows()?,
+            (true, true) => cp.apply_aggregates()?,
+        };
+        if !sorted {
+            sort_rows(&mut rows, &keys);
+        }
+        let mut rowids = Vec::new();
+        for row in &mut rows {
+            if !raw {
+                rowids.push(take_rowid(row));
+            }
+            row.truncate(names.len());
+        }
+        Ok(ResultSet {
+            columns: names,
+            rows,
+            rowids,
+        })
+    }
+}
+
+// take_rowid removes the rowid a row of a plain select carries after its
+// values, see RowCollector::output.
+fn take_rowid(row: &mut Values) -> i64 {
+    match row.pop() {
+        Some(ColType::Integer(rowid)) => rowid,
+        _ => unreachable!("a row of a plain select ends with its rowid"),
+    }
+}
+
+// sort_rows orders rows by (column, descending) keys, the first key that tells
+// two rows apart decides. The sort is stable, so full ties keep their order.
+fn sort_rows(rows: &mut [Values], keys: &[(usize, bool)]) {
+    if keys.is_empty() {
+        return;
+    }
+    rows.sort_by(|a, b| {
+        keys.iter()
+            .map(|&(i, desc)| {
+                let ord = a[i].cmp(&b[i]);
+                if desc { ord.reverse() } else { ord }
+            })
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+// ordinal spells a 1-based position like sqlite's error messages: 1st, 2nd, 3rd.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+// Values are the values of one result row, one per result column.
+type Values = Vec<ColType>;
+
+/// One result row: its values, and the names of the columns they belong to.
+#[derive(Debug, Clone)]
+pub struct Row {
+    columns: Rc<[String]>,
+    values: Values,
+    rowid: Option<i64>,
+}
+
+impl Row {
+    /// Pairs each value with the name of its column, e.g. for the rows a
+    /// [`TableValuedFunction`](crate::TableValuedFunction) returns.
+    pub fn new(columns: Rc<[String]>, values: Vec<Value>) -> Self {
+        Row {
+            columns,
+            values,
+            rowid: None,
+        }
+    }
+
+    /// The number of result columns.
+    pub fn column_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The name of the `i`-th result column, counting from 0, as sqlite3
+    /// would show it in a header.
+    ///
+    /// # Panics
+    ///
+    /// If `i` isn't less than [`Row::column_count`].
+    pub fn column_name(&self, i: usize) -> &str {
+        &self.columns[i]
+    }
+
+    /// The value of the `i`-th result column, counting from 0.
+    ///
+    /// # Panics
+    ///
+    /// If `i` isn't less than [`Row::column_count`].
+    pub fn value(&self, i: usize) -> &Value {
+        &self.values[i]
+    }
+
+    /// The value of the `i`-th result column converted to `T`, e.g.
+    /// `row.get::<i64>(0)`. See [`FromValue`] for the conversions.
+    ///
+    /// Fails with [`SqliteError::WrongType`] when the value doesn't convert,
+    /// NULL included unless `T` is an `Option`.
+    ///
+    /// # Panics
+    ///
+    /// If `i` isn't less than [`Row::column_count`].
+    pub fn get<T: FromValue>(&self, i: usize) -> Result<T, SqliteError> {
+        let v = &self.values[i];
+        T::from_value(v).ok_or_else(|| SqliteError::WrongType {
+            expected: T::EXPECTED,
+            actual: v.type_name(),
+            column: self.columns[i].clone(),
+        })
+    }
+
+    /// Like [`Row::get`], for the column named `name` in the result, an `AS`
+    /// alias if it has one. Names match case-insensitively, the first one wins.
+    ///
+    /// Fails with [`SqliteError::NoSuchColumn`] when no column has the name.
+    pub fn get_named<T: FromValue>(&self, name: &str) -> Result<T, SqliteError> {
+        let i = self
+            .columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+            .ok_or_else(|| SqliteError::NoSuchColumn(name.to_string()))?;
+        self.get(i)
+    }
+
+    /// The rowid of the table row the row was selected from. None when it
+    /// isn't one row of a table: aggregates, groups, window functions,
+    /// compound selects and PRAGMAs.
+    pub fn rowid(&self) -> Option<i64> {
+        self.rowid
+    }
+
+    /// Every value of the row, in result column order.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Takes the values out of the row, in result column order.
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// The row as a [`RowMap`], to serialize it keyed by column name.
+    #[cfg(feature = "serde")]
+    pub fn as_map(&self) -> RowMap<'_> {
+        RowMap::new(&self.columns, &self.values)
+    }
+}
+
+/// The rows of a query, read as they're iterated.
+///
+/// A plain scan reads the table one record at a time, so stopping early also
+/// stops reading the file. Sorting, window functions and compound selects
+/// need every row first: those read the whole table before the first row
+/// comes out. After an error the iterator ends.
+pub struct Rows<'db> {
+    columns: Rc<[String]>,
+    source: RowSource<'db>,
+}
+
+enum RowSource<'db> {
+    // the rows and, when each is a table row, their rowids
+    Buffered(std::vec::IntoIter<Values>, std::vec::IntoIter<i64>),
+    Scan {
+        pager: &'db Pager,
+        cursor: TableCursor<'db>,
+        collector: Box<RowCollector>,
+    },
+    Function {
+        pager: &'db Pager,
+        rows: std::iter::Enumerate<Box<dyn Iterator<Item = Row>>>,
+        collector: Box<RowCollector>,
+    },
+    Rtree {
+        pager: &'db Pager,
+        cursor: RtreeCursor<'db>,
+        collector: Box<RowCollector>,
+    },
+}
+
+impl fmt::Debug for Rows<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rows")
+            .field("columns", &self.columns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'db> Rows<'db> {
+    fn buffered(result: ResultSet) -> Self {
+        Rows {
+            columns: result.columns.into(),
+            source: RowSource::Buffered(result.rows.into_iter(), result.rowids.into_iter()),
+        }
+    }
+
+    fn scan(pager: &'db Pager, query: Query) -> Self {
+        let collector = Box::new(query.collector);
+        let source = match query.source {
+            Source::Table(rootpage) => RowSource::Scan {
+                pager,
+                cursor: TableCursor::new(pager, rootpage),
+                collector,
+            },
+            Source::Function(rows) => RowSource::Function {
+                pager,
+                rows: rows.enumerate(),
+                collector,
+            },
+            Source::Rtree(scan) => RowSource::Rtree {
+                pager,
+                cursor: RtreeCursor::new(pager, scan),
+                collector,
+            },
+        };
+        Rows {
+            columns: query.names.into(),
+            source,
+        }
+    }
+
+    /// The result column names, as sqlite3 would show them in a header.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn next_values(&mut self) -> Option<Result<(Values, Option<i64>)>> {
+        let next = match &mut self.source {
+            RowSource::Buffered(rows, rowids) => {
+                return rows.next().map(|row| Ok((row, rowids.next())));
+            }
+            RowSource::Scan {
+                pager,
+                cursor,
+                collector,
+            } => next_match(pager, collector, || cursor.next_record()).transpose(),
+            RowSource::Rtree {
+                pager,
+                cursor,
+                collector,
+            } => next_match(pager, collector, || cursor.next_record()).transpose(),
+            RowSource::Function {
+                pager,
+                rows,
+                collector,
+            } => loop {
+                let (i, row) = rows.next()?;
+                collector.values = row.into_values();
+                match collector.take_record(i as i64 + 1) {
+                    Ok(Some(row)) => {
+                        pager.rows_matched(1);
+                        break Some(Ok(row));
+                    }
+                    Ok(None) => {}
+                    Err(e) => break Some(Err(e)),
+                }
+            },
+        };
+        // a streamed row is always one of a plain select
+        next.map(|row| {
+            row.map(|mut row| {
+                let rowid = take_rowid(&mut row);
+                (row, Some(rowid))
+            })
+        })
+    }
+}
+
+// next_match feeds records to the collector until one matches.
+fn next_match(
+    pager: &Pager,
+    collector: &mut RowCollector,
+    mut next_record: impl FnMut() -> Result<Option<(i64, Values)>>,
+) -> Result<Option<Values>> {
+    while let Some((rowid, record)) = next_record()? {
+        collector.values = record;
+        if let Some(row) = collector.take_record(rowid)? {
+            pager.rows_matched(1);
+            return Ok(Some(row));
+        }
+    }
+    Ok(None)
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_values();
+        if let Some(Err(_)) = next {
+            self.source = RowSource::Buffered(Vec::new().into_iter(), Vec::new().into_iter());
+        }
+        next.map(|row| {
+            row.map(|(values, rowid)| Row {
+                columns: self.columns.clone(),
+                values,
+                rowid,
+            })
+        })
+    }
+}
+
+// ResultSet is a result held in memory: the result column names and every
+// matched row.
+#[derive(Debug, Default)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Values>,
+    // the rowid of each row when every row is one of a table, else empty
+    pub rowids: Vec<i64>,
+}
+
+// RowCollector evaluates the select list over each record that passes the filter.
+struct RowCollector {
+    table: String,
+    strict: bool, // every stored value is checked against its column's type
+    schema: Vec<parser::ColumnDef>,
+    rowid_column: Option<usize>, // the INTEGER PRIMARY KEY, stored as NULL in the record
+    exprs: Vec<Expr>,
+    filter: Option<Expr>,
+    group_by: Vec<Expr>,   // each group of records folds into one row
+    target: Option<usize>, // the rowid being looked up, if any
+    values: Vec<ColType>,
+    error: Option<anyhow::Error>,
+    // with window or aggregate functions, rows holds whole records (rowid
+    // last) until every record has been read, see apply_windows
+    raw: bool,
+    aggregate: bool, // the select list folds every row into one, see apply_aggregates
+    rows: Vec<Values>,
+}
+
+impl RowCollector {
+    // take_record finishes the record in `values` and evaluates the select list
+    // for it, leaving `values` empty for the next record.
+    fn take_record(&mut self, rowid: i64) -> Result<Option<Values>> {
+        if self.strict {
+            let checked = self.check_strict();
+            if checked.is_err() {
+                self.values.clear();
+            }
+            checked?;
+        }
+        // columns added by ALTER TABLE may be missing from older records
+        self.values.resize(self.schema.len(), ColType::Null);
+        if let Some(i) = self.rowid_column {
+            self.values[i] = ColType::Integer(rowid);
+        }
+        for (v, c) in self.values.iter_mut().zip(&self.schema) {
+            if let ColType::Integer(i) = v
+                && c.real_affinity()
+            {
+                *v = ColType::Float(*i as f64);
+            }
+        }
+        let row = self.output(rowid);
+        self.values.clear();
+        row
+    }
+
+    // check_strict makes sure every value of the record in `values` has its
+    // STRICT column's type. NULL fits anywhere, and a REAL column may hold an
+    // integer, which is how sqlite stores a real with no fraction.
+    fn check_strict(&self) -> Result<()> {
+        for (c, v) in self.schema.iter().zip(&self.values) {
+            let declared = c.ty.as_deref().unwrap_or("ANY").to_ascii_uppercase();
+            let fits = matches!(
+                (declared.as_str(), v.type_name()),
+                (_, "null")
+                    | ("ANY", _)
+                    | ("INT" | "INTEGER", "integer")
+                    | ("REAL", "integer" | "real")
+                    | ("TEXT", "text")
+                    | ("BLOB", "blob")
+            );
+            if !fits {
+                bail!(SqliteError::StrictTypeViolation {
+                    table: self.table.clone(),
+                    column: c.name.clone(),
+                    declared,
+                    stored: v.type_name(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // output evaluates the select list for the current record, None if it's filtered out.
+    fn output(&self, rowid: i64) -> Result<Option<Values>> {
+        if self.target.is_some_and(|target| target != rowid as usize) {
+            return Ok(None);
+        }
+        let lookup = |name: &str| match column_index(&self.schema, name) {
+            Some(i) => Some(self.values[i].clone()),
+            None if is_rowid_alias(name) => Some(ColType::Integer(rowid)),
+            None => None,
+        };
+        if let Some(filter) = &self.filter
+            && !expr::truthy(&filter.eval(&lookup)?)
+        {
+            return Ok(None);
+        }
+        if self.raw {
+            let mut record = self.values.clone();
+            record.push(ColType::Integer(rowid));
+            return Ok(Some(record));
+        }
+        let mut row = self
+            .exprs
+            .iter()
+            .map(|e| e.eval(&lookup))
+            .collect::<Result<Vec<_>>>()?;
+        // the rowid goes last, see take_rowid
+        row.push(ColType::Integer(rowid));
+        Ok(Some(row))
+    }
+}
+
+impl RowCollector {
+    // apply_windows evaluates the select list over the buffered records once
+    // every window function's value is known. Rows come out in the order the
+    // first window sorted them, like sqlite3.
+    fn apply_windows(&self) -> Result<Vec<Values>> {
+        let records = &self.rows;
+        let lookup = |i: usize, name: &str| self.record_column(i, name);
+        let eval = |e: &Expr, i: usize| e.eval(&|name| lookup(i, name));
+        let windows = self
+            .exprs
+            .iter()
+            .flat_map(Expr::windows)
+            .collect::<Vec<_>>();
+        let mut order = None;
+        let mut values = Vec::new();
+        for w in &windows {
+            let (sorted, v) = w.evaluate(records.len(), &eval)?;
+            order.get_or_insert(sorted);
+            values.push(v);
+        }
+        let order = order.unwrap_or_else(|| (0..records.len()).collect());
+        order
+            .into_iter()
+            .map(|i| {
+                let window = |e: &Expr| {
+                    let Expr::Window(w) = e else { return None };
+                    let k = windows.iter().position(|x| std::ptr::eq(*x, &**w))?;
+                    Some(values[k][i].clone())
+                };
+                self.exprs
+                    .iter()
+                    .map(|e| e.eval_with(&|name| lookup(i, name), &window))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // apply_aggregates folds the buffered records into the one row of an
+    // aggregate select, or with GROUP BY into a row per group, in the order
+    // of the group keys like sqlite3.
+    fn apply_aggregates(&self) -> Result<Vec<Values>> {
+        let all = (0..self.rows.len()).collect::<Vec<_>>();
+        if self.group_by.is_empty() {
+            return Ok(vec![self.aggregate_row(&all)?]);
+        }
+        let mut groups = BTreeMap::<Values, Vec<usize>>::new();
+        for i in all {
+            let key = self
+                .group_by
+                .iter()
+                .map(|e| e.eval(&|name| self.record_column(i, name)))
+                .collect::<Result<Values>>()?;
+            groups.entry(key).or_default().push(i);
+        }
+        groups
+            .values()
+            .map(|records| self.aggregate_row(records))
+            .collect()
+    }
+
+    // aggregate_row folds the buffered records at `records` into one row.
+    // Like sqlite3, columns outside any aggregate take their value from the
+    // record holding the value of the select's only min() or max(), otherwise
+    // from the first record, and are NULL when there's none.
+    fn aggregate_row(&self, records: &[usize]) -> Result<Values> {
+        let n = records.len();
+        let eval = |e: &Expr, i: usize| e.eval(&|name| self.record_column(records[i], name));
+        let aggregates = self
+            .exprs
+            .iter()
+            .flat_map(Expr::aggregates)
+            .collect::<Vec<_>>();
+        let values = aggregates
+            .iter()
+            .map(|a| a.evaluate(n, &eval))
+            .collect::<Result<Vec<_>>>()?;
+        let aggregate = |e: &Expr| {
+            let Expr::Aggregate(a) = e else { return None };
+            let k = aggregates.iter().position(|x| std::ptr::eq(*x, &**a))?;
+            Some(values[k].clone())
+        };
+        let min_max = aggregates
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a.func,
+                    AggregateFunction::Min(_) | AggregateFunction::Max(_)
+                )
+            })
+            .collect::<Vec<_>>();
+        let extreme = match min_max.as_slice() {
+            [a] => a.extreme_row(n, &eval)?,
+            _ => None,
+        };
+        let bare_row = extreme.or((n > 0).then_some(0));
+        let column = |name: &str| match bare_row {
+            Some(i) => self.record_column(records[i], name),
+            None => (column_index(&self.schema, name).is_some() || is_rowid_alias(name))
+                .then_some(ColType::Null),
+        };
+        self.exprs
+            .iter()
+            .map(|e| e.eval_with(&column, &aggregate))
+            .collect()
+    }
+
+    // record_column looks a column up in the i-th buffered record.
+    fn record_column(&self, i: usize, name: &str) -> Option<ColType> {
+        match column_index(&self.schema, name) {
+            Some(c) => Some(self.rows[i][c].clone()),
+            None if is_rowid_alias(name) => self.rows[i].last().cloned(),
+            None => None,
+        }
+    }
+}
+
+impl OnColumn for RowCollector {
+    fn on_col(&mut self, cur_type: u8, row: usize, col: usize, v: &ColType, rowid: i64) {
+        trace!(
+            "on_col: 0x{:0x}, {}, row: {}, col: {}, rowid: {}",
+            cur_type, row, col, v, rowid
+        );
+        if cur_type == 0x0d {
+            if self.values.len() <= col {
+                self.values.resize(col + 1, ColType::Null);
+            }
+            self.values[col] = v.clone();
+        }
+    }
+
+    fn on_row(&mut self, cur_type: u8, rowid: i64) {
+        if cur_type != 0x0d || self.error.is_some() {
+            self.values.clear();
+            return;
+        }
+        match self.take_record(rowid) {
+            Ok(Some(row)) => self.rows.push(row),
+            Ok(None) => {}
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn finalize(&mut self) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Access {
+    Scan,
+    // the rows of a table-valued function, which it returns all of
+    Function,
+    // the index keys that start with the values the leading `columns` are
+    // set equal to
+    Index {
+        name: String,
+        columns: Vec<String>,
+    },
+    // the index keys that start with the literal prefix of a LIKE pattern
+    IndexRange {
+        name: String,
+        column: String,
+        prefix: String,
+    },
+    // every entry of an index in key order, forward or backward as ORDER BY
+    // is DESC or not
+    IndexOrder {
+        name: String,
+        desc: bool,
+    },
+    // a seek to the rowid the only condition compares with
+    Rowid,
+    // a scan of an FTS5 table, shown with the index string sqlite3's fts5
+    // would choose, though MATCH only filters the rows it reads
+    FullText(String),
+    // a walk down an rtree, with the index string sqlite3's rtree would
+    // choose
+    Rtree(String),
+}
+
+// fts5_index spells the index string of an FTS5 scan roughly as sqlite3 does: `0:`
+// then M and the column of each MATCH the WHERE clause ANDs together, the
+// table's own name counting as the column after the last.
+fn fts5_index(t: &parser::CreateTableStmt, filter: Option<&Expr>) -> String {
+    fn matches(e: &Expr, t: &parser::CreateTableStmt, out: &mut String) {
+        match e {
+            Expr::Binary { op, lhs, rhs, .. } if op == "and" => {
+                matches(lhs, t, out);
+                matches(rhs, t, out);
+            }
+            Expr::Binary { op, lhs, .. } if op == "match" => {
+                if let Expr::Column(c) = &**lhs {
+                    let columns = t.declared_columns();
+                    let i = column_index(columns, c).unwrap_or(columns.len());
+                    out.push_str(&format!("M{i}"));
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut index = "0:".to_string();
+    if let Some(e) = filter {
+        matches(e, t, &mut index);
+    }
+    index
+}
+
+// Plan is the access path the planner chose for one table.
+#[derive(Debug, Clone, PartialEq)]
+struct Plan {
+    table: String,
+    access: Access,
+}
+
+// Display approximates the detail column of sqlite3's EXPLAIN QUERY PLAN.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.access {
+            Access::Scan => write!(f, "SCAN {}", self.table),
+            Access::Function => write!(f, "SCAN {} VIRTUAL TABLE", self.table),
+            Access::FullText(index) | Access::Rtree(index) => {
+                write!(f, "SCAN {} VIRTUAL TABLE INDEX {index}", self.table)
+            }
+            Access::Index { name, columns } => {
+                let keys = columns.iter().map(|c| format!("{c}=?")).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "SEARCH {} USING INDEX {name} ({})",
+                    self.table,
+                    keys.join(" AND ")
+                )
+            }
+            Access::IndexRange { name, column, .. } => write!(
+                f,
+                "SEARCH {} USING INDEX {name} ({column}>? AND {column}<?)",
+                self.table
+            ),
+            Access::IndexOrder { name, .. } => {
+                write!(f, "SCAN {} USING INDEX {name}", self.table)
+            }
+            Access::Rowid => write!(
+                f,
+                "SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)",
+                self.table
+            ),
+        }
+    }
+}
+
+/// A statement parsed and planned by [`Database::prepare`](crate::Database::prepare),
+/// which can run any number of times, with new values for its parameters
+/// each time, without being parsed again.
+///
+/// The SQL can hold placeholders where a value goes: `?`, numbered one
+/// more than the one before it, `?N`, numbered N, and `:name`, numbered
+/// like `?` where the name first appears.
+#[derive(Debug)]
+pub struct Statement<'db> {
+    pager: &'db Pager,
+    tables: &'db Tables,
+    body: Body,
+    // the name of each parameter by number, None for ?N and ?
+    params: Vec<Option<String>>,
+    parse_time: Duration,
+    plan_time: Duration,
+}
+
+#[derive(Debug)]
+enum Body {
+    Select {
+        compound: Box<parser::CompoundSelect>,
+        plans: Vec<Plan>,
+        explain: bool,
+    },
+    // answered from the schema, without reading any table
+    Pragma(parser::Pragma),
+    Insert(parser::InsertStmt),
+    Update(parser::UpdateStmt),
+    CreateTable(parser::NewTable),
+}
+
+impl<'db> Statement<'db> {
+    /// Runs the statement with `params` bound to its parameters, `params[0]`
+    /// to `?1` and so on, as [`Database::execute`](crate::Database::execute)
+    /// does for one without any.
+    ///
+    /// The values are used as they are and never parsed as SQL, so text
+    /// with quotes in it is just text. Fails with [`SqliteError::Usage`]
+    /// unless there's exactly one value per parameter.
+    pub fn query(&self, params: &[Value]) -> Result<Rows<'db>> {
+        if params.len() != self.params.len() {
+            bail!(SqliteError::Usage(format!(
+                "statement has {} parameters, {} values given",
+                self.params.len(),
+                params.len()
+            )));
+        }
+        run(self.pager, self.tables, self, params)
+    }
+
+    /// Runs the statement with values bound by name, e.g. `(":id",
+    /// Value::Integer(2))`. Every parameter has to be named and given a value.
+    pub fn query_named(&self, params: &[(&str, Value)]) -> Result<Rows<'db>> {
+        let mut values = vec![None; self.params.len()];
+        for (name, v) in params {
+            let Some(i) = self.parameter_index(name) else {
+                bail!(SqliteError::Usage(format!("no parameter named {name}")));
+            };
+            values[i] = Some(v.clone());
+        }
+        let values = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                v.ok_or_else(|| {
+                    let name = self.params[i]
+                        .clone()
+                        .unwrap_or_else(|| format!("?{}", i + 1));
+                    SqliteError::Usage(format!("no value for parameter {name}"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.query(&values)
+    }
+
+    /// How many values [`Statement::query`] takes: the highest parameter
+    /// number.
+    pub fn parameter_count(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Where the parameter `name`, `:` included, goes in the values given to
+    /// [`Statement::query`], or None when the statement has no such name.
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.params.iter().position(|p| p.as_deref() == Some(name))
+    }
+
+    /// The EXPLAIN QUERY PLAN output in sqlite3's tree format, if the statement
+    /// is an `EXPLAIN QUERY PLAN`.
+    pub fn query_plan(&self) -> Option<String> {
+        match &self.body {
+            Body::Select {
+                compound,
+                plans,
+                explain: true,
+            } => Some(plan_tree(compound, plans)),
+            _ => None,
+        }
+    }
+
+    /// How long parsing the statement took.
+    pub fn parse_time(&self) -> Duration {
+        self.parse_time
+    }
+
+    /// How long choosing the access paths took.
+    pub fn plan_time(&self) -> Duration {
+        self.plan_time
+    }
+}
+
+// prepare parses a statement and plans every select in it. Of the statements
+// that modify the database INSERT, UPDATE and CREATE TABLE are run, and
+// only when `writable`.
+pub fn prepare<'db>(
+    pager: &'db Pager,
+    tables: &'db Tables,
+    sql: &str,
+    writable: bool,
+) -> Result<Statement<'db>> {
+    let start = Instant::now();
+    if parser::is_dml(sql) {
+        let (sql, params) = parser::number_params(sql).map_err(SqliteError::SqlParse)?;
+        let stmt = parser::parse_dml(&sql).map_err(SqliteError::SqlParse)?;
+        if !writable {
+            bail!(SqliteError::Unsupported(format!(
+                "cannot modify {}: attempt to write a readonly database",
+                stmt.table()
+            )));
+        }
+        let body = match stmt {
+            parser::DmlStmt::Insert(insert) => Body::Insert(insert),
+            parser::DmlStmt::Update(update) => Body::Update(update),
+            parser::DmlStmt::Delete(delete) => bail!(SqliteError::Unsupported(format!(
+                "cannot modify {}: DELETE is not implemented yet",
+                delete.table
+            ))),
+        };
+        return Ok(Statement {
+            pager,
+            tables,
+            body,
+            params,
+            parse_time: start.elapsed(),
+            plan_time: Duration::ZERO,
+        });
+    }
+    if let Some(create) = parser::parse_new_table(sql) {
+        let create = create.map_err(SqliteError::SqlParse)?;
+        if !writable {
+            bail!(SqliteError::Unsupported(format!(
+                "cannot create {}: attempt to write a readonly database",
+                create.stmt.table
+            )));
+        }
+        return Ok(Statement {
+            pager,
+            tables,
+            body: Body::CreateTable(create),
+            params: Vec::new(),
+            parse_time: start.elapsed(),
+            plan_time: Duration::ZERO,
+        });
+    }
+    if let Some(pragma) = parser::parse_pragma(sql) {
+        return Ok(Statement {
+            pager,
+            tables,
+            body: Body::Pragma(pragma.map_err(SqliteError::SqlParse)?),
+            params: Vec::new(),
+            parse_time: start.elapsed(),
+            plan_time: Duration::ZERO,
+        });
+    }
+    let (sql, params) = parser::number_params(sql).map_err(SqliteError::SqlParse)?;
+    let explain = parser::parse_explain_query_plan(&sql);
+    let compound =
+        parser::parse_compound(explain.unwrap_or(&sql)).map_err(SqliteError::SqlParse)?;
+    let parse_time = start.elapsed();
+    debug!("parsed {compound:?}");
+    debug!(
+        "indexes: {:?}, pos: {:?}, content: {:?}, table: {}",
+        tables.indexes, tables.pos, tables.content, compound.first.table
+    );
+
+    let plans = compound
+        .selects()
+        .map(|select| tables.plan(select))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Statement {
+        pager,
+        tables,
+        body: Body::Select {
+            compound: Box::new(compound),
+            plans,
+            explain: explain.is_some(),
+        },
+        params,
+        parse_time,
+        plan_time: start.elapsed() - parse_time,
+    })
+}
+
+// run executes a prepared statement with `params` bound to its placeholders.
+// EXPLAIN QUERY PLAN yields its tree, a line per row.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn run<'db>(
+    pager: &'db Pager,
+    tables: &Tables,
+    stmt: &Statement,
+    params: &[ColType],
+) -> Result<Rows<'db>> {
+    if let Some(plan) = stmt.query_plan() {
+        return Ok(Rows::buffered(ResultSet {
+            columns: vec!["QUERY PLAN".to_string()],
+            rows: plan
+                .lines()
+                .skip(1)
+                .map(|l| vec![ColType::Text(l.to_string())])
+                .collect(),
+            rowids: Vec::new(),
+        }));
+    }
+    pager.reset_stats();
+    let (compound, plans) = match &stmt.body {
+        Body::Select {
+            compound, plans, ..
+        } => (compound, plans),
+        Body::Pragma(pragma) => return Ok(Rows::buffered(run_pragma(tables, pragma)?)),
+        Body::Insert(insert) => {
+            let result = pager.statement(|| {
+                let returning = Returning::new(tables, &insert.table, &insert.returning, params)?;
+                returning.project(write::insert(pager, tables, insert, params)?)
+            })?;
+            return Ok(Rows::buffered(result));
+        }
+        Body::Update(update) => {
+            let result = pager.statement(|| {
+                let returning = Returning::new(tables, &update.table, &update.returning, params)?;
+                returning.project(write::update(pager, tables, update, params)?)
+            })?;
+            return Ok(Rows::buffered(result));
+        }
+        Body::CreateTable(create) => {
+            pager.statement(|| write::create_table(pager, tables, create))?;
+            return Ok(Rows::buffered(ResultSet::default()));
+        }
+    };
+    if compound.rest.is_empty()
+        && matches!(
+            plans[0].access,
+            Access::Scan | Access::Function | Access::FullText(_) | Access::Rtree(_)
+        )
+        && !is_count_star(&compound.first)
+    {
+        let query = tables.compile(&compound.first, params)?;
+        if query.streams() {
+            return Ok(Rows::scan(pager, query));
+        }
+        return Ok(Rows::buffered(query.run(pager, SelectBy::Scan)?));
+    }
+    let mut result = execute(pager, tables, &compound.first, &plans[0], params)?;
+    for (i, (op, select)) in compound.rest.iter().enumerate() {
+        let right = execute(pager, tables, select, &plans[i + 1], params)?;
+        result = combine(*op, result, right)?;
+    }
+    if !compound.order_by.is_empty() {
+        let keys = compound_order_keys(&compound.order_by, &result.columns)?;
+        sort_rows(&mut result.rows, &keys);
+    }
+    Ok(Rows::buffered(result))
+}
+
+// Returning is the RETURNING clause of an INSERT or UPDATE, compiled against
+// its table: each row the statement stores goes through its expressions.
+struct Returning<'t> {
+    table: &'t parser::CreateTableStmt,
+    exprs: Vec<Expr>,
+    names: Vec<String>,
+}
+
+impl<'t> Returning<'t> {
+    // new compiles `columns`, no clause returning nothing, before any row is
+    // written: an unknown column fails the statement without changing a row.
+    fn new(
+        tables: &'t Tables,
+        table: &str,
+        columns: &Option<Vec<String>>,
+        params: &[ColType],
+    ) -> Result<Self> {
+        let Some(Create::Table(t)) = tables.content.get(table) else {
+            bail!(SqliteError::NoSuchTable(table.to_string()));
+        };
+        let mut exprs = Vec::new();
+        let mut names = Vec::new();
+        for col in columns.iter().flatten() {
+            if col == "*" {
+                let columns = t.declared_columns();
+                exprs.extend(columns.iter().map(|c| Expr::Column(c.name.clone())));
+                names.extend(columns.iter().map(|c| c.name.clone()));
+                continue;
+            }
+            let (sql, alias) = parser::split_alias(col);
+            let mut e = expr::parse_expr(sql).map_err(SqliteError::SqlParse)?;
+            e.bind(params)?;
+            e.resolve_affinity(&|name| column_affinity(t, name));
+            if !e.aggregates().is_empty() || !e.windows().is_empty() {
+                bail!(SqliteError::SqlParse(
+                    "aggregate functions are not allowed in the RETURNING clause".to_string()
+                ));
+            }
+            for c in e.columns() {
+                if column_index(&t.columns, c).is_none() && !is_rowid_alias(c) {
+                    bail!(SqliteError::NoSuchColumn(c.to_string()));
+                }
+            }
+            exprs.push(e);
+            names.push(alias.unwrap_or_else(|| col.clone()));
+        }
+        Ok(Returning {
+            table: t,
+            exprs,
+            names,
+        })
+    }
+
+    // project evaluates the clause over the rows a statement stored, given
+    // with their rowids as SQL sees them.
+    fn project(&self, rows: Vec<(i64, Values)>) -> Result<ResultSet> {
+        let mut result = ResultSet {
+            columns: self.names.clone(),
+            ..ResultSet::default()
+        };
+        if self.exprs.is_empty() {
+            return Ok(result);
+        }
+        for (rowid, values) in rows {
+            let lookup = |name: &str| match column_index(&self.table.columns, name) {
+                Some(i) => values.get(i).cloned(),
+                None if is_rowid_alias(name) => Some(ColType::Integer(rowid)),
+                None => None,
+            };
+            let row = self
+                .exprs
+                .iter()
+                .map(|e| e.eval(&lookup))
+                .collect::<Result<Values>>()?;
+            result.rows.push(row);
+        }
+        Ok(result)
+    }
+}
+
+// run_pragma answers the PRAGMAs that only look at the schema.
+fn run_pragma(tables: &Tables, pragma: &parser::Pragma) -> Result<ResultSet> {
+    match pragma.name.as_str() {
+        // the table's triggers in schema order, with when each one fires
+        "trigger_info" => {
+            let table = pragma.arg.as_deref().unwrap_or_default();
+            let rows = tables
+                .triggers
+                .iter()
+                .filter(|(tbl, _)| tbl.eq_ignore_ascii_case(table))
+                .flat_map(|(_, triggers)| triggers)
+                .map(|t| vec![ColType::Text(t.name.clone()), ColType::Text(t.event_name())])
+                .collect();
+            Ok(ResultSet {
+                columns: vec!["name".to_string(), "event".to_string()],
+                rows,
+                rowids: Vec::new(),
+            })
+        }
+        name => bail!(SqliteError::Unsupported(format!("PRAGMA {name}"))),
+    }
+}
+
+// compound_order_keys resolves the ORDER BY of a compound select, whose terms
+// must name a result column or give its position.
+fn compound_order_keys(
+    order_by: &[(String, bool)],
+    columns: &[String],
+) -> Result<Vec<(usize, bool)>> {
+    order_by
+        .iter()
+        .enumerate()
+        .map(|(n, (term, desc))| {
+            let i = match term.parse::<usize>() {
+                Ok(k) if (1..=columns.len()).contains(&k) => k - 1,
+                Ok(_) => bail!(SqliteError::SqlParse(format!(
+                    "{} ORDER BY term out of range - should be between 1 and {}",
+                    ordinal(n + 1),
+                    columns.len()
+                ))),
+                Err(_) => columns
+                    .iter()
+                    .position(|c| c.eq_ignore_ascii_case(term))
+                    .ok_or_else(|| {
+                        SqliteError::SqlParse(format!(
+                            "{} ORDER BY term does not match any column in the result set",
+                            ordinal(n + 1)
+                        ))
+                    })?,
+            };
+            Ok((i, *desc))
+        })
+        .collect()
+}
+
+// plan_tree renders EXPLAIN QUERY PLAN output, with sqlite3's tree for compounds.
+fn plan_tree(compound: &parser::CompoundSelect, plans: &[Plan]) -> String {
+    let mut out = String::from("QUERY PLAN\n");
+    if compound.rest.is_empty() {
+        out += &format!("`--{}\n", plans[0]);
+        return out;
+    }
+    out += "`--COMPOUND QUERY\n";
+    let labels = std::iter::once("LEFT-MOST SUBQUERY".to_string()).chain(
+        compound
+            .rest
+            .iter()
+            .map(|(op, _)| format!("{} USING TEMP B-TREE", op.keyword())),
+    );
+    for (i, (label, plan)) in labels.zip(plans).enumerate() {
+        if i + 1 < plans.len() {
+            out += &format!("   |--{label}\n   |  `--{plan}\n");
+        } else {
+            out += &format!("   `--{label}\n      `--{plan}\n");
+        }
+    }
+    out
+}
+
+// execute runs one select along its planned access path.
+fn execute(
+    pager: &Pager,
+    tables: &Tables,
+    select: &parser::SelectStmt,
+    plan: &Plan,
+    params: &[ColType],
+) -> Result<ResultSet> {
+    let table = &plan.table;
+    if is_count_star(select) {
+        // every row is a cell of some leaf, the root holds them all only while
+        // it is one
+        let stats = btree::tree_stats(pager, tables.pos[table]).context("parse page err")?;
+        let rows = stats.leaves.iter().map(|l| l.cells).sum::<usize>();
+        return Ok(ResultSet {
+            columns: select.columns.clone(),
+            rows: vec![vec![ColType::Integer(rows as i64)]],
+            rowids: Vec::new(),
+        });
+    }
+    let cond = match &plan.access {
+        Access::Scan
+        | Access::Function
+        | Access::FullText(_)
+        | Access::Rtree(_)
+        | Access::IndexOrder { .. }
+        | Access::Index { .. } => None,
+        _ => bind_condition(&select.conditions[0], params)?,
+    };
+    // a NULL or a rowid that isn't one matches nothing by seeking, the scan
+    // gives the WHERE clause its own say
+    let select_by = match (&plan.access, cond) {
+        (Access::Index { name, columns }, _) => match index_key(tables, select, columns, params)? {
+            Some(key) => {
+                let rowids = tables.select_rowids_by_index(pager, name, IndexTarget::Key(key))?;
+                debug!("searching through index and get rowids: {:?}", rowids);
+                SelectBy::RowIds(rowids)
+            }
+            None => SelectBy::Scan,
+        },
+        // LIKE reads a blob as text, and blobs sort after all text, so they
+        // are a second range, the first in a DESC index. The fetched rows are
+        // still checked against the whole pattern
+        (Access::IndexRange { name, prefix, .. }, Some(_)) => {
+            let mut ranges = [false, true];
+            if tables.index_desc(name).first() == Some(&true) {
+                ranges.reverse();
+            }
+            let mut rowids = Vec::new();
+            for blob in ranges {
+                let target = IndexTarget::Prefix {
+                    prefix: prefix.clone(),
+                    blob,
+                };
+                rowids.extend(tables.select_rowids_by_index(pager, name, target)?);
+            }
+            SelectBy::RowIds(rowids)
+        }
+        // each index entry ends with the rowid of its row
+        (Access::IndexOrder { name, desc }, _) => {
+            let mut rowids = Vec::new();
+            btree::index_entries(pager, tables.pos[name], &mut |mut entry| {
+                match entry.pop() {
+                    Some(ColType::Integer(rowid)) => rowids.push(rowid as usize),
+                    _ => bail!(SqliteError::CorruptDatabase(format!(
+                        "entry of index {name} has no integer rowid"
+                    ))),
+                }
+                Ok(())
+            })?;
+            if (tables.index_desc(name).first() == Some(&true)) != *desc {
+                rowids.reverse();
+            }
+            SelectBy::Sorted(rowids)
+        }
+        (Access::Rowid, Some(cond)) => match cond.value.parse() {
+            Ok(rowid) => SelectBy::RowIds(vec![rowid]),
+            Err(_) => SelectBy::Scan,
+        },
+        _ => SelectBy::Scan,
+    };
+    tables.select(pager, select, select_by, params)
+}
+
+// binds tells whether a condition sets `column` of `t` equal to a value.
+fn binds(cond: &parser::Condition, column: &str, t: &parser::CreateTableStmt) -> bool {
+    cond.op == "=" && cond.column.eq_ignore_ascii_case(column) && cond.is_literal(&t.columns)
+}
+
+// index_key is the key an index search looks for: the value each of the
+// leading `columns` is set equal to, converted as comparing it with the
+// column converts it. None when one is NULL, which no key equals.
+fn index_key(
+    tables: &Tables,
+    select: &parser::SelectStmt,
+    columns: &[String],
+    params: &[ColType],
+) -> Result<Option<Vec<ColType>>> {
+    let Some(Create::Table(t)) = tables.content.get(&select.table) else {
+        bail!(SqliteError::NoSuchTable(select.table.clone()));
+    };
+    let mut key = Vec::with_capacity(columns.len());
+    for column in columns {
+        let Some(cond) = select.conditions.iter().find(|c| binds(c, column, t)) else {
+            bail!(SqliteError::Unsupported(format!(
+                "no value to search the index on {column} for"
+            )));
+        };
+        let value = match (cond.quote, cond.value.strip_prefix('?')) {
+            (Some(_), _) => ColType::Text(cond.value.clone()),
+            (None, Some(n)) => match n.parse::<usize>().ok().and_then(|n| params.get(n - 1)) {
+                Some(v) => v.clone(),
+                None => bail!(SqliteError::Usage(format!(
+                    "parameter {} is not bound",
+                    cond.value
+                ))),
+            },
+            (None, None) => {
+                expr::numeric_text(&cond.value).unwrap_or_else(|| ColType::Text(cond.value.clone()))
+            }
+        };
+        if matches!(value, ColType::Null) {
+            return Ok(None);
+        }
+        key.push(match column_affinity(t, column) {
+            Some(affinity) => affinity.apply(value),
+            None => value,
+        });
+    }
+    Ok(Some(key))
+}
+
+// bind_condition gives a planned condition on a placeholder the bound value,
+// as the text the b-tree compares keys with. None when that's NULL.
+fn bind_condition(
+    cond: &parser::Condition,
+    params: &[ColType],
+) -> Result<Option<parser::Condition>> {
+    let Some(n) = cond.value.strip_prefix('?') else {
+        return Ok(Some(cond.clone()));
+    };
+    let Some(v) = n.parse::<usize>().ok().and_then(|n| params.get(n - 1)) else {
+        bail!(SqliteError::Usage(format!(
+            "parameter {} is not bound",
+            cond.value
+        )));
+    };
+    if matches!(v, ColType::Null) {
+        return Ok(None);
+    }
+    Ok(Some(parser::Condition {
+        value: v.to_string(),
+        ..cond.clone()
+    }))
+}
+
+// is_count_star tells a bare `count(*)` of a whole table, answered without
+// reading any record.
+fn is_count_star(select: &parser::SelectStmt) -> bool {
+    select.columns.len() == 1
+        && select.columns[0].eq_ignore_ascii_case("count(*)")
+        && select.filter.is_none()
+        && select.args.is_none()
+        && select.group_by.is_empty()
+}
+
+// combine applies a compound operator to two results. The output has no
+// duplicate rows, and rows compare by value so NULLs match each other.
+fn combine(op: parser::SetOp, left: ResultSet, right: ResultSet) -> Result<ResultSet> {
+    if left.columns.len() != right.columns.len() {
+        return Err(SqliteError::SqlParse(format!(
+            "SELECTs to the left and right of {} do not have the same number of result columns",
+            op.keyword()
+        ))
+        .into());
+    }
+    let right = right.rows.into_iter().collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    let rows = left
+        .rows
+        .into_iter()
+        .filter(|row| {
+            let keep = match op {
+                parser::SetOp::Intersect => right.contains(row),
+                parser::SetOp::Except => !right.contains(row),
+            };
+            keep && seen.insert(row.clone())
+        })
+        .collect();
+    Ok(ResultSet {
+        columns: left.columns,
+        rows,
+        rowids: Vec::new(),
+    })
+}
+
+#[test]
+fn test_select_returns_rows() {
+    let pager = Pager::open(std::fs::File::open("sample.db").unwrap()).unwrap();
+    let tables = Tables::new(&pager).unwrap();
+    let select =
+        parser::parse_select("select id, upper(name) from apples where color = 'Red'").unwrap();
+    let result = tables.select(&pager, &select, SelectBy::Scan, &[]).unwrap();
+    assert_eq!(result.columns, vec!["id", "upper(name)"]);
+    let rows = result
+        .rows
+        .iter()
+        .map(|r| r.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(rows, vec![vec!["2", "FUJI"]]);
+}
+
+#[test]
+fn test_except_matches_nulls() {
+    let rows = |rows: &[ColType]| ResultSet {
+        columns: vec!["a".to_string()],
+        rows: rows.iter().map(|v| vec![v.clone()]).collect(),
+        rowids: vec![],
+    };
+    let left = rows(&[
+        ColType::Integer(1),
+        ColType::Null,
+        ColType::Integer(2),
+        ColType::Integer(2),
+        ColType::Text("2".to_string()),
+        ColType::Null,
+    ]);
+    let right = rows(&[ColType::Null, ColType::Integer(1)]);
+    let result = combine(parser::SetOp::Except, left, right).unwrap();
+    assert_eq!(
+        result.rows,
+        [[ColType::Integer(2)], [ColType::Text("2".to_string())]]
+    );
+}
+
+#[test]
+fn test_intersect_matches_nulls() {
+    let rows = |rows: &[&[ColType]]| ResultSet {
+        columns: vec!["a".to_string(), "b".to_string()],
+        rows: rows.iter().map(|r| r.to_vec()).collect(),
+        rowids: vec![],
+    };
+    let left = rows(&[
+        &[ColType::Integer(1), ColType::Null],
+        &[ColType::Null, ColType::Null],
+        &[ColType::Integer(2), ColType::Text("x".to_string())],
+        &[ColType::Null, ColType::Null],
+        &[ColType::Integer(3), ColType::Float(1.0)],
+    ]);
+    let right = rows(&[
+        &[ColType::Null, ColType::Null],
+        &[ColType::Integer(3), ColType::Integer(1)],
+        &[ColType::Integer(1), ColType::Null],
+        &[ColType::Integer(2), ColType::Text("y".to_string())],
+    ]);
+    let result = combine(parser::SetOp::Intersect, left, right).unwrap();
+    assert_eq!(
+        result.rows,
+        [
+            [ColType::Integer(1), ColType::Null],
+            [ColType::Null, ColType::Null],
+            [ColType::Integer(3), ColType::Float(1.0)],
+        ]
+    );
+
+    let narrow = ResultSet {
+        columns: vec!["a".to_string()],
+        rows: vec![],
+        rowids: vec![],
+    };
+    let err = combine(parser::SetOp::Intersect, rows(&[]), narrow).unwrap_err();
+    assert!(err.to_string().contains("INTERSECT"));
+}
+
+#[test]
+fn test_select_star_with_extra_columns() {
+    let pager = Pager::open(std::fs::File::open("sample.db").unwrap()).unwrap();
+    let tables = Tables::new(&pager).unwrap();
+    let select =
+        parser::parse_select("select *, rowid, length(name) from apples where id = 2").unwrap();
+    let result = tables.select(&pager, &select, SelectBy::Scan, &[]).unwrap();
+    assert_eq!(
+        result.columns,
+        vec!["id", "name", "color", "id", "length(name)"]
+    );
+    let row = result.rows[0]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(row, vec!["2", "Fuji", "Red", "2", "4"]);
+}
@@ -0,0 +1,1398 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use crate::aggregate::{self, Aggregate, AggregateFunction};
+use crate::error::SqliteError;
+use crate::record::{ColType, Collation};
+use crate::window::{self, OrderTerm, Window, WindowFunction};
+
+// Expr is a scalar expression of the select list or WHERE clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Column(String),
+    // a placeholder, numbered from 1, that takes its value when bound
+    Param(usize),
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Unary {
+        op: String,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: String,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        // the affinity a comparison converts both operands to first, which
+        // the columns compared decide once resolve_affinity knows them
+        affinity: Option<Affinity>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    // a window function, evaluated over all rows once they've been read
+    Window(Box<Window>),
+    // an aggregate function, folding all rows into one value
+    Aggregate(Box<Aggregate>),
+}
+
+// Affinity is the type a column's declaration prefers its values in, as far
+// as a comparison cares: INTEGER, REAL and NUMERIC columns all compare as
+// numbers. https://www.sqlite.org/datatype3.html#type_affinity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Numeric,
+    Text,
+    Blob,
+}
+
+impl Affinity {
+    // apply converts a value before comparing it: text that reads as a
+    // number becomes that number for Numeric, a number becomes its text for
+    // Text, and Blob takes every value as it is.
+    pub fn apply(self, v: ColType) -> ColType {
+        match (self, v) {
+            (Affinity::Numeric, ColType::Text(s)) => numeric_text(&s).unwrap_or(ColType::Text(s)),
+            (Affinity::Text, v @ (ColType::Integer(_) | ColType::Float(_))) => {
+                ColType::Text(v.to_string())
+            }
+            (_, v) => v,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<&ColType> for Literal {
+    fn from(v: &ColType) -> Literal {
+        match v {
+            ColType::Null | ColType::Reserved => Literal::Null,
+            ColType::Integer(i) => Literal::Integer(*i),
+            ColType::Float(f) => Literal::Float(*f),
+            ColType::Text(s) => Literal::Text(s.clone()),
+            ColType::Blob(b) => Literal::Blob(b.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Op(String),
+    // `?N`, the form parser::number_params gives every placeholder
+    Param(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // an exponent, as in 1e3 or 2.5E-1
+            if matches!(chars.get(i), Some('e' | 'E')) {
+                let digits = match chars.get(i + 1) {
+                    Some('+' | '-') => i + 2,
+                    _ => i + 1,
+                };
+                if chars.get(digits).is_some_and(|d| d.is_ascii_digit()) {
+                    i = digits;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c == '\'' {
+            // '' inside a string is an escaped quote
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("unrecognized token: \"{s}\"")),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                        text.push('\'');
+                        i += 2;
+                    }
+                    Some('\'') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => {
+                        text.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Str(text));
+        } else if c == '"' || c == '`' || c == '[' {
+            let close = if c == '[' { ']' } else { c };
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != close {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(format!("unrecognized token: \"{s}\""));
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c == '?' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            tokens.push(Token::Param(chars[start..i].iter().collect()));
+        } else {
+            let two = chars[i..(i + 2).min(chars.len())]
+                .iter()
+                .collect::<String>();
+            let op = match two.as_str() {
+                "||" | "<=" | ">=" | "!=" | "<>" | "==" => two,
+                _ if "(),+-*/%<>=.".contains(c) => c.to_string(),
+                _ => return Err(format!("unrecognized token: \"{c}\"")),
+            };
+            i += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if self.peek() == Some(&Token::Op(op.to_string())) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        if let Some(Token::Ident(id)) = self.peek()
+            && id.eq_ignore_ascii_case(kw)
+        {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn binary(op: &str, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op: op.to_string(),
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            affinity: None,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and()?;
+        while self.eat_keyword("or") {
+            lhs = Self::binary("or", lhs, self.and()?);
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.not()?;
+        while self.eat_keyword("and") {
+            lhs = Self::binary("and", lhs, self.not()?);
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Unary {
+                op: "not".to_string(),
+                expr: Box::new(self.not()?),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.concat()?;
+        loop {
+            if self.eat_keyword("is") {
+                let negated = self.eat_keyword("not");
+                if !self.eat_keyword("null") {
+                    return Err("expected NULL after IS".to_string());
+                }
+                lhs = Expr::IsNull {
+                    expr: Box::new(lhs),
+                    negated,
+                };
+                continue;
+            }
+            if self.eat_keyword("match") {
+                lhs = Self::binary("match", lhs, self.concat()?);
+                continue;
+            }
+            const PATTERN_OPS: [&str; 3] = ["glob", "regexp", "like"];
+            let negated = matches!(
+                self.tokens.get(self.pos + 1),
+                Some(Token::Ident(id)) if PATTERN_OPS.iter().any(|op| id.eq_ignore_ascii_case(op))
+            ) && self.eat_keyword("not");
+            if let Some(op) = PATTERN_OPS.into_iter().find(|op| self.eat_keyword(op)) {
+                let rhs = self.concat()?;
+                // like sqlite, `x REGEXP y` calls the function regexp(y, x),
+                // and `x LIKE y ESCAPE z` calls like(y, x, z)
+                let mut e = match op {
+                    "glob" => Self::binary(op, lhs, rhs),
+                    _ => {
+                        let mut args = vec![rhs, lhs];
+                        if op == "like" && self.eat_keyword("escape") {
+                            args.push(self.concat()?);
+                        }
+                        Expr::Call {
+                            name: op.to_string(),
+                            args,
+                        }
+                    }
+                };
+                if negated {
+                    e = Expr::Unary {
+                        op: "not".to_string(),
+                        expr: Box::new(e),
+                    };
+                }
+                lhs = e;
+                continue;
+            }
+            let op = match self.peek() {
+                Some(Token::Op(op))
+                    if ["=", "==", "!=", "<>", "<", "<=", ">", ">="].contains(&op.as_str()) =>
+                {
+                    op.clone()
+                }
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let op = match op.as_str() {
+                "==" => "=",
+                "<>" => "!=",
+                op => op,
+            };
+            lhs = Self::binary(op, lhs, self.concat()?);
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.additive()?;
+        while self.eat_op("||") {
+            lhs = Self::binary("||", lhs, self.additive()?);
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = if self.eat_op("+") {
+                "+"
+            } else if self.eat_op("-") {
+                "-"
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Self::binary(op, lhs, self.multiplicative()?);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat_op("*") {
+                "*"
+            } else if self.eat_op("/") {
+                "/"
+            } else if self.eat_op("%") {
+                "%"
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Self::binary(op, lhs, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_op("-") {
+            return Ok(Expr::Unary {
+                op: "-".to_string(),
+                expr: Box::new(self.unary()?),
+            });
+        }
+        if self.eat_op("+") {
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => {
+                if let Ok(i) = n.parse::<i64>() {
+                    Ok(Expr::Literal(Literal::Integer(i)))
+                } else {
+                    n.parse::<f64>()
+                        .map(|f| Expr::Literal(Literal::Float(f)))
+                        .map_err(|_| format!("unrecognized token: \"{n}\""))
+                }
+            }
+            Some(Token::Str(s)) => Ok(Expr::Literal(Literal::Text(s))),
+            Some(Token::Param(p)) => match p[1..].parse() {
+                Ok(n) if n > 0 => Ok(Expr::Param(n)),
+                _ => Err(format!("near \"{p}\": syntax error")),
+            },
+            Some(Token::Ident(id)) if id.eq_ignore_ascii_case("null") => {
+                Ok(Expr::Literal(Literal::Null))
+            }
+            Some(Token::Ident(id)) => {
+                // a column of another table, as `excluded.col` in an UPSERT
+                if self.eat_op(".") {
+                    return match self.next() {
+                        Some(Token::Ident(col)) => Ok(Expr::Column(format!("{id}.{col}"))),
+                        _ => Err(format!("near \"{id}.\": syntax error")),
+                    };
+                }
+                if !self.eat_op("(") {
+                    return Ok(Expr::Column(id));
+                }
+                let mut args = Vec::new();
+                // DISTINCT only means something to an aggregate, ALL nothing
+                let distinct = self.eat_keyword("distinct");
+                if !distinct {
+                    self.eat_keyword("all");
+                }
+                if !self.eat_op(")") {
+                    loop {
+                        // count(*)
+                        if !distinct && self.eat_op("*") {
+                            args.push(Expr::Column("*".to_string()));
+                        } else {
+                            args.push(self.or()?);
+                        }
+                        if self.eat_op(")") {
+                            break;
+                        }
+                        if !self.eat_op(",") {
+                            return Err(format!("expected ',' or ')' in call to {id}"));
+                        }
+                    }
+                }
+                let name = id.to_ascii_lowercase();
+                let filter = if self.eat_keyword("filter") {
+                    Some(self.filter()?)
+                } else {
+                    None
+                };
+                if self.eat_keyword("over") {
+                    if distinct {
+                        return Err("DISTINCT is not supported for window functions".to_string());
+                    }
+                    if filter.is_some() {
+                        return Err(
+                            "FILTER clause may only be used with aggregate window functions"
+                                .to_string(),
+                        );
+                    }
+                    return self.over(&name, args);
+                }
+                if aggregate::is_aggregate(&name, args.len()) {
+                    if distinct && args.len() != 1 {
+                        return Err(
+                            "DISTINCT aggregates must have exactly one argument".to_string()
+                        );
+                    }
+                    return Ok(Expr::Aggregate(Box::new(Aggregate {
+                        func: AggregateFunction::new(&name, args)?,
+                        distinct,
+                        filter,
+                    })));
+                }
+                if filter.is_some() {
+                    return Err(format!(
+                        "FILTER may not be used with non-aggregate {name}()"
+                    ));
+                }
+                Ok(Expr::Call { name, args })
+            }
+            Some(Token::Op(op)) if op == "(" => {
+                let e = self.or()?;
+                if !self.eat_op(")") {
+                    return Err("expected ')'".to_string());
+                }
+                Ok(e)
+            }
+            Some(Token::Op(op)) => Err(format!("near \"{op}\": syntax error")),
+            None => Err("incomplete input".to_string()),
+        }
+    }
+}
+
+impl ExprParser {
+    // filter parses the `(WHERE condition)` after FILTER.
+    fn filter(&mut self) -> Result<Expr, String> {
+        if !self.eat_op("(") || !self.eat_keyword("where") {
+            return Err("expected (WHERE after FILTER".to_string());
+        }
+        let e = self.or()?;
+        if !self.eat_op(")") {
+            return Err("expected ')' after FILTER condition".to_string());
+        }
+        Ok(e)
+    }
+
+    // over parses the OVER clause after a window function call.
+    fn over(&mut self, name: &str, args: Vec<Expr>) -> Result<Expr, String> {
+        let func = WindowFunction::new(name, args)?;
+        if !self.eat_op("(") {
+            return Err("expected '(' after OVER".to_string());
+        }
+        let mut partition_by = Vec::new();
+        if self.eat_keyword("partition") {
+            if !self.eat_keyword("by") {
+                return Err("expected BY after PARTITION".to_string());
+            }
+            loop {
+                partition_by.push(self.or()?);
+                if !self.eat_op(",") {
+                    break;
+                }
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.eat_keyword("order") {
+            if !self.eat_keyword("by") {
+                return Err("expected BY after ORDER".to_string());
+            }
+            loop {
+                let expr = self.or()?;
+                let desc = self.eat_keyword("desc");
+                if !desc {
+                    self.eat_keyword("asc");
+                }
+                order_by.push(OrderTerm { expr, desc });
+                if !self.eat_op(",") {
+                    break;
+                }
+            }
+        }
+        if !self.eat_op(")") {
+            return Err("expected ')' after window definition".to_string());
+        }
+        Ok(Expr::Window(Box::new(Window {
+            func,
+            partition_by,
+            order_by,
+        })))
+    }
+}
+
+// parse_expr parses a whole expression, trailing tokens are an error.
+pub fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut p = ExprParser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let e = p.or()?;
+    match p.peek() {
+        None => Ok(e),
+        Some(t) => Err(format!("near \"{}\": syntax error", token_text(t))),
+    }
+}
+
+fn token_text(t: &Token) -> &str {
+    match t {
+        Token::Ident(s) | Token::Number(s) | Token::Str(s) | Token::Op(s) | Token::Param(s) => s,
+    }
+}
+
+impl Expr {
+    // columns lists the columns referenced, so they can be checked before any row is read.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut cols = Vec::new();
+        self.collect_columns(&mut cols);
+        cols
+    }
+
+    fn collect_columns<'a>(&'a self, cols: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) | Expr::Param(_) => {}
+            Expr::Column(c) => cols.push(c),
+            Expr::Call { args, .. } => args.iter().for_each(|a| a.collect_columns(cols)),
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => expr.collect_columns(cols),
+            Expr::Binary { lhs, rhs, .. } => {
+                lhs.collect_columns(cols);
+                rhs.collect_columns(cols);
+            }
+            Expr::Window(w) => {
+                let order = w.order_by.iter().map(|t| &t.expr);
+                for e in w
+                    .func
+                    .args()
+                    .into_iter()
+                    .chain(&w.partition_by)
+                    .chain(order)
+                {
+                    e.collect_columns(cols);
+                }
+            }
+            Expr::Aggregate(a) => {
+                for e in a.func.args().into_iter().chain(&a.filter) {
+                    e.collect_columns(cols);
+                }
+            }
+        }
+    }
+
+    // windows lists the window functions in the expression, outermost first.
+    pub fn windows(&self) -> Vec<&Window> {
+        let mut windows = Vec::new();
+        self.collect_windows(&mut windows);
+        windows
+    }
+
+    fn collect_windows<'a>(&'a self, windows: &mut Vec<&'a Window>) {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Param(_) | Expr::Aggregate(_) => {}
+            Expr::Call { args, .. } => args.iter().for_each(|a| a.collect_windows(windows)),
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => expr.collect_windows(windows),
+            Expr::Binary { lhs, rhs, .. } => {
+                lhs.collect_windows(windows);
+                rhs.collect_windows(windows);
+            }
+            Expr::Window(w) => windows.push(w),
+        }
+    }
+
+    // aggregates lists the aggregate functions in the expression, outermost first.
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        let mut aggregates = Vec::new();
+        self.collect_aggregates(&mut aggregates);
+        aggregates
+    }
+
+    fn collect_aggregates<'a>(&'a self, aggregates: &mut Vec<&'a Aggregate>) {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Param(_) | Expr::Window(_) => {}
+            Expr::Call { args, .. } => args.iter().for_each(|a| a.collect_aggregates(aggregates)),
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => {
+                expr.collect_aggregates(aggregates)
+            }
+            Expr::Binary { lhs, rhs, .. } => {
+                lhs.collect_aggregates(aggregates);
+                rhs.collect_aggregates(aggregates);
+            }
+            Expr::Aggregate(a) => aggregates.push(a),
+        }
+    }
+
+    // bind replaces every placeholder with its value, `params[n - 1]` for ?n.
+    // The values become literals of the tree, so they're never parsed as SQL.
+    pub fn bind(&mut self, params: &[ColType]) -> Result<()> {
+        if let Expr::Param(n) = self {
+            let Some(v) = params.get(*n - 1) else {
+                bail!(SqliteError::Usage(format!("parameter ?{n} is not bound")));
+            };
+            *self = Expr::Literal(Literal::from(v));
+            return Ok(());
+        }
+        self.children_mut()
+            .into_iter()
+            .try_for_each(|e| e.bind(params))
+    }
+
+    // resolve_match readies the MATCH operators for a select from `table`,
+    // an FTS5 table searching its `indexed` columns, or None for any other
+    // table, where MATCH is an error as in sqlite3. MATCH on the table's own
+    // name searches all of its indexed columns.
+    pub fn resolve_match(&mut self, table: &str, indexed: Option<&[String]>) -> Result<()> {
+        if let Expr::Binary { op, lhs, .. } = self
+            && op == "match"
+        {
+            let Some(indexed) = indexed else {
+                bail!(SqliteError::SqlParse(
+                    "unable to use function MATCH in the requested context".to_string()
+                ));
+            };
+            if matches!(&**lhs, Expr::Column(c) if c.eq_ignore_ascii_case(table)) {
+                let columns = indexed.iter().map(|c| Expr::Column(c.clone()));
+                **lhs = Expr::Call {
+                    name: "concat_ws".to_string(),
+                    args: std::iter::once(Expr::Literal(Literal::Text(" ".to_string())))
+                        .chain(columns)
+                        .collect(),
+                };
+            }
+        }
+        self.children_mut()
+            .into_iter()
+            .try_for_each(|e| e.resolve_match(table, indexed))
+    }
+
+    // resolve_affinity decides what each comparison converts its operands to
+    // by the rules of https://www.sqlite.org/datatype3.html#comparisons:
+    // a number when either side is a column of numbers and the other isn't,
+    // text when one is a TEXT column and the other no column at all, and
+    // nothing otherwise. `affinity` gives the affinity of a column by name.
+    pub fn resolve_affinity(&mut self, affinity: &dyn Fn(&str) -> Option<Affinity>) {
+        if let Expr::Binary {
+            op,
+            lhs,
+            rhs,
+            affinity: compared,
+        } = self
+            && matches!(op.as_str(), "=" | "!=" | "<" | "<=" | ">" | ">=")
+        {
+            let of = |e: &Expr| match e {
+                Expr::Column(c) => affinity(c),
+                _ => None,
+            };
+            *compared = match (of(lhs), of(rhs)) {
+                (Some(Affinity::Numeric), _) | (_, Some(Affinity::Numeric)) => {
+                    Some(Affinity::Numeric)
+                }
+                (Some(Affinity::Text), None) | (None, Some(Affinity::Text)) => Some(Affinity::Text),
+                _ => None,
+            };
+        }
+        for e in self.children_mut() {
+            e.resolve_affinity(affinity);
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::Param(_) => Vec::new(),
+            Expr::Call { args, .. } => args.iter_mut().collect(),
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => vec![&mut **expr],
+            Expr::Binary { lhs, rhs, .. } => vec![&mut **lhs, &mut **rhs],
+            Expr::Window(w) => {
+                let Window {
+                    func,
+                    partition_by,
+                    order_by,
+                } = &mut **w;
+                func.args_mut()
+                    .into_iter()
+                    .chain(partition_by)
+                    .chain(order_by.iter_mut().map(|t| &mut t.expr))
+                    .collect()
+            }
+            Expr::Aggregate(a) => {
+                let Aggregate { func, filter, .. } = &mut **a;
+                func.args_mut().into_iter().chain(filter).collect()
+            }
+        }
+    }
+
+    // eval computes the expression for one row, `column` looks up the row's values.
+    pub fn eval(&self, column: &dyn Fn(&str) -> Option<ColType>) -> Result<ColType> {
+        self.eval_with(column, &|_| None)
+    }
+
+    // eval_with is eval for a row whose window and aggregate function values
+    // are known, `known` looks them up by the Window or Aggregate expression.
+    pub fn eval_with(
+        &self,
+        column: &dyn Fn(&str) -> Option<ColType>,
+        known: &dyn Fn(&Expr) -> Option<ColType>,
+    ) -> Result<ColType> {
+        let eval = |e: &Expr| e.eval_with(column, known);
+        Ok(match self {
+            Expr::Literal(l) => match l {
+                Literal::Null => ColType::Null,
+                Literal::Integer(i) => ColType::Integer(*i),
+                Literal::Float(f) => ColType::Float(*f),
+                Literal::Text(s) => ColType::Text(s.clone()),
+                Literal::Blob(b) => ColType::Blob(b.clone()),
+            },
+            Expr::Param(n) => bail!(SqliteError::Usage(format!("parameter ?{n} is not bound"))),
+            Expr::Column(c) => match column(c) {
+                Some(v) => v,
+                None => bail!(SqliteError::NoSuchColumn(c.clone())),
+            },
+            Expr::Call { name, args } => {
+                let args = args.iter().map(eval).collect::<Result<Vec<_>>>()?;
+                call(name, &args)?
+            }
+            Expr::Unary { op, expr } => {
+                let v = eval(expr)?;
+                match (op.as_str(), v) {
+                    (_, ColType::Null) => ColType::Null,
+                    ("not", v) => ColType::Integer(!truthy(&v) as i64),
+                    (_, ColType::Integer(i)) => match i.checked_neg() {
+                        Some(n) => ColType::Integer(n),
+                        None => ColType::Float(-(i as f64)),
+                    },
+                    (_, v) => match to_number(&v) {
+                        ColType::Integer(i) => ColType::Integer(-i),
+                        ColType::Float(f) => ColType::Float(-f),
+                        _ => ColType::Integer(0),
+                    },
+                }
+            }
+            Expr::IsNull { expr, negated } => {
+                let is_null = matches!(eval(expr)?, ColType::Null);
+                ColType::Integer((is_null != *negated) as i64)
+            }
+            Expr::Binary {
+                op,
+                lhs,
+                rhs,
+                affinity,
+            } => {
+                let l = eval(lhs)?;
+                // AND/OR use three-valued logic
+                if op == "and" || op == "or" {
+                    let r = eval(rhs)?;
+                    let (l, r) = (truth(&l), truth(&r));
+                    let v = if op == "and" {
+                        match (l, r) {
+                            (Some(false), _) | (_, Some(false)) => Some(false),
+                            (Some(true), Some(true)) => Some(true),
+                            _ => None,
+                        }
+                    } else {
+                        match (l, r) {
+                            (Some(true), _) | (_, Some(true)) => Some(true),
+                            (Some(false), Some(false)) => Some(false),
+                            _ => None,
+                        }
+                    };
+                    return Ok(v.map_or(ColType::Null, |b| ColType::Integer(b as i64)));
+                }
+                let r = eval(rhs)?;
+                match affinity {
+                    Some(a) => binary(op, a.apply(l), a.apply(r)),
+                    None => binary(op, l, r),
+                }
+            }
+            Expr::Window(w) => match known(self) {
+                Some(v) => v,
+                None => bail!(SqliteError::SqlParse(format!(
+                    "misuse of window function {}()",
+                    w.func.name()
+                ))),
+            },
+            Expr::Aggregate(a) => match known(self) {
+                Some(v) => v,
+                None => bail!(SqliteError::SqlParse(format!(
+                    "misuse of aggregate function {}()",
+                    a.func.name()
+                ))),
+            },
+        })
+    }
+}
+
+// truthy tells whether a WHERE expression selects its row, NULL doesn't.
+pub fn truthy(v: &ColType) -> bool {
+    truth(v).unwrap_or(false)
+}
+
+fn truth(v: &ColType) -> Option<bool> {
+    match to_number(v) {
+        ColType::Null => None,
+        ColType::Integer(i) => Some(i != 0),
+        ColType::Float(f) => Some(f != 0.0),
+        _ => Some(false),
+    }
+}
+
+// to_number converts text to a number the way sqlite does for arithmetic,
+// using its longest numeric prefix, and 0 when there is none.
+pub fn to_number(v: &ColType) -> ColType {
+    match v {
+        ColType::Null | ColType::Integer(_) | ColType::Float(_) => v.clone(),
+        ColType::Text(s) => {
+            let s = s.trim();
+            if let Ok(i) = s.parse::<i64>() {
+                return ColType::Integer(i);
+            }
+            let end = (0..=s.len())
+                .rev()
+                .find(|&e| s.is_char_boundary(e) && s[..e].parse::<f64>().is_ok())
+                .unwrap_or(0);
+            match s[..end].parse::<f64>() {
+                Ok(f) if f.fract() == 0.0 && !s[..end].contains(['.', 'e', 'E']) => {
+                    ColType::Integer(f as i64)
+                }
+                Ok(f) => ColType::Float(f),
+                Err(_) => ColType::Integer(0),
+            }
+        }
+        _ => ColType::Integer(0),
+    }
+}
+
+// numeric_text parses text that is entirely a number, for comparing against numbers.
+pub fn numeric_text(s: &str) -> Option<ColType> {
+    let s = s.trim();
+    // Rust reads inf and NaN too, sqlite3 only digits
+    if !s.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match s.parse::<i64>() {
+        Ok(i) => Some(ColType::Integer(i)),
+        Err(_) => s.parse::<f64>().ok().map(ColType::Float),
+    }
+}
+
+fn as_f64(v: &ColType) -> Option<f64> {
+    match v {
+        ColType::Integer(i) => Some(*i as f64),
+        ColType::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn binary(op: &str, l: ColType, r: ColType) -> ColType {
+    if matches!(l, ColType::Null) || matches!(r, ColType::Null) {
+        return ColType::Null;
+    }
+    match op {
+        "=" | "!=" | "<" | "<=" | ">" | ">=" => {
+            // the operands already have the affinity of the comparison
+            let ord = l.sqlite_cmp(&r, Collation::Binary);
+            let b = match op {
+                "=" => ord.is_eq(),
+                "!=" => ord.is_ne(),
+                "<" => ord.is_lt(),
+                "<=" => ord.is_le(),
+                ">" => ord.is_gt(),
+                _ => ord.is_ge(),
+            };
+            ColType::Integer(b as i64)
+        }
+        "||" => ColType::Text(format!("{l}{r}")),
+        "match" => ColType::Integer(fts_match(&l.to_string(), &r.to_string()) as i64),
+        "glob" => ColType::Integer(glob_match(&r.to_string(), &l.to_string()) as i64),
+        _ => arithmetic(op, to_number(&l), to_number(&r)),
+    }
+}
+
+// fts_match tells whether `text` holds every term of an FTS5 `query`, the
+// terms joined by AND or nothing at all. Both are split into tokens like
+// fts5's unicode61 tokenizer does: runs of letters and digits, case folded.
+// Phrases, OR, NOT, prefixes and column filters aren't understood.
+fn fts_match(text: &str, query: &str) -> bool {
+    let tokens = fts_tokens(text).collect::<HashSet<_>>();
+    let mut terms = query
+        .split_whitespace()
+        .filter(|w| *w != "AND")
+        .flat_map(fts_tokens)
+        .peekable();
+    terms.peek().is_some() && terms.all(|t| tokens.contains(&t))
+}
+
+thread_local! {
+    // the patterns REGEXP has compiled, every row of a query matching the same
+    static REGEXES: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+// regexp_match tells whether the regular expression `pattern` matches
+// anywhere in `text`, in the syntax of the regex crate.
+pub fn regexp_match(pattern: &str, text: &str) -> Result<bool> {
+    REGEXES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !cache.contains_key(pattern) {
+            let re = Regex::new(pattern).map_err(|e| {
+                // a parse error draws the pattern over several lines, the
+                // last one says what's wrong
+                let e = e.to_string();
+                let why = e.lines().last().unwrap_or_default();
+                let why = why.strip_prefix("error: ").unwrap_or(why);
+                SqliteError::SqlParse(format!("invalid regular expression {pattern:?}: {why}"))
+            })?;
+            cache.insert(pattern.to_string(), re);
+        }
+        Ok(cache[pattern].is_match(text))
+    })
+}
+
+// like_match tells whether `text` matches the LIKE `pattern`: `%` matches any
+// run of characters and `_` any one character, the character after `escape`
+// stands for itself, and ASCII letters match either case, like sqlite3
+// without ICU.
+pub fn like_match(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    let (p, t) = (
+        pattern.chars().collect::<Vec<_>>(),
+        text.chars().collect::<Vec<_>>(),
+    );
+    let (mut pi, mut ti) = (0, 0);
+    // where the last `%` was and the text it's been let to match up to
+    let mut any = None;
+    while ti < t.len() {
+        let step = match p.get(pi) {
+            Some(&c) if Some(c) == escape => p
+                .get(pi + 1)
+                .filter(|e| e.eq_ignore_ascii_case(&t[ti]))
+                .map(|_| pi + 2),
+            Some('%') => {
+                any = Some((pi, ti));
+                pi += 1;
+                continue;
+            }
+            Some('_') => Some(pi + 1),
+            Some(c) if c.eq_ignore_ascii_case(&t[ti]) => Some(pi + 1),
+            _ => None,
+        };
+        match (step, any) {
+            (Some(next), _) => (pi, ti) = (next, ti + 1),
+            (None, Some((ap, at))) => {
+                any = Some((ap, at + 1));
+                (pi, ti) = (ap + 1, at + 1);
+            }
+            (None, None) => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '%' && Some(c) != escape)
+}
+
+// like_prefix is what every text a LIKE `pattern` without an ESCAPE matches
+// starts with: the pattern up to its first wildcard.
+pub fn like_prefix(pattern: &str) -> &str {
+    let end = pattern.find(['%', '_']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+// glob_match tells whether `text` matches the GLOB `pattern`, case
+// sensitively: `*` matches any run of characters, `?` any one character and
+// `[...]` one of a class, ranges like `a-z` included, `[^...]` one outside
+// it. A `]` right after the `[` or `[^` is part of the class.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (
+        pattern.chars().collect::<Vec<_>>(),
+        text.chars().collect::<Vec<_>>(),
+    );
+    let (mut pi, mut ti) = (0, 0);
+    // where the last `*` was and the text it's been let to match up to
+    let mut star = None;
+    while ti < t.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ti));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => glob_class(&p, pi + 1, t[ti]),
+            Some(&c) if c == t[ti] => Some(pi + 1),
+            _ => None,
+        };
+        match (step, star) {
+            (Some(next), _) => (pi, ti) = (next, ti + 1),
+            (None, Some((sp, st))) => {
+                star = Some((sp, st + 1));
+                (pi, ti) = (sp + 1, st + 1);
+            }
+            (None, None) => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// glob_class matches `c` against the class whose body starts at `start`,
+// giving where the pattern goes on after its `]`. An unclosed class
+// matches nothing.
+fn glob_class(p: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start;
+    let negated = p.get(i) == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut found = false;
+    let mut first = true;
+    loop {
+        let lo = *p.get(i)?;
+        if lo == ']' && !first {
+            break;
+        }
+        first = false;
+        if p.get(i + 1) == Some(&'-') && p.get(i + 2).is_some_and(|&hi| hi != ']') {
+            found |= (lo..=p[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= lo == c;
+            i += 1;
+        }
+    }
+    (found != negated).then_some(i + 1)
+}
+
+fn fts_tokens(s: &str) -> impl Iterator<Item = String> + '_ {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn arithmetic(op: &str, l: ColType, r: ColType) -> ColType {
+    if let (ColType::Integer(a), ColType::Integer(b)) = (&l, &r) {
+        let v = match op {
+            "+" => a.checked_add(*b),
+            "-" => a.checked_sub(*b),
+            "*" => a.checked_mul(*b),
+            "/" | "%" if *b == 0 => return ColType::Null,
+            "/" => a.checked_div(*b),
+            _ => a.checked_rem(*b),
+        };
+        if let Some(v) = v {
+            return ColType::Integer(v);
+        }
+    }
+    let (a, b) = (as_f64(&l).unwrap_or(0.0), as_f64(&r).unwrap_or(0.0));
+    match op {
+        "+" => ColType::Float(a + b),
+        "-" => ColType::Float(a - b),
+        "*" => ColType::Float(a * b),
+        "/" | "%" if b == 0.0 => ColType::Null,
+        "/" => ColType::Float(a / b),
+        _ => ColType::Float(((a as i64) % (b as i64)) as f64),
+    }
+}
+
+fn arity(name: &str, args: &[ColType], n: impl std::ops::RangeBounds<usize>) -> Result<()> {
+    if !n.contains(&args.len()) {
+        bail!(SqliteError::SqlParse(format!(
+            "wrong number of arguments to function {name}()"
+        )));
+    }
+    Ok(())
+}
+
+fn call(name: &str, args: &[ColType]) -> Result<ColType> {
+    Ok(match name {
+        "length" => {
+            arity(name, args, 1..=1)?;
+            match &args[0] {
+                ColType::Null => ColType::Null,
+                ColType::Text(s) => ColType::Integer(s.chars().count() as i64),
+                ColType::Blob(b) => ColType::Integer(b.len() as i64),
+                v => ColType::Integer(v.to_string().chars().count() as i64),
+            }
+        }
+        // like sqlite without ICU, only ASCII letters change case
+        "upper" | "lower" => {
+            arity(name, args, 1..=1)?;
+            match &args[0] {
+                ColType::Null => ColType::Null,
+                v if name == "upper" => ColType::Text(v.to_string().to_ascii_uppercase()),
+                v => ColType::Text(v.to_string().to_ascii_lowercase()),
+            }
+        }
+        "regexp" => {
+            arity(name, args, 2..=2)?;
+            match args {
+                [ColType::Null, _] | [_, ColType::Null] => ColType::Null,
+                [pattern, text] => {
+                    ColType::Integer(regexp_match(&pattern.to_string(), &text.to_string())? as i64)
+                }
+                _ => unreachable!(),
+            }
+        }
+        "like" => {
+            arity(name, args, 2..=3)?;
+            if args.iter().any(|v| matches!(v, ColType::Null)) {
+                return Ok(ColType::Null);
+            }
+            let escape = match args.get(2).map(|v| v.to_string()) {
+                None => None,
+                Some(e) if e.chars().count() == 1 => e.chars().next(),
+                Some(_) => bail!(SqliteError::SqlParse(
+                    "ESCAPE expression must be a single character".to_string()
+                )),
+            };
+            let (pattern, text) = (args[0].to_string(), args[1].to_string());
+            ColType::Integer(like_match(&pattern, &text, escape) as i64)
+        }
+        // NULLs are skipped, only a NULL separator makes the result NULL
+        "concat_ws" => {
+            arity(name, args, 2..)?;
+            if matches!(args[0], ColType::Null) {
+                return Ok(ColType::Null);
+            }
+            let parts = args[1..]
+                .iter()
+                .filter(|v| !matches!(v, ColType::Null))
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>();
+            ColType::Text(parts.join(&args[0].to_string()))
+        }
+        // the scalar forms, with two arguments or more: any NULL makes the
+        // result NULL, otherwise it's the first least or greatest argument
+        // the way values sort, numbers before text before blobs
+        "min" | "max" => {
+            arity(name, args, 2..)?;
+            if args.iter().any(|a| matches!(a, ColType::Null)) {
+                return Ok(ColType::Null);
+            }
+            let mut best = &args[0];
+            for v in &args[1..] {
+                let ord = v.sqlite_cmp(best, Collation::Binary);
+                if (name == "min" && ord.is_lt()) || (name == "max" && ord.is_gt()) {
+                    best = v;
+                }
+            }
+            best.clone()
+        }
+        "typeof" => {
+            arity(name, args, 1..=1)?;
+            ColType::Text(args[0].type_name().to_string())
+        }
+        "substr" | "substring" => {
+            arity(name, args, 2..=3)?;
+            if args.iter().any(|a| matches!(a, ColType::Null)) {
+                return Ok(ColType::Null);
+            }
+            let start = int_arg(&args[1]);
+            let len = args.get(2).map(int_arg);
+            ColType::Text(substr(&args[0].to_string(), start, len))
+        }
+        _ if window::NAMES.contains(&name) => bail!(SqliteError::SqlParse(format!(
+            "misuse of window function {name}()"
+        ))),
+        _ => bail!(SqliteError::SqlParse(format!("no such function: {name}"))),
+    })
+}
+
+fn int_arg(v: &ColType) -> i64 {
+    match to_number(v) {
+        ColType::Integer(i) => i,
+        ColType::Float(f) => f as i64,
+        _ => 0,
+    }
+}
+
+// substr follows sqlite's substrFunc: `start` is 1-based, a negative start
+// counts from the end, and a negative length takes the characters before start.
+fn substr(s: &str, start: i64, len: Option<i64>) -> String {
+    let chars = s.chars().collect::<Vec<_>>();
+    let n = chars.len() as i64;
+    let mut p1 = start;
+    let (mut p2, neg) = match len {
+        Some(l) if l < 0 => (-l, true),
+        Some(l) => (l, false),
+        None => (n, false),
+    };
+    if p1 < 0 {
+        p1 += n;
+        if p1 < 0 {
+            p2 += p1;
+            if p2 < 0 {
+                p2 = 0;
+            }
+            p1 = 0;
+        }
+    } else if p1 > 0 {
+        p1 -= 1;
+    } else if p2 > 0 {
+        p2 -= 1;
+    }
+    if neg {
+        p1 -= p2;
+        if p1 < 0 {
+            p2 += p1;
+            p1 = 0;
+        }
+    }
+    let from = p1.min(n) as usize;
+    let to = (p1 + p2).min(n) as usize;
+    chars[from..to.max(from)].iter().collect()
+}
+
+#[test]
+fn test_substr() {
+    let cases = [
+        (1, Some(3), "hel"),
+        (2, None, "ello"),
+        (0, Some(2), "h"),
+        (-3, None, "llo"),
+        (-3, Some(2), "ll"),
+        (3, Some(-2), "he"),
+        (10, Some(2), ""),
+        (-10, Some(7), "he"),
+        (1, Some(0), ""),
+        (2, Some(100), "ello"),
+    ];
+    for (start, len, want) in cases {
+        assert_eq!(
+            substr("hello", start, len),
+            want,
+            "substr(hello, {start}, {len:?})"
+        );
+    }
+    assert_eq!(substr("😀ab", 2, Some(1)), "a");
+}
+
+#[test]
+fn test_glob_match() {
+    let cases = [
+        ("*.rs", "main.rs", true),
+        ("*.rs", "main.rs.bak", false),
+        ("*.RS", "main.rs", false),
+        ("*", "", true),
+        ("?", "", false),
+        ("a?c", "abc", true),
+        ("a?c", "a😀c", true),
+        ("a*b*c", "axxbyyc", true),
+        ("a*b*c", "axxbyy", false),
+        ("[a-z]*", "file", true),
+        ("[a-z]*", "File", false),
+        ("[^a-z]*", "File", true),
+        ("[]x]", "]", true),
+        ("[^]x]", "]", false),
+        ("[a-]", "-", true),
+        ("[abc", "a", false),
+        ("*[0-9][0-9]", "v10", true),
+        ("%", "abc", false),
+    ];
+    for (pattern, text, want) in cases {
+        assert_eq!(glob_match(pattern, text), want, "{text} GLOB {pattern}");
+    }
+}
+
+#[test]
+fn test_like_match() {
+    let cases = [
+        ("App%", "apple", None, true),
+        ("app%", "APPLE", None, true),
+        ("%le", "Apple", None, true),
+        ("a_p%", "apple", None, true),
+        ("a_p", "apple", None, false),
+        ("%", "", None, true),
+        ("_", "", None, false),
+        ("é%", "É", None, false),
+        ("%b%c", "abxbc", None, true),
+        ("100!%", "100%", Some('!'), true),
+        ("100!%", "1000", Some('!'), false),
+        ("a!_c", "a_c", Some('!'), true),
+        ("a!_c", "abc", Some('!'), false),
+        ("*", "abc", None, false),
+    ];
+    for (pattern, text, escape, want) in cases {
+        assert_eq!(
+            like_match(pattern, text, escape),
+            want,
+            "{text} LIKE {pattern} ESCAPE {escape:?}"
+        );
+    }
+    assert_eq!(like_prefix("12-%-x"), "12-");
+    assert_eq!(like_prefix("a_c"), "a");
+    assert_eq!(like_prefix("%x"), "");
+    assert_eq!(like_prefix("abc"), "abc");
+}
+
+#[test]
+fn test_eval_functions() {
+    let row = |c: &str| match c {
+        "name" => Some(ColType::Text("Grin 😀".to_string())),
+        "n" => Some(ColType::Integer(-120)),
+        "nothing" => Some(ColType::Null),
+        _ => None,
+    };
+    let eval = |s: &str| parse_expr(s).unwrap().eval(&row).unwrap().to_string();
+    assert_eq!(eval("length(name)"), "6");
+    assert_eq!(eval("length(n)"), "4");
+    assert_eq!(eval("upper(name)"), "GRIN 😀");
+    assert_eq!(eval("lower('AbC')"), "abc");
+    assert_eq!(eval("substr(name, -1)"), "😀");
+    assert_eq!(eval("length(nothing) is null"), "1");
+    assert_eq!(eval("upper(substr(name, 1, 2)) || '!'"), "GR!");
+    assert_eq!(eval("length(name) = 6 and n < 0"), "1");
+    assert_eq!(eval("1 + 2 * 3"), "7");
+    assert_eq!(
+        eval("typeof(name) || typeof(n) || typeof(nothing)"),
+        "textintegernull"
+    );
+    assert_eq!(eval("typeof(n / 7.0)"), "real");
+    assert_eq!(eval("1e3 + 2.5E-1"), "1000.25");
+    assert_eq!(eval("n < -1.2e2 or n = -120.0"), "1");
+    assert_eq!(eval("concat_ws('-', name, nothing, n)"), "Grin 😀--120");
+    assert_eq!(eval("name match 'GRIN'"), "1");
+    assert_eq!(eval("'a-b c' match 'C AND b'"), "1");
+    assert_eq!(eval("name match 'grin smile'"), "0");
+    assert_eq!(eval("nothing match 'x' is null"), "1");
+    assert_eq!(eval("name glob 'Grin *'"), "1");
+    assert_eq!(eval("name glob 'grin *'"), "0");
+    assert_eq!(eval("name not glob 'G?in*'"), "0");
+    assert_eq!(eval("n glob '-1[0-9]0'"), "1");
+    assert_eq!(eval("nothing glob '*' is null"), "1");
+    assert_eq!(eval("name regexp '^G.+ .$'"), "1");
+    assert_eq!(
+        eval("n regexp '^-?[0-9]+$' and name not regexp 'grin'"),
+        "1"
+    );
+    assert_eq!(eval("regexp('[0-9]', name)"), "0");
+    assert_eq!(eval("nothing regexp 'x' is null"), "1");
+    assert!(parse_expr("name regexp '('").unwrap().eval(&row).is_err());
+    assert_eq!(eval("name like 'grin%'"), "1");
+    assert_eq!(eval("name not like 'g_in'"), "1");
+    assert_eq!(eval("n like '-12!0' escape '!'"), "1");
+    assert_eq!(eval("like('%r%', name)"), "1");
+    assert_eq!(eval("nothing like '%' is null"), "1");
+    assert!(
+        parse_expr("name like 'x' escape 'ab'")
+            .unwrap()
+            .eval(&row)
+            .is_err()
+    );
+    assert_eq!(eval("min(n, 3, -7.5)"), "-120");
+    assert_eq!(eval("max(n, 3, -7.5)"), "3");
+    assert_eq!(eval("max(n, '1', 2.5)"), "1");
+    assert_eq!(eval("min(2, 2.0) || max(2.0, 2)"), "22.0");
+    assert_eq!(eval("max(name, n, nothing) is null"), "1");
+    assert!(parse_expr("min(1)").unwrap().aggregates().len() == 1);
+    assert!(parse_expr("x").unwrap().eval(&row).is_err());
+    assert!(parse_expr("nosuch(name)").unwrap().eval(&row).is_err());
+    assert!(parse_expr("length(name").is_err());
+}
@@ -0,0 +1,532 @@
+use anyhow::Result;
+use std::borrow::Cow;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::error::SqliteError;
+use crate::record::ColType;
+
+/// The output mode of query results, as set by `.mode` or `-list`/`-csv`/`-json`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Values separated by `|`, as they are.
+    #[default]
+    List,
+    /// Comma-separated values, quoted where CSV needs it.
+    Csv,
+    /// A JSON array of an object per row.
+    Json,
+    /// Values as SQL literals, separated by commas.
+    Quote,
+}
+
+impl FromStr for Mode {
+    type Err = SqliteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Mode::List),
+            "csv" => Ok(Mode::Csv),
+            "json" => Ok(Mode::Json),
+            "quote" => Ok(Mode::Quote),
+            _ => Err(SqliteError::Usage(format!(
+                "mode should be one of: csv json list quote, not {s}"
+            ))),
+        }
+    }
+}
+
+/// Renders the rows of one statement the way sqlite3 prints them. It's the
+/// only place that decides how values look, NULL included.
+pub struct RowFormatter<'a> {
+    mode: Mode,
+    nullvalue: &'a str,
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    names: &'a [String],
+    escape: bool,
+    rows: usize,
+}
+
+impl<'a> RowFormatter<'a> {
+    /// `names` are the result column names, used as keys in json mode.
+    pub fn new(mode: Mode, nullvalue: &'a str, names: &'a [String]) -> Self {
+        RowFormatter {
+            mode,
+            nullvalue,
+            names,
+            escape: false,
+            rows: 0,
+        }
+    }
+
+    /// Shows control characters as `\xNN`, for viewing untrusted data on a terminal.
+    pub fn escape(mut self, on: bool) -> Self {
+        self.escape = on;
+        self
+    }
+
+    /// Writes one row.
+    pub fn write_row(&mut self, out: &mut dyn Write, values: &[ColType]) -> Result<()> {
+        match self.mode {
+            Mode::List => {
+                let fields = values.iter().map(|v| self.text(v));
+                self.write_fields(out, fields, b"|")?;
+            }
+            Mode::Csv => {
+                let fields = values.iter().map(|v| self.csv(v));
+                self.write_fields(out, fields, b",")?;
+            }
+            Mode::Quote => {
+                // sqlite3 shows an infinity as Inf here, only its insert
+                // mode writes the 9.0e+999 that reads back
+                let fields = values.iter().map(|v| match v {
+                    ColType::Float(f) if f.is_infinite() => self.text(v),
+                    v => Cow::from(sql_literal(v).into_bytes()),
+                });
+                self.write_fields(out, fields, b",")?;
+            }
+            #[cfg(feature = "serde")]
+            Mode::Json => {
+                use serde::Serializer;
+                out.write_all(if self.rows == 0 { b"[" } else { b",\n" })?;
+                let mut ser = serde_json::Serializer::with_formatter(&mut *out, ShellJson);
+                ser.collect_map(self.names.iter().zip(values.iter().map(ShellValue)))?;
+            }
+            #[cfg(not(feature = "serde"))]
+            Mode::Json => {
+                return Err(SqliteError::Unsupported(
+                    "json mode without the serde feature".to_string(),
+                )
+                .into());
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn write_fields<'v>(
+        &self,
+        out: &mut dyn Write,
+        fields: impl Iterator<Item = Cow<'v, [u8]>>,
+        sep: &[u8],
+    ) -> Result<()> {
+        for (i, field) in fields.enumerate() {
+            if i > 0 {
+                out.write_all(sep)?;
+            }
+            write_value(out, &field, self.escape)?;
+        }
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Closes the output of a statement, json mode ends its array here.
+    pub fn finish(&mut self, out: &mut dyn Write) -> Result<()> {
+        if self.mode == Mode::Json && self.rows > 0 {
+            writeln!(out, "]")?;
+        }
+        Ok(())
+    }
+
+    fn text<'v>(&'v self, v: &'v ColType) -> Cow<'v, [u8]> {
+        match v {
+            ColType::Null | ColType::Reserved => self.nullvalue.as_bytes().into(),
+            ColType::Text(s) => s.as_bytes().into(),
+            ColType::Blob(b) => b.as_slice().into(),
+            v => v.to_string().into_bytes().into(),
+        }
+    }
+
+    // csv quotes text with a space, quote, comma, control or non-ASCII byte,
+    // and the empty string, like sqlite3's output_csv.
+    fn csv<'v>(&'v self, v: &'v ColType) -> Cow<'v, [u8]> {
+        let s = self.text(v);
+        let quote = !matches!(v, ColType::Null | ColType::Reserved)
+            && (s.is_empty()
+                || s.iter()
+                    .any(|&b| b <= b' ' || b"\"',".contains(&b) || b >= 0x7f));
+        if !quote {
+            return s;
+        }
+        let mut quoted = vec![b'"'];
+        for &b in s.iter() {
+            if b == b'"' {
+                quoted.push(b'"');
+            }
+            quoted.push(b);
+        }
+        quoted.push(b'"');
+        quoted.into()
+    }
+}
+
+// write_value is where every displayed value becomes output: the bytes as they
+// are, embedded NULs and blobs included, or with control characters as \xNN.
+pub fn write_value(out: &mut dyn Write, bytes: &[u8], escape: bool) -> std::io::Result<()> {
+    if !escape {
+        return out.write_all(bytes);
+    }
+    for chunk in bytes.split_inclusive(|&b| b < 0x20 || b == 0x7f) {
+        match chunk.split_last() {
+            Some((&b, rest)) if b < 0x20 || b == 0x7f => {
+                out.write_all(rest)?;
+                write!(out, "\\x{b:02x}")?;
+            }
+            _ => out.write_all(chunk)?,
+        }
+    }
+    Ok(())
+}
+
+// sql_literal renders a value as SQL that reads back as the same value:
+// quote mode, and the single implementation for anything emitting SQL text.
+pub fn sql_literal(v: &ColType) -> String {
+    match v {
+        ColType::Null => "NULL".to_string(),
+        ColType::Integer(i) => i.to_string(),
+        ColType::Float(f) => float_literal(*f),
+        ColType::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        ColType::Blob(b) => {
+            let hex = b.iter().map(|x| format!("{x:02x}")).collect::<String>();
+            format!("X'{hex}'")
+        }
+        ColType::Reserved => "NULL".to_string(),
+    }
+}
+
+// float_literal renders a REAL like sqlite3's quote mode, with its "%!.20g":
+// up to 20 significant digits, as a decimal when the exponent is from -4 to
+// 19 and in e-notation with a signed exponent otherwise, always with a digit
+// after the point. Infinities are 9.0e+999, which reads back as one.
+fn float_literal(f: f64) -> String {
+    if f.is_nan() {
+        return "NULL".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "9.0e+999" } else { "-9.0e+999" }.to_string();
+    }
+    let (digits, dp) = fp_decode(f.abs());
+    let digit = |j: usize| digits.get(j).map_or('0', |&d| d as char);
+    // -0.0 is 0.0, as sqlite decodes it
+    let mut out = if f < 0.0 { "-" } else { "" }.to_string();
+    let exp = dp - 1;
+    let scientific = !(-4..=19).contains(&exp);
+    let mut j = 0;
+    if scientific || exp < 0 {
+        out.push(if scientific { digit(0) } else { '0' });
+        j = scientific as usize;
+    } else {
+        for _ in 0..=exp {
+            out.push(digit(j));
+            j += 1;
+        }
+    }
+    out.push('.');
+    if scientific {
+        out.extend((1..20).map(digit));
+    } else {
+        let zeros = (-exp - 1).max(0) as usize;
+        out.extend(std::iter::repeat_n('0', zeros));
+        out.extend((j..j + (19 - exp) as usize - zeros).map(digit));
+    }
+    out.truncate(out.trim_end_matches('0').len());
+    if out.ends_with('.') {
+        out.push('0');
+    }
+    if scientific {
+        let sign = if exp < 0 { '-' } else { '+' };
+        out.push_str(&format!("e{sign}{:02}", exp.abs()));
+    }
+    out
+}
+
+// fp_decode is sqlite's sqlite3FpDecode of a positive, finite `r`: its
+// significant digits, without trailing zeros, and where the decimal point
+// goes among them. `r` is scaled by powers of ten into a u64 of at most 19
+// digits in double-double arithmetic, so the digits past the 17th are the
+// ones sqlite prints rather than those of the exact expansion. That's also
+// why 20 digits are never rounded. The constants are sqlite's, as written.
+#[allow(clippy::excessive_precision)]
+fn fp_decode(r: f64) -> (Vec<u8>, i32) {
+    if r == 0.0 {
+        return (vec![b'0'], 1);
+    }
+    let mut rr = [r, 0.0];
+    let mut exp = 0;
+    if rr[0] > 9.223372036854774784e18 {
+        while rr[0] > 9.223372036854774784e118 {
+            exp += 100;
+            dekker_mul2(&mut rr, 1.0e-100, -1.99918998026028836196e-117);
+        }
+        while rr[0] > 9.223372036854774784e28 {
+            exp += 10;
+            dekker_mul2(&mut rr, 1.0e-10, -3.6432197315497741579e-27);
+        }
+        while rr[0] > 9.223372036854774784e18 {
+            exp += 1;
+            dekker_mul2(&mut rr, 1.0e-01, -5.5511151231257827021e-18);
+        }
+    } else {
+        while rr[0] < 9.223372036854774784e-83 {
+            exp -= 100;
+            dekker_mul2(&mut rr, 1.0e100, -1.5902891109759918046e83);
+        }
+        while rr[0] < 9.223372036854774784e07 {
+            exp -= 10;
+            dekker_mul2(&mut rr, 1.0e10, 0.0);
+        }
+        while rr[0] < 9.22337203685477478e17 {
+            exp -= 1;
+            dekker_mul2(&mut rr, 1.0e01, 0.0);
+        }
+    }
+    let v = if rr[1] < 0.0 {
+        (rr[0] as u64).wrapping_sub(-rr[1] as u64)
+    } else {
+        (rr[0] as u64).wrapping_add(rr[1] as u64)
+    };
+    let mut digits = v.to_string().into_bytes();
+    let dp = digits.len() as i32 + exp;
+    digits.truncate(digits.iter().rposition(|&d| d != b'0').map_or(1, |i| i + 1));
+    (digits, dp)
+}
+
+// dekker_mul2 multiplies the double-double `x` by `(y, yy)`, like sqlite's
+// dekkerMul2.
+fn dekker_mul2(x: &mut [f64; 2], y: f64, yy: f64) {
+    let split = |v: f64| f64::from_bits(v.to_bits() & 0xffff_ffff_fc00_0000);
+    let hx = split(x[0]);
+    let tx = x[0] - hx;
+    let hy = split(y);
+    let ty = y - hy;
+    let p = hx * hy;
+    let q = hx * ty + tx * hy;
+    let c = p + q;
+    let cc = p - c + q + tx * ty;
+    let cc = x[0] * yy + x[1] * y + cc;
+    x[0] = c + cc;
+    x[1] = c - x[0];
+    x[1] += cc;
+}
+
+// ShellJson writes the json of sqlite3's json mode, where reals look like
+// they do in list mode.
+#[cfg(feature = "serde")]
+struct ShellJson;
+
+#[cfg(feature = "serde")]
+impl serde_json::ser::Formatter for ShellJson {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        writer.write_all(crate::record::format_float(value).as_bytes())
+    }
+}
+
+// ShellValue serializes a value like Value does, except that sqlite3 shows
+// a blob as a string of its bytes rather than as base64.
+#[cfg(feature = "serde")]
+struct ShellValue<'a>(&'a ColType);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ShellValue<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            ColType::Blob(b) => serializer.serialize_str(&String::from_utf8_lossy(b)),
+            v => v.serialize(serializer),
+        }
+    }
+}
+
+// read_csv splits CSV text into records, each with the line it starts on:
+// fields are separated by commas and records by LF or CRLF, and a quoted
+// field can hold both, with "" for a quote. A quote inside an unquoted field
+// is kept as it is, like sqlite3 does.
+pub fn read_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, SqliteError> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        loop {
+            let mut field = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(SqliteError::Usage(format!(
+                                "line {start}: unterminated quoted field"
+                            )));
+                        }
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\n' | '\r')) {
+                    return Err(SqliteError::Usage(format!(
+                        "line {line}: unescaped \" character"
+                    )));
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '\n' || (c == '\r' && field_ends(&chars)) {
+                    break;
+                }
+                field.push(c);
+                chars.next();
+            }
+            fields.push(field);
+            match chars.next() {
+                Some(',') => continue,
+                Some('\r') => {
+                    chars.next();
+                }
+                _ => {}
+            }
+            line += 1;
+            break;
+        }
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+// field_ends tells a CR ending a record from one inside a field.
+fn field_ends(chars: &std::iter::Peekable<std::str::Chars>) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next();
+    matches!(ahead.next(), None | Some('\n'))
+}
+
+#[test]
+fn test_read_csv() {
+    let records = read_csv("a,b\r\n1,\"two, \"\"2\"\"\"\n\"multi\nline\",\n,x\"y\n").unwrap();
+    assert_eq!(
+        records,
+        [
+            (1, vec!["a".to_string(), "b".to_string()]),
+            (2, vec!["1".to_string(), "two, \"2\"".to_string()]),
+            (3, vec!["multi\nline".to_string(), String::new()]),
+            (5, vec![String::new(), "x\"y".to_string()]),
+        ]
+    );
+    assert_eq!(read_csv("a\nb").unwrap().len(), 2);
+    assert!(read_csv("").unwrap().is_empty());
+    let err = read_csv("a\n\"b\nc").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: unterminated quoted field");
+    let err = read_csv("a\n\n\"b\"c").unwrap_err();
+    assert_eq!(err.to_string(), "line 3: unescaped \" character");
+}
+
+#[test]
+fn test_null_rendering() {
+    let row = [
+        ColType::Integer(1),
+        ColType::Null,
+        ColType::Text("a,\"b\"".to_string()),
+    ];
+    let names = vec!["id".to_string(), "note".to_string(), "name".to_string()];
+    let render = |mode, nullvalue| {
+        let mut out = Vec::new();
+        let mut f = RowFormatter::new(mode, nullvalue, &names);
+        f.write_row(&mut out, &row).unwrap();
+        f.write_row(&mut out, &row).unwrap();
+        f.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!(render(Mode::List, ""), "1||a,\"b\"\n1||a,\"b\"\n");
+    assert_eq!(
+        render(Mode::List, "NULL"),
+        "1|NULL|a,\"b\"\n1|NULL|a,\"b\"\n"
+    );
+    assert_eq!(
+        render(Mode::Csv, ""),
+        "1,,\"a,\"\"b\"\"\"\n1,,\"a,\"\"b\"\"\"\n"
+    );
+    assert_eq!(
+        render(Mode::Quote, ""),
+        "1,NULL,'a,\"b\"'\n1,NULL,'a,\"b\"'\n"
+    );
+    #[cfg(feature = "serde")]
+    assert_eq!(
+        render(Mode::Json, "NULL"),
+        "[{\"id\":1,\"note\":null,\"name\":\"a,\\\"b\\\"\"},\n\
+         {\"id\":1,\"note\":null,\"name\":\"a,\\\"b\\\"\"}]\n"
+    );
+}
+
+#[test]
+fn test_sql_literal() {
+    let cases = [
+        (ColType::Null, "NULL"),
+        (ColType::Integer(-7), "-7"),
+        (ColType::Float(2.0), "2.0"),
+        (ColType::Float(1.5), "1.5"),
+        (ColType::Float(0.1), "0.1000000000000000055"),
+        (ColType::Float(1e20), "1.0e+20"),
+        (ColType::Float(1e16), "10000000000000000.0"),
+        (ColType::Float(0.1 + 0.2), "0.3000000000000000445"),
+        (ColType::Float(1e-5), "1.000000000000000082e-05"),
+        (ColType::Float(1e300 * 10.0), "1.00000000000000013e+301"),
+        (ColType::Float(f64::MAX), "1.797693134862315692e+308"),
+        (ColType::Float(i64::MIN as f64), "-9223372036854775810.0"),
+        (ColType::Float(f64::NEG_INFINITY), "-9.0e+999"),
+        (ColType::Float(-0.0), "0.0"),
+        (ColType::Text(String::new()), "''"),
+        (ColType::Text("it's".to_string()), "'it''s'"),
+        (ColType::Blob(vec![0x00, 0xff, 0x10]), "X'00ff10'"),
+        (ColType::Blob(vec![]), "X''"),
+    ];
+    for (v, want) in cases {
+        assert_eq!(sql_literal(&v), want);
+    }
+}
+
+#[test]
+fn test_write_value() {
+    let render = |bytes: &[u8], escape| {
+        let mut out = Vec::new();
+        write_value(&mut out, bytes, escape).unwrap();
+        out
+    };
+    assert_eq!(render(b"a\0b", false), b"a\0b");
+    assert_eq!(render(b"a\0b", true), b"a\\x00b");
+    assert_eq!(
+        render(b"\x1b[31mred\r\n\x7f", true),
+        b"\\x1b[31mred\\x0d\\x0a\\x7f"
+    );
+    assert_eq!(render("caf\u{e9}".as_bytes(), true), "caf\u{e9}".as_bytes());
+
+    let names = vec!["b".to_string()];
+    let mut out = Vec::new();
+    let mut f = RowFormatter::new(Mode::List, "", &names);
+    f.write_row(&mut out, &[ColType::Blob(vec![0x00, 0xff])])
+        .unwrap();
+    assert_eq!(out, b"\0\xff\n");
+}
+
+#[test]
+fn test_reserved_renders_as_null() {
+    let names = vec!["a".to_string(), "b".to_string()];
+    let row = [ColType::Reserved, ColType::Integer(1)];
+    let render = |mode| {
+        let mut out = Vec::new();
+        let mut f = RowFormatter::new(mode, "N", &names);
+        f.write_row(&mut out, &row).unwrap();
+        f.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!(render(Mode::List), "N|1\n");
+    assert_eq!(render(Mode::Csv), "N,1\n");
+    assert_eq!(render(Mode::Quote), "NULL,1\n");
+    #[cfg(feature = "serde")]
+    assert_eq!(render(Mode::Json), "[{\"a\":null,\"b\":1}]\n");
+    assert_eq!(ColType::Reserved.to_string(), "");
+}
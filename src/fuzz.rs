@@ -0,0 +1,48 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`, built with the `fuzz`
+//! feature. They reach decoders that are otherwise private, and aren't a
+//! stable API.
+use anyhow::{Result, bail};
+use std::io::Cursor;
+
+use crate::btree;
+use crate::error::SqliteError;
+use crate::pager::{self, Pager};
+use crate::parser;
+use crate::record::Value;
+
+pub use crate::record::decode_varint;
+
+/// Decodes `payload` as the record of a cell, whose values are text in
+/// `text_encoding`, 1 to 3 as in the database header.
+pub fn decode_record(payload: &[u8], text_encoding: u32) -> Result<Vec<Value>> {
+    btree::record_values(payload, text_encoding, false, 2)
+}
+
+/// Reads `page` as page 2 of a database whose pages are as big, and decodes
+/// every cell on it. Returns the number of cells.
+///
+/// Overflow pages and children are past the end of that database, so a
+/// cell that points at one fails.
+pub fn walk_page(page: &[u8]) -> Result<usize> {
+    let size = page.len();
+    if !(512..=32768).contains(&size) || !size.is_power_of_two() {
+        bail!(SqliteError::Usage(format!(
+            "{size} bytes is not a page size"
+        )));
+    }
+    let mut image = vec![0; size];
+    image[..16].copy_from_slice(b"SQLite format 3\0");
+    image[16..18].copy_from_slice(&(size as u16).to_be_bytes());
+    image[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    image[56..60].copy_from_slice(&1u32.to_be_bytes());
+    image.extend_from_slice(page);
+    let dbinfo = pager::parse_dbinfo(&mut Cursor::new(&image), 0)?;
+    let pager = Pager::from_bytes(image, dbinfo);
+    btree::walk_cells(&pager, 2)
+}
+
+/// Parses `sql` as a select, compound or not, and as a CREATE TABLE.
+pub fn parse_sql(sql: &str) {
+    let _ = parser::parse_compound(sql);
+    let _ = parser::parse_create(sql);
+}
@@ -0,0 +1,251 @@
+use anyhow::{Result, bail};
+
+use crate::error::SqliteError;
+
+// https://www.sqlite.org/fileformat.html#the_database_header
+pub const HEADER_SIZE: usize = 100;
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// The 100-byte header at the start of every database file, field by field
+/// as <https://www.sqlite.org/fileformat.html#the_database_header> lays it
+/// out. `.dbinfo` shows most of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseHeader {
+    /// The size of every page in bytes, a power of two from 512 to 65536.
+    pub page_size: u32,
+    /// The file format version for writing, 1 for a rollback journal and 2
+    /// for WAL.
+    pub write_version: u8,
+    /// The file format version for reading, as `write_version`.
+    pub read_version: u8,
+    /// Bytes left unused at the end of every page, e.g. for an extension
+    /// encrypting it.
+    pub reserved_bytes: u8,
+    /// The largest share of a page a cell's payload may take, in 255ths.
+    /// Always 64.
+    pub max_payload_fraction: u8,
+    /// The least share of a page a spilling cell keeps, in 255ths. Always 32.
+    pub min_payload_fraction: u8,
+    /// The least share a spilling table leaf cell keeps. Always 32.
+    pub leaf_payload_fraction: u8,
+    /// Bumped by every transaction that changes the file, outside WAL mode.
+    pub file_change_counter: u32,
+    /// The size of the file in pages. Only to be trusted when
+    /// `version_valid_for` equals `file_change_counter`.
+    pub page_count: u32,
+    /// The first freelist trunk page, 0 when no page is free.
+    pub freelist_trunk_page: u32,
+    /// How many pages are free.
+    pub freelist_page_count: u32,
+    /// Bumped by every change to the schema.
+    pub schema_cookie: u32,
+    /// The schema format number, 1 to 4, or 0 before any schema was written.
+    pub schema_format: u32,
+    /// The page cache size `PRAGMA default_cache_size` suggests.
+    pub default_cache_size: i32,
+    /// The largest root page in an auto-vacuum or incremental-vacuum
+    /// database, 0 when neither is on.
+    pub largest_root_page: u32,
+    /// The text encoding: 1 for UTF-8, 2 for UTF-16le, 3 for UTF-16be.
+    pub text_encoding: u32,
+    /// The value of `PRAGMA user_version`.
+    pub user_version: i32,
+    /// Whether auto-vacuum waits for `PRAGMA incremental_vacuum`.
+    pub incremental_vacuum: bool,
+    /// The value of `PRAGMA application_id`.
+    pub application_id: i32,
+    /// The `file_change_counter` the `page_count` was last written with.
+    pub version_valid_for: u32,
+    /// `SQLITE_VERSION_NUMBER` of the library that last wrote the file, e.g.
+    /// 3045001 for 3.45.1.
+    pub sqlite_version_number: u32,
+}
+
+impl DatabaseHeader {
+    /// Reads the header from its 100 bytes, failing with
+    /// [`SqliteError::NotADatabase`] without the magic string, with
+    /// [`SqliteError::Unsupported`] when they carry the signature of a
+    /// compressed or encrypted database or another format sqlite3 writes
+    /// instead, and with
+    /// [`SqliteError::CorruptDatabase`] when a byte that is constant in every
+    /// valid file isn't.
+    pub fn parse(bytes: &[u8; HEADER_SIZE]) -> Result<DatabaseHeader> {
+        if !bytes.starts_with(MAGIC) {
+            if let Some(what) = foreign_format(bytes) {
+                bail!(SqliteError::Unsupported(format!(
+                    "file appears to be {what}, not a plain database"
+                )));
+            }
+            bail!(SqliteError::NotADatabase);
+        }
+        let u32_at = |i: usize| u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+        let corrupt = |what: String| SqliteError::CorruptDatabase(what);
+
+        // 1 stands for 65536, which two bytes can't hold
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65536,
+            n => n as u32,
+        };
+        if !page_size.is_power_of_two() || page_size < 512 {
+            bail!(corrupt(format!("invalid page size {page_size}")));
+        }
+        // the maximum, minimum and leaf payload fractions must be 64, 32 and 32
+        if bytes[21..24] != [64, 32, 32] {
+            bail!(corrupt(format!(
+                "invalid payload fractions {}, {} and {}",
+                bytes[21], bytes[22], bytes[23]
+            )));
+        }
+        let text_encoding = u32_at(56);
+        if !(1..=3).contains(&text_encoding) {
+            bail!(corrupt(format!("invalid text encoding {text_encoding}")));
+        }
+        Ok(DatabaseHeader {
+            page_size,
+            write_version: bytes[18],
+            read_version: bytes[19],
+            reserved_bytes: bytes[20],
+            max_payload_fraction: bytes[21],
+            min_payload_fraction: bytes[22],
+            leaf_payload_fraction: bytes[23],
+            file_change_counter: u32_at(24),
+            page_count: u32_at(28),
+            freelist_trunk_page: u32_at(32),
+            freelist_page_count: u32_at(36),
+            schema_cookie: u32_at(40),
+            schema_format: u32_at(44),
+            default_cache_size: u32_at(48) as i32,
+            largest_root_page: u32_at(52),
+            text_encoding,
+            user_version: u32_at(60) as i32,
+            incremental_vacuum: u32_at(64) != 0,
+            application_id: u32_at(68) as i32,
+            version_valid_for: u32_at(92),
+            sqlite_version_number: u32_at(96),
+        })
+    }
+
+    /// The name `.dbinfo` gives the text encoding.
+    pub fn text_encoding_name(&self) -> &'static str {
+        match self.text_encoding {
+            1 => "utf8",
+            2 => "utf16le",
+            _ => "utf16be",
+        }
+    }
+}
+
+// foreign_format recognizes what a file without the magic string is, when
+// it's something a database is mistaken for: a compressed or encrypted
+// database, a compressed copy of one, or a journal or WAL. Only a signature
+// counts: a database encrypted whole looks like any other binary file, and
+// isn't told apart from one.
+fn foreign_format(bytes: &[u8; HEADER_SIZE]) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 9] = [
+        (b"ZV-", "a ZipVFS compressed database"),
+        (b"\x1f\x8b", "gzip-compressed"),
+        (b"\x28\xb5\x2f\xfd", "zstd-compressed"),
+        (b"\xfd7zXZ\0", "xz-compressed"),
+        (b"BZh", "bzip2-compressed"),
+        (b"PK\x03\x04", "a zip archive"),
+        (b"\xd9\xd5\x05\xf9\x20\xa1\x63\xd7", "a rollback journal"),
+        (b"\x37\x7f\x06\x82", "a write-ahead log"),
+        (b"\x37\x7f\x06\x83", "a write-ahead log"),
+    ];
+    if let Some((_, what)) = SIGNATURES.iter().find(|(sig, _)| bytes.starts_with(sig)) {
+        return Some(what);
+    }
+    // SEE and SQLCipher can leave bytes 16 to 23 in the clear so the page size
+    // is known, and encrypt the rest, magic string included: a damaged one
+    // still has most of its bytes
+    let page_size = u16::from_be_bytes([bytes[16], bytes[17]]);
+    let magic_left = bytes.iter().zip(MAGIC).filter(|(a, b)| a == b).count();
+    if (page_size == 1 || page_size >= 512 && page_size.is_power_of_two())
+        && bytes[21..24] == [64, 32, 32]
+        && magic_left < 4
+    {
+        return Some("encrypted, with a plaintext header");
+    }
+    None
+}
+
+#[test]
+fn test_parse_header() {
+    let mut bytes = [0; HEADER_SIZE];
+    bytes[..16].copy_from_slice(MAGIC);
+    bytes[16..24].copy_from_slice(&[0, 1, 1, 1, 0, 64, 32, 32]);
+    bytes[48..52].copy_from_slice(&(-2000i32).to_be_bytes());
+    bytes[56..60].copy_from_slice(&3u32.to_be_bytes());
+    bytes[60..64].copy_from_slice(&(-1i32).to_be_bytes());
+    let header = DatabaseHeader::parse(&bytes).unwrap();
+    assert_eq!(header.page_size, 65536);
+    assert_eq!((header.write_version, header.read_version), (1, 1));
+    assert_eq!(
+        (
+            header.max_payload_fraction,
+            header.min_payload_fraction,
+            header.leaf_payload_fraction
+        ),
+        (64, 32, 32)
+    );
+    assert_eq!(header.default_cache_size, -2000);
+    assert_eq!(header.user_version, -1);
+    assert_eq!(header.text_encoding_name(), "utf16be");
+    assert!(!header.incremental_vacuum);
+
+    let fails = |i: usize, b: u8| {
+        let mut bad = bytes;
+        bad[i] = b;
+        DatabaseHeader::parse(&bad).unwrap_err()
+    };
+    assert!(matches!(
+        fails(3, b'l').downcast_ref(),
+        Some(SqliteError::NotADatabase)
+    ));
+    for (i, b) in [(16, 3), (17, 0), (21, 65), (22, 0), (23, 33), (59, 4)] {
+        let err = fails(i, b);
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::CorruptDatabase(_))),
+            "byte {i}: {err}"
+        );
+    }
+}
+
+#[test]
+fn test_foreign_formats() {
+    let unsupported = |bytes: &[u8; HEADER_SIZE]| {
+        let err = DatabaseHeader::parse(bytes).unwrap_err();
+        match err.downcast_ref() {
+            Some(SqliteError::Unsupported(msg)) => msg.clone(),
+            _ => panic!("{err}"),
+        }
+    };
+    let mut bytes = [0; HEADER_SIZE];
+    bytes[..8].copy_from_slice(b"ZV-zlib\0");
+    assert!(unsupported(&bytes).contains("ZipVFS"));
+    bytes[..4].copy_from_slice(&[0x1f, 0x8b, 8, 0]);
+    assert!(unsupported(&bytes).contains("gzip"));
+
+    // a header whose magic string alone was encrypted
+    let mut see = [0; HEADER_SIZE];
+    see[..16].copy_from_slice(&[0xa5; 16]);
+    see[16..24].copy_from_slice(&[16, 0, 1, 1, 0, 64, 32, 32]);
+    assert!(unsupported(&see).contains("plaintext header"));
+
+    // without a signature, random bytes and text are no database
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    let random = std::array::from_fn(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x as u8
+    });
+    let mut text = [b' '; HEADER_SIZE];
+    text[..22].copy_from_slice(b"CREATE TABLE t (a, b);");
+    for bytes in [random, text] {
+        assert!(matches!(
+            DatabaseHeader::parse(&bytes).unwrap_err().downcast_ref(),
+            Some(SqliteError::NotADatabase)
+        ));
+    }
+}
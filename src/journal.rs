@@ -0,0 +1,259 @@
+use crate::error::SqliteError;
+use anyhow::Result;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+
+// https://www.sqlite.org/fileformat.html#the_rollback_journal
+const JOURNAL_MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+#[derive(Debug, PartialEq)]
+pub struct JournalHeader {
+    pub page_count: u32,
+    pub nonce: u32,
+    pub initial_pages: u32,
+    pub sector_size: u32,
+    pub page_size: u32,
+}
+
+pub fn journal_path(db_path: &str) -> String {
+    format!("{db_path}-journal")
+}
+
+// read_journal_header returns None when there is no journal, when it has been
+// zeroed/truncated by a committed transaction (journal_mode=persist/truncate),
+// or when its header was torn before the journal was synced.
+pub fn read_journal_header(path: &str) -> Result<Option<JournalHeader>> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut header = [0; JOURNAL_HEADER_SIZE];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    Ok(parse_journal_header(&header, file.metadata()?.len()))
+}
+
+// JOURNAL_HEADER_SIZE is how much of the journal parse_journal_header reads.
+pub const JOURNAL_HEADER_SIZE: usize = 28;
+
+// parse_journal_header is None unless the header starts with the magic number,
+// has sector and page sizes sqlite3 could have written, and counts no more
+// page records than the `len` bytes of journal from the header on can hold.
+pub fn parse_journal_header(header: &[u8; JOURNAL_HEADER_SIZE], len: u64) -> Option<JournalHeader> {
+    if header[..8] != JOURNAL_MAGIC {
+        return None;
+    }
+    let field = |i: usize| u32::from_be_bytes(header[i..i + 4].try_into().unwrap());
+    let header = JournalHeader {
+        page_count: field(8),
+        nonce: field(12),
+        initial_pages: field(16),
+        sector_size: field(20),
+        page_size: field(24),
+    };
+    let size_ok = |size: u32| (512..=65536).contains(&size) && size.is_power_of_two();
+    if !size_ok(header.sector_size) || !size_ok(header.page_size) {
+        return None;
+    }
+    // u32::MAX says the records go on to the end of the file
+    let records =
+        header.sector_size as u64 + header.page_count as u64 * (header.page_size as u64 + 8);
+    if header.page_count != u32::MAX && records > len {
+        return None;
+    }
+    Some(header)
+}
+
+// check_hot_journal refuses to read a database whose rollback journal is still live,
+// since the main file may hold half-written pages.
+pub fn check_hot_journal(db_path: &str) -> Result<()> {
+    let path = journal_path(db_path);
+    match read_journal_header(&path)? {
+        Some(header) => Err(hot_journal(path, &header).into()),
+        None => Ok(()),
+    }
+}
+
+pub fn hot_journal(path: String, header: &JournalHeader) -> SqliteError {
+    SqliteError::HotJournal {
+        path,
+        pages: header.page_count,
+    }
+}
+
+// roll_back undoes the transaction a hot journal belongs to on `db`, an image
+// of the main file, the way sqlite3 plays a journal back: the page records of
+// each segment go back in place until one's checksum fails or one has no page
+// number, and the file is cut back to the size it had before. Records of pages
+// past that size are skipped, since the cut drops them anyway. A journal
+// without a valid header leaves `db` as it is.
+pub fn roll_back(mut db: Vec<u8>, journal: &[u8]) -> Vec<u8> {
+    let mut offset = 0;
+    let mut initial = None;
+    'segments: while let Some(header) = journal
+        .get(offset..offset + JOURNAL_HEADER_SIZE)
+        .and_then(|h| parse_journal_header(h.try_into().unwrap(), (journal.len() - offset) as u64))
+    {
+        let (sector_size, page_size) = (header.sector_size as usize, header.page_size as usize);
+        let (initial_pages, _) = *initial.get_or_insert((header.initial_pages as usize, page_size));
+        offset += sector_size;
+        let record_size = page_size + 8;
+        let records = match header.page_count {
+            // the records go on to the end of the file
+            u32::MAX => journal.len().saturating_sub(offset) / record_size,
+            n => n as usize,
+        };
+        for _ in 0..records {
+            let Some(record) = journal.get(offset..offset + record_size) else {
+                break 'segments;
+            };
+            offset += record_size;
+            let field = |i: usize| u32::from_be_bytes(record[i..i + 4].try_into().unwrap());
+            let pgno = field(0) as usize;
+            if pgno == 0 {
+                break 'segments;
+            }
+            if pgno > initial_pages {
+                continue;
+            }
+            let page = &record[4..4 + page_size];
+            if field(4 + page_size) != checksum(header.nonce, page) {
+                break 'segments;
+            }
+            let start = (pgno - 1) * page_size;
+            if db.len() < start + page_size {
+                db.resize(start + page_size, 0);
+            }
+            db[start..start + page_size].copy_from_slice(page);
+        }
+        offset = offset.div_ceil(sector_size) * sector_size;
+    }
+    if let Some((pages, page_size)) = initial {
+        db.truncate(pages * page_size);
+    }
+    db
+}
+
+// checksum is a page record's checksum: the nonce plus every 200th byte of
+// the page, counting down from 200 bytes before its end.
+fn checksum(nonce: u32, page: &[u8]) -> u32 {
+    (1..=(page.len() - 1) / 200)
+        .map(|k| page[page.len() - 200 * k] as u32)
+        .fold(nonce, u32::wrapping_add)
+}
+
+#[test]
+fn test_roll_back() {
+    let page = |fill: u8| vec![fill; 512];
+    let record = |pgno: u32, data: &[u8], nonce: u32| {
+        let mut r = pgno.to_be_bytes().to_vec();
+        r.extend(data);
+        r.extend(checksum(nonce, data).to_be_bytes());
+        r
+    };
+    let header = |records: u32, initial: u32| {
+        let mut h = JOURNAL_MAGIC.to_vec();
+        for v in [records, 9, initial, 512, 512] {
+            h.extend(v.to_be_bytes());
+        }
+        h.resize(512, 0);
+        h
+    };
+    // pages 1 and 2 were changed and page 3 added after the journal saved
+    // the first two
+    let db = [page(0xa1), page(0xa2), page(0xa3)].concat();
+    let mut journal = header(2, 2);
+    journal.extend(record(1, &page(1), 9));
+    journal.extend(record(2, &page(2), 9));
+    assert_eq!(roll_back(db.clone(), &journal), [page(1), page(2)].concat());
+
+    // a record whose checksum fails ends the playback
+    let mut journal = header(2, 3);
+    journal.extend(record(1, &page(1), 9));
+    journal.extend(record(2, &page(2), 8));
+    assert_eq!(
+        roll_back(db.clone(), &journal),
+        [page(1), page(0xa2), page(0xa3)].concat()
+    );
+
+    // records counted from the file size
+    let mut journal = header(u32::MAX, 3);
+    journal.extend(record(3, &page(3), 9));
+    assert_eq!(
+        roll_back(db.clone(), &journal),
+        [page(0xa1), page(0xa2), page(3)].concat()
+    );
+
+    // a second segment starts at the sector boundary after the first
+    let mut journal = header(1, 3);
+    journal.extend(record(2, &page(2), 9));
+    journal.resize(1536, 0);
+    journal.extend(header(1, 3));
+    journal.extend(record(3, &page(3), 9));
+    assert_eq!(
+        roll_back(db.clone(), &journal),
+        [page(0xa1), page(2), page(3)].concat()
+    );
+
+    // records of pages past the initial size are skipped, whatever their
+    // page number, and no page goes back without its page number
+    let mut journal = header(3, 2);
+    journal.extend(record(0x7fff_ffff, &page(7), 9));
+    journal.extend(record(1, &page(1), 9));
+    journal.extend(record(0, &page(2), 9));
+    journal.extend(record(2, &page(2), 9));
+    assert_eq!(
+        roll_back(db.clone(), &journal),
+        [page(1), page(0xa2)].concat()
+    );
+
+    // a header counting more records than follow it was torn
+    let mut journal = header(3, 2);
+    journal.extend(record(1, &page(1), 9));
+    assert_eq!(roll_back(db.clone(), &journal), db);
+
+    // a zeroed header was committed, there's nothing to undo
+    assert_eq!(roll_back(db.clone(), &[0; 1024]), db);
+}
+
+#[test]
+fn test_read_journal_header() {
+    let path = std::env::temp_dir().join(format!("journal-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let mut bytes = JOURNAL_MAGIC.to_vec();
+    for v in [3u32, 0x1234, 2, 512, 4096] {
+        bytes.extend(v.to_be_bytes());
+    }
+    // three records of 4096-byte pages, after the 512-byte sector of the header
+    bytes.resize(512 + 3 * (4 + 4096 + 4), 0);
+    std::fs::write(path, &bytes).unwrap();
+    let header = read_journal_header(path).unwrap();
+    assert_eq!(
+        header,
+        Some(JournalHeader {
+            page_count: 3,
+            nonce: 0x1234,
+            initial_pages: 2,
+            sector_size: 512,
+            page_size: 4096,
+        })
+    );
+
+    // a header whose records aren't all there, or with sizes sqlite3 never
+    // writes, was torn
+    std::fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(read_journal_header(path).unwrap(), None);
+    let mut torn = bytes.clone();
+    torn[20..24].copy_from_slice(&1000u32.to_be_bytes());
+    std::fs::write(path, &torn).unwrap();
+    assert_eq!(read_journal_header(path).unwrap(), None);
+
+    // a committed journal in persist mode has its header zeroed
+    std::fs::write(path, vec![0; bytes.len()]).unwrap();
+    assert_eq!(read_journal_header(path).unwrap(), None);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(read_journal_header(path).unwrap(), None);
+}
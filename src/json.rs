@@ -0,0 +1,413 @@
+use std::fmt;
+
+use crate::record::ColType;
+
+// Json is a parsed JSON value. Numbers keep the text they were written as,
+// which is what sqlite3 shows when they're part of a bigger value, and
+// objects keep their members in document order, duplicates included.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    True,
+    False,
+    Number(String),
+    Text(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+// PathStep is one step of a JSON path: `.key` or `[index]`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+impl Json {
+    // type_name is the name json_type() and json_each() give the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::True => "true",
+            Json::False => "false",
+            Json::Number(n) if is_integer(n) => "integer",
+            Json::Number(_) => "real",
+            Json::Text(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    // atom is the SQL value of a primitive, NULL for an array or object.
+    pub fn atom(&self) -> ColType {
+        match self {
+            Json::Null | Json::Array(_) | Json::Object(_) => ColType::Null,
+            Json::True => ColType::Integer(1),
+            Json::False => ColType::Integer(0),
+            Json::Number(n) => match n.parse() {
+                Ok(i) if is_integer(n) => ColType::Integer(i),
+                _ => ColType::Float(n.parse().unwrap_or(f64::NAN)),
+            },
+            Json::Text(s) => ColType::Text(s.clone()),
+        }
+    }
+
+    // value is the SQL value of the element: its atom, or the minified text
+    // of an array or object.
+    pub fn value(&self) -> ColType {
+        match self {
+            Json::Array(_) | Json::Object(_) => ColType::Text(self.to_string()),
+            _ => self.atom(),
+        }
+    }
+
+    // from_value is the JSON for an SQL value, None for a blob, which JSON
+    // can't hold. An infinite real is 9e999 like in sqlite3's JSON.
+    pub fn from_value(v: &ColType) -> Option<Json> {
+        Some(match v {
+            ColType::Null | ColType::Reserved => Json::Null,
+            ColType::Integer(i) => Json::Number(i.to_string()),
+            ColType::Float(f) if f.is_nan() => Json::Null,
+            ColType::Float(f) if f.is_infinite() => {
+                Json::Number(if *f > 0.0 { "9e999" } else { "-9e999" }.to_string())
+            }
+            ColType::Float(f) => Json::Number(format!("{f:?}")),
+            ColType::Text(s) => Json::Text(s.clone()),
+            ColType::Blob(_) => return None,
+        })
+    }
+
+    // lookup follows a path from this value, None when it leads nowhere.
+    pub fn lookup(&self, path: &[PathStep]) -> Option<&Json> {
+        path.iter().try_fold(self, |v, step| match (v, step) {
+            (Json::Object(members), PathStep::Key(k)) => {
+                members.iter().find(|(name, _)| name == k).map(|(_, v)| v)
+            }
+            (Json::Array(items), PathStep::Index(i)) => items.get(*i),
+            _ => None,
+        })
+    }
+}
+
+// is_integer tells an integer literal from a real one by its spelling, like
+// sqlite3: 1e3 is a real and 99999999999999999999 an integer.
+fn is_integer(n: &str) -> bool {
+    !n.contains(['.', 'e', 'E'])
+}
+
+// Display writes the value as minified JSON.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::True => write!(f, "true"),
+            Json::False => write!(f, "false"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::Text(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{v}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// parse reads a JSON text, with sqlite3's error for anything that isn't one.
+pub fn parse(s: &str) -> Result<Json, String> {
+    let mut p = Parser {
+        s: s.as_bytes(),
+        pos: 0,
+    };
+    let v = p.value().ok_or("malformed JSON")?;
+    p.skip_ws();
+    if p.pos != p.s.len() {
+        return Err("malformed JSON".to_string());
+    }
+    Ok(v)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.s.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_ws();
+        let found = self.s.get(self.pos) == Some(&b);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, word: &str, v: Json) -> Option<Json> {
+        self.s[self.pos..].starts_with(word.as_bytes()).then(|| {
+            self.pos += word.len();
+            v
+        })
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_ws();
+        match self.s.get(self.pos)? {
+            b'n' => self.keyword("null", Json::Null),
+            b't' => self.keyword("true", Json::True),
+            b'f' => self.keyword("false", Json::False),
+            b'"' => self.string().map(Json::Text),
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Array(items))
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_ws();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        members.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Json::Object(members))
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while p.s.get(p.pos).is_some_and(u8::is_ascii_digit) {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if self.s.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        if !digits(self) {
+            return None;
+        }
+        if self.s.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return None;
+            }
+        }
+        if matches!(self.s.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.s.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return None;
+            }
+        }
+        let n = std::str::from_utf8(&self.s[start..self.pos]).ok()?;
+        Some(Json::Number(n.to_string()))
+    }
+
+    // string reads a quoted string at the cursor, unescaping it.
+    fn string(&mut self) -> Option<String> {
+        if self.s.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let b = *self.s.get(self.pos)?;
+            self.pos += 1;
+            match b {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let e = *self.s.get(self.pos)?;
+                    self.pos += 1;
+                    let c = match e {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b if b < 0x20 => return None,
+                b => out.push(b),
+            }
+        }
+    }
+
+    // unicode_escape reads the digits of a \u escape, and the second half of a
+    // surrogate pair when they start one.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let hex = |p: &mut Self| {
+            let digits = std::str::from_utf8(p.s.get(p.pos..p.pos + 4)?).ok()?;
+            p.pos += 4;
+            u32::from_str_radix(digits, 16).ok()
+        };
+        let hi = hex(self)?;
+        if !(0xd800..0xdc00).contains(&hi) {
+            return char::from_u32(hi);
+        }
+        if !self.s[self.pos..].starts_with(b"\\u") {
+            return None;
+        }
+        self.pos += 2;
+        let lo = hex(self)?;
+        char::from_u32(0x10000 + ((hi - 0xd800) << 10) + lo.checked_sub(0xdc00)?)
+    }
+}
+
+// parse_path reads a path like `$.a."b c"[2]`, with sqlite3's error for a
+// malformed one.
+pub fn parse_path(path: &str) -> Result<Vec<PathStep>, String> {
+    let bad = || format!("bad JSON path: '{path}'");
+    let mut rest = path.strip_prefix('$').ok_or_else(bad)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix(".\"") {
+            let end = r.find('"').ok_or_else(bad)?;
+            steps.push(PathStep::Key(r[..end].to_string()));
+            rest = &r[end + 1..];
+        } else if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            if end == 0 {
+                return Err(bad());
+            }
+            steps.push(PathStep::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(bad)?;
+            let i = r[..end].trim().parse().map_err(|_| bad())?;
+            steps.push(PathStep::Index(i));
+            rest = &r[end + 1..];
+        } else {
+            return Err(bad());
+        }
+    }
+    Ok(steps)
+}
+
+// path_key is how a path spells a step to an object member: quoted unless
+// it's a letter followed by letters and digits.
+pub fn path_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let plain = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric());
+    if plain {
+        format!(".{key}")
+    } else {
+        format!(".\"{key}\"")
+    }
+}
+
+#[test]
+fn test_json() {
+    let v =
+        parse(r#" {"a": [1, 2.50, -3e2], "b c": "x\"\u00e9\ud83d\ude00", "d": null, "e": true} "#)
+            .unwrap();
+    assert_eq!(
+        v.to_string(),
+        r#"{"a":[1,2.50,-3e2],"b c":"x\"é😀","d":null,"e":true}"#
+    );
+    let path = parse_path(r#"$.a[1]"#).unwrap();
+    assert_eq!(v.lookup(&path).unwrap().atom(), ColType::Float(2.5));
+    let path = parse_path(r#"$."b c""#).unwrap();
+    assert_eq!(v.lookup(&path).unwrap().type_name(), "text");
+    assert_eq!(v.lookup(&parse_path("$.a[3]").unwrap()), None);
+    assert_eq!(
+        parse("[-3e2]")
+            .unwrap()
+            .lookup(&[PathStep::Index(0)])
+            .unwrap()
+            .type_name(),
+        "real"
+    );
+    for v in [
+        ColType::Integer(-7),
+        ColType::Float(1e300),
+        ColType::Float(2.0),
+        ColType::Float(f64::INFINITY),
+        ColType::Text("1".to_string()),
+        ColType::Null,
+    ] {
+        let text = Json::from_value(&v).unwrap().to_string();
+        assert_eq!(parse(&text).unwrap().atom(), v, "{text}");
+    }
+    assert_eq!(Json::from_value(&ColType::Blob(vec![1])), None);
+    assert_eq!(path_key("a1"), ".a1");
+    assert_eq!(path_key("_x"), ".\"_x\"");
+    for bad in ["", "[1", "{\"a\" 1}", "[1,]", "01x", "\"\\q\"", "tru"] {
+        assert!(parse(bad).is_err(), "{bad}");
+    }
+    for bad in ["x", "$.", "$[x]", "$a"] {
+        assert!(parse_path(bad).is_err(), "{bad}");
+    }
+}
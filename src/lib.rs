@@ -0,0 +1,515 @@
+//! A reader for SQLite database files, written without linking sqlite3.
+//!
+//! [`Database`] opens a file and answers the SELECT statements the
+//! `codecrafters-sqlite` binary understands, returning values instead of
+//! printing them:
+//!
+//! ```
+//! use codecrafters_sqlite::Database;
+//!
+//! let db = Database::open("sample.db")?;
+//! assert_eq!(db.table_names(), ["apples", "sqlite_sequence", "oranges"]);
+//! let mut rows = db.query("select name from apples where id = 2")?;
+//! assert_eq!(rows.columns(), ["name"]);
+//! let row = rows.next().unwrap()?;
+//! assert_eq!(row.get::<String>(0)?, "Fuji");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+#![warn(missing_docs)]
+use anyhow::{Context, Result};
+use std::fs::File;
+
+mod aggregate;
+#[cfg(feature = "tokio")]
+mod async_db;
+mod btree;
+mod error;
+mod exec;
+mod expr;
+mod format;
+#[cfg(feature = "fuzz")]
+#[doc(hidden)]
+pub mod fuzz;
+mod header;
+mod journal;
+mod json;
+mod pager;
+mod parser;
+mod pool;
+mod record;
+mod recover;
+mod rtree;
+mod schema;
+#[cfg(feature = "serde")]
+mod ser;
+mod tvf;
+mod uri;
+mod wal;
+mod window;
+mod write;
+
+#[cfg(feature = "tokio")]
+pub use async_db::AsyncDatabase;
+pub use btree::{LeafStats, RowidViolation, TableCursor, TreeStats, compare_index_keys};
+pub use error::SqliteError;
+pub use exec::{Row, Rows, Statement};
+pub use format::{Mode, RowFormatter};
+pub use header::DatabaseHeader;
+pub use pager::{DBInfo, Stats};
+pub use parser::{ColumnDef, split_statements};
+pub use pool::{DatabasePool, PooledDatabase};
+pub use record::{ColType, Collation, FromValue, Value};
+pub use recover::{RecoveredTable, Recovery};
+pub use schema::{IndexInfo, SchemaEntry, SchemaKind, TableInfo, UniqueViolation};
+#[cfg(feature = "serde")]
+pub use ser::RowMap;
+pub use tvf::{FunctionRegistry, TableValuedFunction, array_param};
+
+use pager::Pager;
+use schema::Tables;
+
+/// An open database: the file, its header and its schema.
+///
+/// The file is only read unless [`Database::set_writable`] allows the few
+/// statements that modify it.
+#[derive(Debug)]
+pub struct Database {
+    pager: Pager,
+    tables: Tables,
+    read_only: bool, // the URI forbids writing, whatever set_writable says
+    writable: bool,
+}
+
+impl Database {
+    /// Opens the database at `path`, a file name or a `file:` URI with the
+    /// `mode`, `immutable` and `vfs` parameters of
+    /// <https://www.sqlite.org/uri.html>.
+    ///
+    /// Unless the URI says the file is immutable, opening fails with
+    /// [`SqliteError::HotJournal`] while a rollback journal needs replaying.
+    /// A WAL holding commits the file doesn't have yet doesn't stop it: only
+    /// the file is read, so a warning is logged that their changes are missing.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug"))]
+    pub fn open(path: &str) -> Result<Database> {
+        Self::open_path(uri::DbPath::parse(path)?)
+    }
+
+    /// Opens the database at `path` as if its URI said `immutable=1`: the
+    /// file is read as it is, its base pages even while a rollback journal
+    /// holds the ones a crashed writer replaced, and it can't be made
+    /// writable.
+    pub fn open_immutable(path: &str) -> Result<Database> {
+        let mut db_path = uri::DbPath::parse(path)?;
+        db_path.immutable = true;
+        Self::open_path(db_path)
+    }
+
+    /// Opens the database at `path` as it was before the transaction a hot
+    /// rollback journal belongs to: the journal's saved pages are played back
+    /// over an image of the file in memory, leaving the file and the journal
+    /// as they are. Without a hot journal the file is read as it is.
+    ///
+    /// The database can't be made writable.
+    pub fn open_rolled_back(path: &str) -> Result<Database> {
+        let db_path = uri::DbPath::parse(path)?;
+        wal::check_wal(&db_path.path)?;
+        let image = std::fs::read(&db_path.path)?;
+        let image = match std::fs::read(journal::journal_path(&db_path.path)) {
+            Ok(journal) => journal::roll_back(image, &journal),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => image,
+            Err(e) => return Err(e.into()),
+        };
+        let mut db = Self::from_bytes(image)?;
+        db.read_only = true;
+        Ok(db)
+    }
+
+    fn open_path(db_path: uri::DbPath) -> Result<Database> {
+        let file = File::open(&db_path.path)?;
+        // immutable promises there's no other writer, so like sqlite3 we don't
+        // look for a journal or WAL
+        if !db_path.immutable {
+            journal::check_hot_journal(&db_path.path)?;
+            wal::check_wal(&db_path.path)?;
+        }
+        let mut pager = Pager::open(file)?;
+        pager.set_path(db_path.path.clone().into());
+        let mut db = Self::with_pager(pager)?;
+        db.read_only = db_path.read_only();
+        Ok(db)
+    }
+
+    /// Opens a database embedded `offset` bytes into the file at `path`, e.g.
+    /// in a backup or container format, without carving it out first. The
+    /// header, magic string included, is read at `offset` and every page
+    /// after it.
+    ///
+    /// The embedded database is taken to be immutable: there's no journal or
+    /// WAL of its own to look for, and it can't be made writable.
+    pub fn open_at_offset(path: &str, offset: u64) -> Result<Database> {
+        let file = File::open(path)?;
+        let mut db = Self::with_pager(Pager::open_at(file, offset)?)?;
+        db.read_only = true;
+        Ok(db)
+    }
+
+    /// Reads a database from an image of the whole file held in memory, e.g.
+    /// one read from a pipe.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Database> {
+        let dbinfo = pager::parse_dbinfo(&mut std::io::Cursor::new(&bytes), 0)?;
+        Self::with_pager(Pager::from_bytes(bytes, dbinfo))
+    }
+
+    fn with_pager(pager: Pager) -> Result<Database> {
+        let tables = Tables::new(&pager)?;
+        // reading the schema isn't any statement's work
+        pager.reset_stats();
+        Ok(Database {
+            pager,
+            tables,
+            read_only: false,
+            writable: false,
+        })
+    }
+
+    /// Memory-maps the file, so pages are borrowed from the mapping instead of
+    /// being read into a buffer each time. Databases read from bytes are
+    /// left as they are.
+    #[cfg(feature = "mmap")]
+    pub fn map_file(&mut self) -> Result<()> {
+        self.pager.map_file()
+    }
+
+    /// Allows statements that modify the database, which is off by default and
+    /// stays off when the URI asked for a read-only or immutable file.
+    ///
+    /// Only INSERT and an UPDATE that leaves the rowid alone are implemented,
+    /// on rowid tables without indexes or triggers, and while the leaf a row
+    /// goes in has room for it: splitting pages and overflow pages aren't.
+    /// CREATE TABLE is too, for tables without UNIQUE constraints or a
+    /// PRIMARY KEY that needs an index; see [`Database::reload_schema`].
+    ///
+    /// Each statement commits on its own unless [`Database::begin`] started
+    /// a transaction. A statement that fails writes nothing.
+    pub fn set_writable(&mut self, on: bool) {
+        self.writable = on && !self.read_only;
+    }
+
+    /// Reads the schema again if the file's schema cookie says it changed
+    /// since it was read, as it does after a CREATE TABLE: statements
+    /// prepared before see the schema they were prepared with. Returns
+    /// whether it was read again.
+    pub fn reload_schema(&mut self) -> Result<bool> {
+        let header = self.pager.page(0, true)?;
+        let cookie = u32::from_be_bytes(header.page[40..44].try_into().unwrap());
+        drop(header);
+        if cookie == self.pager.dbinfo().header.schema_cookie {
+            return Ok(false);
+        }
+        self.pager.reload_header()?;
+        let functions = std::mem::take(&mut self.tables.functions);
+        self.tables = Tables::new(&self.pager)?;
+        self.tables.functions = functions;
+        self.pager.reset_stats();
+        Ok(true)
+    }
+
+    /// Starts a transaction: until [`Database::commit`], the statements
+    /// that modify the database keep their pages in memory, where later
+    /// statements read them, and the file stays as it was.
+    ///
+    /// Without one, each statement commits on its own.
+    pub fn begin(&mut self) -> Result<()> {
+        self.pager.begin()
+    }
+
+    /// Commits the transaction [`Database::begin`] started. The new file is
+    /// written whole next to the database as `<path>.tmp`, synced, and
+    /// renamed over it, so a crash leaves either the old file or the new
+    /// one. If that fails, the file is as it was and the transaction stays
+    /// open, to commit again or roll back.
+    pub fn commit(&mut self) -> Result<()> {
+        self.pager.commit_transaction()
+    }
+
+    /// Ends the transaction [`Database::begin`] started, forgetting what its
+    /// statements wrote, and reads the schema again if they changed it.
+    pub fn rollback(&mut self) -> Result<()> {
+        self.pager.rollback()?;
+        self.reload_schema()?;
+        Ok(())
+    }
+
+    /// Inserts the records of the CSV text `csv` into the table `table`, as
+    /// `.import` does, and returns how many rows went in. Without such a
+    /// table, the first record names the columns of a new one, all TEXT like
+    /// sqlite3 makes them; otherwise every record is a row, and must have a
+    /// field per column. Values are stored as text, converted by the
+    /// columns' affinity.
+    ///
+    /// The rows go in through INSERT statements in a transaction of their
+    /// own, so the database must be writable, and a failure, e.g. on a
+    /// record with the wrong number of fields, inserts none of them. Errors
+    /// name the line the offending record starts on.
+    pub fn import_csv(&mut self, table: &str, csv: &str) -> Result<usize> {
+        let mut records = format::read_csv(csv)?.into_iter();
+        self.begin()?;
+        let res = self.import_records(table, &mut records);
+        match res.and_then(|rows| self.commit().map(|_| rows)) {
+            Ok(rows) => Ok(rows),
+            Err(e) => {
+                self.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    fn import_records(
+        &mut self,
+        table: &str,
+        records: &mut impl Iterator<Item = (usize, Vec<String>)>,
+    ) -> Result<usize> {
+        let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+        let columns = match self.table(table) {
+            Some(t) => t.columns.len(),
+            None => {
+                let Some((_, names)) = records.next() else {
+                    return Ok(0);
+                };
+                let columns = names
+                    .iter()
+                    .map(|name| format!("{} TEXT", quote(name)))
+                    .collect::<Vec<_>>();
+                let sql = format!("CREATE TABLE {}({})", quote(table), columns.join(", "));
+                self.query(&sql)?;
+                self.reload_schema()?;
+                names.len()
+            }
+        };
+        let params = (1..=columns)
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let insert = self.prepare(&format!("INSERT INTO {} VALUES ({params})", quote(table)))?;
+        let mut rows = 0;
+        for (line, fields) in records {
+            if fields.len() != columns {
+                anyhow::bail!(SqliteError::Usage(format!(
+                    "line {line}: expected {columns} columns but found {}",
+                    fields.len()
+                )));
+            }
+            let values = fields.into_iter().map(Value::Text).collect::<Vec<_>>();
+            insert
+                .query(&values)
+                .with_context(|| format!("line {line}"))?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    /// Makes the table-valued function `function` callable in the FROM
+    /// clause of later statements, replacing one with the same name, built-in
+    /// ones included. See [`TableValuedFunction`] for an example.
+    pub fn register_function(&mut self, function: impl TableValuedFunction + 'static) {
+        self.tables.functions.register(Box::new(function));
+    }
+
+    /// The table-valued functions FROM can call.
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.tables.functions
+    }
+
+    /// Logs every page read and b-tree descent to stderr, like `--trace`.
+    pub fn set_trace(&self, on: bool) {
+        self.pager.set_trace(on);
+    }
+
+    /// Strips trailing NUL bytes from every TEXT value read from now on, like
+    /// `--trim-nul`, for legacy data padded out to fixed-width fields.
+    /// sqlite3 keeps them.
+    pub fn set_trim_nul(&self, on: bool) {
+        self.pager.set_trim_nul(on);
+    }
+
+    /// The work done by the last executed statement, so far for rows still
+    /// being iterated.
+    pub fn stats(&self) -> Stats {
+        self.pager.stats()
+    }
+
+    /// What the database header says, as shown by `.dbinfo`.
+    pub fn info(&self) -> DBInfo {
+        DBInfo {
+            table_count: self.tables.entries.len(),
+            ..self.pager.dbinfo()
+        }
+    }
+
+    /// Every field of the database header, as read when the database was
+    /// opened.
+    pub fn header(&self) -> DatabaseHeader {
+        self.pager.dbinfo().header
+    }
+
+    /// The name of every table, in schema order.
+    pub fn table_names(&self) -> Vec<&str> {
+        self.tables.names.iter().map(String::as_str).collect()
+    }
+
+    /// The columns and indexes of the table `name`, matched
+    /// case-insensitively, or None when there's no such table.
+    pub fn table(&self, name: &str) -> Option<&TableInfo> {
+        self.tables
+            .info
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Every row of sqlite_schema in schema order: tables, indexes, views and
+    /// triggers.
+    pub fn schema(&self) -> &[SchemaEntry] {
+        &self.tables.entries
+    }
+
+    /// The rows of sqlite_schema describing a `kind` of object, e.g. every
+    /// index, in schema order.
+    pub fn objects_of_type(&self, kind: SchemaKind) -> impl Iterator<Item = &SchemaEntry> {
+        self.tables.objects_of_type(kind)
+    }
+
+    /// Walks the b-tree of the table or index `name` and reports its depth
+    /// and how full each leaf page is, as `.pagestats` shows them.
+    pub fn page_stats(&self, name: &str) -> Result<TreeStats> {
+        let Some(&rootpage) = self.tables.pos.get(name) else {
+            anyhow::bail!(SqliteError::NoSuchTable(name.to_string()));
+        };
+        btree::tree_stats(&self.pager, rootpage)
+    }
+
+    /// How many levels the b-tree of the table or index `name` has, 1 when
+    /// it fits in its root page. Only the leftmost path is read, so it's a
+    /// cheap hint of how many pages a lookup costs.
+    pub fn tree_depth(&self, name: &str) -> Result<usize> {
+        self.tables.tree_depth(&self.pager, name)
+    }
+
+    /// Reads every UNIQUE index of the table `name`, declared or made for a
+    /// UNIQUE or PRIMARY KEY constraint, and reports the keys it holds more
+    /// than once. sqlite3 never writes such an index, so any violation means
+    /// the file is corrupt.
+    ///
+    /// Fails with [`SqliteError::NoSuchTable`] when there's no such table,
+    /// and with [`SqliteError::Unsupported`] for a WITHOUT ROWID one.
+    pub fn check_unique(&self, name: &str) -> Result<Vec<UniqueViolation>> {
+        self.tables.check_unique(&self.pager, name)
+    }
+
+    /// Reads the rowid of every row of the rowid table `name`, in b-tree
+    /// order, and reports each one that isn't above the rowid before it.
+    /// sqlite3 never writes rows out of order, so any violation means the
+    /// file is corrupt, or that a page was misread.
+    ///
+    /// Fails as [`Database::check_unique`] does.
+    pub fn check_rowids(&self, name: &str) -> Result<Vec<RowidViolation>> {
+        self.tables.check_rowids(&self.pager, name)
+    }
+
+    /// Reads every page of the file in turn and keeps the rows of the table
+    /// leaf pages that parse, for when a corrupt page makes the b-trees fail
+    /// to walk. Rows go to the table whose root page is above their leaf in
+    /// whatever interior pages could be read, going by the schema rows found
+    /// the same way, and to an `unknown_table_N` otherwise.
+    ///
+    /// The schema read on opening isn't used, but opening still needs page 1
+    /// to parse.
+    pub fn recover(&self) -> Result<Recovery> {
+        recover::recover(&self.pager)
+    }
+
+    /// A cursor over the rows of the table `name` in rowid order, reading
+    /// the b-tree directly instead of through SQL:
+    ///
+    /// ```
+    /// use codecrafters_sqlite::{Database, Value};
+    ///
+    /// let db = Database::open("sample.db")?;
+    /// let mut apples = db.scan_table("apples")?;
+    /// apples.seek(3)?;
+    /// let (rowid, values) = apples.next().unwrap()?;
+    /// assert_eq!(rowid, 3);
+    /// assert_eq!(values[1], Value::Text("Honeycrisp".to_string()));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// Fails with [`SqliteError::NoSuchTable`] when there's no such table,
+    /// and with [`SqliteError::Unsupported`] for a WITHOUT ROWID one.
+    pub fn scan_table(&self, name: &str) -> Result<TableCursor<'_>> {
+        self.tables.scan_table(&self.pager, name)
+    }
+
+    /// Parses `sql` and chooses how each of its selects reads its table,
+    /// without reading any row.
+    ///
+    /// Fails on SQL this crate can't parse, on unknown tables, and on
+    /// statements that modify the database.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        exec::prepare(&self.pager, &self.tables, sql, self.writable)
+    }
+
+    /// Runs a prepared statement that has no parameters, see
+    /// [`Statement::query`] for one that has. An `EXPLAIN QUERY PLAN`
+    /// statement yields the lines of [`Statement::query_plan`] below its
+    /// header, one per row.
+    pub fn execute<'db>(&'db self, stmt: &Statement<'db>) -> Result<Rows<'db>> {
+        stmt.query(&[])
+    }
+
+    /// Prepares and executes `sql` in one go.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn query(&self, sql: &str) -> Result<Rows<'_>> {
+        self.execute(&self.prepare(sql)?)
+    }
+
+    /// Runs `sql` and collects every row's values, reading the whole result
+    /// before returning. It's [`Database::query`] collected, so the rows are
+    /// the same ones the iterator yields:
+    ///
+    /// ```
+    /// use codecrafters_sqlite::{Database, Value};
+    ///
+    /// let db = Database::open("sample.db")?;
+    /// let rows = db.query_to_vec("select id, name from apples where color != 'Red' and id < 4")?;
+    /// assert_eq!(
+    ///     rows,
+    ///     [
+    ///         [Value::Integer(1), Value::Text("Granny Smith".to_string())],
+    ///         [Value::Integer(3), Value::Text("Honeycrisp".to_string())],
+    ///     ]
+    /// );
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn query_to_vec(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        self.query(sql)?
+            .map(|row| row.map(Row::into_values))
+            .collect()
+    }
+
+    /// Runs `sql` and returns the first column of its first row, or None
+    /// when there's no row, for queries with a single value as their result:
+    ///
+    /// ```
+    /// use codecrafters_sqlite::{Database, Value};
+    ///
+    /// let db = Database::open("sample.db")?;
+    /// let name = db.query_one("select name from apples where id = 3")?;
+    /// assert_eq!(name, Some(Value::Text("Honeycrisp".to_string())));
+    /// assert_eq!(db.query_one("select name from apples where id = 9")?, None);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn query_one(&self, sql: &str) -> Result<Option<Value>> {
+        let row = self.query(sql)?.next().transpose()?;
+        Ok(row.map(|row| row.into_values().swap_remove(0)))
+    }
+}
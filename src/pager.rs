@@ -0,0 +1,792 @@
+use anyhow::{Result, bail};
+use log::debug;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::error::SqliteError;
+use crate::header::{DatabaseHeader, HEADER_SIZE};
+use crate::record::decode_varint;
+
+/// What the database header says about the file as a whole.
+#[derive(Debug, Copy, Clone)]
+pub struct DBInfo {
+    /// The size of every page, in bytes.
+    pub page_size: u32,
+    /// The text encoding: 1 for UTF-8, 2 for UTF-16le, 3 for UTF-16be.
+    pub text_encoding: u32,
+    /// The number of rows in sqlite_schema: tables, indexes, views and triggers.
+    pub table_count: usize,
+    /// The schema format number, 1 to 4. From 4 on, DESC index keys are
+    /// stored in descending order.
+    pub schema_format: u32,
+    /// Every field of the header, as stored.
+    pub header: DatabaseHeader,
+}
+
+pub struct Page<'a> {
+    pub number: usize, // 1-based
+    pub page_type: u8,
+    freeblock_start: u16,
+    pub cell_num: u16,
+    cell_content_area: u16,
+    // bytes in gaps under 4 bytes between cells, too small for a freeblock
+    fragmented: u8,
+    pub page: Cow<'a, [u8]>, // read into a buffer, or borrowed from a mapped file
+
+    pub cell_offsets: Vec<u16>,
+
+    pub right: Option<u32>,
+}
+
+impl Page<'_> {
+    // free_bytes is the space no cell uses: the gap between the cell pointers
+    // and the cell content area, the freeblocks and the fragmented bytes.
+    pub fn free_bytes(&self) -> usize {
+        let header = if self.number == 1 { 100 } else { 0 };
+        let leaf = matches!(self.page_type, 0x0d | 0x0a);
+        let pointers_end = header + if leaf { 8 } else { 12 } + 2 * self.cell_num as usize;
+        // 0 stands for 65536, an empty page of a 64KiB database
+        let content = match self.cell_content_area {
+            0 => 65536,
+            n => n as usize,
+        };
+        let mut free = content.saturating_sub(pointers_end) + self.fragmented as usize;
+        // freeblocks are chained in increasing order, anything else ends the walk
+        let (mut prev, mut next) = (0, self.freeblock_start as usize);
+        while next > prev && next + 4 <= self.page.len() {
+            let block = &self.page[next..next + 4];
+            free += u16::from_be_bytes([block[2], block[3]]) as usize;
+            (prev, next) = (next, u16::from_be_bytes([block[0], block[1]]) as usize);
+        }
+        free
+    }
+}
+
+// parse_dbinfo reads the header of the database starting `offset` bytes into
+// `reader`, 0 unless it's embedded in a larger file.
+pub fn parse_dbinfo(reader: &mut (impl Read + Seek), offset: u64) -> Result<DBInfo> {
+    let mut bytes = [0; HEADER_SIZE];
+    reader.seek(SeekFrom::Start(offset))?;
+    if reader.read_exact(&mut bytes).is_err() {
+        bail!(SqliteError::NotADatabase);
+    }
+    let header = DatabaseHeader::parse(&bytes)?;
+    // Bytes of unused "reserved" space at the end of each page. Usually 0.
+    if header.reserved_bytes != 0 {
+        bail!(SqliteError::Unsupported(format!(
+            "{} reserved bytes at the end of each page",
+            header.reserved_bytes
+        )));
+    }
+    // 0 in a database that has never had a schema, read like format 1
+    let schema_format = header.schema_format.max(1);
+    if schema_format > 4 {
+        bail!(SqliteError::Unsupported(format!(
+            "schema format {schema_format}, only formats 1 to 4 can be read"
+        )));
+    }
+    Ok(DBInfo {
+        page_size: header.page_size,
+        text_encoding: header.text_encoding,
+        // known once the schema is read
+        table_count: 0,
+        schema_format,
+        header,
+    })
+}
+
+// read_page copies the page at the 0-based `idx` out of the file, whose
+// database starts at `offset`.
+fn read_page(
+    idx: usize,
+    reader: &mut (impl Read + Seek),
+    dbinfo: &DBInfo,
+    offset: u64,
+) -> Result<Vec<u8>> {
+    let page_size = dbinfo.page_size as usize;
+    let mut page = vec![0; page_size];
+    reader.seek(SeekFrom::Start(offset + (idx * page_size) as u64))?;
+    reader
+        .read_exact(&mut page)
+        .map_err(|_| past_end_of_file(idx))?;
+    Ok(page)
+}
+
+fn read_only() -> SqliteError {
+    SqliteError::Unsupported(
+        "cannot write a database that isn't read from a file of its own".to_string(),
+    )
+}
+
+fn past_end_of_file(idx: usize) -> SqliteError {
+    SqliteError::CorruptDatabase(format!("page {} is past the end of the file", idx + 1))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip_all, fields(page_idx = idx, cell_count))
+)]
+fn parse_page(idx: usize, page: Cow<'_, [u8]>, overflow: bool) -> Result<Page<'_>> {
+    if overflow {
+        return Ok(Page {
+            number: idx + 1,
+            page_type: 0,
+            freeblock_start: 0,
+            cell_num: 0,
+            cell_content_area: 0,
+            fragmented: 0,
+            page,
+            cell_offsets: Vec::new(),
+            right: None,
+        });
+    }
+
+    // page 1 starts with the 100-byte file header, its b-tree header and cell
+    // pointers follow it but cell offsets still count from the page start
+    let page_after_fh = if idx == 0 { &page[100..] } else { &page };
+    let page_header = &page_after_fh[..12];
+
+    let page_type = page_header[0];
+    if !matches!(page_type, 0x0d | 0x05 | 0x02 | 0x0a) {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "page {} has invalid type 0x{page_type:02x}",
+            idx + 1
+        )));
+    }
+    let is_leaf = page_type == 0x0d || page_type == 0x0a;
+    let freeblock_start = u16::from_be_bytes(page_header[1..3].try_into().unwrap());
+    let cell_num = u16::from_be_bytes(page_header[3..5].try_into().unwrap());
+    let cell_content_area = u16::from_be_bytes(page_header[5..7].try_into().unwrap());
+    let fragmented = page_header[7];
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("cell_count", cell_num);
+    let mut cell_offsets = Vec::new();
+    let mut i = if is_leaf { 8 } else { 12 };
+    let right = if !is_leaf {
+        match u32::from_be_bytes(page_header[8..12].try_into().unwrap()) {
+            0 => bail!(SqliteError::CorruptDatabase(format!(
+                "interior page {} has no right child",
+                idx + 1
+            ))),
+            right => Some(right),
+        }
+    } else {
+        None
+    };
+    for _ in 0..cell_num {
+        let Some(pointer) = page_after_fh.get(i..i + 2) else {
+            bail!(SqliteError::CorruptDatabase(format!(
+                "{cell_num} cell pointers run past the end of page {}",
+                idx + 1
+            )));
+        };
+        cell_offsets.push(u16::from_be_bytes(pointer.try_into().unwrap()));
+        i += 2;
+    }
+
+    validate_cell_offsets(idx, &page, page_type, &cell_offsets, page.len())?;
+
+    let p = Page {
+        number: idx + 1,
+        page_type,
+        freeblock_start,
+        cell_num,
+        cell_content_area,
+        fragmented,
+        cell_offsets,
+        page,
+        right,
+    };
+    Ok(p)
+}
+
+// validate_cell_offsets checks the cell pointers of the b-tree page at the
+// 0-based `idx` before anything follows them, like sqlite3 does: each cell
+// starts past the page header and the pointer array, ends within the first
+// `usable_size` bytes, and overlaps no other cell.
+fn validate_cell_offsets(
+    idx: usize,
+    page: &[u8],
+    page_type: u8,
+    offsets: &[u16],
+    usable_size: usize,
+) -> Result<()> {
+    let corrupt =
+        |what: String| SqliteError::CorruptDatabase(format!("cell {what} of page {}", idx + 1));
+    let header = if idx == 0 { 100 } else { 0 };
+    let header_end = header
+        + if matches!(page_type, 0x0d | 0x0a) {
+            8
+        } else {
+            12
+        };
+    let pointers_end = header_end + 2 * offsets.len();
+    let mut cells = Vec::with_capacity(offsets.len());
+    for (i, &offset) in offsets.iter().enumerate() {
+        let start = offset as usize;
+        if start < header_end {
+            bail!(corrupt(format!("{i} starts at {start}, in the header")));
+        }
+        if start < pointers_end {
+            bail!(corrupt(format!(
+                "{i} starts at {start}, in the cell pointer array"
+            )));
+        }
+        if start >= usable_size {
+            bail!(corrupt(format!("{i} starts at {start}, past the end")));
+        }
+        let end = cell_size(&page[start..usable_size], page_type, usable_size)
+            .map(|size| start + size)
+            .filter(|&end| end <= usable_size);
+        let Some(end) = end else {
+            bail!(corrupt(format!("{i} at {start} runs past the end")));
+        };
+        cells.push((start, end, i));
+    }
+    cells.sort_unstable();
+    for pair in cells.windows(2) {
+        let ((_, end, a), (start, _, b)) = (pair[0], pair[1]);
+        if start < end {
+            bail!(SqliteError::CorruptDatabase(format!(
+                "cells {a} and {b} of page {} overlap",
+                idx + 1
+            )));
+        }
+    }
+    Ok(())
+}
+
+// cell_size is the number of bytes a cell takes on its page: its header,
+// the payload kept locally, and the first overflow page's number when the
+// rest spills. None when the cell runs past `cell`.
+fn cell_size(cell: &[u8], page_type: u8, usable_size: usize) -> Option<usize> {
+    let u = usable_size;
+    let mut i = 0;
+    if matches!(page_type, 0x05 | 0x02) {
+        i += 4;
+    }
+    if page_type == 0x05 {
+        let (_, n) = decode_varint(cell.get(i..)?).ok()?;
+        return Some(i + n);
+    }
+    let (size, n) = decode_varint(cell.get(i..)?).ok()?;
+    i += n;
+    if page_type == 0x0d {
+        i += decode_varint(cell.get(i..)?).ok()?.1;
+    }
+    let max_local = match page_type {
+        0x0d => u - 35,
+        _ => ((u - 12) * 64 / 255) - 23,
+    };
+    let size = usize::try_from(size).ok()?;
+    let local = crate::btree::local_size(size, max_local, u);
+    let overflow = if size > max_local { 4 } else { 0 };
+    Some(i + local + overflow)
+}
+
+/// Counts the work done by a statement, for `--trace` and for tests asserting
+/// how much of the file a query touched.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Pages read from the file, overflow pages included.
+    pub pages_read: usize,
+    /// B-tree cells decoded, on the way down as well as in the leaves.
+    pub cells_decoded: usize,
+    /// Rows that passed the WHERE clause.
+    pub rows_matched: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pages read, {} cells decoded, {} rows matched",
+            self.pages_read, self.cells_decoded, self.rows_matched
+        )
+    }
+}
+
+// Pager is the only way b-tree code reads pages, so every access is counted
+// and, with --trace, logged to stderr.
+#[derive(Debug)]
+pub struct Pager {
+    source: Source,
+    dbinfo: DBInfo,
+    offset: u64,           // where the database starts in the source
+    path: Option<PathBuf>, // the file, when writes can go to it
+    trace: Cell<bool>,
+    trim_nul: Cell<bool>, // TEXT values lose trailing NULs
+    stats: Cell<Stats>,
+    // pages written and not committed yet, by 0-based index
+    dirty: RefCell<BTreeMap<usize, Vec<u8>>>,
+    // pages committed since the source was opened: the file they went to
+    // replaced the one the source still reads
+    committed: RefCell<BTreeMap<usize, Vec<u8>>>,
+    in_transaction: Cell<bool>,
+}
+
+// Source is where the pages come from.
+#[derive(Debug)]
+enum Source {
+    File(File),
+    // the whole database in memory, e.g. read from a pipe
+    Bytes(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Pager {
+    // open reads the header of `file` and pages through it.
+    pub fn open(file: File) -> Result<Self> {
+        Self::open_at(file, 0)
+    }
+
+    // open_at pages through a database embedded in `file`, `offset` bytes in:
+    // every read is shifted by it, the header's included.
+    pub fn open_at(mut file: File, offset: u64) -> Result<Self> {
+        let dbinfo = parse_dbinfo(&mut file, offset)?;
+        Ok(Self::with_source(Source::File(file), dbinfo, offset))
+    }
+
+    // from_bytes reads pages out of a database image held in memory.
+    pub fn from_bytes(bytes: Vec<u8>, dbinfo: DBInfo) -> Self {
+        Self::with_source(Source::Bytes(bytes), dbinfo, 0)
+    }
+
+    fn with_source(source: Source, dbinfo: DBInfo, offset: u64) -> Self {
+        Pager {
+            source,
+            dbinfo,
+            offset,
+            path: None,
+            trace: Cell::new(false),
+            trim_nul: Cell::new(false),
+            stats: Cell::new(Stats::default()),
+            dirty: RefCell::new(BTreeMap::new()),
+            committed: RefCell::new(BTreeMap::new()),
+            in_transaction: Cell::new(false),
+        }
+    }
+
+    // map_file memory-maps the database, so pages are borrowed from the mapping
+    // instead of being read into a buffer each time.
+    #[cfg(feature = "mmap")]
+    pub fn map_file(&mut self) -> Result<()> {
+        if let Source::File(file) = &self.source {
+            // SAFETY: the database is opened read-only and we never write to it; like
+            // sqlite3 with mmap_size set, another process truncating it under us is
+            // undefined behaviour we accept.
+            let map = unsafe { memmap2::Mmap::map(file)? };
+            self.source = Source::Mapped(map);
+        }
+        Ok(())
+    }
+
+    // set_path names the file the pages come from, which write_page opens
+    // for writing. Without one the database can't be written.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+
+    // write_page replaces the page at the 0-based `idx` with `page`, in
+    // memory until the write is committed.
+    pub fn write_page(&self, idx: usize, page: &[u8]) -> Result<()> {
+        debug!("write page {}", idx + 1);
+        self.write_at((idx * self.dbinfo.page_size as usize) as u64, page)
+    }
+
+    // commit ends a statement's writes: outside a transaction they go to the
+    // file at once, inside one they wait for commit_transaction.
+    pub fn commit(&self) -> Result<()> {
+        if self.in_transaction.get() {
+            return Ok(());
+        }
+        self.flush()
+    }
+
+    // statement runs a statement's writes and commits them once it has
+    // succeeded, so they all take effect or, when it fails, none of them does.
+    pub fn statement<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let before = self.dirty.borrow().clone();
+        let res = f().and_then(|v| self.commit().map(|_| v));
+        if res.is_err() {
+            *self.dirty.borrow_mut() = before;
+        }
+        res
+    }
+
+    // begin starts a transaction: the writes of the statements that follow
+    // are kept in memory, where reads see them, until commit_transaction.
+    pub fn begin(&self) -> Result<()> {
+        if self.in_transaction.replace(true) {
+            bail!(SqliteError::Usage(
+                "cannot start a transaction within a transaction".to_string()
+            ));
+        }
+        Ok(())
+    }
+
+    // commit_transaction writes the transaction's pages to the file. If that
+    // fails the file is as it was and the transaction stays open.
+    pub fn commit_transaction(&self) -> Result<()> {
+        if !self.in_transaction.get() {
+            bail!(SqliteError::Usage(
+                "cannot commit - no transaction is active".to_string()
+            ));
+        }
+        self.flush()?;
+        self.in_transaction.set(false);
+        Ok(())
+    }
+
+    // rollback ends the transaction, forgetting its writes.
+    pub fn rollback(&self) -> Result<()> {
+        if !self.in_transaction.replace(false) {
+            bail!(SqliteError::Usage(
+                "cannot rollback - no transaction is active".to_string()
+            ));
+        }
+        self.dirty.borrow_mut().clear();
+        Ok(())
+    }
+
+    // flush commits the written pages the only way a crash can't leave the
+    // file half written: the whole new file goes to `<path>.tmp`, which is
+    // synced and renamed over the database. The change counter goes up, and
+    // the page count is stored as valid for the new counter, as sqlite3 does
+    // on every commit.
+    //
+    // The source keeps reading the replaced file, so the committed pages
+    // stay in memory in front of it.
+    fn flush(&self) -> Result<()> {
+        if self.dirty.borrow().is_empty() {
+            return Ok(());
+        }
+        let Some(path) = &self.path else {
+            bail!(read_only());
+        };
+        let header = self.bytes(0)?;
+        let counter = u32::from_be_bytes(header[24..28].try_into().unwrap()).wrapping_add(1);
+        drop(header);
+        let pages = self.page_count()?;
+        let before = self.dirty.borrow().get(&0).cloned();
+        self.write_at(24, &counter.to_be_bytes())?;
+        self.write_at(28, &(pages as u32).to_be_bytes())?;
+        self.write_at(92, &counter.to_be_bytes())?;
+        if let Err(e) = self.replace_file(path, pages) {
+            // the header is bumped again on the next try
+            match before {
+                Some(page) => self.dirty.borrow_mut().insert(0, page),
+                None => self.dirty.borrow_mut().remove(&0),
+            };
+            return Err(e);
+        }
+        let dirty = std::mem::take(&mut *self.dirty.borrow_mut());
+        self.committed.borrow_mut().extend(dirty);
+        Ok(())
+    }
+
+    fn replace_file(&self, path: &std::path::Path, pages: usize) -> Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let stored = self.file_pages()?;
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.set_permissions(std::fs::metadata(path)?.permissions())?;
+        for idx in 0..pages {
+            match self.bytes(idx) {
+                Ok(page) => tmp.write_all(&page)?,
+                // the lock-byte page is never allocated, a page past the
+                // end of the file that isn't written is zeros
+                Err(_) if idx >= stored => {
+                    tmp.write_all(&vec![0; self.dbinfo.page_size as usize])?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        tmp.sync_all()?;
+        drop(tmp);
+        std::fs::rename(&tmp_path, path)?;
+        // the rename itself is durable once the directory is synced
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                dir
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    // page_count is the number of pages of the database being written, which
+    // a page written past the end of the file adds to.
+    pub fn page_count(&self) -> Result<usize> {
+        let written = [&self.dirty, &self.committed]
+            .iter()
+            .filter_map(|pages| pages.borrow().last_key_value().map(|(&idx, _)| idx + 1))
+            .max()
+            .unwrap_or(0);
+        Ok(self.file_pages()?.max(written))
+    }
+
+    // file_pages is the number of pages the file holds.
+    fn file_pages(&self) -> Result<usize> {
+        let len = match &self.path {
+            Some(path) => std::fs::metadata(path)?.len().saturating_sub(self.offset),
+            None => 0,
+        };
+        Ok((len / self.dbinfo.page_size as u64) as usize)
+    }
+
+    // write_header stores `value` as the 4-byte field of the database header
+    // at `offset`.
+    pub fn write_header(&self, offset: usize, value: u32) -> Result<()> {
+        self.write_at(offset as u64, &value.to_be_bytes())
+    }
+
+    // reload_header reads the database header again, once a statement has
+    // changed it.
+    pub fn reload_header(&mut self) -> Result<()> {
+        let page = self.bytes(0)?.into_owned();
+        self.dbinfo = parse_dbinfo(&mut std::io::Cursor::new(page), 0)?;
+        Ok(())
+    }
+
+    // write_at stages `bytes` at `pos`, within a single page.
+    fn write_at(&self, pos: u64, bytes: &[u8]) -> Result<()> {
+        if self.path.is_none() {
+            bail!(read_only());
+        }
+        let page_size = self.dbinfo.page_size as usize;
+        let (idx, start) = (pos as usize / page_size, pos as usize % page_size);
+        let mut page = match self.bytes(idx) {
+            Ok(page) => page.into_owned(),
+            // a page past the end of the file
+            Err(_) => vec![0; page_size],
+        };
+        page[start..start + bytes.len()].copy_from_slice(bytes);
+        self.dirty.borrow_mut().insert(idx, page);
+        Ok(())
+    }
+
+    pub fn dbinfo(&self) -> DBInfo {
+        self.dbinfo
+    }
+
+    pub fn set_trace(&self, on: bool) {
+        self.trace.set(on);
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.trace.get()
+    }
+
+    pub fn set_trim_nul(&self, on: bool) {
+        self.trim_nul.set(on);
+    }
+
+    pub fn trims_nul(&self) -> bool {
+        self.trim_nul.get()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    // reset_stats starts counting a new statement.
+    pub fn reset_stats(&self) {
+        self.stats.set(Stats::default());
+    }
+
+    // page reads the page at the 0-based `idx`, overflow pages have no b-tree header.
+    pub fn page(&self, idx: usize, overflow: bool) -> Result<Page<'_>> {
+        let p = parse_page(idx, self.bytes(idx)?, overflow)?;
+        self.update(|s| s.pages_read += 1);
+        let what = if overflow {
+            format!("page {} (overflow)", idx + 1)
+        } else {
+            format!(
+                "page {} ({}, {} cells)",
+                idx + 1,
+                page_type_name(p.page_type),
+                p.cell_num
+            )
+        };
+        debug!("read {what}");
+        if self.tracing() {
+            eprintln!("trace: {what}");
+        }
+        Ok(p)
+    }
+
+    fn bytes(&self, idx: usize) -> Result<Cow<'_, [u8]>> {
+        for pages in [&self.dirty, &self.committed] {
+            if let Some(page) = pages.borrow().get(&idx) {
+                return Ok(Cow::Owned(page.clone()));
+            }
+        }
+        let image: &[u8] = match &self.source {
+            Source::File(file) => {
+                let mut file: &File = file;
+                return Ok(Cow::Owned(read_page(
+                    idx,
+                    &mut file,
+                    &self.dbinfo,
+                    self.offset,
+                )?));
+            }
+            Source::Bytes(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => map,
+        };
+        let page_size = self.dbinfo.page_size as usize;
+        let offset = self.offset as usize + idx * page_size;
+        let page = image
+            .get(offset..offset + page_size)
+            .ok_or_else(|| past_end_of_file(idx))?;
+        Ok(Cow::Borrowed(page))
+    }
+
+    pub fn cell_decoded(&self) {
+        self.update(|s| s.cells_decoded += 1);
+    }
+
+    pub fn rows_matched(&self, n: usize) {
+        self.update(|s| s.rows_matched += n);
+    }
+
+    // descend records a b-tree descent: the key compared at `page` and the child chosen.
+    pub fn descend(
+        &self,
+        page: usize,
+        key: &dyn fmt::Display,
+        target: &dyn fmt::Display,
+        child: usize,
+    ) {
+        debug!("descend page {page} key {key} vs {target} -> page {child}");
+        if self.tracing() {
+            eprintln!("trace: page {page} key {key} vs {target} -> page {child}");
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Stats)) {
+        let mut s = self.stats.get();
+        f(&mut s);
+        self.stats.set(s);
+    }
+}
+
+fn page_type_name(page_type: u8) -> &'static str {
+    match page_type {
+        0x02 => "index interior",
+        0x05 => "table interior",
+        0x0a => "index leaf",
+        0x0d => "table leaf",
+        _ => "unknown",
+    }
+}
+
+#[test]
+fn test_pager_counts_reads() {
+    let pager = Pager::open(File::open("sample.db").unwrap()).unwrap();
+    pager.page(0, false).unwrap();
+    pager.page(1, false).unwrap();
+    pager.cell_decoded();
+    assert_eq!(
+        pager.stats(),
+        Stats {
+            pages_read: 2,
+            cells_decoded: 1,
+            rows_matched: 0
+        }
+    );
+    pager.reset_stats();
+    assert_eq!(pager.stats(), Stats::default());
+}
+
+#[test]
+fn test_in_memory_pages_match_reads() {
+    let bytes = std::fs::read("sample.db").unwrap();
+    let db = parse_dbinfo(&mut std::io::Cursor::new(&bytes), 0).unwrap();
+    let pager = Pager::open(File::open("sample.db").unwrap()).unwrap();
+    assert_eq!(db.header, pager.dbinfo().header);
+    let memory = Pager::from_bytes(bytes, db);
+    for idx in 0..4 {
+        let (a, b) = (
+            memory.page(idx, false).unwrap(),
+            pager.page(idx, false).unwrap(),
+        );
+        assert!(matches!(a.page, Cow::Borrowed(_)));
+        assert_eq!(a.page, b.page);
+    }
+    assert!(memory.page(4, false).is_err());
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mapped_pages_match_reads() {
+    let mut mapped = Pager::open(File::open("sample.db").unwrap()).unwrap();
+    mapped.map_file().unwrap();
+    let pager = Pager::open(File::open("sample.db").unwrap()).unwrap();
+    for idx in 0..4 {
+        let (a, b) = (
+            mapped.page(idx, false).unwrap(),
+            pager.page(idx, false).unwrap(),
+        );
+        assert!(matches!(a.page, Cow::Borrowed(_)));
+        assert_eq!(a.page, b.page);
+        assert_eq!(a.cell_offsets, b.cell_offsets);
+    }
+    assert!(mapped.page(1000, false).is_err());
+}
+
+#[test]
+fn test_free_bytes_count_fragments() {
+    // a 512-byte table leaf whose content area starts with a 20-byte
+    // freeblock at 400, then a cell from 420 to the end, and that has 2
+    // fragmented bytes
+    let mut page = vec![0; 512];
+    page[0] = 0x0d;
+    page[1..3].copy_from_slice(&400u16.to_be_bytes());
+    page[3..5].copy_from_slice(&1u16.to_be_bytes());
+    page[5..7].copy_from_slice(&400u16.to_be_bytes());
+    page[7] = 2;
+    page[8..10].copy_from_slice(&420u16.to_be_bytes());
+    page[402..404].copy_from_slice(&20u16.to_be_bytes());
+    let p = parse_page(1, Cow::Owned(page.clone()), false).unwrap();
+    // 390 unallocated between the pointer and the content area
+    assert_eq!(p.free_bytes(), 390 + 20 + 2);
+    page[7] = 0;
+    let p = parse_page(1, Cow::Owned(page), false).unwrap();
+    assert_eq!(p.free_bytes(), 390 + 20);
+}
+
+#[test]
+fn test_validate_cell_offsets() {
+    // a 512-byte table leaf with two 5-byte cells, rowids 1 and 2 holding 7
+    let mut page = vec![0; 512];
+    page[0] = 0x0d;
+    page[507..].copy_from_slice(&[3, 1, 2, 1, 7]);
+    page[502..507].copy_from_slice(&[3, 2, 2, 1, 7]);
+    let check = |offsets: &[u16]| {
+        validate_cell_offsets(1, &page, 0x0d, offsets, 512).map_err(|e| e.to_string())
+    };
+    assert!(check(&[507, 502]).is_ok());
+    let err = |offsets: &[u16]| check(offsets).unwrap_err();
+    assert!(err(&[507, 4]).ends_with("cell 1 starts at 4, in the header of page 2"));
+    assert!(err(&[507, 10]).ends_with("cell 1 starts at 10, in the cell pointer array of page 2"));
+    assert!(err(&[512]).ends_with("cell 0 starts at 512, past the end of page 2"));
+    assert!(err(&[510]).ends_with("cell 0 at 510 runs past the end of page 2"));
+    assert!(err(&[507, 505]).ends_with("cells 1 and 0 of page 2 overlap"));
+    // an interior cell is its left child and a varint key
+    page[0] = 0x05;
+    assert!(validate_cell_offsets(1, &page, 0x05, &[505, 500], 512).is_ok());
+    // page 1 starts with the file header
+    assert!(validate_cell_offsets(0, &page, 0x0d, &[104], 512).is_err());
+}
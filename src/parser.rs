@@ -2,32 +2,77 @@ use anyhow::Result;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::expr::{self, Affinity, Expr};
+
 #[derive(Debug)]
 pub struct SelectStmt {
     pub columns: Vec<String>,
     pub table: String,
+    // the arguments when FROM calls a table-valued function, `table` naming it
+    pub args: Option<Vec<Expr>>,
+    // the WHERE clause as simple `col op value` terms joined by AND, for planning;
+    // empty when it's anything more complex
     pub conditions: Vec<Condition>,
+    pub filter: Option<Expr>,
+    // GROUP BY terms left to right, resolved against the result columns when
+    // the select is compiled
+    pub group_by: Vec<String>,
+    // ORDER BY terms left to right, each with whether it's descending
+    pub order_by: Vec<(String, bool)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Condition {
     pub column: String,
     pub op: String,
     pub value: String,
+    // the quote the value was written in, if any
+    pub quote: Option<char>,
+}
+
+impl Condition {
+    // is_literal tells whether the value is a constant an index can seek: a
+    // string, a number or a placeholder. A bare word names a column, and so
+    // does a double-quoted one when `columns` has it, as sqlite3 reads it.
+    pub(crate) fn is_literal(&self, columns: &[ColumnDef]) -> bool {
+        match self.quote {
+            Some('"') => !columns
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(&self.value)),
+            Some(_) => true,
+            None => self.value.starts_with('?') || self.value.parse::<f64>().is_ok(),
+        }
+    }
 }
 
 static SELECT_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?is)^\s*select\s+(?P<cols>.+?)\s+from\s+(?P<table>\w+)(?:\s+where\s+(?P<where>.+?))?\s*;?\s*$")
+    Regex::new(r"(?is)^\s*select\s+(?P<cols>.+?)\s+from\s+(?P<table>\w+)(?:\s*\((?P<args>.*?)\))?(?:\s+where\s+(?P<where>.+?))?(?:\s+group\s+by\s+(?P<group>.+?))?(?:\s+order\s+by\s+(?P<order>.+?))?\s*;?\s*$")
         .unwrap()
 });
 
 static COND_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
-        r#"(?is)^\s*(?P<col>\w+)\s*(?P<op>=|!=|<=|>=|<|>)\s*(?P<val>'[^']*'|"[^"]*"|\d+|\w+)\s*$"#,
+        r#"(?is)^\s*(?P<col>\w+)\s*(?P<op>=|!=|<=|>=|<|>|(?:not\s+)?(?:glob|regexp|like)\b)\s*(?P<val>'[^']*'|"[^"]*"|-?(?:\d+\.?\d*|\.\d+)(?:e[+-]?\d+)?|\w+|\?\d+)\s*$"#,
     )
     .unwrap()
 });
 
+static ORDER_TERM_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)^\s*(?P<term>.+?)(?:\s+(?P<dir>asc|desc))?\s*$").unwrap());
+
+static ALIAS_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)^(?P<expr>.+?)\s+as\s+(?P<alias>\w+|"[^"]+")$"#).unwrap());
+
+static EXPLAIN_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)^\s*explain\s+query\s+plan\s+(?P<stmt>.*)$").unwrap());
+
+// parse_explain_query_plan returns the statement behind an `EXPLAIN QUERY PLAN` prefix.
+pub fn parse_explain_query_plan(sql: &str) -> Option<&str> {
+    EXPLAIN_RE
+        .captures(sql)
+        .map(|caps| caps.name("stmt").unwrap().as_str())
+}
+
 pub fn parse_select(sql: &str) -> Result<SelectStmt, String> {
     let caps = SELECT_RE
         .captures(sql)
@@ -36,73 +81,575 @@ pub fn parse_select(sql: &str) -> Result<SelectStmt, String> {
     let cols_raw = caps.name("cols").unwrap().as_str();
     let table = caps.name("table").unwrap().as_str().to_string();
 
-    let columns = cols_raw
-        .split(',')
+    let columns = split_top_level(cols_raw, ',')
+        .into_iter()
         .map(|s| s.trim().to_string())
         .filter(|s| !s.is_empty())
         .collect::<Vec<_>>();
 
-    let mut conditions = Vec::new();
+    let (conditions, filter) = match caps.name("where") {
+        Some(where_part) => (
+            parse_conditions(where_part.as_str()).unwrap_or_default(),
+            Some(expr::parse_expr(where_part.as_str())?),
+        ),
+        None => (Vec::new(), None),
+    };
+    let group_by = match caps.name("group") {
+        Some(group) => split_top_level(group.as_str(), ',')
+            .into_iter()
+            .map(|term| match term.trim() {
+                "" => Err(format!("near \"{}\": syntax error", group.as_str().trim())),
+                term => Ok(term.to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    let order_by = match caps.name("order") {
+        Some(order) => parse_order_by(order.as_str())?,
+        None => Vec::new(),
+    };
+    let args = match caps.name("args") {
+        Some(args) => Some(
+            split_top_level(args.as_str(), ',')
+                .into_iter()
+                .filter(|a| !a.trim().is_empty())
+                .map(expr::parse_expr)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
 
-    if let Some(where_part) = caps.name("where") {
-        let where_raw = where_part.as_str();
+    Ok(SelectStmt {
+        columns,
+        table,
+        args,
+        conditions,
+        filter,
+        group_by,
+        order_by,
+    })
+}
 
-        for cond_str in where_raw.split(|c| c == 'A' || c == 'a') {
-            // NOTE: this is NOT correct splitting logic for general SQL
-            // We'll do a more controlled AND split below instead.
-        }
+// split_alias separates a result column from its `AS alias`, if it has one.
+pub fn split_alias(col: &str) -> (&str, Option<String>) {
+    match ALIAS_RE.captures(col) {
+        Some(caps) => (
+            caps.name("expr").unwrap().as_str(),
+            Some(unquote_ident(caps.name("alias").unwrap().as_str())),
+        ),
+        None => (col, None),
+    }
+}
 
-        for cond_str in where_raw.split(|_| false) {
-            let _ = cond_str;
+// parse_order_by splits `a ASC, b DESC` into its terms, ascending unless DESC.
+fn parse_order_by(s: &str) -> Result<Vec<(String, bool)>, String> {
+    split_top_level(s, ',')
+        .into_iter()
+        .map(|term| {
+            let caps = ORDER_TERM_RE
+                .captures(term)
+                .ok_or_else(|| format!("near \"{}\": syntax error", term.trim()))?;
+            let desc = caps
+                .name("dir")
+                .is_some_and(|d| d.as_str().eq_ignore_ascii_case("desc"));
+            Ok((caps.name("term").unwrap().as_str().to_string(), desc))
+        })
+        .collect()
+}
+
+// SetOp is the operator joining two selects of a compound statement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Intersect,
+    Except,
+}
+
+impl SetOp {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SetOp::Intersect => "INTERSECT",
+            SetOp::Except => "EXCEPT",
         }
+    }
+}
 
-        // Proper simple AND split:
-        let and_re = Regex::new(r"(?i)\s+and\s+").unwrap();
+// CompoundSelect is a select followed by the selects combined into it, applied
+// left to right like sqlite does.
+#[derive(Debug)]
+pub struct CompoundSelect {
+    pub first: SelectStmt,
+    pub rest: Vec<(SetOp, SelectStmt)>,
+    // ORDER BY after the last select sorts the whole compound
+    pub order_by: Vec<(String, bool)>,
+}
 
-        let parts = and_re
-            .split(where_raw)
-            .map(str::trim)
-            .filter(|s| !s.is_empty());
+impl CompoundSelect {
+    // selects iterates over every select, left to right.
+    pub fn selects(&self) -> impl Iterator<Item = &SelectStmt> {
+        std::iter::once(&self.first).chain(self.rest.iter().map(|(_, select)| select))
+    }
+}
 
-        for cond_str in parts {
-            let c = COND_RE
-                .captures(cond_str)
-                .ok_or_else(|| format!("Invalid condition: {cond_str}"))?;
+const SET_OPS: [(&str, SetOp); 2] = [("intersect", SetOp::Intersect), ("except", SetOp::Except)];
 
-            let mut val = c.name("val").unwrap().as_str().to_string();
+pub fn parse_compound(sql: &str) -> Result<CompoundSelect, String> {
+    let mut parts = split_set_ops(sql).into_iter();
+    let (_, first) = parts.next().unwrap();
+    let first = parse_select(first)?;
+    let mut rest = parts
+        .map(|(op, sql)| Ok((op.unwrap(), parse_select(sql)?)))
+        .collect::<Result<Vec<(SetOp, SelectStmt)>, String>>()?;
+    let order_by = match rest.last_mut() {
+        Some((_, last)) => std::mem::take(&mut last.order_by),
+        None => Vec::new(),
+    };
+    Ok(CompoundSelect {
+        first,
+        rest,
+        order_by,
+    })
+}
 
-            // remove quotes if string literal
-            if (val.starts_with('\'') && val.ends_with('\''))
-                || (val.starts_with('"') && val.ends_with('"'))
-            {
-                val = val[1..val.len() - 1].to_string();
+// split_set_ops splits a statement at the compound operators outside of
+// parentheses and quotes, pairing each part with the operator before it.
+fn split_set_ops(sql: &str) -> Vec<(Option<SetOp>, &str)> {
+    let mut parts = Vec::new();
+    let mut op = None;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    let mut skip_to = 0;
+    let bytes = sql.as_bytes();
+    for (i, c) in sql.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, _) if depth == 0 && (i == 0 || !is_word_byte(bytes[i - 1])) => {
+                for (word, set_op) in SET_OPS {
+                    let end = i + word.len();
+                    if sql
+                        .get(i..end)
+                        .is_some_and(|w| w.eq_ignore_ascii_case(word))
+                        && bytes.get(end).is_none_or(|b| !is_word_byte(*b))
+                    {
+                        parts.push((op, &sql[start..i]));
+                        op = Some(set_op);
+                        start = end;
+                        skip_to = end;
+                        break;
+                    }
+                }
             }
+            _ => {}
+        }
+    }
+    parts.push((op, &sql[start..]));
+    parts
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+// parse_conditions parses a WHERE clause made of simple comparisons joined by AND.
+fn parse_conditions(where_raw: &str) -> Result<Vec<Condition>, String> {
+    let mut conditions = Vec::new();
+    let parts = AND_RE
+        .split(where_raw)
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+
+    for cond_str in parts {
+        let c = COND_RE
+            .captures(cond_str)
+            .ok_or_else(|| format!("Invalid condition: {cond_str}"))?;
 
-            conditions.push(Condition {
-                column: c.name("col").unwrap().as_str().to_string(),
-                op: c.name("op").unwrap().as_str().to_string(),
-                value: val,
-            });
+        conditions.push(Condition {
+            column: c.name("col").unwrap().as_str().to_string(),
+            op: c
+                .name("op")
+                .unwrap()
+                .as_str()
+                .split_whitespace()
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>()
+                .join(" "),
+            value: unquote_literal(c.name("val").unwrap().as_str()),
+            quote: c
+                .name("val")
+                .unwrap()
+                .as_str()
+                .chars()
+                .next()
+                .filter(|q| matches!(q, '\'' | '"')),
+        });
+    }
+    Ok(conditions)
+}
+
+// unquote_literal removes the quotes of a string literal.
+fn unquote_literal(val: &str) -> String {
+    if val.len() >= 2
+        && ((val.starts_with('\'') && val.ends_with('\''))
+            || (val.starts_with('"') && val.ends_with('"')))
+    {
+        val[1..val.len() - 1].to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+#[allow(dead_code)] // executed once the pager can write
+#[derive(Debug)]
+pub enum DmlStmt {
+    Insert(InsertStmt),
+    Update(UpdateStmt),
+    Delete(DeleteStmt),
+}
+
+impl DmlStmt {
+    pub fn table(&self) -> &str {
+        match self {
+            DmlStmt::Insert(s) => &s.table,
+            DmlStmt::Update(s) => &s.table,
+            DmlStmt::Delete(s) => &s.table,
         }
     }
+}
 
-    Ok(SelectStmt {
-        columns,
-        table,
-        conditions,
-    })
+#[derive(Debug)]
+pub struct InsertStmt {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub values: Vec<Vec<String>>, // the SQL of each value's expression
+    pub on_conflict: Option<OnConflict>,
+    pub returning: Option<Vec<String>>,
+}
+
+// OnConflict is the UPSERT clause: `ON CONFLICT (target) DO NOTHING | DO UPDATE SET ...`.
+// In DO UPDATE, `excluded.col` refers to the value that would have been inserted.
+#[derive(Debug, PartialEq)]
+pub struct OnConflict {
+    pub target: Vec<String>,
+    pub action: ConflictAction,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConflictAction {
+    Nothing,
+    Update {
+        assignments: Vec<(String, String)>, // the SQL of each value's expression
+        filter: Option<Expr>,
+    },
+}
+
+#[derive(Debug)]
+pub struct UpdateStmt {
+    pub table: String,
+    pub assignments: Vec<(String, String)>, // the SQL of each value's expression
+    pub filter: Option<Expr>,
+    pub returning: Option<Vec<String>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct DeleteStmt {
+    pub table: String,
+    pub conditions: Vec<Condition>,
+    pub returning: Option<Vec<String>>,
+}
+
+static DML_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)^\s*(?:insert|replace|update|delete)\b").unwrap());
+
+static INSERT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*(?:insert(?:\s+or\s+\w+)?|replace)\s+into\s+(?P<table>"[^"]+"|\w+)\s*(?:\((?P<cols>[^)]*)\))?\s*values\s*(?P<values>.*?)\s*;?\s*$"#,
+    )
+    .unwrap()
+});
+
+static UPDATE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*update\s+(?:or\s+\w+\s+)?(?P<table>"[^"]+"|\w+)\s+set\s+(?P<set>.+?)\s*;?\s*$"#,
+    )
+    .unwrap()
+});
+
+static DELETE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*delete\s+from\s+(?P<table>"[^"]+"|\w+)(?:\s+where\s+(?P<where>.+?))?\s*;?\s*$"#,
+    )
+    .unwrap()
+});
+
+// ON_CONFLICT_RE matches what follows the ON CONFLICT of an UPSERT.
+static ON_CONFLICT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?is)^\s*(?:\((?P<target>[^)]*)\))?\s*do\s+(?:(?P<nothing>nothing)|update\s+set\s+(?P<set>.+?))\s*;?\s*$",
+    )
+    .unwrap()
+});
+
+static AND_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\s+and\s+").unwrap());
+
+// parse_assignments parses the `col = value, ...` list of a SET clause,
+// keeping the SQL of each value's expression.
+fn parse_assignments(set: &str) -> Result<Vec<(String, String)>, String> {
+    split_top_level(set, ',')
+        .into_iter()
+        .map(|a| {
+            let (col, val) = a
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid assignment: {}", a.trim()))?;
+            Ok((unquote_ident(col.trim()), val.trim().to_string()))
+        })
+        .collect()
+}
+
+// is_dml tells whether a statement modifies the database.
+pub fn is_dml(sql: &str) -> bool {
+    DML_RE.is_match(sql)
+}
+
+// parse_dml parses INSERT, UPDATE and DELETE, including a trailing `RETURNING col, ...`.
+pub fn parse_dml(sql: &str) -> Result<DmlStmt, String> {
+    let (body, returning) = match find_keyword(sql, "returning") {
+        Some((start, end)) => {
+            let cols = sql[end..].trim().trim_end_matches(';');
+            let cols = split_top_level(cols, ',')
+                .into_iter()
+                .map(|c| c.trim().to_string())
+                .collect::<Vec<_>>();
+            (&sql[..start], Some(cols))
+        }
+        None => (sql, None),
+    };
+
+    let upsert = find_keyword(body, "on conflict").map(|(start, end)| {
+        let caps = ON_CONFLICT_RE.captures(&body[end..]);
+        (&body[..start], caps)
+    });
+    let (body, on_conflict) = match upsert {
+        Some((_, None)) => return Err("Invalid ON CONFLICT clause".to_string()),
+        Some((body, Some(caps))) => {
+            let target = caps
+                .name("target")
+                .map(|t| {
+                    split_top_level(t.as_str(), ',')
+                        .into_iter()
+                        .map(|c| unquote_ident(c.trim()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let action = match caps.name("set") {
+                None => ConflictAction::Nothing,
+                Some(set) => {
+                    let set = set.as_str();
+                    let (set, filter) = match find_keyword(set, "where") {
+                        Some((start, end)) => (&set[..start], Some(expr::parse_expr(&set[end..])?)),
+                        None => (set, None),
+                    };
+                    ConflictAction::Update {
+                        assignments: parse_assignments(set)?,
+                        filter,
+                    }
+                }
+            };
+            (body, Some(OnConflict { target, action }))
+        }
+        None => (body, None),
+    };
+
+    if let Some(caps) = INSERT_RE.captures(body) {
+        let columns = caps
+            .name("cols")
+            .map(|c| {
+                split_top_level(c.as_str(), ',')
+                    .into_iter()
+                    .map(|c| unquote_ident(c.trim()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut values = Vec::new();
+        for row in split_top_level(caps.name("values").unwrap().as_str(), ',') {
+            let row = row
+                .trim()
+                .strip_prefix('(')
+                .and_then(|r| r.strip_suffix(')'))
+                .ok_or_else(|| format!("Invalid VALUES row: {}", row.trim()))?;
+            values.push(
+                split_top_level(row, ',')
+                    .into_iter()
+                    .map(|v| v.trim().to_string())
+                    .collect(),
+            );
+        }
+        return Ok(DmlStmt::Insert(InsertStmt {
+            table: unquote_ident(caps.name("table").unwrap().as_str()),
+            columns,
+            values,
+            on_conflict,
+            returning,
+        }));
+    }
+
+    if let Some(caps) = UPDATE_RE.captures(body) {
+        if on_conflict.is_some() {
+            return Err("ON CONFLICT is only allowed on INSERT".to_string());
+        }
+        // a WHERE inside a quoted value doesn't start the clause
+        let set = caps.name("set").unwrap().as_str();
+        let (set, filter) = match find_keyword(set, "where") {
+            Some((start, end)) => (&set[..start], Some(expr::parse_expr(&set[end..])?)),
+            None => (set, None),
+        };
+        let assignments = parse_assignments(set)?;
+        return Ok(DmlStmt::Update(UpdateStmt {
+            table: unquote_ident(caps.name("table").unwrap().as_str()),
+            assignments,
+            filter,
+            returning,
+        }));
+    }
+
+    if let Some(caps) = DELETE_RE.captures(body)
+        && on_conflict.is_none()
+    {
+        let conditions = match caps.name("where") {
+            Some(w) => parse_conditions(w.as_str())?,
+            None => Vec::new(),
+        };
+        return Ok(DmlStmt::Delete(DeleteStmt {
+            table: unquote_ident(caps.name("table").unwrap().as_str()),
+            conditions,
+            returning,
+        }));
+    }
+
+    Err("Invalid INSERT, UPDATE or DELETE statement".to_string())
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct CreateTableStmt {
     pub table: String,
     pub columns: Vec<ColumnDef>,
+    // rows are kept in an index b-tree keyed by the primary key
+    pub without_rowid: bool,
+    // every column declares one of STRICT_TYPES, and values must have it
+    pub strict: bool,
+    // the INTEGER PRIMARY KEY never reuses a rowid, sqlite_sequence keeps
+    // the largest one it had
+    pub autoincrement: bool,
+    // a virtual table using fts5, whose columns start with the rowid of its
+    // content table
+    pub fts5: Option<Fts5>,
+    // a virtual table using rtree or rtree_i32
+    pub rtree: Option<Rtree>,
+}
+
+// Fts5 is what a CREATE VIRTUAL TABLE ... USING fts5(...) says beyond its
+// column names.
+#[derive(Debug, Default, Clone)]
+pub struct Fts5 {
+    // the columns MATCH searches, all but the UNINDEXED ones
+    pub indexed: Vec<String>,
+    // the content= option, a table other than `{table}_content` that holds
+    // the text, or none at all when it's empty
+    pub content: Option<String>,
+}
+
+// Rtree is what a CREATE VIRTUAL TABLE ... USING rtree(...) says beyond its
+// column names: the id, then a min and max coordinate per dimension, then
+// the auxiliary columns declared with a leading `+`.
+#[derive(Debug, Default, Clone)]
+pub struct Rtree {
+    // rtree_i32 keeps coordinates as 32-bit integers, rtree as 32-bit floats
+    pub int32: bool,
+    pub coordinates: usize,
+}
+
+impl CreateTableStmt {
+    // declared_columns are the columns of the CREATE statement, what `*`
+    // stands for: all but the rowid an FTS5 table's records start with.
+    pub fn declared_columns(&self) -> &[ColumnDef] {
+        &self.columns[self.fts5.is_some() as usize..]
+    }
+
+    // rowid_column returns the INTEGER PRIMARY KEY column, which is an alias for the rowid.
+    pub fn rowid_column(&self) -> Option<&str> {
+        let mut keys = self.columns.iter().filter(|c| c.primary_key);
+        match (keys.next(), keys.next()) {
+            (Some(c), None)
+                if !self.without_rowid
+                    && c.ty
+                        .as_deref()
+                        .is_some_and(|ty| ty.eq_ignore_ascii_case("integer")) =>
+            {
+                Some(c.name.as_str())
+            }
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// A column as its table's CREATE TABLE statement declares it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColumnDef {
+    /// The column's name, without the quotes it may be declared with.
     pub name: String,
+    /// The declared type as written, e.g. `VARCHAR(10)`, if there is one.
     pub ty: Option<String>,
+    /// Part of the primary key, declared on the column or by a PRIMARY KEY
+    /// table constraint.
+    pub primary_key: bool,
+    /// Declared NOT NULL.
+    pub not_null: bool,
+    /// The SQL text of the DEFAULT value, without the parentheses around an
+    /// expression.
+    pub default: Option<String>,
+    /// The collation a COLLATE clause names, lowercased, e.g. `nocase`, if
+    /// there is one. Without one it's `binary`.
+    pub collation: Option<String>,
+}
+
+impl ColumnDef {
+    // real_affinity tells whether the declared type gives the column REAL affinity,
+    // whose integer-valued records are stored as integers but read back as REAL.
+    pub(crate) fn real_affinity(&self) -> bool {
+        let ty = self.ty.as_deref().unwrap_or_default().to_ascii_uppercase();
+        !ty.contains("INT") && ["REAL", "FLOA", "DOUB"].iter().any(|t| ty.contains(t))
+    }
+
+    // affinity is the affinity the declared type gives the column, by the
+    // rules of https://www.sqlite.org/datatype3.html#determination_of_column_affinity.
+    pub(crate) fn affinity(&self) -> Affinity {
+        let ty = self.ty.as_deref().unwrap_or_default().to_ascii_uppercase();
+        if ty.contains("INT") {
+            Affinity::Numeric
+        } else if self.text_affinity() {
+            Affinity::Text
+        } else if ty.is_empty() || ty.contains("BLOB") {
+            Affinity::Blob
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    // text_affinity tells whether the declared type gives the column TEXT
+    // affinity, which stores numbers as text.
+    pub(crate) fn text_affinity(&self) -> bool {
+        let ty = self.ty.as_deref().unwrap_or_default().to_ascii_uppercase();
+        !ty.contains("INT") && ["CHAR", "CLOB", "TEXT"].iter().any(|t| ty.contains(t))
+    }
 }
 
 // static CREATE_RE: Lazy<Regex> = Lazy::new(|| {
@@ -120,7 +667,7 @@ pub struct ColumnDef {
 
 static CREATE_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
-        r#"(?is)^\s*create\s+table\s+(?P<table>"[^"]+"|\w+)\s*\(\s*(?P<body>.*?)\s*\)\s*;?\s*$"#,
+        r#"(?is)^\s*create\s+table\s+(?:if\s+not\s+exists\s+)?(?P<table>"[^"]+"|'[^']+'|\w+)\s*\(\s*(?P<body>.*?)\s*\)(?P<options>(?:\s*,?\s*(?:without\s+rowid|strict))*)\s*;?\s*$"#,
     )
     .unwrap()
 });
@@ -129,10 +676,48 @@ static CREATE_RE: Lazy<Regex> = Lazy::new(|| {
 //     Lazy::new(|| Regex::new(r"(?is)^\s*(?P<name>\w+)(?:\s+(?P<ty>\w+))?").unwrap());
 
 static COL_RE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"^\s*(?P<name>"[^"]+"|\w+)(?:\s+(?P<ty>\w+))?"#).unwrap());
+    Lazy::new(|| Regex::new(r#"(?s)^\s*(?P<name>"[^"]+"|\w+)(?P<rest>.*)$"#).unwrap());
 
+// TABLE_CONSTRAINT_RE matches the parts of a CREATE TABLE body that aren't
+// columns, capturing the columns of a PRIMARY KEY.
+static TABLE_CONSTRAINT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*(?:constraint\s+(?:"[^"]+"|\w+)\s+)?(?:primary\s+key\s*\((?P<pk>.*)\)|unique\b|check\b|foreign\s+key\b)"#,
+    )
+    .unwrap()
+});
+
+static AUTOINCREMENT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bautoincrement\b").unwrap());
+static NOT_NULL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bnot\s+null\b").unwrap());
+
+static DEFAULT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bdefault\s+").unwrap());
+
+// COLUMN_CONSTRAINTS are the words that end a column's declared type.
+const COLUMN_CONSTRAINTS: [&str; 13] = [
+    "constraint",
+    "primary",
+    "not",
+    "null",
+    "unique",
+    "check",
+    "default",
+    "collate",
+    "references",
+    "generated",
+    "as",
+    "autoincrement",
+    "on",
+];
+
+static COLLATE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bcollate\s+(\w+)").unwrap());
+
+static PRIMARY_KEY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bprimary\s+key\b").unwrap());
+
+// unquote_ident strips the quotes around a name, double or, as sqlite3 still
+// accepts and FTS5 writes for its shadow tables, single.
 fn unquote_ident(s: &str) -> String {
-    if s.starts_with('"') && s.ends_with('"') {
+    let quoted = |q| s.len() >= 2 && s.starts_with(q) && s.ends_with(q);
+    if quoted('"') || quoted('\'') {
         s[1..s.len() - 1].to_string()
     } else {
         s.to_string()
@@ -146,38 +731,350 @@ pub fn parse_create(sql: &str) -> Result<CreateTableStmt, String> {
 
     let table = unquote_ident(caps.name("table").unwrap().as_str());
     let body = caps.name("body").unwrap().as_str();
+    let options = caps["options"].to_ascii_lowercase();
+    let without_rowid = options.contains("rowid");
+    let strict = options.contains("strict");
+    let autoincrement = AUTOINCREMENT_RE.is_match(body);
 
     let mut columns = Vec::new();
+    let mut primary_key = Vec::new();
 
-    for part in body.split(',') {
+    for part in split_top_level(body, ',') {
         let part = part.trim();
         if part.is_empty() {
             continue;
         }
+        if let Some(caps) = TABLE_CONSTRAINT_RE.captures(part) {
+            if let Some(pk) = caps.name("pk") {
+                for key in split_top_level(pk.as_str(), ',') {
+                    let key = INDEX_COL_RE
+                        .captures(key)
+                        .ok_or_else(|| format!("Invalid PRIMARY KEY column: {}", key.trim()))?;
+                    primary_key.push(unquote_ident(&key["name"]));
+                }
+            }
+            continue;
+        }
 
         let caps = COL_RE
             .captures(part)
             .ok_or_else(|| format!("Invalid column definition: {}", part))?;
 
-        let name = unquote_ident(caps.name("name").unwrap().as_str());
-        let ty = caps.name("ty").map(|m| m.as_str().to_string());
+        let rest = caps.name("rest").unwrap().as_str();
+        columns.push(ColumnDef {
+            name: unquote_ident(caps.name("name").unwrap().as_str()),
+            ty: column_type(rest),
+            primary_key: PRIMARY_KEY_RE.is_match(rest),
+            not_null: NOT_NULL_RE.is_match(rest),
+            default: DEFAULT_RE
+                .find(rest)
+                .map(|m| default_value(&rest[m.end()..])),
+            collation: COLLATE_RE.captures(rest).map(|c| c[1].to_ascii_lowercase()),
+        });
+    }
+    for key in primary_key {
+        let column = columns
+            .iter_mut()
+            .find(|c| c.name.eq_ignore_ascii_case(&key))
+            .ok_or_else(|| format!("no such column: {key}"))?;
+        column.primary_key = true;
+    }
+    if strict {
+        for c in &columns {
+            match &c.ty {
+                None => return Err(format!("missing datatype for {table}.{}", c.name)),
+                Some(ty) if !STRICT_TYPES.iter().any(|t| t.eq_ignore_ascii_case(ty)) => {
+                    return Err(format!("unknown datatype for {table}.{}: \"{ty}\"", c.name));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    Ok(CreateTableStmt {
+        table,
+        columns,
+        without_rowid,
+        strict,
+        autoincrement,
+        fts5: None,
+        rtree: None,
+    })
+}
+
+static NEW_TABLE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?is)^\s*create\s+(?P<temp>(?:temp|temporary)\s+)?table\s+(?P<exists>if\s+not\s+exists\s+)?(?P<rest>.*?)\s*;?\s*$",
+    )
+    .unwrap()
+});
+
+static UNIQUE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bunique\b").unwrap());
+
+// NewTable is a CREATE TABLE to run.
+#[derive(Debug)]
+pub struct NewTable {
+    pub stmt: CreateTableStmt,
+    pub temp: bool,
+    pub if_not_exists: bool,
+    // a UNIQUE constraint, on a column or the table
+    pub unique: bool,
+    // what sqlite_schema keeps, as sqlite3 does: `CREATE TABLE` and the
+    // statement from the table's name on
+    pub sql: String,
+}
+
+// parse_new_table returns the CREATE TABLE a statement is, None for any
+// other statement, CREATE VIRTUAL TABLE included.
+pub fn parse_new_table(sql: &str) -> Option<Result<NewTable, String>> {
+    let caps = NEW_TABLE_RE.captures(sql)?;
+    let sql = format!("CREATE TABLE {}", &caps["rest"]);
+    Some(parse_create(&sql).map(|stmt| NewTable {
+        stmt,
+        temp: caps.name("temp").is_some(),
+        if_not_exists: caps.name("exists").is_some(),
+        unique: UNIQUE_RE.is_match(&caps["rest"]),
+        sql,
+    }))
+}
+
+static CREATE_VIRTUAL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*create\s+virtual\s+table\s+(?:if\s+not\s+exists\s+)?(?P<table>"[^"]+"|'[^']+'|\w+)\s+using\s+(?P<module>\w+)\s*(?:\((?P<args>.*)\))?\s*;?\s*$"#,
+    )
+    .unwrap()
+});
+
+// is_create_virtual tells a CREATE VIRTUAL TABLE from a plain CREATE TABLE.
+pub fn is_create_virtual(sql: &str) -> bool {
+    CREATE_VIRTUAL_RE.is_match(sql)
+}
 
-        columns.push(ColumnDef { name, ty });
+// parse_create_virtual reads a CREATE VIRTUAL TABLE. Only fts5 and rtree
+// tables can be queried, None for any other module. An fts5 table's columns
+// mirror the records of its content table: the rowid, then a column per
+// argument that isn't an option like `tokenize = 'porter'`.
+pub fn parse_create_virtual(sql: &str) -> Result<Option<CreateTableStmt>, String> {
+    let caps = CREATE_VIRTUAL_RE
+        .captures(sql)
+        .ok_or("Invalid CREATE VIRTUAL TABLE statement")?;
+    let module = caps["module"].to_ascii_lowercase();
+    if module == "rtree" || module == "rtree_i32" {
+        return parse_rtree(&caps, module == "rtree_i32").map(Some);
+    }
+    if module != "fts5" {
+        return Ok(None);
     }
+    let table = unquote_ident(&caps["table"]);
+    let mut columns = vec![ColumnDef {
+        name: "rowid".to_string(),
+        ty: Some("INTEGER".to_string()),
+        primary_key: true,
+        not_null: false,
+        default: None,
+        collation: None,
+    }];
+    let mut fts5 = Fts5::default();
+    let args = caps.name("args").map_or("", |m| m.as_str());
+    for arg in split_top_level(args, ',') {
+        let arg = arg.trim();
+        if let Some((option, value)) = arg.split_once('=') {
+            if option.trim().eq_ignore_ascii_case("content") {
+                let value = value.trim().trim_matches(['\'', '"']);
+                fts5.content = Some(value.to_string());
+            }
+            continue;
+        }
+        let mut words = arg.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err("Invalid CREATE VIRTUAL TABLE statement".to_string());
+        };
+        let name = unquote_ident(name);
+        if !words.any(|w| w.eq_ignore_ascii_case("unindexed")) {
+            fts5.indexed.push(name.clone());
+        }
+        columns.push(ColumnDef {
+            name,
+            ty: None,
+            primary_key: false,
+            not_null: false,
+            default: None,
+            collation: None,
+        });
+    }
+    Ok(Some(CreateTableStmt {
+        table,
+        columns,
+        without_rowid: false,
+        strict: false,
+        autoincrement: false,
+        fts5: Some(fts5),
+        rtree: None,
+    }))
+}
 
-    Ok(CreateTableStmt { table, columns })
+// parse_rtree reads the arguments of an rtree virtual table: the id column,
+// then 2 to 10 coordinates in min/max pairs, then the `+` auxiliary columns.
+fn parse_rtree(caps: &regex::Captures, int32: bool) -> Result<CreateTableStmt, String> {
+    let table = unquote_ident(&caps["table"]);
+    let args = caps.name("args").map_or("", |m| m.as_str());
+    let mut columns = Vec::new();
+    let mut coordinates = 0;
+    for arg in split_top_level(args, ',') {
+        let mut words = arg.split_whitespace();
+        let Some(name) = words.next() else {
+            return Err("Invalid CREATE VIRTUAL TABLE statement".to_string());
+        };
+        let aux = name.starts_with('+');
+        if !aux && !columns.is_empty() {
+            if columns.len() > coordinates + 1 {
+                return Err("Auxiliary rtree columns must be last".to_string());
+            }
+            coordinates += 1;
+        }
+        columns.push(ColumnDef {
+            name: unquote_ident(name.trim_start_matches('+')),
+            ty: None,
+            primary_key: columns.is_empty(),
+            not_null: false,
+            default: None,
+            collation: None,
+        });
+    }
+    if !(2..=10).contains(&coordinates) || coordinates % 2 == 1 {
+        return Err(format!(
+            "Wrong number of columns for an rtree table: {}",
+            columns.len()
+        ));
+    }
+    // the id is the rowid
+    columns[0].ty = Some("INTEGER".to_string());
+    Ok(CreateTableStmt {
+        table,
+        columns,
+        without_rowid: false,
+        strict: false,
+        autoincrement: false,
+        fts5: None,
+        rtree: Some(Rtree { int32, coordinates }),
+    })
+}
+
+// STRICT_TYPES are the only column types a STRICT table may declare.
+const STRICT_TYPES: [&str; 6] = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+
+// column_type is the declared type at the start of a column definition: the
+// words before the first constraint, and a size like (10, 2) after them.
+fn column_type(rest: &str) -> Option<String> {
+    let mut words = Vec::new();
+    let mut s = rest.trim_start();
+    loop {
+        let len = s
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(s.len());
+        let word = &s[..len];
+        if word.is_empty() || COLUMN_CONSTRAINTS.contains(&word.to_ascii_lowercase().as_str()) {
+            break;
+        }
+        words.push(word);
+        s = s[len..].trim_start();
+    }
+    if words.is_empty() {
+        return None;
+    }
+    let mut ty = words.join(" ");
+    if s.starts_with('(')
+        && let Some(end) = s.find(')')
+    {
+        ty.push_str(&s[..=end]);
+    }
+    Some(ty)
+}
+
+// default_value takes the value after DEFAULT: a parenthesized expression, a
+// string, or a possibly signed number or keyword.
+fn default_value(s: &str) -> String {
+    let s = s.trim_start();
+    let end = match s.chars().next() {
+        Some('(' | '\'') => split_top_level(s, ' ')[0].len(),
+        _ => s
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| !c.is_alphanumeric() && !matches!(c, '_' | '.'))
+            .map_or(s.len(), |(i, _)| i),
+    };
+    let value = &s[..end];
+    match value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        Some(expr) => expr.trim().to_string(),
+        None => value.to_string(),
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct CreateIndexStmt {
     pub name: String,
     pub table: String,
+    // plain column names, or the raw text of expression keys
     pub columns: Vec<String>,
+    // indexes on expressions like `a+b` or `lower(name)` can't serve column lookups
+    pub expression: bool,
+    // some key is DESC, which only changes the order on disk from schema format 4
+    pub desc: bool,
+    // whether each key is DESC, in the order of `columns`
+    pub descending: Vec<bool>,
+    // the collation each key names, lowercased, None for a key that sorts
+    // by its column's
+    pub collations: Vec<Option<String>>,
+    pub unique: bool,
+    // the WHERE clause of a partial index, which only holds the rows it's
+    // true for
+    pub predicate: Option<String>,
+    // the predicate as simple comparisons joined by AND, empty when there's
+    // none or it's anything more complex
+    pub predicate_conditions: Vec<Condition>,
+}
+
+impl CreateIndexStmt {
+    // sorts_binary tells whether key `i` of the index on `t` sorts by BINARY,
+    // the collation values are compared by here, as its column does: an
+    // index sorted otherwise can't be searched or walked for them.
+    pub(crate) fn sorts_binary(&self, i: usize, t: &CreateTableStmt) -> bool {
+        let column = t.columns.iter().find(|c| {
+            self.columns
+                .get(i)
+                .is_some_and(|k| c.name.eq_ignore_ascii_case(k))
+        });
+        let binary = |c: &Option<String>| c.as_deref().is_none_or(|c| c == "binary");
+        column.is_some_and(|c| binary(&c.collation)) && self.collations.get(i).is_some_and(binary)
+    }
+
+    // covers tells whether the index holds every row a query whose WHERE
+    // clause ANDs `conditions` can return: it isn't partial, or each term of
+    // its predicate is one of them.
+    pub(crate) fn covers(&self, conditions: &[Condition]) -> bool {
+        self.predicate.is_none()
+            || !self.predicate_conditions.is_empty()
+                && self.predicate_conditions.iter().all(|p| {
+                    conditions.iter().any(|c| {
+                        c.column.eq_ignore_ascii_case(&p.column)
+                            && c.op == p.op
+                            && c.value == p.value
+                            && c.quote == p.quote
+                    })
+                })
+    }
 }
 
 static CREATE_INDEX_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
-        r#"(?is)^\s*create\s+(?:unique\s+)?index\s+(?:if\s+not\s+exists\s+)?(?P<name>"[^"]+"|\w+)\s+on\s+(?P<table>"[^"]+"|\w+)\s*\(\s*(?P<cols>.*?)\s*\)\s*;?\s*$"#
+        r#"(?is)^\s*create\s+(?P<unique>unique\s+)?index\s+(?:if\s+not\s+exists\s+)?(?P<name>"[^"]+"|\w+)\s+on\s+(?P<table>"[^"]+"|\w+)\s*\(\s*(?P<cols>.*?)\s*\)(?:\s*where\s+(?P<where>.+?))?\s*;?\s*$"#
+    )
+    .unwrap()
+});
+
+static INDEX_COL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*(?P<name>"[^"]+"|\w+)(?:\s+collate\s+(?P<collation>\w+))?(?:\s+(?:asc|(?P<desc>desc)))?\s*$"#,
     )
     .unwrap()
 });
@@ -191,19 +1088,316 @@ pub fn parse_create_index(sql: &str) -> Result<CreateIndexStmt, String> {
     let table = unquote_ident(caps.name("table").unwrap().as_str());
 
     let cols_raw = caps.name("cols").unwrap().as_str();
+    let predicate = caps.name("where").map(|w| w.as_str());
+    let predicate_conditions = predicate
+        .and_then(|w| parse_conditions(w).ok())
+        .unwrap_or_default();
 
-    let columns = cols_raw
-        .split(',')
-        .map(|c| unquote_ident(c.trim()))
+    let mut expression = false;
+    let mut collations = Vec::new();
+    let mut descending = Vec::new();
+    let columns = split_top_level(cols_raw, ',')
+        .into_iter()
+        .map(|c| match INDEX_COL_RE.captures(c) {
+            Some(caps) => {
+                descending.push(caps.name("desc").is_some());
+                collations.push(
+                    caps.name("collation")
+                        .map(|c| c.as_str().to_ascii_lowercase()),
+                );
+                unquote_ident(caps.name("name").unwrap().as_str())
+            }
+            None => {
+                // an expression key may end in its own DESC too
+                let words = c.trim().rsplit_once(char::is_whitespace);
+                descending.push(words.is_some_and(|(_, w)| w.eq_ignore_ascii_case("desc")));
+                collations.push(None);
+                expression = true;
+                c.trim().to_string()
+            }
+        })
         .collect::<Vec<_>>();
 
     Ok(CreateIndexStmt {
         name,
         table,
         columns,
+        expression,
+        desc: descending.contains(&true),
+        descending,
+        collations,
+        unique: caps.name("unique").is_some(),
+        predicate: predicate.map(str::to_string),
+        predicate_conditions,
     })
 }
 
+// TriggerDef is what the schema keeps of a CREATE TRIGGER: when it fires and
+// on which table. The body isn't parsed, triggers never run here.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TriggerDef {
+    pub name: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    pub table: String,
+    pub when: Option<String>, // the WHEN condition as written
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriggerTiming {
+    Before,
+    After,
+    InsteadOf,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TriggerEvent {
+    Insert,
+    // UPDATE OF columns, empty for an update of any column
+    Update(Vec<String>),
+    Delete,
+}
+
+impl TriggerDef {
+    // event_name describes when the trigger fires, e.g. `AFTER UPDATE OF a, b`.
+    pub fn event_name(&self) -> String {
+        let timing = match self.timing {
+            TriggerTiming::Before => "BEFORE",
+            TriggerTiming::After => "AFTER",
+            TriggerTiming::InsteadOf => "INSTEAD OF",
+        };
+        match &self.event {
+            TriggerEvent::Insert => format!("{timing} INSERT"),
+            TriggerEvent::Update(cols) if cols.is_empty() => format!("{timing} UPDATE"),
+            TriggerEvent::Update(cols) => format!("{timing} UPDATE OF {}", cols.join(", ")),
+            TriggerEvent::Delete => format!("{timing} DELETE"),
+        }
+    }
+}
+
+static CREATE_TRIGGER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*create\s+(?:temp\s+|temporary\s+)?trigger\s+(?:if\s+not\s+exists\s+)?(?P<name>"[^"]+"|\w+)\s+(?:(?P<timing>before|after|instead\s+of)\s+)?(?P<event>insert|delete|update)(?:\s+of\s+(?P<cols>.+?))?\s+on\s+(?P<table>"[^"]+"|\w+)(?:\s+for\s+each\s+row)?(?:\s+when\s+(?P<when>.+?))?\s+begin\b.*$"#
+    )
+    .unwrap()
+});
+
+pub fn parse_create_trigger(sql: &str) -> Result<TriggerDef, String> {
+    let caps = CREATE_TRIGGER_RE
+        .captures(sql)
+        .ok_or("Invalid CREATE TRIGGER statement")?;
+    // sqlite makes a trigger without a timing a BEFORE trigger
+    let timing = match caps.name("timing").map(|t| t.as_str().to_ascii_lowercase()) {
+        None => TriggerTiming::Before,
+        Some(t) if t == "after" => TriggerTiming::After,
+        Some(t) if t == "before" => TriggerTiming::Before,
+        Some(_) => TriggerTiming::InsteadOf,
+    };
+    let event = match caps["event"].to_ascii_lowercase().as_str() {
+        "insert" => TriggerEvent::Insert,
+        "delete" => TriggerEvent::Delete,
+        _ => TriggerEvent::Update(
+            caps.name("cols")
+                .map(|cols| {
+                    split_top_level(cols.as_str(), ',')
+                        .into_iter()
+                        .map(|c| unquote_ident(c.trim()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        ),
+    };
+    Ok(TriggerDef {
+        name: unquote_ident(&caps["name"]),
+        timing,
+        event,
+        table: unquote_ident(&caps["table"]),
+        when: caps.name("when").map(|w| w.as_str().to_string()),
+    })
+}
+
+// Pragma is a `PRAGMA name` or `PRAGMA name(arg)` statement.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pragma {
+    pub name: String,
+    pub arg: Option<String>,
+}
+
+static PRAGMA_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?is)^\s*pragma\s+(?P<name>\w+)\s*(?:\(\s*(?P<arg>"[^"]+"|'[^']*'|\w+)\s*\))?\s*;?\s*$"#,
+    )
+    .unwrap()
+});
+
+// parse_pragma returns the PRAGMA a statement is, None for any other statement.
+pub fn parse_pragma(sql: &str) -> Option<Result<Pragma, String>> {
+    if !sql.trim_start().get(..6)?.eq_ignore_ascii_case("pragma") {
+        return None;
+    }
+    Some(match PRAGMA_RE.captures(sql) {
+        Some(caps) => Ok(Pragma {
+            name: caps["name"].to_ascii_lowercase(),
+            arg: caps.name("arg").map(|a| {
+                let a = a.as_str();
+                match a.strip_prefix('\'').and_then(|a| a.strip_suffix('\'')) {
+                    Some(s) => s.to_string(),
+                    None => unquote_ident(a),
+                }
+            }),
+        }),
+        None => Err("Invalid PRAGMA statement".to_string()),
+    })
+}
+
+// find_keyword finds the first `phrase`, words apart by any whitespace, that
+// stands outside of parentheses and quotes as words of its own, and returns
+// where it starts and ends.
+fn find_keyword(sql: &str, phrase: &str) -> Option<(usize, usize)> {
+    let bytes = sql.as_bytes();
+    let matches_at = |start: usize| {
+        let mut end = start;
+        for (n, word) in phrase.split_whitespace().enumerate() {
+            let space = sql[end..].len() - sql[end..].trim_start().len();
+            if n > 0 && space == 0 {
+                return None;
+            }
+            end += if n > 0 { space } else { 0 };
+            let word_end = end + word.len();
+            if !sql.get(end..word_end)?.eq_ignore_ascii_case(word) {
+                return None;
+            }
+            end = word_end;
+        }
+        bytes
+            .get(end)
+            .is_none_or(|b| !is_word_byte(*b))
+            .then_some(end)
+    };
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in sql.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, _) if depth == 0 && (i == 0 || !is_word_byte(bytes[i - 1])) => {
+                if let Some(end) = matches_at(i) {
+                    return Some((i, end));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// split_top_level splits on `sep` outside of parentheses and quotes.
+pub fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, c) if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+// MAX_PARAM is the highest placeholder number, sqlite's default
+// SQLITE_MAX_VARIABLE_NUMBER.
+const MAX_PARAM: usize = 32766;
+
+// number_params gives every placeholder of a statement its number the way
+// sqlite does, and rewrites each one as `?N`: a bare `?` is one more than the
+// highest number so far, `?N` is N, and `:name` takes the next number the
+// first time it appears and the same one after. Also returned is each
+// number's name, None for those without one.
+pub fn number_params(sql: &str) -> Result<(String, Vec<Option<String>>), String> {
+    let mut out = String::with_capacity(sql.len());
+    let mut names: Vec<Option<String>> = Vec::new();
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '?' | ':') => {
+                let mut tail = String::new();
+                while let Some(n) = chars.next_if(|n| n.is_alphanumeric() || *n == '_') {
+                    tail.push(n);
+                }
+                let n = match c {
+                    '?' if tail.is_empty() => names.len() + 1,
+                    '?' => match tail.parse::<usize>() {
+                        Ok(n) if (1..=MAX_PARAM).contains(&n) => n,
+                        _ => {
+                            return Err(format!(
+                                "variable number must be between ?1 and ?{MAX_PARAM}"
+                            ));
+                        }
+                    },
+                    _ if tail.is_empty() => return Err("unrecognized token: \":\"".to_string()),
+                    _ => {
+                        let name = format!(":{tail}");
+                        match names.iter().position(|p| p.as_ref() == Some(&name)) {
+                            Some(i) => i + 1,
+                            None => {
+                                names.push(Some(name));
+                                names.len()
+                            }
+                        }
+                    }
+                };
+                if names.len() < n {
+                    names.resize(n, None);
+                }
+                out += &format!("?{n}");
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    if names.len() > MAX_PARAM {
+        return Err("too many SQL variables".to_string());
+    }
+    Ok((out, names))
+}
+
+#[test]
+fn test_number_params() {
+    let (sql, names) = number_params("select ?, :a, ?5, ?, :a, ':b ?' from t").unwrap();
+    assert_eq!(sql, "select ?1, ?2, ?5, ?6, ?2, ':b ?' from t");
+    assert_eq!(
+        names,
+        [None, Some(":a".to_string()), None, None, None, None]
+    );
+    let (sql, names) = number_params("select a from t").unwrap();
+    assert_eq!(sql, "select a from t");
+    assert!(names.is_empty());
+    assert!(number_params("select ?0").is_err());
+    assert!(number_params("select ?x").is_err());
+}
+
 #[test]
 fn test_parse_create_index() {
     let r = parse_create_index("CREATE INDEX idx_companies_country on companies (country)");
@@ -213,6 +1407,571 @@ fn test_parse_create_index() {
         name: "idx_companies_country".to_string(),
         table: "companies".to_string(),
         columns: ["country".to_string()].to_vec(),
+        expression: false,
+        desc: false,
+        descending: vec![false],
+        collations: vec![None],
+        unique: false,
+        predicate: None,
+        predicate_conditions: Vec::new(),
     };
-    assert_eq!(r, e)
+    assert_eq!(r, e);
+
+    let r = parse_create_index("create index i on t (a, \"b\" DESC)").unwrap();
+    assert!(r.desc);
+    assert_eq!(r.columns, vec!["a", "b"]);
+    assert_eq!(r.descending, [false, true]);
+
+    let r =
+        parse_create_index("CREATE UNIQUE INDEX IF NOT EXISTS \"u k\" ON \"my t\"(k);").unwrap();
+    assert!(r.unique && !r.desc && !r.expression);
+    assert_eq!((r.name.as_str(), r.table.as_str()), ("u k", "my t"));
+    assert_eq!(r.columns, vec!["k"]);
+
+    let r = parse_create_index(
+        "create index i on t (last collate nocase desc, \"first name\" COLLATE rtrim, age asc)",
+    )
+    .unwrap();
+    assert!(r.desc && !r.unique && !r.expression);
+    assert_eq!(r.columns, vec!["last", "first name", "age"]);
+    assert_eq!(
+        r.collations,
+        [Some("nocase".to_string()), Some("rtrim".to_string()), None]
+    );
+    assert_eq!(r.descending, [true, false, false]);
+
+    let r = parse_create_index(
+        "create index i on orders(customer) where status = 'open' and total > 10;",
+    )
+    .unwrap();
+    assert_eq!(r.columns, vec!["customer"]);
+    assert_eq!(
+        r.predicate.as_deref(),
+        Some("status = 'open' and total > 10")
+    );
+    assert_eq!(r.predicate_conditions.len(), 2);
+    let cond = |column: &str, op: &str, value: &str| Condition {
+        column: column.to_string(),
+        op: op.to_string(),
+        value: value.to_string(),
+        quote: value.parse::<f64>().is_err().then_some('\''),
+    };
+    let open = cond("STATUS", "=", "open");
+    assert!(!r.covers(&[cond("customer", "=", "5"), open.clone()]));
+    assert!(r.covers(&[open.clone(), cond("total", ">", "10")]));
+    assert!(!r.covers(&[cond("status", "=", "closed"), cond("total", ">", "10")]));
+    // a predicate that isn't simple comparisons can't be shown to hold
+    let r = parse_create_index("create index i on t (a) where b is not null or c").unwrap();
+    assert!(r.predicate_conditions.is_empty() && !r.covers(&[open]));
+    assert!(
+        parse_create_index("create index i on t (a)")
+            .unwrap()
+            .covers(&[])
+    );
+
+    assert!(parse_create_index("create index i on t").is_err());
+    assert!(parse_create_index("create index on t (a)").is_err());
+}
+
+#[test]
+fn test_parse_new_table() {
+    let t =
+        parse_new_table("create table if not exists \"my notes\" (id integer primary key, body)")
+            .unwrap()
+            .unwrap();
+    assert_eq!(t.stmt.table, "my notes");
+    assert!(t.if_not_exists && !t.temp && !t.unique);
+    assert_eq!(
+        t.sql,
+        "CREATE TABLE \"my notes\" (id integer primary key, body)"
+    );
+    let t = parse_new_table("CREATE TEMP TABLE t (a UNIQUE);")
+        .unwrap()
+        .unwrap();
+    assert!(t.temp && t.unique);
+    assert_eq!(t.sql, "CREATE TABLE t (a UNIQUE)");
+    assert!(
+        parse_new_table("create table t as select 1")
+            .unwrap()
+            .is_err()
+    );
+    assert!(parse_new_table("create virtual table t using fts5(a)").is_none());
+    assert!(parse_new_table("select 1").is_none());
+}
+
+#[test]
+fn test_parse_create_virtual() {
+    let sql = "CREATE VIRTUAL TABLE \"docs\" USING fts5(title, body UNINDEXED, tokenize = 'porter ascii')";
+    assert!(is_create_virtual(sql));
+    let t = parse_create_virtual(sql).unwrap().unwrap();
+    assert_eq!(t.table, "docs");
+    let names = t
+        .declared_columns()
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["title", "body"]);
+    assert_eq!(t.rowid_column(), Some("rowid"));
+    let fts5 = t.fts5.unwrap();
+    assert_eq!(fts5.indexed, ["title"]);
+    assert_eq!(fts5.content, None);
+    let t = parse_create_virtual("create virtual table d using fts5(a, content='')").unwrap();
+    assert_eq!(t.unwrap().fts5.unwrap().content.as_deref(), Some(""));
+    let t = parse_create_virtual("create virtual table r using rtree_i32(id, x0, x1, +note)")
+        .unwrap()
+        .unwrap();
+    assert_eq!(t.rowid_column(), Some("id"));
+    assert_eq!(t.declared_columns().len(), 4);
+    assert_eq!(t.columns[3].name, "note");
+    let rtree = t.rtree.unwrap();
+    assert!(rtree.int32);
+    assert_eq!(rtree.coordinates, 2);
+    assert!(parse_create_virtual("create virtual table r using rtree(id, x0)").is_err());
+    assert!(parse_create_virtual("create virtual table r using rtree(id, x0, +a, x1)").is_err());
+    let t = parse_create_virtual("create virtual table s using spellfix1").unwrap();
+    assert!(t.is_none());
+    assert!(!is_create_virtual("create table t (a)"));
+    assert_eq!(
+        parse_create("CREATE TABLE 'a_data'(id)").unwrap().table,
+        "a_data"
+    );
+}
+
+/// Splits a script into statements, each paired with the line it starts on.
+/// Semicolons inside quotes or comments don't end a statement, and a line
+/// starting with '.' is a dot command running to the end of that line.
+pub fn split_statements(script: &str) -> Vec<(usize, String)> {
+    let mut stmts = Vec::new();
+    let mut cur = String::new();
+    let mut line = 1;
+    let mut start_line = 1;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if cur.trim().is_empty() && !c.is_whitespace() {
+            cur.clear();
+            start_line = line;
+            if c == '.' {
+                let mut cmd = String::from(c);
+                while let Some(n) = chars.next_if(|n| *n != '\n') {
+                    cmd.push(n);
+                }
+                stmts.push((start_line, cmd.trim().to_string()));
+                continue;
+            }
+        }
+        match c {
+            '\n' => line += 1,
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                cur.push(c);
+                for n in chars.by_ref() {
+                    if n == '\n' {
+                        line += 1;
+                    }
+                    cur.push(n);
+                    if n == close {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                while chars.next_if(|n| *n != '\n').is_some() {}
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for n in chars.by_ref() {
+                    if n == '\n' {
+                        line += 1;
+                    }
+                    if prev == '*' && n == '/' {
+                        break;
+                    }
+                    prev = n;
+                }
+                cur.push(' ');
+                continue;
+            }
+            ';' => {
+                if !cur.trim().is_empty() {
+                    stmts.push((start_line, cur.trim().to_string()));
+                }
+                cur.clear();
+                continue;
+            }
+            _ => {}
+        }
+        cur.push(c);
+    }
+    if !cur.trim().is_empty() {
+        stmts.push((start_line, cur.trim().to_string()));
+    }
+    stmts
+}
+
+#[test]
+fn test_split_statements() {
+    let script = "
+.tables
+select name from apples; -- a comment; still one
+select 'a;b' from t
+  where x = \"c;d\";
+/* block; comment */ select 1;
+";
+    let r = split_statements(script);
+    assert_eq!(
+        r,
+        vec![
+            (2, ".tables".to_string()),
+            (3, "select name from apples".to_string()),
+            (4, "select 'a;b' from t\n  where x = \"c;d\"".to_string()),
+            (6, "select 1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_explain_query_plan() {
+    assert_eq!(
+        parse_explain_query_plan("EXPLAIN QUERY PLAN select a from t"),
+        Some("select a from t")
+    );
+    assert_eq!(parse_explain_query_plan("select a from t"), None);
+}
+
+#[test]
+fn test_rowid_column() {
+    let c =
+        parse_create("CREATE TABLE t (id integer primary key autoincrement, name text)").unwrap();
+    assert_eq!(c.rowid_column(), Some("id"));
+    let c = parse_create("CREATE TABLE t (id text primary key, name text)").unwrap();
+    assert_eq!(c.rowid_column(), None);
+    let c = parse_create("CREATE TABLE t (id integer, n integer, primary key (id, n))").unwrap();
+    assert_eq!(c.rowid_column(), None);
+    let c = parse_create("CREATE TABLE t (id integer, name text, primary key (id))").unwrap();
+    assert_eq!(c.rowid_column(), Some("id"));
+    let c = parse_create("CREATE TABLE t (id integer primary key) without rowid").unwrap();
+    assert_eq!(c.rowid_column(), None);
+}
+
+#[test]
+fn test_parse_glob_condition() {
+    let s = parse_select("select name from files where name NOT  GLOB '*.[ch]'").unwrap();
+    assert_eq!(s.conditions.len(), 1);
+    assert_eq!(s.conditions[0].column, "name");
+    assert_eq!(s.conditions[0].op, "not glob");
+    assert_eq!(s.conditions[0].value, "*.[ch]");
+    let s = parse_select("select name from files where size > 10 and name glob'?.rs'").unwrap();
+    assert_eq!(s.conditions[1].op, "glob");
+    let s = parse_select("select name from files where name not regexp '\\.rs$'").unwrap();
+    assert_eq!(s.conditions[0].op, "not regexp");
+}
+
+#[test]
+fn test_parse_create_columns() {
+    let c = parse_create(
+        "create table if not exists t(a decimal(10, 2) not null default 0, \
+         b text default 'x,y', c varchar ( 5 ) default (1 + 2), d int default -1 not null, \
+         \"e f\" default current_timestamp, constraint pk primary key (a, \"B\" desc), \
+         unique (c), check (d > -5)) without rowid",
+    )
+    .unwrap();
+    assert_eq!(c.table, "t");
+    assert!(c.without_rowid);
+    let columns = c
+        .columns
+        .iter()
+        .map(|c| {
+            (
+                c.name.as_str(),
+                c.ty.as_deref(),
+                c.primary_key,
+                c.not_null,
+                c.default.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        [
+            ("a", Some("decimal(10, 2)"), true, true, Some("0")),
+            ("b", Some("text"), true, false, Some("'x,y'")),
+            ("c", Some("varchar( 5 )"), false, false, Some("1 + 2")),
+            ("d", Some("int"), false, true, Some("-1")),
+            ("e f", None, false, false, Some("current_timestamp")),
+        ]
+    );
+    assert!(parse_create("create table t (a, primary key (b))").is_err());
+    let t = parse_create("create table t (a text collate NoCase not null, b collate rtrim, c)")
+        .unwrap();
+    let collations = t.columns.iter().map(|c| c.collation.as_deref());
+    assert!(collations.eq([Some("nocase"), Some("rtrim"), None]));
+    let t = parse_create("create table t (a integer primary key, b Text, c any) strict").unwrap();
+    assert!(t.strict && !t.without_rowid);
+    let t = parse_create("create table t (a int primary key) strict, without rowid").unwrap();
+    assert!(t.strict && t.without_rowid);
+    assert!(!parse_create("create table t (a)").unwrap().strict);
+    assert_eq!(
+        parse_create("create table t (a) strict").unwrap_err(),
+        "missing datatype for t.a"
+    );
+    assert_eq!(
+        parse_create("create table t (a varchar(10)) strict").unwrap_err(),
+        "unknown datatype for t.a: \"varchar(10)\""
+    );
+}
+
+#[test]
+fn test_parse_expression_index() {
+    let r = parse_create_index("CREATE INDEX i_sum ON t(a+b)").unwrap();
+    assert!(r.expression);
+    assert_eq!(r.columns, vec!["a+b"]);
+
+    let r = parse_create_index("create index i on t (substr(name, 1, 2), \"b\" desc)").unwrap();
+    assert!(r.expression);
+    assert_eq!(r.columns, vec!["substr(name, 1, 2)", "b"]);
+
+    let r = parse_create_index("create index i on t (a collate nocase, b)").unwrap();
+    assert!(!r.expression);
+    assert_eq!(r.columns, vec!["a", "b"]);
+}
+
+#[test]
+fn test_parse_dml_returning() {
+    let DmlStmt::Insert(i) =
+        parse_dml("INSERT INTO t (id, name) VALUES (1, 'a, b'), (2, 'c') RETURNING id, name;")
+            .unwrap()
+    else {
+        panic!("expected INSERT");
+    };
+    assert_eq!(i.table, "t");
+    assert_eq!(i.columns, vec!["id", "name"]);
+    assert_eq!(i.values, vec![vec!["1", "'a, b'"], vec!["2", "'c'"]]);
+    assert_eq!(
+        i.returning,
+        Some(vec!["id".to_string(), "name".to_string()])
+    );
+
+    let DmlStmt::Update(u) = parse_dml("update t set name = 'x' where id = 1 returning *").unwrap()
+    else {
+        panic!("expected UPDATE");
+    };
+    assert_eq!(u.assignments, vec![("name".to_string(), "'x'".to_string())]);
+    assert_eq!(u.filter, Some(expr::parse_expr("id = 1").unwrap()));
+    assert_eq!(u.returning, Some(vec!["*".to_string()]));
+
+    let DmlStmt::Insert(i) =
+        parse_dml("insert into apples (name, color) values ('x returning y', 'Red')").unwrap()
+    else {
+        panic!("expected INSERT");
+    };
+    assert_eq!(i.values, vec![vec!["'x returning y'", "'Red'"]]);
+    assert_eq!(i.returning, None);
+
+    let DmlStmt::Update(u) =
+        parse_dml("update apples set color = 'a where b' where id = 1").unwrap()
+    else {
+        panic!("expected UPDATE");
+    };
+    assert_eq!(
+        u.assignments,
+        vec![("color".to_string(), "'a where b'".to_string())]
+    );
+    assert_eq!(u.filter, Some(expr::parse_expr("id = 1").unwrap()));
+
+    let DmlStmt::Delete(d) = parse_dml("DELETE FROM t WHERE id = 2").unwrap() else {
+        panic!("expected DELETE");
+    };
+    assert_eq!(d.table, "t");
+    assert_eq!(d.returning, None);
+
+    assert!(is_dml("  insert into t values (1)"));
+    assert!(!is_dml("select * from inserts"));
+}
+
+#[test]
+fn test_parse_upsert() {
+    let DmlStmt::Insert(i) = parse_dml(
+        "INSERT INTO t (id, val) VALUES (1, 'x') ON CONFLICT (id) DO UPDATE SET val = excluded.val",
+    )
+    .unwrap() else {
+        panic!("expected INSERT");
+    };
+    assert_eq!(i.values, vec![vec!["1", "'x'"]]);
+    assert_eq!(
+        i.on_conflict,
+        Some(OnConflict {
+            target: vec!["id".to_string()],
+            action: ConflictAction::Update {
+                assignments: vec![("val".to_string(), "excluded.val".to_string())],
+                filter: None,
+            },
+        })
+    );
+
+    let DmlStmt::Insert(i) =
+        parse_dml("insert into t values (1) on conflict do nothing returning id").unwrap()
+    else {
+        panic!("expected INSERT");
+    };
+    assert_eq!(
+        i.on_conflict.unwrap(),
+        OnConflict {
+            target: vec![],
+            action: ConflictAction::Nothing
+        }
+    );
+    assert_eq!(i.returning, Some(vec!["id".to_string()]));
+
+    // keywords inside literals don't start a clause
+    let DmlStmt::Insert(i) = parse_dml(
+        "insert into t values (1, 'on conflict do nothing') on conflict (id) do update \
+         set val = excluded.val || ' where ' where val <> excluded.val",
+    )
+    .unwrap() else {
+        panic!("expected INSERT");
+    };
+    assert_eq!(i.values, vec![vec!["1", "'on conflict do nothing'"]]);
+    assert_eq!(
+        i.on_conflict.unwrap().action,
+        ConflictAction::Update {
+            assignments: vec![("val".to_string(), "excluded.val || ' where '".to_string())],
+            filter: Some(expr::parse_expr("val <> excluded.val").unwrap()),
+        }
+    );
+    assert!(parse_dml("insert into t values (1) on conflict do").is_err());
+}
+
+#[test]
+fn test_parse_compound() {
+    let c = parse_compound("select a from t1 INTERSECT select a from t2 where b = 'x intersect y'")
+        .unwrap();
+    assert_eq!(c.first.table, "t1");
+    assert_eq!(c.rest.len(), 1);
+    assert_eq!(c.rest[0].0, SetOp::Intersect);
+    assert_eq!(c.rest[0].1.table, "t2");
+    let c = parse_compound("select a from t1 except select a from t2 intersect select a from t3")
+        .unwrap();
+    let ops = c.rest.iter().map(|(op, _)| *op).collect::<Vec<_>>();
+    assert_eq!(ops, [SetOp::Except, SetOp::Intersect]);
+    let c = parse_compound("select a from t1 except select a from t2 order by 1 desc").unwrap();
+    assert_eq!(c.order_by, [("1".to_string(), true)]);
+    assert!(c.rest[0].1.order_by.is_empty());
+    let c = parse_compound("select intersection from t1").unwrap();
+    assert_eq!(c.first.columns, ["intersection"]);
+    assert!(c.rest.is_empty());
+}
+
+#[test]
+fn test_split_alias() {
+    assert_eq!(
+        split_alias("count(*) AS n"),
+        ("count(*)", Some("n".to_string()))
+    );
+    assert_eq!(
+        split_alias("a || ' as ' || b as \"Full Name\""),
+        ("a || ' as ' || b", Some("Full Name".to_string()))
+    );
+    assert_eq!(split_alias("name"), ("name", None));
+    assert_eq!(split_alias("'x as y'"), ("'x as y'", None));
+}
+
+#[test]
+fn test_parse_order_by() {
+    let s =
+        parse_select("select a, b from t where a > 1 order by a ASC, length(b) desc, b").unwrap();
+    assert_eq!(s.columns, ["a", "b"]);
+    assert!(s.filter.is_some());
+    assert_eq!(
+        s.order_by,
+        [
+            ("a".to_string(), false),
+            ("length(b)".to_string(), true),
+            ("b".to_string(), false),
+        ]
+    );
+    let s = parse_select("select a from t order by a desc;").unwrap();
+    assert_eq!(s.order_by, [("a".to_string(), true)]);
+    assert!(parse_select("select a from t order by a,").is_err());
+
+    let s =
+        parse_select("select a, count(*) from t where b group by a, lower(b) order by 2").unwrap();
+    assert_eq!(s.group_by, ["a", "lower(b)"]);
+    assert_eq!(s.order_by, [("2".to_string(), false)]);
+    assert!(parse_select("select a from t group by a,").is_err());
+}
+
+#[test]
+fn test_parse_table_function() {
+    let s = parse_select("select value from generate_series(1, abs(-5)) where value > 2").unwrap();
+    assert_eq!(s.table, "generate_series");
+    assert_eq!(s.args.map(|a| a.len()), Some(2));
+    assert!(s.filter.is_some());
+    let s = parse_select("select * from json_each('[1, \"(\"]')").unwrap();
+    assert_eq!(s.args.map(|a| a.len()), Some(1));
+    assert_eq!(
+        parse_select("select * from f()").unwrap().args,
+        Some(Vec::new())
+    );
+    assert_eq!(parse_select("select * from t").unwrap().args, None);
+}
+
+#[test]
+fn test_parse_create_trigger() {
+    let t = parse_create_trigger(
+        "CREATE TRIGGER t_ins after insert on t when new.a > 0 begin insert into log values (new.a); end",
+    )
+    .unwrap();
+    assert_eq!(
+        t,
+        TriggerDef {
+            name: "t_ins".to_string(),
+            timing: TriggerTiming::After,
+            event: TriggerEvent::Insert,
+            table: "t".to_string(),
+            when: Some("new.a > 0".to_string()),
+        }
+    );
+    assert_eq!(t.event_name(), "AFTER INSERT");
+
+    let t = parse_create_trigger(
+        "create trigger if not exists \"audit price\" update of price, qty on \"items\" \
+         for each row begin select 1; end",
+    )
+    .unwrap();
+    assert_eq!(t.name, "audit price");
+    assert_eq!(t.table, "items");
+    assert_eq!(t.when, None);
+    assert_eq!(t.event_name(), "BEFORE UPDATE OF price, qty");
+
+    let t = parse_create_trigger("CREATE TRIGGER v_del INSTEAD OF DELETE ON v BEGIN SELECT 1; END")
+        .unwrap();
+    assert_eq!(t.event_name(), "INSTEAD OF DELETE");
+    assert!(parse_create_trigger("CREATE TRIGGER x ON t BEGIN SELECT 1; END").is_err());
+}
+
+#[test]
+fn test_parse_pragma() {
+    assert_eq!(
+        parse_pragma("PRAGMA trigger_info(\"my table\");"),
+        Some(Ok(Pragma {
+            name: "trigger_info".to_string(),
+            arg: Some("my table".to_string()),
+        }))
+    );
+    assert_eq!(
+        parse_pragma("pragma Page_Size"),
+        Some(Ok(Pragma {
+            name: "page_size".to_string(),
+            arg: None,
+        }))
+    );
+    assert!(matches!(
+        parse_pragma("pragma trigger_info(a, b)"),
+        Some(Err(_))
+    ));
+    assert_eq!(parse_pragma("select 1 from t"), None);
 }
@@ -0,0 +1,98 @@
+use anyhow::{Result, bail};
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+use crate::Database;
+use crate::error::SqliteError;
+
+/// A fixed number of [`Database`]s open on the same file, for threads that
+/// read it concurrently. Each has its own file handle, so reads don't wait
+/// on one another.
+///
+/// The pool is shared by reference, e.g. in an `Arc` or across
+/// [`std::thread::scope`].
+#[derive(Debug)]
+pub struct DatabasePool {
+    idle: Mutex<VecDeque<Database>>,
+    returned: Condvar,
+}
+
+impl DatabasePool {
+    /// Opens `max_connections` databases at `path`, as [`Database::open`]
+    /// does, failing with [`SqliteError::Usage`] when that's 0.
+    pub fn new(path: &str, max_connections: usize) -> Result<DatabasePool> {
+        if max_connections == 0 {
+            bail!(SqliteError::Usage(
+                "a pool needs at least one connection".to_string()
+            ));
+        }
+        let idle = (0..max_connections)
+            .map(|_| Database::open(path))
+            .collect::<Result<_>>()?;
+        Ok(DatabasePool {
+            idle: Mutex::new(idle),
+            returned: Condvar::new(),
+        })
+    }
+
+    /// Takes a database out of the pool, waiting for one to be returned when
+    /// all are in use. It goes back when the [`PooledDatabase`] is dropped.
+    pub fn acquire(&self) -> PooledDatabase<'_> {
+        let mut idle = self.lock();
+        loop {
+            if let Some(db) = idle.pop_front() {
+                return PooledDatabase {
+                    pool: self,
+                    db: Some(db),
+                };
+            }
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// The number of databases not in use right now.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    // lock ignores poisoning: a thread that panicked holding the lock can't
+    // have left the queue half changed.
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Database>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A database taken out of a [`DatabasePool`], usable as a [`Database`]
+/// until it's dropped and goes back to the pool.
+#[derive(Debug)]
+pub struct PooledDatabase<'p> {
+    pool: &'p DatabasePool,
+    db: Option<Database>, // None only while dropping
+}
+
+impl Deref for PooledDatabase<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledDatabase<'_> {
+    fn deref_mut(&mut self) -> &mut Database {
+        self.db.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledDatabase<'_> {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            self.pool.lock().push_back(db);
+            self.pool.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,868 @@
+use anyhow::{Result, bail};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::error::SqliteError;
+
+/// A value decoded from a record, named after the storage classes of
+/// <https://www.sqlite.org/datatype3.html>.
+#[derive(Debug, Clone)]
+pub enum ColType {
+    /// SQL NULL.
+    Null,
+    /// A signed integer, whatever its size on disk.
+    Integer(i64),
+    /// An IEEE 754 double.
+    Float(f64),
+    /// Serial types 10 and 11, which sqlite3 reserves for internal use and reads
+    /// back as NULL.
+    Reserved,
+    /// Raw bytes.
+    Blob(Vec<u8>),
+    /// Text, already decoded from the database text encoding.
+    Text(String),
+}
+
+/// The values of query results.
+///
+/// Values compare like sqlite3 compares them, see [`ColType::sqlite_cmp`]:
+/// `==`, [`Ord`] and [`Hash`] all follow it with the BINARY collation, so an
+/// integer equals the real of the same value and NULL equals NULL, as rows do
+/// in DISTINCT and compound selects.
+pub type Value = ColType;
+
+/// How text compares, as a `COLLATE` clause would choose.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    /// Byte by byte, sqlite3's default.
+    #[default]
+    Binary,
+    /// Byte by byte with ASCII letters folded to lower case.
+    NoCase,
+    /// Byte by byte ignoring trailing spaces.
+    RTrim,
+}
+
+impl ColType {
+    /// Orders two values like sqlite3 sorts them: NULL first, then numbers
+    /// by value whether integer or real, then text under `collation`, then
+    /// blobs byte by byte.
+    ///
+    /// Storage classes never convert: the text `'1'` sorts after every number.
+    /// NaN, which sqlite3 never stores, equals itself and sorts below every
+    /// other number.
+    pub fn sqlite_cmp(&self, other: &Value, collation: Collation) -> Ordering {
+        let class = |v: &ColType| match v {
+            ColType::Null | ColType::Reserved => 0,
+            ColType::Integer(_) | ColType::Float(_) => 1,
+            ColType::Text(_) => 2,
+            ColType::Blob(_) => 3,
+        };
+        match (self, other) {
+            (ColType::Integer(a), ColType::Integer(b)) => a.cmp(b),
+            (ColType::Float(a), ColType::Float(b)) => float_cmp(*a, *b),
+            (ColType::Integer(i), ColType::Float(f)) => int_float_cmp(*i, *f),
+            (ColType::Float(f), ColType::Integer(i)) => int_float_cmp(*i, *f).reverse(),
+            (ColType::Text(a), ColType::Text(b)) => match collation {
+                Collation::Binary => a.cmp(b),
+                Collation::NoCase => a
+                    .bytes()
+                    .map(|c| c.to_ascii_lowercase())
+                    .cmp(b.bytes().map(|c| c.to_ascii_lowercase())),
+                Collation::RTrim => a.trim_end_matches(' ').cmp(b.trim_end_matches(' ')),
+            },
+            (ColType::Blob(a), ColType::Blob(b)) => a.cmp(b),
+            (a, b) => class(a).cmp(&class(b)),
+        }
+    }
+}
+
+// TWO_63 is 2^63, the first real past i64::MAX.
+const TWO_63: f64 = 9_223_372_036_854_775_808.0;
+
+// float_cmp is total: NaN equals NaN and is less than any other number.
+fn float_cmp(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        // 0.0 and -0.0 are the same number
+        _ => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+// int_float_cmp compares exactly, where converting either side could round:
+// i64::MAX and 2^63 differ although i64::MAX as f64 is 2^63.
+fn int_float_cmp(i: i64, f: f64) -> Ordering {
+    if f.is_nan() {
+        return Ordering::Greater;
+    }
+    if f < -TWO_63 {
+        return Ordering::Greater;
+    }
+    if f >= TWO_63 {
+        return Ordering::Less;
+    }
+    // f is in range now, compare its integer part and then its fraction
+    let t = f.trunc();
+    i.cmp(&(t as i64)).then_with(|| float_cmp(0.0, f - t))
+}
+
+impl PartialEq for ColType {
+    fn eq(&self, other: &Self) -> bool {
+        self.sqlite_cmp(other, Collation::Binary).is_eq()
+    }
+}
+
+impl Eq for ColType {}
+
+impl PartialOrd for ColType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ColType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sqlite_cmp(other, Collation::Binary)
+    }
+}
+
+impl Hash for ColType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            ColType::Null | ColType::Reserved => state.write_u8(0),
+            ColType::Integer(i) => {
+                state.write_u8(1);
+                i.hash(state);
+            }
+            // a real equal to an integer hashes like it, -0.0 like 0
+            ColType::Float(f) if f.fract() == 0.0 && (-TWO_63..TWO_63).contains(f) => {
+                state.write_u8(1);
+                (*f as i64).hash(state);
+            }
+            ColType::Float(f) if f.is_nan() => state.write_u8(2),
+            ColType::Float(f) => {
+                state.write_u8(1);
+                f.to_bits().hash(state);
+            }
+            ColType::Text(s) => {
+                state.write_u8(3);
+                s.hash(state);
+            }
+            ColType::Blob(b) => {
+                state.write_u8(4);
+                b.hash(state);
+            }
+        }
+    }
+}
+
+impl ColType {
+    /// The name of the storage class, as `typeof()` returns it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ColType::Null | ColType::Reserved => "null",
+            ColType::Integer(_) => "integer",
+            ColType::Float(_) => "real",
+            ColType::Text(_) => "text",
+            ColType::Blob(_) => "blob",
+        }
+    }
+}
+
+/// A Rust type that result values convert to, see [`Row::get`](crate::Row::get).
+///
+/// Conversions don't lose information: an integer becomes an `f64` only when
+/// the double holds it exactly, and NULL only converts to `None`.
+pub trait FromValue: Sized {
+    /// The storage class the conversion wants, for [`SqliteError::WrongType`].
+    const EXPECTED: &'static str;
+
+    /// `v` as `Self`, or None when its storage class doesn't convert.
+    fn from_value(v: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    const EXPECTED: &'static str = "any value";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        Some(v.clone())
+    }
+}
+
+impl FromValue for i64 {
+    const EXPECTED: &'static str = "integer";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "real";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Float(f) => Some(*f),
+            // 2^63 itself would saturate back to i64::MAX
+            ColType::Integer(i) => {
+                let f = *i as f64;
+                (f < TWO_63 && f as i64 == *i).then_some(f)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "text";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+// text converts to its UTF-8 bytes, like sqlite3_column_blob
+impl FromValue for Vec<u8> {
+    const EXPECTED: &'static str = "blob";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Blob(b) => Some(b.clone()),
+            ColType::Text(s) => Some(s.as_bytes().to_vec()),
+            _ => None,
+        }
+    }
+}
+
+// sqlite has no boolean type, any non-zero integer is true
+impl FromValue for bool {
+    const EXPECTED: &'static str = "integer";
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Integer(i) => Some(*i != 0),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_value(v: &Value) -> Option<Self> {
+        match v {
+            ColType::Null | ColType::Reserved => Some(None),
+            v => T::from_value(v).map(Some),
+        }
+    }
+}
+
+impl fmt::Display for ColType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // how NULL looks is up to the output mode, see format.rs
+            ColType::Null => Ok(()),
+            ColType::Integer(v) => write!(f, "{v}"),
+            ColType::Float(v) => write!(f, "{}", format_float(*v)),
+            ColType::Reserved => Ok(()),
+            ColType::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+            ColType::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// format_float renders a REAL like sqlite's "%!.15g": 15 significant digits,
+// always with a decimal point, and exponents from 1e15 or below 1e-4.
+pub fn format_float(v: f64) -> String {
+    if !v.is_finite() {
+        return if v.is_nan() {
+            String::new()
+        } else if v > 0.0 {
+            "Inf".to_string()
+        } else {
+            "-Inf".to_string()
+        };
+    }
+    // the 15 digits are rounded half away from zero like sqlite3 does, where
+    // Rust's own rounding would take an exact tie to even
+    let sci = format!("{:.30e}", v.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let mut exp: i32 = exp.parse().unwrap();
+    let mut digits = mantissa
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .take(16)
+        .collect::<Vec<_>>();
+    if digits.pop().unwrap() >= b'5' {
+        match digits.iter().rposition(|&d| d != b'9') {
+            Some(i) => {
+                digits[i] += 1;
+                digits[i + 1..].fill(b'0');
+            }
+            None => {
+                digits.fill(b'0');
+                digits[0] = b'1';
+                exp += 1;
+            }
+        }
+    }
+    let digits = String::from_utf8(digits).unwrap();
+    // -0.0 shows as 0.0, like sqlite3
+    let sign = if v < 0.0 { "-" } else { "" };
+    let trim = |s: &str| {
+        let s = s.trim_end_matches('0');
+        if let Some(s) = s.strip_suffix('.') {
+            format!("{s}.0")
+        } else {
+            s.to_string()
+        }
+    };
+    if !(-4..15).contains(&exp) {
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        let mantissa = trim(&format!("{}.{}", &digits[..1], &digits[1..]));
+        format!("{sign}{mantissa}e{exp_sign}{:02}", exp.abs())
+    } else if exp >= 0 {
+        let (int, frac) = digits.split_at(exp as usize + 1);
+        trim(&format!("{sign}{int}.{frac}"))
+    } else {
+        let zeros = "0".repeat((-exp - 1) as usize);
+        trim(&format!("{sign}0.{zeros}{digits}"))
+    }
+}
+
+impl TryFrom<ColType> for i64 {
+    type Error = anyhow::Error;
+
+    fn try_from(v: ColType) -> anyhow::Result<Self> {
+        match v {
+            ColType::Integer(n) => Ok(n),
+            other => Err(anyhow::anyhow!("expected Integer, got {}", other)),
+        }
+    }
+}
+
+impl TryFrom<ColType> for usize {
+    type Error = anyhow::Error;
+
+    fn try_from(v: ColType) -> anyhow::Result<Self> {
+        match v {
+            ColType::Integer(n) => Ok(n as usize),
+            other => Err(anyhow::anyhow!("expected Integer, got {}", other)),
+        }
+    }
+}
+
+// col_value decodes the value of serial type `serial_type` at `start`. With
+// `trim_nul`, TEXT loses the NULs padding it out, as fixed-width fields
+// imported from legacy data have.
+pub fn col_value(
+    serial_type: i64,
+    buf: &[u8],
+    start: usize,
+    text_encoding: u32,
+    trim_nul: bool,
+) -> Result<ColType> {
+    let size = serial_type_size(serial_type)?;
+    let Some(bytes) = start.checked_add(size).and_then(|end| buf.get(start..end)) else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "a value of serial type {serial_type} runs past the end of its record"
+        )));
+    };
+    Ok(match serial_type {
+        0 => ColType::Null,
+        // 1 to 6 are big-endian two's complement integers of 1, 2, 3, 4, 6 and 8 bytes
+        1..=6 => {
+            let sign = if bytes[0] & 0x80 != 0 { 0xff } else { 0 };
+            let mut be = [sign; 8];
+            be[8 - size..].copy_from_slice(bytes);
+            ColType::Integer(i64::from_be_bytes(be))
+        }
+        7 => ColType::Float(f64::from_be_bytes(bytes.try_into().unwrap())), // 64-bit floating pointer
+        8 => ColType::Integer(0),
+        9 => ColType::Integer(1),
+        // sqlite3 uses these internally and reads them back as NULL
+        10 | 11 => ColType::Reserved,
+        n if n % 2 == 0 => ColType::Blob(bytes.to_vec()), // BLOB
+        _ => {
+            let mut s = decode_text(bytes, text_encoding);
+            if trim_nul {
+                s.truncate(s.trim_end_matches('\0').len());
+            }
+            ColType::Text(s)
+        } // TEXT
+    })
+}
+
+// negative types can only come from a damaged record.
+fn invalid_serial_type(serial_type: i64) -> SqliteError {
+    SqliteError::CorruptDatabase(format!("invalid serial type {serial_type}"))
+}
+
+// decode_text decodes a TEXT value in the database text encoding,
+// combining UTF-16 surrogate pairs into a single char.
+fn decode_text(bytes: &[u8], text_encoding: u32) -> String {
+    match text_encoding {
+        2 | 3 => {
+            let units = bytes.chunks_exact(2).map(|c| {
+                if text_encoding == 2 {
+                    u16::from_le_bytes([c[0], c[1]])
+                } else {
+                    u16::from_be_bytes([c[0], c[1]])
+                }
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        // like sqlite3, invalid UTF-8 is shown rather than rejected
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+pub fn serial_type_size(serial_type: i64) -> Result<usize> {
+    Ok(match serial_type {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        5 => 6,
+        6 => 8,
+        7 => 8, // 64-bit floating pointer
+        8 => 0,
+        9 => 0,
+        10 | 11 => 0,
+        n if n >= 12 && n % 2 == 0 => (n as usize - 12) / 2, // BLOB
+        n if n >= 13 && n % 2 == 1 => (n as usize - 13) / 2, // TEXT
+        other => bail!(invalid_serial_type(other)),
+    })
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip_all, ret)
+)]
+/// Reads a varint of 1 to 9 bytes and returns it with its length: 7 bits
+/// from each byte whose high bit says another follows, and all 8 bits of a
+/// ninth. One cut short by the end of `buf` is corruption.
+pub fn decode_varint(buf: &[u8]) -> Result<(i64, usize)> {
+    let mut res: u64 = 0;
+    for (i, &b) in buf.iter().take(9).enumerate() {
+        if i == 8 {
+            return Ok(((res << 8 | b as u64) as i64, 9));
+        }
+        res = res << 7 | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            return Ok((res as i64, i + 1));
+        }
+    }
+    bail!(SqliteError::CorruptDatabase(
+        "varint runs past the end of its cell".to_string()
+    ))
+}
+
+// encode_varint is the inverse of decode_varint, using as few bytes as the
+// value needs.
+pub fn encode_varint(v: i64) -> Vec<u8> {
+    let v = v as u64;
+    if v >> 56 != 0 {
+        // the ninth byte holds 8 bits, the eight before it 7 each
+        let mut out = (0..8)
+            .map(|i| (v >> (8 + 7 * (7 - i))) as u8 | 0x80)
+            .collect::<Vec<_>>();
+        out.push(v as u8);
+        return out;
+    }
+    let mut out = Vec::new();
+    let mut rest = v;
+    loop {
+        out.push(rest as u8 & 0x7f | 0x80);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    *out.last_mut().unwrap() &= 0x7f;
+    out
+}
+
+// encode_record is the inverse of decoding a record: a header of serial
+// types, each the smallest that holds its value, and the values after it.
+// Serial types 8 and 9 for 0 and 1 only exist from schema format 4 on.
+pub fn encode_record(values: &[ColType], text_encoding: u32, schema_format: u32) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for v in values {
+        let serial_type = match v {
+            ColType::Null | ColType::Reserved => 0,
+            ColType::Integer(i @ 0..=1) if schema_format >= 4 => 8 + *i,
+            ColType::Integer(i) => {
+                let (serial_type, size) = match i {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&i.to_be_bytes()[8 - size..]);
+                serial_type
+            }
+            ColType::Float(f) => {
+                body.extend_from_slice(&f.to_be_bytes());
+                7
+            }
+            ColType::Blob(b) => {
+                body.extend_from_slice(b);
+                12 + 2 * b.len() as i64
+            }
+            ColType::Text(t) => {
+                let bytes = encode_text(t, text_encoding);
+                body.extend_from_slice(&bytes);
+                13 + 2 * bytes.len() as i64
+            }
+        };
+        types.extend(encode_varint(serial_type));
+    }
+    // the header size counts its own varint, which may need a second byte
+    let mut header_size = types.len() + 1;
+    if encode_varint(header_size as i64).len() > 1 {
+        header_size += 1;
+    }
+    let mut record = encode_varint(header_size as i64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+// encode_text is the inverse of decode_text.
+fn encode_text(s: &str, text_encoding: u32) -> Vec<u8> {
+    match text_encoding {
+        2 => s.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        3 => s.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        _ => s.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn test_encode_record() {
+    for v in [
+        0,
+        1,
+        127,
+        128,
+        240,
+        2287,
+        2288,
+        67823,
+        1 << 56,
+        -1,
+        i64::MAX,
+    ] {
+        let bytes = encode_varint(v);
+        assert_eq!(decode_varint(&bytes).unwrap(), (v, bytes.len()), "{v}");
+    }
+    assert_eq!(encode_varint(300), [0x82, 0x2c]);
+    let values = [
+        ColType::Null,
+        ColType::Integer(1),
+        ColType::Integer(-129),
+        ColType::Integer(1 << 40),
+        ColType::Integer(i64::MIN),
+        ColType::Float(2.5),
+        ColType::Text("naïve".to_string()),
+        ColType::Blob(vec![0, 0xff]),
+    ];
+    for (encoding, format) in [(1, 4), (2, 4), (3, 1)] {
+        let record = encode_record(&values, encoding, format);
+        let (header_size, mut i) = decode_varint(&record).unwrap();
+        let mut start = header_size as usize;
+        let mut decoded = Vec::new();
+        while i < header_size as usize {
+            let (serial_type, n) = decode_varint(&record[i..]).unwrap();
+            i += n;
+            decoded.push(col_value(serial_type, &record, start, encoding, false).unwrap());
+            start += serial_type_size(serial_type).unwrap();
+        }
+        assert_eq!(start, record.len());
+        assert_eq!(decoded, values);
+    }
+    assert_eq!(encode_record(&[ColType::Integer(1)], 1, 4), [2, 9]);
+    assert_eq!(encode_record(&[ColType::Integer(1)], 1, 1), [2, 1, 1]);
+    // a header of 127 bytes and more needs two for its own size
+    let many = encode_record(&vec![ColType::Null; 200], 1, 4);
+    assert_eq!(decode_varint(&many).unwrap(), (202, 2));
+}
+
+#[test]
+fn test_decode_varint() {
+    let decode = |buf: &[u8]| decode_varint(buf).unwrap();
+    assert_eq!(decode(&[0x78]), (120, 1));
+    assert_eq!(decode(&[0x07]), (7, 1));
+    assert_eq!(decode(&[0x17]), (23, 1));
+    assert_eq!(decode(&[0x1b]), (27, 1));
+    assert_eq!(decode(&[0x81, 0x47, 0xff]), (199, 2));
+    // the ninth byte gives all of its 8 bits
+    assert_eq!(decode(&[0xff; 9]), (-1, 9));
+    assert_eq!(
+        decode(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+        (1, 9)
+    );
+    assert_eq!(
+        decode(&[0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]),
+        (1 << 57, 9)
+    );
+    // found by fuzzing: an empty or cut short varint used to read as 0
+    assert!(decode_varint(&[]).is_err());
+    assert!(decode_varint(&[0x81, 0x82]).is_err());
+}
+
+#[test]
+fn test_decode_utf16_surrogate_pairs() {
+    // "a😀": 'a' then U+1F600 as the surrogate pair D83D DE00
+    let le = [0x61, 0x00, 0x3d, 0xd8, 0x00, 0xde];
+    let be = [0x00, 0x61, 0xd8, 0x3d, 0xde, 0x00];
+    for (bytes, encoding) in [(&le, 2), (&be, 3)] {
+        let serial_type = 13 + 2 * bytes.len() as i64;
+        match col_value(serial_type, bytes, 0, encoding, false).unwrap() {
+            ColType::Text(s) => assert_eq!(s, "a😀"),
+            other => panic!("unexpected {other}"),
+        }
+    }
+    // a lone high surrogate can't be decoded
+    assert_eq!(decode_text(&[0x3d, 0xd8], 2), "\u{fffd}");
+}
+
+#[test]
+fn test_trim_nul() {
+    let bytes = b"ab\0c\0\0";
+    let serial_type = 13 + 2 * bytes.len() as i64;
+    for (trim_nul, want) in [(false, "ab\0c\0\0"), (true, "ab\0c")] {
+        match col_value(serial_type, bytes, 0, 1, trim_nul).unwrap() {
+            ColType::Text(s) => assert_eq!(s, want),
+            other => panic!("unexpected {other}"),
+        }
+    }
+    // UTF-16 pads with two-byte NULs
+    let le = [0x61, 0x00, 0x00, 0x00, 0x00, 0x00];
+    match col_value(13 + 2 * le.len() as i64, &le, 0, 2, true).unwrap() {
+        ColType::Text(s) => assert_eq!(s, "a"),
+        other => panic!("unexpected {other}"),
+    }
+}
+
+#[test]
+fn test_format_float() {
+    let cases = [
+        (2.0, "2.0"),
+        (1.5, "1.5"),
+        (-0.5, "-0.5"),
+        (0.1 + 0.2, "0.3"),
+        (1e20, "1.0e+20"),
+        (123456789012345678.0, "1.23456789012346e+17"),
+        (1e-7, "1.0e-07"),
+        (0.0001, "0.0001"),
+        (100.25, "100.25"),
+        // exact ties round away from zero
+        (93.20001220703125, "93.2000122070313"),
+        (999999999999999.5, "1.0e+15"),
+        (-0.00012345678901234567, "-0.000123456789012346"),
+        (-0.0, "0.0"),
+        (4.9e-324, "4.94065645841247e-324"),
+        (f64::MAX, "1.79769313486232e+308"),
+        (999999999999999.0, "999999999999999.0"),
+        (1e15, "1.0e+15"),
+        (100.0 / 3.0, "33.3333333333333"),
+        (f64::INFINITY, "Inf"),
+    ];
+    for (v, want) in cases {
+        assert_eq!(format_float(v), want);
+    }
+}
+
+#[test]
+fn test_col_value_integers() {
+    let cases: [(i64, &[u8], i64); 17] = [
+        (1, &[0xf9], -7),
+        (1, &[0x80], -128),
+        (1, &[0x7f], 127),
+        (2, &[0xff, 0xfe], -2),
+        (2, &[0x80, 0x00], -32768),
+        (3, &[0x80, 0x00, 0x00], -8388608),
+        (3, &[0xff, 0xff, 0x85], -123),
+        (4, &[0x00, 0x01, 0x00, 0x00], 65536),
+        (4, &[0xff, 0xfe, 0xff, 0xff], -65537),
+        (4, &[0x80, 0x00, 0x00, 0x00], i32::MIN as i64),
+        (5, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff], -1),
+        (5, &[0x80, 0x00, 0x00, 0x00, 0x00, 0x00], -(1 << 47)),
+        (5, &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00], 1 << 32),
+        (
+            6,
+            &[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            i64::MAX,
+        ),
+        (6, &[0x80, 0, 0, 0, 0, 0, 0, 0], i64::MIN),
+        (8, &[], 0),
+        (9, &[], 1),
+    ];
+    for (serial_type, bytes, want) in cases {
+        match col_value(serial_type, bytes, 0, 1, false).unwrap() {
+            ColType::Integer(v) => assert_eq!(v, want, "serial type {serial_type}"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    for serial_type in [10, 11] {
+        assert_eq!(serial_type_size(serial_type).unwrap(), 0);
+        let v = col_value(serial_type, &[], 0, 1, false).unwrap();
+        assert!(matches!(v, ColType::Reserved));
+    }
+}
+
+#[test]
+fn test_from_value() {
+    let text = ColType::Text("héllo".to_string());
+    let blob = ColType::Blob(vec![0, 0xff]);
+    assert_eq!(i64::from_value(&ColType::Integer(-7)), Some(-7));
+    assert_eq!(i64::from_value(&ColType::Float(1.0)), None);
+    assert_eq!(i64::from_value(&text), None);
+    assert_eq!(f64::from_value(&ColType::Float(2.5)), Some(2.5));
+    // integers widen to REAL only when the double is exact
+    assert_eq!(f64::from_value(&ColType::Integer(3)), Some(3.0));
+    assert_eq!(
+        f64::from_value(&ColType::Integer(1 << 60)),
+        Some((1u64 << 60) as f64)
+    );
+    assert_eq!(f64::from_value(&ColType::Integer((1 << 53) + 1)), None);
+    assert_eq!(f64::from_value(&ColType::Integer(i64::MAX)), None);
+    assert_eq!(f64::from_value(&text), None);
+    assert_eq!(String::from_value(&text), Some("héllo".to_string()));
+    assert_eq!(String::from_value(&ColType::Integer(1)), None);
+    assert_eq!(String::from_value(&blob), None);
+    assert_eq!(Vec::<u8>::from_value(&blob), Some(vec![0, 0xff]));
+    assert_eq!(
+        Vec::<u8>::from_value(&ColType::Text("ab".to_string())),
+        Some(b"ab".to_vec())
+    );
+    assert_eq!(Vec::<u8>::from_value(&ColType::Integer(1)), None);
+    assert_eq!(bool::from_value(&ColType::Integer(0)), Some(false));
+    assert_eq!(bool::from_value(&ColType::Integer(-2)), Some(true));
+    assert_eq!(bool::from_value(&ColType::Float(1.0)), None);
+    assert!(matches!(Value::from_value(&blob), Some(ColType::Blob(_))));
+
+    // NULL converts to None and to nothing else
+    for null in [ColType::Null, ColType::Reserved] {
+        assert_eq!(i64::from_value(&null), None);
+        assert_eq!(f64::from_value(&null), None);
+        assert_eq!(String::from_value(&null), None);
+        assert_eq!(Vec::<u8>::from_value(&null), None);
+        assert_eq!(bool::from_value(&null), None);
+        assert_eq!(Option::<i64>::from_value(&null), Some(None));
+        assert_eq!(Option::<f64>::from_value(&null), Some(None));
+        assert_eq!(Option::<String>::from_value(&null), Some(None));
+        assert_eq!(Option::<Vec<u8>>::from_value(&null), Some(None));
+        assert_eq!(Option::<bool>::from_value(&null), Some(None));
+    }
+    assert_eq!(
+        Option::<i64>::from_value(&ColType::Integer(5)),
+        Some(Some(5))
+    );
+    assert_eq!(
+        Option::<f64>::from_value(&ColType::Integer(5)),
+        Some(Some(5.0))
+    );
+    assert_eq!(
+        Option::<String>::from_value(&text),
+        Some(Some("héllo".to_string()))
+    );
+    assert_eq!(
+        Option::<Vec<u8>>::from_value(&blob),
+        Some(Some(vec![0, 0xff]))
+    );
+    assert_eq!(
+        Option::<bool>::from_value(&ColType::Integer(1)),
+        Some(Some(true))
+    );
+    assert_eq!(Option::<i64>::from_value(&text), None);
+}
+
+#[test]
+fn test_sqlite_cmp() {
+    use ColType::*;
+    // ascending as sqlite3 sorts them
+    let sorted = [
+        Null,
+        Float(f64::NAN),
+        Float(f64::NEG_INFINITY),
+        Integer(i64::MIN),
+        Float(-1.5),
+        Integer(-1),
+        Integer(0),
+        Float(0.5),
+        Integer(1),
+        Float(1.5),
+        Integer(i64::MAX),
+        Float(TWO_63),
+        Text(String::new()),
+        Text("1".to_string()),
+        Text("B".to_string()),
+        Text("a".to_string()),
+        Blob(Vec::new()),
+        Blob(vec![0]),
+        Blob(vec![0, 0]),
+        Blob(vec![1]),
+    ];
+    for (i, a) in sorted.iter().enumerate() {
+        for (j, b) in sorted.iter().enumerate() {
+            assert_eq!(a.cmp(b), i.cmp(&j), "{a:?} vs {b:?}");
+        }
+    }
+
+    assert_eq!(Integer(1), Float(1.0));
+    assert_eq!(Float(0.0), Float(-0.0));
+    assert_eq!(Null, Reserved);
+    assert_eq!(Float(f64::NAN), Float(f64::NAN));
+    assert_ne!(Integer(1), Text("1".to_string()));
+    assert_ne!(Text("a".to_string()), Blob(b"a".to_vec()));
+    assert!(Integer(9_007_199_254_740_993) > Float(9_007_199_254_740_992.0));
+
+    let text = |s: &str| Text(s.to_string());
+    assert!(text("B").sqlite_cmp(&text("a"), Collation::Binary).is_lt());
+    assert!(text("é").sqlite_cmp(&text("z"), Collation::Binary).is_gt());
+    assert!(text("B").sqlite_cmp(&text("a"), Collation::NoCase).is_gt());
+    assert!(
+        text("ABC")
+            .sqlite_cmp(&text("abc"), Collation::NoCase)
+            .is_eq()
+    );
+    assert!(text("x  ").sqlite_cmp(&text("x"), Collation::RTrim).is_eq());
+    assert!(
+        text("x  ")
+            .sqlite_cmp(&text("x"), Collation::Binary)
+            .is_gt()
+    );
+
+    let hash = |v: &ColType| {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        v.hash(&mut h);
+        h.finish()
+    };
+    for (a, b) in [
+        (Integer(3), Float(3.0)),
+        (Float(0.0), Float(-0.0)),
+        (Null, Reserved),
+        (Float(f64::NAN), Float(-f64::NAN)),
+        (Integer(i64::MIN), Float(-TWO_63)),
+    ] {
+        assert_eq!(hash(&a), hash(&b), "{a:?} vs {b:?}");
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::btree;
+use crate::format::sql_literal;
+use crate::pager::Pager;
+use crate::parser;
+use crate::record::{ColType, Value};
+
+/// What [`Database::recover`](crate::Database::recover) reads back from the
+/// table leaf pages of a database, however broken the b-trees above them are.
+///
+/// Displayed, it's the SQL script `.recover` prints: the CREATE TABLE and
+/// INSERT statements that rebuild every table with the rows that were found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    /// The tables of the schema in schema order, then the unknown ones.
+    pub tables: Vec<RecoveredTable>,
+}
+
+/// A table of a [`Recovery`] and the rows found for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveredTable {
+    /// The table's name, or `unknown_table_N` for rows on pages no table of
+    /// the schema leads to, N being the topmost page above them.
+    pub name: String,
+    /// The CREATE TABLE statement, None for an unknown table.
+    pub sql: Option<String>,
+    /// The column names, `c0`, `c1`, ... for an unknown table.
+    pub columns: Vec<String>,
+    /// The column aliasing the rowid, whose value the rowid is.
+    pub rowid_column: Option<usize>,
+    /// Each row's rowid and values, in rowid order.
+    pub rows: Vec<(i64, Vec<Value>)>,
+}
+
+// recover reads every page of the file in turn. Table leaf pages that parse
+// give their well-formed cells, and interior table pages tell which page is
+// whose child, so each leaf belongs to the topmost page above it: page 1 for
+// the schema, a table's root page for its rows.
+pub fn recover(pager: &Pager) -> Result<Recovery> {
+    let mut parents = HashMap::new();
+    let mut leaves = Vec::new();
+    let mut number = 1;
+    // a raw read only fails past the end of the file
+    while pager.page(number - 1, true).is_ok() {
+        match pager.page(number - 1, false) {
+            Ok(p) if p.page_type == 0x05 => {
+                for child in btree::children(&p).unwrap_or_default() {
+                    parents.entry(child).or_insert(number);
+                }
+            }
+            Ok(p) if p.page_type == 0x0d => {
+                let rows = (0..p.cell_offsets.len())
+                    .filter_map(|i| btree::table_row(&p, i, pager).ok())
+                    .collect::<Vec<_>>();
+                leaves.push((number, rows));
+            }
+            _ => {}
+        }
+        number += 1;
+    }
+    let top = |mut page: usize| {
+        let mut seen = HashSet::from([page]);
+        while let Some(&parent) = parents.get(&page) {
+            if !seen.insert(parent) {
+                break;
+            }
+            page = parent;
+        }
+        page
+    };
+
+    // the schema rows that could be read: type, name, tbl_name, rootpage, sql
+    let mut tables = Vec::new();
+    let mut roots = HashMap::new();
+    for (_, rows) in leaves.iter().filter(|(page, _)| top(*page) == 1) {
+        for (_, values) in rows {
+            let [
+                ColType::Text(kind),
+                _,
+                ColType::Text(name),
+                ColType::Integer(root),
+                ColType::Text(sql),
+            ] = &values[..]
+            else {
+                continue;
+            };
+            if kind != "table" || *root <= 0 {
+                continue;
+            }
+            let t = parser::parse_create(sql).ok();
+            roots.insert(*root as usize, tables.len());
+            tables.push(RecoveredTable {
+                name: name.clone(),
+                sql: Some(sql.clone()),
+                columns: t.as_ref().map_or_else(Vec::new, |t| {
+                    t.columns.iter().map(|c| c.name.clone()).collect()
+                }),
+                rowid_column: t.as_ref().and_then(|t| {
+                    let alias = t.rowid_column()?;
+                    t.columns.iter().position(|c| c.name == alias)
+                }),
+                rows: Vec::new(),
+            });
+        }
+    }
+
+    let mut unknown = HashMap::new();
+    for (page, rows) in leaves {
+        let root = top(page);
+        if root == 1 {
+            continue;
+        }
+        for (rowid, values) in rows {
+            let known = roots
+                .get(&root)
+                .filter(|&&i| values.len() <= tables[i].columns.len());
+            let i = match known {
+                Some(&i) => i,
+                // rows a table's columns can't hold are as good as unknown
+                None => *unknown.entry(root).or_insert_with(|| {
+                    tables.push(RecoveredTable {
+                        name: format!("unknown_table_{root}"),
+                        sql: None,
+                        columns: Vec::new(),
+                        rowid_column: None,
+                        rows: Vec::new(),
+                    });
+                    tables.len() - 1
+                }),
+            };
+            let t = &mut tables[i];
+            if t.sql.is_none() && t.columns.len() < values.len() {
+                t.columns = (0..values.len()).map(|c| format!("c{c}")).collect();
+            }
+            t.rows.push((rowid, values));
+        }
+    }
+    // a page freed but still holding old cells can repeat a rowid, the first
+    // page read keeps it
+    for t in &mut tables {
+        t.rows.sort_by_key(|(rowid, _)| *rowid);
+        t.rows.dedup_by_key(|(rowid, _)| *rowid);
+    }
+    Ok(Recovery { tables })
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "BEGIN;")?;
+        // sqlite3 makes sqlite_sequence along with the first AUTOINCREMENT
+        // table, and adds a row to it for each such table the inserts fill,
+        // so its own rows replace those once every other table is filled
+        let (sequence, tables): (Vec<_>, Vec<_>) = self
+            .tables
+            .iter()
+            .partition(|t| t.sql.is_some() && t.name == "sqlite_sequence");
+        for t in tables.into_iter().chain(sequence) {
+            match &t.sql {
+                Some(_) if t.name == "sqlite_sequence" => {
+                    if !t.rows.is_empty() {
+                        writeln!(f, "DELETE FROM \"sqlite_sequence\";")?;
+                    }
+                }
+                Some(sql) => writeln!(f, "{sql};")?,
+                None => {
+                    let columns = t.columns.iter().map(|c| ident(c)).collect::<Vec<_>>();
+                    writeln!(
+                        f,
+                        "CREATE TABLE {}({});",
+                        ident(&t.name),
+                        columns.join(", ")
+                    )?;
+                }
+            }
+            for (rowid, values) in &t.rows {
+                let mut names = vec!["rowid".to_string()];
+                let mut literals = vec![rowid.to_string()];
+                for (i, v) in values.iter().enumerate() {
+                    if t.rowid_column != Some(i) {
+                        names.push(ident(&t.columns[i]));
+                        literals.push(sql_literal(v));
+                    }
+                }
+                writeln!(
+                    f,
+                    "INSERT INTO {}({}) VALUES({});",
+                    ident(&t.name),
+                    names.join(", "),
+                    literals.join(", ")
+                )?;
+            }
+        }
+        writeln!(f, "COMMIT;")
+    }
+}
+
+fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
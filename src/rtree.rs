@@ -0,0 +1,323 @@
+use anyhow::{Result, bail};
+
+use crate::btree::TableCursor;
+use crate::error::SqliteError;
+use crate::expr::Expr;
+use crate::pager::Pager;
+use crate::parser::CreateTableStmt;
+use crate::record::{ColType, Value};
+use crate::schema::{column_index, is_rowid_alias};
+
+// An rtree's nodes are the blobs of its {name}_node table, keyed by node
+// number, the root being node 1. A node starts with the depth of the tree,
+// only meaningful in the root, and its cell count, then every cell is an
+// 8-byte id and the box: a 4-byte min and max per dimension. A leaf cell's
+// id is the row's, an interior one's the child node's.
+// https://sqlite.org/rtree.html
+
+// Constraint is a comparison the WHERE clause ANDs in that an rtree can use:
+// the id equal to a value, or a coordinate compared with one, by sqlite3's
+// letter for the comparison.
+enum Constraint<'e> {
+    Rowid(&'e Expr),
+    Bound {
+        coordinate: usize,
+        op: char,
+        value: &'e Expr,
+    },
+}
+
+fn constraints<'e>(t: &CreateTableStmt, filter: Option<&'e Expr>) -> Vec<Constraint<'e>> {
+    fn walk<'e>(e: &'e Expr, t: &CreateTableStmt, out: &mut Vec<Constraint<'e>>) {
+        let Expr::Binary { op, lhs, rhs, .. } = e else {
+            return;
+        };
+        if op == "and" {
+            walk(lhs, t, out);
+            walk(rhs, t, out);
+            return;
+        }
+        // a value on the left compares the other way round
+        let (column, value, op) = match (&**lhs, &**rhs) {
+            (Expr::Column(c), value) => (c, value, op.as_str()),
+            (value, Expr::Column(c)) => (
+                c,
+                value,
+                match op.as_str() {
+                    "<" => ">",
+                    "<=" => ">=",
+                    ">" => "<",
+                    ">=" => "<=",
+                    op => op,
+                },
+            ),
+            _ => return,
+        };
+        if !value.columns().is_empty() {
+            return;
+        }
+        let i = column_index(&t.columns, column);
+        if op == "=" && (i == Some(0) || (i.is_none() && is_rowid_alias(column))) {
+            out.push(Constraint::Rowid(value));
+            return;
+        }
+        let coordinates = t.rtree.as_ref().map_or(0, |r| r.coordinates);
+        let op = match op {
+            "=" => 'A',
+            "<=" => 'B',
+            "<" => 'C',
+            ">=" => 'D',
+            ">" => 'E',
+            _ => return,
+        };
+        if let Some(i) = i.filter(|i| (1..=coordinates).contains(i)) {
+            out.push(Constraint::Bound {
+                coordinate: i - 1,
+                op,
+                value,
+            });
+        }
+    }
+    let mut out = Vec::new();
+    if let Some(e) = filter {
+        walk(e, t, &mut out);
+    }
+    out
+}
+
+// index spells the index string of an rtree scan as sqlite3 does: `1:` for a
+// lookup of the id, else `2:` then each constraint on a coordinate as its
+// letter and the coordinate's number.
+pub fn index(t: &CreateTableStmt, filter: Option<&Expr>) -> String {
+    let constraints = constraints(t, filter);
+    if constraints
+        .iter()
+        .any(|c| matches!(c, Constraint::Rowid(_)))
+    {
+        return "1:".to_string();
+    }
+    let mut index = "2:".to_string();
+    for c in constraints {
+        if let Constraint::Bound { coordinate, op, .. } = c {
+            index.push(op);
+            index.push_str(&coordinate.to_string());
+        }
+    }
+    index
+}
+
+// RtreeScan is what a query reads of an rtree: where its shadow tables are,
+// and what the WHERE clause lets it skip. The WHERE clause still decides
+// which rows match.
+#[derive(Debug, Clone)]
+pub struct RtreeScan {
+    node_root: usize,
+    rowid_root: usize,
+    int32: bool,
+    coordinates: usize,
+    aux: usize,
+    rowid: Option<i64>,
+    bounds: Vec<(usize, char, f64)>,
+}
+
+impl RtreeScan {
+    // new evaluates the constraints of a bound filter. Those with a value
+    // that isn't a number can't skip anything, and are left to the filter.
+    pub fn new(
+        t: &CreateTableStmt,
+        node_root: usize,
+        rowid_root: usize,
+        filter: Option<&Expr>,
+    ) -> Result<Self> {
+        let rtree = t.rtree.clone().unwrap_or_default();
+        let mut scan = RtreeScan {
+            node_root,
+            rowid_root,
+            int32: rtree.int32,
+            coordinates: rtree.coordinates,
+            aux: t.columns.len() - 1 - rtree.coordinates,
+            rowid: None,
+            bounds: Vec::new(),
+        };
+        for c in constraints(t, filter) {
+            match c {
+                Constraint::Rowid(value) => {
+                    if let ColType::Integer(rowid) = value.eval(&|_| None)? {
+                        scan.rowid = Some(rowid);
+                    }
+                }
+                Constraint::Bound {
+                    coordinate,
+                    op,
+                    value,
+                } => match value.eval(&|_| None)? {
+                    ColType::Integer(i) => scan.bounds.push((coordinate, op, i as f64)),
+                    ColType::Float(f) => scan.bounds.push((coordinate, op, f)),
+                    _ => {}
+                },
+            }
+        }
+        Ok(scan)
+    }
+}
+
+// RtreeCursor reads the rows of an rtree depth first, as sqlite3 does,
+// leaving out the subtrees whose box can't hold a row within the bounds.
+pub struct RtreeCursor<'p> {
+    pager: &'p Pager,
+    scan: RtreeScan,
+    depth: usize,
+    // the nodes on the way down from the root, with the next cell of each
+    stack: Vec<(Vec<u8>, usize)>,
+    started: bool,
+}
+
+impl<'p> RtreeCursor<'p> {
+    pub fn new(pager: &'p Pager, scan: RtreeScan) -> Self {
+        RtreeCursor {
+            pager,
+            scan,
+            depth: 0,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    // next_record returns the next row's id and values: the id, the
+    // coordinates and the auxiliary columns.
+    pub fn next_record(&mut self) -> Result<Option<(i64, Vec<Value>)>> {
+        if !self.started {
+            self.started = true;
+            match self.scan.rowid {
+                Some(rowid) => return self.lookup(rowid),
+                None => {
+                    let root = self.node(1)?;
+                    self.depth = u16::from_be_bytes([root[0], root[1]]) as usize;
+                    // sqlite3 never builds a deeper tree
+                    if self.depth > 40 {
+                        bail!(corrupt(format!("depth {} is too deep", self.depth)));
+                    }
+                    self.stack.push((root, 0));
+                }
+            }
+        }
+        while let Some((node, i)) = self.stack.last() {
+            let Some((id, coordinates)) = self.cell(node, *i)? else {
+                self.stack.pop();
+                continue;
+            };
+            self.stack.last_mut().unwrap().1 += 1;
+            if self.stack.len() > self.depth {
+                return self.row(id, coordinates).map(Some);
+            }
+            if self.may_hold(&coordinates) {
+                let child = self.node(id)?;
+                self.stack.push((child, 0));
+            }
+        }
+        Ok(None)
+    }
+
+    // lookup reads the row `rowid` alone, from the leaf {name}_rowid says
+    // holds it.
+    fn lookup(&mut self, rowid: i64) -> Result<Option<(i64, Vec<Value>)>> {
+        let Some(record) = seek(self.pager, self.scan.rowid_root, rowid)? else {
+            return Ok(None);
+        };
+        let Some(&ColType::Integer(nodeno)) = record.get(1) else {
+            bail!(corrupt(format!("no node for row {rowid}")));
+        };
+        let node = self.node(nodeno)?;
+        for i in 0.. {
+            match self.cell(&node, i)? {
+                Some((id, coordinates)) if id == rowid => {
+                    return self.row(id, coordinates).map(Some);
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        bail!(corrupt(format!("row {rowid} isn't in node {nodeno}")));
+    }
+
+    // node reads the blob of node `nodeno`.
+    fn node(&self, nodeno: i64) -> Result<Vec<u8>> {
+        match seek(self.pager, self.scan.node_root, nodeno)?.and_then(|r| r.into_iter().nth(1)) {
+            Some(ColType::Blob(node)) if node.len() >= 4 => Ok(node),
+            _ => bail!(corrupt(format!("node {nodeno} is missing"))),
+        }
+    }
+
+    // cell reads the id and coordinates of cell `i` of a node, None past the
+    // last.
+    fn cell(&self, node: &[u8], i: usize) -> Result<Option<(i64, Vec<f64>)>> {
+        let count = u16::from_be_bytes([node[2], node[3]]) as usize;
+        if i >= count {
+            return Ok(None);
+        }
+        let size = 8 + 4 * self.scan.coordinates;
+        let Some(cell) = node.get(4 + i * size..4 + (i + 1) * size) else {
+            bail!(corrupt(format!("{count} cells don't fit in a node")));
+        };
+        let id = i64::from_be_bytes(cell[..8].try_into().unwrap());
+        let coordinates = cell[8..]
+            .chunks(4)
+            .map(|c| {
+                let c = c.try_into().unwrap();
+                match self.scan.int32 {
+                    true => i32::from_be_bytes(c) as f64,
+                    false => f32::from_be_bytes(c) as f64,
+                }
+            })
+            .collect();
+        Ok(Some((id, coordinates)))
+    }
+
+    // may_hold tells whether a box leaves room for rows within every bound:
+    // the coordinates of the rows under an interior cell are all within the
+    // min and max of their dimension.
+    fn may_hold(&self, coordinates: &[f64]) -> bool {
+        self.scan.bounds.iter().all(|&(coordinate, op, v)| {
+            let dimension = coordinate / 2;
+            let (min, max) = (coordinates[2 * dimension], coordinates[2 * dimension + 1]);
+            match op {
+                'A' => min <= v && v <= max,
+                'B' => min <= v,
+                'C' => min < v,
+                'D' => max >= v,
+                _ => max > v,
+            }
+        })
+    }
+
+    // row is what a leaf cell holds, with the auxiliary columns from its
+    // {name}_rowid record.
+    fn row(&self, id: i64, coordinates: Vec<f64>) -> Result<(i64, Vec<Value>)> {
+        let mut values = vec![ColType::Integer(id)];
+        values.extend(coordinates.into_iter().map(|c| match self.scan.int32 {
+            true => ColType::Integer(c as i64),
+            false => ColType::Float(c),
+        }));
+        if self.scan.aux > 0 {
+            let record = seek(self.pager, self.scan.rowid_root, id)?.unwrap_or_default();
+            let mut aux = record.into_iter().skip(2).collect::<Vec<_>>();
+            aux.resize(self.scan.aux, ColType::Null);
+            values.extend(aux);
+        }
+        Ok((id, values))
+    }
+}
+
+// seek reads the record `rowid` of the table rooted at `rootpage`.
+fn seek(pager: &Pager, rootpage: usize, rowid: i64) -> Result<Option<Vec<Value>>> {
+    let mut cursor = TableCursor::new(pager, rootpage);
+    cursor.seek(rowid)?;
+    Ok(match cursor.next().transpose()? {
+        Some((r, record)) if r == rowid => Some(record),
+        _ => None,
+    })
+}
+
+fn corrupt(what: String) -> SqliteError {
+    SqliteError::CorruptDatabase(format!("rtree {what}"))
+}
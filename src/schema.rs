@@ -0,0 +1,435 @@
+use anyhow::{Result, bail};
+use log::{debug, warn};
+use std::collections::HashMap;
+
+use crate::btree::{OnColumn, scan_btree};
+use crate::error::SqliteError;
+use crate::expr::Affinity;
+use crate::pager::Pager;
+use crate::parser;
+use crate::record::ColType;
+use crate::tvf::FunctionRegistry;
+
+#[derive(Debug, Clone)]
+pub enum Create {
+    Table(parser::CreateTableStmt),
+    Index(parser::CreateIndexStmt),
+    Trigger(parser::TriggerDef),
+    Null,
+}
+
+/// What a row of sqlite_schema describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SchemaKind {
+    /// A table, virtual tables included.
+    Table,
+    /// An index, one sqlite makes itself included.
+    Index,
+    /// A view.
+    View,
+    /// A trigger.
+    Trigger,
+}
+
+/// One row of sqlite_schema.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SchemaEntry {
+    /// What kind of object it is.
+    pub kind: SchemaKind,
+    /// The object's name.
+    pub name: String,
+    /// The table it belongs to, its own name for a table or view.
+    pub table: String,
+    /// The root page of its b-tree, 0 for views and triggers.
+    pub rootpage: usize,
+    /// The CREATE statement, None for the indexes sqlite makes itself for
+    /// UNIQUE and PRIMARY KEY constraints.
+    pub sql: Option<String>,
+}
+
+/// A table as the schema declares it, with its indexes.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableInfo {
+    /// The table's name, as sqlite_schema has it.
+    pub name: String,
+    /// The root page of its b-tree, the one of its content table for an
+    /// FTS5 table and of its `_rowid` table for an R*Tree.
+    pub rootpage: usize,
+    /// Its columns in declaration order.
+    pub columns: Vec<parser::ColumnDef>,
+    /// Declared WITHOUT ROWID, so its rows are kept in an index b-tree keyed
+    /// by the primary key. Such tables can't be queried yet.
+    pub without_rowid: bool,
+    /// Declared STRICT, so every value has its column's declared type.
+    pub strict: bool,
+    /// Its indexes in schema order, the automatic ones included.
+    pub indexes: Vec<IndexInfo>,
+}
+
+/// An index of a [`TableInfo`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexInfo {
+    /// The index's name.
+    pub name: String,
+    /// The root page of its b-tree.
+    pub rootpage: usize,
+    /// The indexed columns, or the SQL text of indexed expressions. Empty for
+    /// an automatic index, which has no CREATE INDEX to read them from.
+    pub columns: Vec<String>,
+    /// Declared UNIQUE, or made for a UNIQUE or PRIMARY KEY constraint.
+    pub unique: bool,
+    /// The CREATE INDEX statement, None for an automatic index.
+    pub sql: Option<String>,
+}
+
+/// Two rows a UNIQUE index holds the same key for, as
+/// [`Database::check_unique`](crate::Database::check_unique) finds them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UniqueViolation {
+    /// The UNIQUE index holding the key twice.
+    pub index_name: String,
+    /// The values of the indexed columns both entries hold.
+    pub key_value: Vec<ColType>,
+    /// The rowids of the two rows, the one the index sorts first before the
+    /// other.
+    pub rowids: [i64; 2],
+}
+
+const SCHEMA_SQL: &str =
+    "CREATE TABLE sqlite_schema (type text, name text, tbl_name text, rootpage integer, sql text)";
+
+// Tables is the schema read from sqlite_schema on page 1.
+#[derive(Debug)]
+pub struct Tables {
+    // state
+    cur_tbl_name: String,
+    cur_name: String,
+    cur_rootpage: usize,
+    cur_create: Create,
+    cur_sql: Option<String>,
+    create_type: String,
+    schema_format: u32,
+    // the largest root page of an auto-vacuum database, 0 otherwise
+    largest_root_page: usize,
+
+    pub names: Vec<String>,               // every table in schema order
+    pub pos: HashMap<String, usize>,      // key: name, value: rootpage
+    pub content: HashMap<String, Create>, // key: name, value: Table with column names
+    // TODO: we only support one index per table
+    pub indexes: HashMap<String, (String, String)>, // key: tbl_name,
+    // value: (col_name,  index_name/name)
+    pub triggers: HashMap<String, Vec<parser::TriggerDef>>, // key: tbl_name, in schema order
+    pub entries: Vec<SchemaEntry>,                          // every row of sqlite_schema, in order
+    pub info: Vec<TableInfo>,                               // every table in schema order
+    pub functions: FunctionRegistry, // the table-valued functions FROM can call
+    error: Option<SqliteError>,
+}
+
+impl OnColumn for Tables {
+    fn on_col(&mut self, _cur_type: u8, _row: usize, col: usize, v: &ColType, _rowid: i64) {
+        // schema: type name tbl_name rootpage sql
+        if col == 0 {
+            self.create_type = v.to_string()
+        }
+        if col == 1 {
+            self.cur_name = v.to_string()
+        }
+        if col == 2
+            && let ColType::Text(text) = v
+        {
+            self.cur_tbl_name = text.clone();
+        }
+        if col == 3
+            && let ColType::Integer(o) = v
+        {
+            self.cur_rootpage = *o as usize;
+        }
+        if col == 4
+            && let ColType::Text(sql) = v
+        {
+            debug!("schema sql: {sql}");
+            self.cur_sql = Some(sql.clone());
+            let cols = match self.create_type.as_str() {
+                "index" => parser::parse_create_index(sql).map(Create::Index),
+                "trigger" => parser::parse_create_trigger(sql).map(Create::Trigger),
+                // views can't be queried yet, only listed by .schema
+                "view" => Ok(Create::Null),
+                // only fts5 and rtree virtual tables can be queried, the
+                // others are listed but have no rows
+                _ if parser::is_create_virtual(sql) => {
+                    parser::parse_create_virtual(sql).map(|t| t.map_or(Create::Null, Create::Table))
+                }
+                _ => parser::parse_create(sql).map(Create::Table),
+            };
+            match cols {
+                Ok(cols) => self.cur_create = cols,
+                Err(e) => {
+                    // keep the first failure, on_col can't return it
+                    self.error
+                        .get_or_insert(SqliteError::CorruptDatabase(format!(
+                            "malformed database schema ({}) - {e}",
+                            self.cur_name
+                        )));
+                }
+            }
+        }
+    }
+
+    fn on_row(&mut self, cur_type: u8, _rowid: i64) {
+        // a schema too big for page 1 has interior cells, they hold no row
+        if cur_type != 0x0d {
+            return;
+        }
+        debug!(
+            "cur_name:{}, cur_create:{:?}",
+            self.cur_name, self.cur_create
+        );
+        // The CREATE statement may name the table differently from the schema's
+        // tbl_name (e.g. a hand-edited or renamed schema), tbl_name wins.
+        let declared = match &self.cur_create {
+            Create::Table(t) => &t.table,
+            Create::Index(i) => &i.table,
+            Create::Trigger(t) => &t.table,
+            Create::Null => &self.cur_tbl_name,
+        };
+        if !declared.eq_ignore_ascii_case(&self.cur_tbl_name) {
+            warn!(
+                "{} {} refers to table {} but the schema says {}",
+                self.create_type, self.cur_name, declared, self.cur_tbl_name
+            );
+        }
+        // auto-vacuum moves every root page below the pages it can move, so
+        // a root past the largest one the header knows is damage
+        if self.largest_root_page > 0 && self.cur_rootpage > self.largest_root_page {
+            self.error
+                .get_or_insert(SqliteError::CorruptDatabase(format!(
+                    "malformed database schema ({}) - root page {} is past the largest root page {}",
+                    self.cur_name, self.cur_rootpage, self.largest_root_page
+                )));
+        }
+        let kind = match self.create_type.as_str() {
+            "index" => SchemaKind::Index,
+            "view" => SchemaKind::View,
+            "trigger" => SchemaKind::Trigger,
+            _ => SchemaKind::Table,
+        };
+        self.entries.push(SchemaEntry {
+            kind,
+            name: self.cur_name.clone(),
+            table: self.cur_tbl_name.clone(),
+            rootpage: self.cur_rootpage,
+            sql: self.cur_sql.take(),
+        });
+        match std::mem::replace(&mut self.cur_create, Create::Null) {
+            Create::Trigger(t) => {
+                self.triggers
+                    .entry(self.cur_tbl_name.clone())
+                    .or_default()
+                    .push(t);
+                return;
+            }
+            _ if self.create_type == "view" => return,
+            create => self.cur_create = create,
+        }
+        let name = if self.create_type == "table" {
+            self.cur_tbl_name.clone()
+        } else {
+            self.cur_name.clone()
+        };
+        if self.create_type == "table" {
+            self.names.push(name.clone());
+        }
+        self.pos.insert(name.clone(), self.cur_rootpage);
+        self.content.insert(name, self.cur_create.clone());
+        // only indexes on plain columns can serve lookups, on their leading
+        // column; one of several columns or a partial one only while the
+        // table has no other
+        if let Create::Index(i) = &self.cur_create
+            && !i.expression
+            && (i.columns.len() == 1 && i.predicate.is_none()
+                || !self.indexes.contains_key(&self.cur_tbl_name))
+        {
+            self.indexes.insert(
+                self.cur_tbl_name.clone(),
+                (i.columns[0].clone(), i.name.clone()),
+            );
+        }
+        // autoindexes have no sql, don't let them inherit the previous row's
+        self.cur_create = Create::Null;
+    }
+
+    fn finalize(&mut self) {}
+}
+
+impl Tables {
+    // new reads the schema table, whose root is page 1.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "schema", skip_all)
+    )]
+    pub fn new(pager: &Pager) -> Result<Self> {
+        let mut res = Tables {
+            names: Vec::new(),
+            pos: HashMap::new(),
+            content: HashMap::new(),
+            cur_tbl_name: String::new(),
+            cur_name: String::new(),
+            cur_rootpage: 0,
+            cur_create: Create::Null,
+            create_type: "table".to_string(),
+            cur_sql: None,
+            schema_format: pager.dbinfo().schema_format,
+            largest_root_page: pager.dbinfo().header.largest_root_page as usize,
+            indexes: HashMap::new(),
+            triggers: HashMap::new(),
+            entries: Vec::new(),
+            info: Vec::new(),
+            functions: FunctionRegistry::new(),
+            error: None,
+        };
+
+        scan_btree(&pager.page(0, false)?, &mut res, pager, None, None)?;
+        if let Some(e) = res.error.take() {
+            bail!(e);
+        }
+        // an fts5 table has no b-tree of its own, its rows are the records
+        // of its content table; an rtree's are the nodes of {name}_node, and
+        // {name}_rowid has a record per row
+        for name in &res.names {
+            let shadow = match res.content.get(name) {
+                Some(Create::Table(t)) if t.fts5.as_ref().is_some_and(|f| f.content.is_none()) => {
+                    "content"
+                }
+                Some(Create::Table(t)) if t.rtree.is_some() => "rowid",
+                _ => continue,
+            };
+            if let Some(&rootpage) = res.pos.get(&format!("{name}_{shadow}")) {
+                res.pos.insert(name.clone(), rootpage);
+            }
+        }
+        res.info = res.names.iter().map(|name| res.table_info(name)).collect();
+        // the schema table describes itself nowhere, it's always rooted at page 1
+        let schema = parser::parse_create(SCHEMA_SQL).map_err(SqliteError::SqlParse)?;
+        for name in ["sqlite_schema", "sqlite_master"] {
+            res.pos.insert(name.to_string(), 1);
+            res.content
+                .insert(name.to_string(), Create::Table(schema.clone()));
+        }
+        Ok(res)
+    }
+
+    // objects_of_type yields the rows of sqlite_schema describing a `kind`,
+    // in schema order.
+    pub fn objects_of_type(&self, kind: SchemaKind) -> impl Iterator<Item = &SchemaEntry> {
+        self.entries.iter().filter(move |e| e.kind == kind)
+    }
+
+    // index_desc tells, key by key, whether the index `name` keeps it in
+    // descending order: DESC keys are, from schema format 4 on.
+    pub(crate) fn index_desc(&self, name: &str) -> Vec<bool> {
+        match self.content.get(name) {
+            Some(Create::Index(i)) if self.schema_format >= 4 => i.descending.clone(),
+            Some(Create::Index(i)) => vec![false; i.descending.len()],
+            _ => Vec::new(),
+        }
+    }
+
+    // table_info gathers what the schema says about the table `name`.
+    fn table_info(&self, name: &str) -> TableInfo {
+        let (columns, without_rowid, strict) = match self.content.get(name) {
+            Some(Create::Table(t)) => (t.declared_columns().to_vec(), t.without_rowid, t.strict),
+            _ => (Vec::new(), false, false),
+        };
+        let indexes = self
+            .objects_of_type(SchemaKind::Index)
+            .filter(|e| e.table.eq_ignore_ascii_case(name))
+            .map(|e| {
+                let def = match self.content.get(&e.name) {
+                    Some(Create::Index(i)) => Some(i),
+                    _ => None,
+                };
+                IndexInfo {
+                    name: e.name.clone(),
+                    rootpage: e.rootpage,
+                    columns: def.map_or_else(Vec::new, |i| i.columns.clone()),
+                    unique: def.is_none_or(|i| i.unique),
+                    sql: e.sql.clone(),
+                }
+            })
+            .collect();
+        TableInfo {
+            name: name.to_string(),
+            rootpage: self.pos[name],
+            columns,
+            without_rowid,
+            strict,
+            indexes,
+        }
+    }
+}
+
+pub fn column_index(columns: &[parser::ColumnDef], name: &str) -> Option<usize> {
+    columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name))
+}
+
+// column_affinity is the affinity of the column `name` of a table, the
+// rowid's included, for comparing it.
+pub fn column_affinity(t: &parser::CreateTableStmt, name: &str) -> Option<Affinity> {
+    match column_index(&t.columns, name) {
+        Some(i) => Some(t.columns[i].affinity()),
+        None if is_rowid_alias(name) => Some(Affinity::Numeric),
+        None => None,
+    }
+}
+
+pub fn is_rowid_alias(name: &str) -> bool {
+    ["rowid", "_rowid_", "oid"]
+        .iter()
+        .any(|r| name.eq_ignore_ascii_case(r))
+}
+
+#[test]
+fn test_logs_page_reads() {
+    use std::sync::Mutex;
+
+    struct Capture(Mutex<Vec<String>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let line = format!("{} {}", record.level(), record.args());
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let pager = Pager::open(std::fs::File::open("sample.db").unwrap()).unwrap();
+    Tables::new(&pager).unwrap();
+    let lines = CAPTURE.0.lock().unwrap();
+    assert!(
+        lines
+            .iter()
+            .any(|l| l == "DEBUG read page 1 (table leaf, 3 cells)")
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.starts_with("DEBUG schema sql: CREATE TABLE apples"))
+    );
+    assert!(!lines.iter().any(|l| l.starts_with("TRACE")));
+}
@@ -0,0 +1,247 @@
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::fmt;
+
+use crate::exec::Row;
+use crate::record::Value;
+
+/// NULL serializes as none, integers and reals as themselves and text as a
+/// string. A blob is bytes in binary formats and a base64 string in
+/// human-readable ones like JSON, where NaN and the infinities, which have
+/// no number, are none.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null | Value::Reserved => serializer.serialize_none(),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::Float(f) if !f.is_finite() && serializer.is_human_readable() => {
+                serializer.serialize_none()
+            }
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Text(s) => serializer.serialize_str(s),
+            Value::Blob(b) if serializer.is_human_readable() => {
+                serializer.serialize_str(&base64(b))
+            }
+            Value::Blob(b) => serializer.serialize_bytes(b),
+        }
+    }
+}
+
+/// The storage class follows the serialized type: none or unit is NULL, a
+/// string is text and bytes or a sequence of bytes are a blob. A blob that
+/// went through a human-readable format comes back as its base64 text.
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("NULL, an integer, a real, text or a blob")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    // past i64::MAX only a REAL holds it, as in sqlite
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(i64::try_from(v).map_or(Value::Float(v as f64), Value::Integer))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Text(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Blob(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        Ok(Value::Blob(bytes))
+    }
+}
+
+/// A row serializes as the sequence of its values, see [`Row::as_map`] for
+/// one keyed by column name.
+impl Serialize for Row {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.column_count()))?;
+        for v in self.values() {
+            seq.serialize_element(v)?;
+        }
+        seq.end()
+    }
+}
+
+/// A row deserializes from a map of column names to values, as a
+/// [`RowMap`] serializes, or from a sequence of values, whose columns are
+/// then named `column1`, `column2`... like those of a VALUES clause.
+impl<'de> Deserialize<'de> for Row {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RowVisitor)
+    }
+}
+
+struct RowVisitor;
+
+impl<'de> Visitor<'de> for RowVisitor {
+    type Value = Row;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of values or a map of column names to values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Row, A::Error> {
+        let mut values = Vec::new();
+        while let Some(v) = seq.next_element()? {
+            values.push(v);
+        }
+        let columns = (1..=values.len()).map(|i| format!("column{i}")).collect();
+        Ok(Row::new(columns, values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Row, A::Error> {
+        let (mut columns, mut values) = (Vec::new(), Vec::new());
+        while let Some((name, v)) = map.next_entry()? {
+            columns.push(name);
+            values.push(v);
+        }
+        Ok(Row::new(columns.into(), values))
+    }
+}
+
+/// A view of a row that serializes as a map from column name to value, e.g.
+/// a JSON object like those of sqlite3's json mode. Made by [`Row::as_map`].
+///
+/// Columns keep their result order. Like sqlite3, a name that two columns
+/// share appears twice.
+#[derive(Debug, Clone, Copy)]
+pub struct RowMap<'a> {
+    columns: &'a [String],
+    values: &'a [Value],
+}
+
+impl<'a> RowMap<'a> {
+    pub(crate) fn new(columns: &'a [String], values: &'a [Value]) -> Self {
+        RowMap { columns, values }
+    }
+}
+
+impl Serialize for RowMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.values.len()))?;
+        for (name, v) in self.columns.iter().zip(self.values) {
+            map.serialize_entry(name, v)?;
+        }
+        map.end()
+    }
+}
+
+// base64 encodes with the standard alphabet and padding of RFC 4648.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_base64() {
+    let cases: [(&[u8], &str); 5] = [
+        (b"", ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (&[0x00, 0xff, 0x10, 0x80], "AP8QgA=="),
+    ];
+    for (bytes, want) in cases {
+        assert_eq!(base64(bytes), want);
+    }
+}
+
+#[test]
+fn test_serialize_values() {
+    let names = ["id", "score", "name", "note", "raw", "inf"].map(String::from);
+    let values = [
+        Value::Integer(-3),
+        Value::Float(1.5),
+        Value::Text("a \"b\"".to_string()),
+        Value::Null,
+        Value::Blob(vec![0, 255]),
+        Value::Float(f64::INFINITY),
+    ];
+    let map = serde_json::to_string(&RowMap::new(&names, &values)).unwrap();
+    assert_eq!(
+        map,
+        r#"{"id":-3,"score":1.5,"name":"a \"b\"","note":null,"raw":"AP8=","inf":null}"#
+    );
+
+    let row: Row = serde_json::from_str(&map).unwrap();
+    assert_eq!(row.column_name(4), "raw");
+    assert_eq!(row.values()[..4], values[..4]);
+    assert_eq!(row.values()[4], Value::Text("AP8=".to_string()));
+    assert_eq!(row.values()[5], Value::Null);
+
+    let row: Row = serde_json::from_str("[1, 18446744073709551615, [1, 2], true]").unwrap();
+    assert_eq!(row.column_name(1), "column2");
+    assert_eq!(
+        row.values(),
+        [
+            Value::Integer(1),
+            Value::Float(u64::MAX as f64),
+            Value::Blob(vec![1, 2]),
+            Value::Integer(1),
+        ]
+    );
+    assert!(serde_json::from_str::<Row>("1").is_err());
+}
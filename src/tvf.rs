@@ -0,0 +1,371 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::SqliteError;
+use crate::exec::Row;
+use crate::expr;
+use crate::json::{self, Json};
+use crate::record::ColType;
+
+/// A function a select can read rows from, called in its FROM clause like
+/// `select value from generate_series(1, 10)`.
+///
+/// The rows it returns are then filtered, sorted and aggregated like the
+/// rows of a table. Register one with
+/// [`Database::register_function`](crate::Database::register_function):
+///
+/// ```
+/// use codecrafters_sqlite::{Database, Row, TableValuedFunction, Value};
+/// use std::rc::Rc;
+///
+/// // repeat(value, n) is n rows of value
+/// struct Repeat;
+///
+/// impl TableValuedFunction for Repeat {
+///     fn name(&self) -> &str {
+///         "repeat"
+///     }
+///
+///     fn columns(&self) -> Vec<String> {
+///         vec!["value".to_string()]
+///     }
+///
+///     fn call(&self, args: &[Value]) -> anyhow::Result<Box<dyn Iterator<Item = Row>>> {
+///         let (value, n) = (args[0].clone(), args[1].to_string().parse()?);
+///         let columns: Rc<[String]> = self.columns().into();
+///         let rows = std::iter::repeat_n(value, n).map(move |v| Row::new(columns.clone(), vec![v]));
+///         Ok(Box::new(rows))
+///     }
+/// }
+///
+/// let mut db = Database::open("sample.db")?;
+/// db.register_function(Repeat);
+/// let rows = db.query_to_vec("select value from repeat('ab', 2)")?;
+/// assert_eq!(rows, [[Value::Text("ab".to_string())], [Value::Text("ab".to_string())]]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub trait TableValuedFunction: Send {
+    /// The name the FROM clause calls the function by, matched
+    /// case-insensitively.
+    fn name(&self) -> &str;
+
+    /// The names of the columns of every row, known before any call.
+    fn columns(&self) -> Vec<String>;
+
+    /// The rows for the arguments of one call, each with a value per column
+    /// of [`TableValuedFunction::columns`].
+    fn call(&self, args: &[ColType]) -> Result<Box<dyn Iterator<Item = Row>>>;
+}
+
+/// The table-valued functions a database knows by name, starting with
+/// `generate_series`, `json_each` and `carray`.
+pub struct FunctionRegistry {
+    functions: HashMap<String, Box<dyn TableValuedFunction>>,
+}
+
+impl FunctionRegistry {
+    /// A registry holding the built-in functions.
+    pub fn new() -> Self {
+        let mut registry = FunctionRegistry {
+            functions: HashMap::new(),
+        };
+        registry.register(Box::new(GenerateSeries));
+        registry.register(Box::new(JsonEach));
+        registry.register(Box::new(Carray));
+        registry
+    }
+
+    /// Adds `function`, returning the function it replaces under the same
+    /// name, if any.
+    pub fn register(
+        &mut self,
+        function: Box<dyn TableValuedFunction>,
+    ) -> Option<Box<dyn TableValuedFunction>> {
+        self.functions
+            .insert(function.name().to_ascii_lowercase(), function)
+    }
+
+    /// The function called `name`, matched case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&dyn TableValuedFunction> {
+        self.functions.get(&name.to_ascii_lowercase()).map(|f| &**f)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.functions.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &names)
+            .finish()
+    }
+}
+
+// GenerateSeries is generate_series(start, stop, step): the integers from
+// start to stop, step apart. Like sqlite3, stop defaults to 4294967295 and
+// step to 1, a step of 0 counts as 1, a negative step counts down from start,
+// and a NULL argument gives no rows.
+struct GenerateSeries;
+
+impl TableValuedFunction for GenerateSeries {
+    fn name(&self) -> &str {
+        "generate_series"
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec!["value".to_string()]
+    }
+
+    fn call(&self, args: &[ColType]) -> Result<Box<dyn Iterator<Item = Row>>> {
+        if args.is_empty() {
+            bail!(SqliteError::SqlParse(
+                "first argument to \"generate_series()\" missing or unusable".to_string()
+            ));
+        }
+        if args.len() > 3 {
+            bail!(SqliteError::SqlParse(
+                "too many arguments on generate_series() - max 3".to_string()
+            ));
+        }
+        let int = |v: &ColType| match expr::to_number(v) {
+            ColType::Integer(i) => Some(i),
+            ColType::Float(f) => Some(f as i64),
+            _ => None,
+        };
+        let start = int(&args[0]);
+        let stop = args.get(1).map_or(Some(0xffff_ffff), int);
+        let step = args.get(2).map_or(Some(1), int);
+        let (Some(start), Some(stop), Some(step)) = (start, stop, step) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let step = if step == 0 { 1 } else { step };
+        let columns: Rc<[String]> = self.columns().into();
+        let values = std::iter::successors(Some(start), move |v| v.checked_add(step))
+            .take_while(move |&v| if step > 0 { v <= stop } else { v >= stop });
+        Ok(Box::new(values.map(move |v| {
+            Row::new(columns.clone(), vec![ColType::Integer(v)])
+        })))
+    }
+}
+
+// JsonEach is json_each(json, path): a row per element of the array or
+// member of the object at path, `$` by default, or a single row when that's
+// a primitive. The id column numbers the rows from 0, where sqlite3 gives
+// an offset into its binary JSON, and parent is always NULL.
+struct JsonEach;
+
+impl TableValuedFunction for JsonEach {
+    fn name(&self) -> &str {
+        "json_each"
+    }
+
+    fn columns(&self) -> Vec<String> {
+        [
+            "key", "value", "type", "atom", "id", "parent", "fullkey", "path",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    fn call(&self, args: &[ColType]) -> Result<Box<dyn Iterator<Item = Row>>> {
+        if !(1..=2).contains(&args.len()) {
+            bail!(SqliteError::SqlParse(
+                "wrong number of arguments to function json_each()".to_string()
+            ));
+        }
+        let text = match &args[0] {
+            ColType::Null => return Ok(Box::new(std::iter::empty())),
+            ColType::Blob(_) => bail!("malformed JSON"),
+            v => v.to_string(),
+        };
+        let doc = json::parse(&text).map_err(anyhow::Error::msg)?;
+        let path = match args.get(1) {
+            Some(ColType::Null) => return Ok(Box::new(std::iter::empty())),
+            Some(p) => p.to_string(),
+            None => "$".to_string(),
+        };
+        let steps = json::parse_path(&path).map_err(anyhow::Error::msg)?;
+        let Some(root) = doc.lookup(&steps) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let elements: Vec<(ColType, String, &Json)> = match root {
+            Json::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (ColType::Integer(i as i64), format!("{path}[{i}]"), v))
+                .collect(),
+            Json::Object(members) => members
+                .iter()
+                .map(|(k, v)| {
+                    let full = format!("{path}{}", json::path_key(k));
+                    (ColType::Text(k.clone()), full, v)
+                })
+                .collect(),
+            v => vec![(ColType::Null, path.clone(), v)],
+        };
+        let columns: Rc<[String]> = self.columns().into();
+        let rows = elements
+            .into_iter()
+            .enumerate()
+            .map(|(id, (key, fullkey, v))| {
+                let values = vec![
+                    key,
+                    v.value(),
+                    ColType::Text(v.type_name().to_string()),
+                    v.atom(),
+                    ColType::Integer(id as i64),
+                    ColType::Null,
+                    ColType::Text(fullkey),
+                    ColType::Text(path.clone()),
+                ];
+                Row::new(columns.clone(), values)
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(rows.into_iter()))
+    }
+}
+
+/// Encodes `values` as the JSON array `carray()` reads, to bind to its
+/// parameter:
+///
+/// ```
+/// use codecrafters_sqlite::{Database, Value, array_param};
+///
+/// let db = Database::open("sample.db")?;
+/// let stmt = db.prepare("select value from carray(?)")?;
+/// let ids = array_param(&[Value::Integer(3), Value::Integer(1)])?;
+/// let rows = stmt.query(&[ids])?.collect::<anyhow::Result<Vec<_>>>()?;
+/// assert_eq!(rows[0].get::<i64>(0)?, 3);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// Fails with [`SqliteError::Usage`] for a blob, which JSON can't hold.
+pub fn array_param(values: &[ColType]) -> Result<ColType> {
+    let items = values
+        .iter()
+        .map(|v| {
+            Json::from_value(v)
+                .ok_or_else(|| SqliteError::Usage("carray() can't take a blob element".to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ColType::Text(Json::Array(items).to_string()))
+}
+
+// Carray is carray(array, count): a row per element of a JSON array, the
+// first count of them when count is given. sqlite3's carray() reads a C
+// array through a pointer, which a bound value can't be here, so the array
+// comes as text, usually made by array_param.
+struct Carray;
+
+impl TableValuedFunction for Carray {
+    fn name(&self) -> &str {
+        "carray"
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec!["value".to_string()]
+    }
+
+    fn call(&self, args: &[ColType]) -> Result<Box<dyn Iterator<Item = Row>>> {
+        if !(1..=2).contains(&args.len()) {
+            bail!(SqliteError::SqlParse(
+                "wrong number of arguments to function carray()".to_string()
+            ));
+        }
+        let items = match &args[0] {
+            ColType::Null => return Ok(Box::new(std::iter::empty())),
+            ColType::Text(s) => match json::parse(s) {
+                Ok(Json::Array(items)) => items,
+                _ => bail!("carray() argument is not a JSON array"),
+            },
+            _ => bail!("carray() argument is not a JSON array"),
+        };
+        let count = match args.get(1) {
+            None => items.len(),
+            Some(ColType::Integer(n)) if *n >= 0 => *n as usize,
+            Some(_) => bail!("carray() count is not a non-negative integer"),
+        };
+        let columns: Rc<[String]> = self.columns().into();
+        let rows = items
+            .iter()
+            .take(count)
+            .map(|v| Row::new(columns.clone(), vec![v.value()]))
+            .collect::<Vec<_>>();
+        Ok(Box::new(rows.into_iter()))
+    }
+}
+
+#[test]
+fn test_builtin_functions() {
+    let registry = FunctionRegistry::new();
+    let call = |name: &str, args: &[ColType]| {
+        registry
+            .get(name)
+            .unwrap()
+            .call(args)
+            .map(|rows| rows.map(|r| r.into_values()).collect::<Vec<_>>())
+    };
+    let series = |args: &[i64]| {
+        let args = args
+            .iter()
+            .map(|&i| ColType::Integer(i))
+            .collect::<Vec<_>>();
+        call("Generate_Series", &args)
+            .unwrap()
+            .into_iter()
+            .map(|r| r[0].to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    assert_eq!(series(&[1, 5]), "1,2,3,4,5");
+    assert_eq!(series(&[1, 10, 3]), "1,4,7,10");
+    assert_eq!(series(&[10, 1, -3]), "10,7,4,1");
+    assert_eq!(series(&[1, 3, 0]), "1,2,3");
+    assert_eq!(series(&[10, 1]), "");
+    assert_eq!(
+        series(&[i64::MAX - 1, i64::MAX]),
+        format!("{},{}", i64::MAX - 1, i64::MAX)
+    );
+    assert!(call("generate_series", &[]).is_err());
+
+    let text = |s: &str| ColType::Text(s.to_string());
+    let rows = call("json_each", &[text(r#"{"a": [1, 2], "b c": null}"#)]).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][1], text("[1,2]"));
+    assert_eq!(rows[0][2], text("array"));
+    assert_eq!(rows[1][6], text(r#"$."b c""#));
+    let rows = call("json_each", &[text(r#"{"a": [1, 2]}"#), text("$.a")]).unwrap();
+    assert_eq!(
+        rows[1][..4],
+        [
+            ColType::Integer(1),
+            ColType::Integer(2),
+            text("integer"),
+            ColType::Integer(2)
+        ]
+    );
+    assert_eq!(rows[1][6], text("$.a[1]"));
+    assert!(call("json_each", &[text("[1")]).is_err());
+    assert!(call("json_each", &[text("[1]"), text("x")]).is_err());
+
+    let array = array_param(&[ColType::Integer(1), text("a'b"), ColType::Null]).unwrap();
+    assert_eq!(array, text(r#"[1,"a'b",null]"#));
+    let rows = call("carray", std::slice::from_ref(&array)).unwrap();
+    assert_eq!(
+        rows,
+        [[ColType::Integer(1)], [text("a'b")], [ColType::Null]]
+    );
+    let rows = call("carray", &[array, ColType::Integer(1)]).unwrap();
+    assert_eq!(rows, [[ColType::Integer(1)]]);
+    assert!(call("carray", &[text("{}")]).is_err());
+    assert!(array_param(&[ColType::Blob(vec![0])]).is_err());
+    assert!(registry.get("nosuch").is_none());
+}
@@ -0,0 +1,176 @@
+use crate::error::SqliteError;
+
+// DbPath is the database argument: a plain path, or a `file:` URI with the
+// query parameters sqlite3 understands.
+// https://www.sqlite.org/uri.html
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DbPath {
+    pub path: String,
+    pub mode: Option<OpenMode>,
+    // immutable promises nobody changes the file while it's open
+    pub immutable: bool,
+    pub vfs: Option<String>,
+}
+
+// OpenMode is the `mode` parameter, named like its values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenMode {
+    Ro,
+    Rw,
+    Rwc,
+}
+
+// the unix VFSes sqlite3 registers, we read through plain files whichever is named
+const VFS_NAMES: [&str; 4] = ["unix", "unix-dotfile", "unix-excl", "unix-none"];
+
+impl DbPath {
+    pub fn parse(arg: &str) -> Result<Self, SqliteError> {
+        let Some(uri) = arg.strip_prefix("file:") else {
+            return Ok(DbPath {
+                path: arg.to_string(),
+                ..Default::default()
+            });
+        };
+        let uri = uri.split('#').next().unwrap();
+        let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+        let path = match path.strip_prefix("//") {
+            Some(rest) => {
+                let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                if !authority.is_empty() && authority != "localhost" {
+                    return Err(SqliteError::Usage(format!(
+                        "invalid uri authority: {authority}"
+                    )));
+                }
+                path
+            }
+            None => path,
+        };
+        let path = percent_decode(path)?;
+        if path.is_empty() {
+            return Err(SqliteError::Usage(format!("missing path in URI {arg}")));
+        }
+
+        let mut db = DbPath {
+            path,
+            ..Default::default()
+        };
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let (key, value) = (percent_decode(key)?, percent_decode(value)?);
+            match key.as_str() {
+                "mode" => {
+                    db.mode = Some(match value.as_str() {
+                        "ro" => OpenMode::Ro,
+                        "rw" => OpenMode::Rw,
+                        "rwc" => OpenMode::Rwc,
+                        "memory" => {
+                            return Err(SqliteError::Unsupported(
+                                "in-memory databases".to_string(),
+                            ));
+                        }
+                        _ => {
+                            return Err(SqliteError::Usage(format!(
+                                "no such access mode: {value}"
+                            )));
+                        }
+                    });
+                }
+                "immutable" => {
+                    db.immutable = match value.to_ascii_lowercase().as_str() {
+                        "1" | "yes" | "true" | "on" => true,
+                        "0" | "no" | "false" | "off" => false,
+                        _ => {
+                            return Err(SqliteError::Usage(format!(
+                                "invalid immutable value: {value}"
+                            )));
+                        }
+                    };
+                }
+                "vfs" => {
+                    if !VFS_NAMES.contains(&value.as_str()) {
+                        return Err(SqliteError::Usage(format!("no such vfs: {value}")));
+                    }
+                    db.vfs = Some(value);
+                }
+                _ => return Err(SqliteError::Usage(format!("unknown URI parameter: {key}"))),
+            }
+        }
+        Ok(db)
+    }
+
+    // read_only tells whether the URI itself forbids writing, --write or not.
+    pub fn read_only(&self) -> bool {
+        self.immutable || self.mode == Some(OpenMode::Ro)
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String, SqliteError> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| {
+                    SqliteError::Usage(format!("invalid percent-encoding in URI: {s}"))
+                })?;
+            out.push(hex);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| SqliteError::Usage(format!("URI is not valid UTF-8: {s}")))
+}
+
+#[test]
+fn test_parse_uri() {
+    let db = DbPath::parse("sample.db").unwrap();
+    assert_eq!(db.path, "sample.db");
+    assert!(!db.read_only());
+
+    let db = DbPath::parse("file:/tmp/my%20data/test%2Bone.db").unwrap();
+    assert_eq!(db.path, "/tmp/my data/test+one.db");
+
+    let db = DbPath::parse("file:///tmp/a.db?immutable=1&vfs=unix-none#frag").unwrap();
+    assert_eq!(
+        db,
+        DbPath {
+            path: "/tmp/a.db".to_string(),
+            mode: None,
+            immutable: true,
+            vfs: Some("unix-none".to_string()),
+        }
+    );
+    assert!(db.read_only());
+
+    let db = DbPath::parse("file://localhost/a.db?mode=rw&immutable=off").unwrap();
+    assert_eq!(db.mode, Some(OpenMode::Rw));
+    assert!(!db.read_only());
+    assert!(DbPath::parse("file:a.db?mode=ro").unwrap().read_only());
+}
+
+#[test]
+fn test_parse_uri_errors() {
+    for arg in [
+        "file://example.com/a.db",
+        "file:a.db?cache=shared",
+        "file:a.db?mode=append",
+        "file:a.db?immutable=maybe",
+        "file:a.db?vfs=win32",
+        "file:a%2.db",
+        "file:",
+    ] {
+        assert!(
+            matches!(DbPath::parse(arg), Err(SqliteError::Usage(_))),
+            "{arg}"
+        );
+    }
+    assert!(matches!(
+        DbPath::parse("file:a.db?mode=memory"),
+        Err(SqliteError::Unsupported(_))
+    ));
+}
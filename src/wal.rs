@@ -0,0 +1,195 @@
+use anyhow::{Result, bail};
+use log::warn;
+use std::collections::HashMap;
+
+// https://www.sqlite.org/walformat.html#the_wal_index_file_format
+// The wal-index lives in the -shm file in native byte order. It starts with two
+// copies of the 48-byte index header (written in opposite order so a reader can
+// detect a torn update), followed by checkpoint info, then hash table segments.
+const INDEX_HEADER_SIZE: usize = 48;
+const WALINDEX_HDR_SIZE: usize = 136;
+const SEGMENT_SIZE: usize = 32768;
+// number of page-number slots in each segment, the first one is shared with the header
+const HASHTABLE_NPAGE: usize = 4096;
+const HASHTABLE_NPAGE_ONE: usize = HASHTABLE_NPAGE - WALINDEX_HDR_SIZE / 4;
+const WAL_INDEX_VERSION: u32 = 3007000;
+
+#[derive(Debug)]
+pub struct WalIndex {
+    /// index of the last valid (committed) frame in the WAL
+    pub max_frame: u32,
+    /// size of the database in pages after the last commit
+    pub page_count: u32,
+    // key: page number, value: latest frame (1-based) holding that page
+    frames: HashMap<u32, u32>,
+}
+
+impl WalIndex {
+    // open reads the wal-index of the -shm file at `shm_path`, see parse.
+    pub fn open(shm_path: &str) -> Result<Option<WalIndex>> {
+        let shm = std::fs::read(shm_path)?;
+        WalIndex::parse(&shm)
+    }
+
+    // parse reads a wal-index, or returns None when its two header copies
+    // differ: a writer is updating it, which is normal while one is active,
+    // and what the WAL holds is unknown until it's done.
+    pub fn parse(shm: &[u8]) -> Result<Option<WalIndex>> {
+        if shm.len() < WALINDEX_HDR_SIZE {
+            bail!("wal-index too small: {} bytes", shm.len());
+        }
+        let hdr1 = &shm[..INDEX_HEADER_SIZE];
+        let hdr2 = &shm[INDEX_HEADER_SIZE..INDEX_HEADER_SIZE * 2];
+        if hdr1 != hdr2 {
+            return Ok(None);
+        }
+        let u32_at = |buf: &[u8], i: usize| u32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+
+        let version = u32_at(hdr1, 0);
+        if version != WAL_INDEX_VERSION {
+            bail!("unsupported wal-index version {version}");
+        }
+        if hdr1[12] != 1 {
+            bail!("wal-index is not initialized");
+        }
+        let big_endian_cksum = hdr1[13] == 1;
+        let cksum = wal_checksum(&hdr1[..40], big_endian_cksum == cfg!(target_endian = "big"));
+        if cksum != [u32_at(hdr1, 40), u32_at(hdr1, 44)] {
+            bail!("wal-index header checksum mismatch");
+        }
+
+        let max_frame = u32_at(hdr1, 16);
+
+        let mut frames = HashMap::new();
+        for frame in 1..=max_frame {
+            let offset = frame_slot_offset(frame as usize);
+            if offset + 4 > shm.len() {
+                bail!("wal-index truncated, frame {frame} is out of range");
+            }
+            // later frames override earlier ones for the same page
+            frames.insert(u32_at(shm, offset), frame);
+        }
+
+        Ok(Some(WalIndex {
+            max_frame,
+            page_count: u32_at(hdr1, 20),
+            frames,
+        }))
+    }
+
+    /// Returns how many distinct pages have committed copies in the WAL.
+    pub fn page_changes(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+// check_wal warns when committed transactions are still sitting in the WAL:
+// we only read the main database file, so their changes are invisible to us.
+pub fn check_wal(db_path: &str) -> Result<()> {
+    let shm_path = format!("{db_path}-shm");
+    if !std::path::Path::new(&shm_path).exists() {
+        return Ok(());
+    }
+    warn_uncheckpointed(db_path, WalIndex::open(&shm_path)?.as_ref());
+    Ok(())
+}
+
+// warn_uncheckpointed logs a warning when the WAL holds commits the database
+// file doesn't have yet, or may hold some while its index is being updated.
+pub fn warn_uncheckpointed(db_path: &str, index: Option<&WalIndex>) {
+    match index {
+        None => warn!(
+            "{db_path}-shm is being updated by a writer, {db_path}-wal may hold \
+             changes not yet checkpointed, results may be stale"
+        ),
+        Some(index) if index.max_frame > 0 => warn!(
+            "{db_path}-wal holds {} committed frames ({} of {} pages) \
+             not yet checkpointed, results may be stale",
+            index.max_frame,
+            index.page_changes(),
+            index.page_count
+        ),
+        Some(_) => {}
+    }
+}
+
+// frame_slot_offset returns where the page number of `frame` is stored in the shm.
+fn frame_slot_offset(frame: usize) -> usize {
+    if frame <= HASHTABLE_NPAGE_ONE {
+        WALINDEX_HDR_SIZE + (frame - 1) * 4
+    } else {
+        let rest = frame - HASHTABLE_NPAGE_ONE - 1;
+        SEGMENT_SIZE * (1 + rest / HASHTABLE_NPAGE) + (rest % HASHTABLE_NPAGE) * 4
+    }
+}
+
+// wal_checksum is the WAL's Fibonacci-weighted checksum over pairs of u32.
+fn wal_checksum(buf: &[u8], native: bool) -> [u32; 2] {
+    let (mut s1, mut s2) = (0u32, 0u32);
+    for pair in buf.chunks_exact(8) {
+        let mut x0 = u32::from_ne_bytes(pair[..4].try_into().unwrap());
+        let mut x1 = u32::from_ne_bytes(pair[4..].try_into().unwrap());
+        if !native {
+            x0 = x0.swap_bytes();
+            x1 = x1.swap_bytes();
+        }
+        s1 = s1.wrapping_add(x0).wrapping_add(s2);
+        s2 = s2.wrapping_add(x1).wrapping_add(s1);
+    }
+    [s1, s2]
+}
+
+#[cfg(test)]
+fn build_shm(max_frame: u32, pages: &[u32]) -> Vec<u8> {
+    let mut hdr = Vec::new();
+    for v in [WAL_INDEX_VERSION, 0, 9] {
+        hdr.extend(v.to_ne_bytes());
+    }
+    hdr.extend([1, cfg!(target_endian = "big") as u8]);
+    hdr.extend(4096u16.to_ne_bytes());
+    for v in [max_frame, 2, 0, 0, 0xaa, 0xbb] {
+        hdr.extend(v.to_ne_bytes());
+    }
+    let cksum = wal_checksum(&hdr, true);
+    hdr.extend(cksum[0].to_ne_bytes());
+    hdr.extend(cksum[1].to_ne_bytes());
+
+    let mut shm = vec![0; SEGMENT_SIZE * 2];
+    shm[..INDEX_HEADER_SIZE].copy_from_slice(&hdr);
+    shm[INDEX_HEADER_SIZE..INDEX_HEADER_SIZE * 2].copy_from_slice(&hdr);
+    for (i, pgno) in pages.iter().enumerate() {
+        let offset = frame_slot_offset(i + 1);
+        shm[offset..offset + 4].copy_from_slice(&pgno.to_ne_bytes());
+    }
+    shm
+}
+
+#[test]
+fn test_wal_index_parse() {
+    // frames 1..=4 hold pages 1, 2, 2, 3, frame 5 is not committed yet
+    let shm = build_shm(4, &[1, 2, 2, 3, 1]);
+    let index = WalIndex::parse(&shm).unwrap().unwrap();
+    assert_eq!(index.max_frame, 4);
+    assert_eq!(index.page_count, 2);
+    assert_eq!(index.frames.get(&1), Some(&1));
+    assert_eq!(index.frames.get(&2), Some(&3));
+    assert_eq!(index.frames.get(&3), Some(&4));
+    assert_eq!(index.frames.get(&4), None);
+    assert_eq!(index.page_changes(), 3);
+
+    // frames past the first segment live in the next one
+    let pages = (1..=HASHTABLE_NPAGE_ONE as u32 + 2).collect::<Vec<_>>();
+    let shm = build_shm(pages.len() as u32, &pages);
+    let index = WalIndex::parse(&shm).unwrap().unwrap();
+    assert_eq!(index.frames.get(&4064), Some(&4064));
+
+    // a writer updating the header leaves the copies different for a while
+    let mut torn = build_shm(4, &[1, 2, 2, 3]);
+    torn[INDEX_HEADER_SIZE + 16] = 5;
+    assert!(WalIndex::parse(&torn).unwrap().is_none());
+
+    let mut corrupt = build_shm(4, &[1, 2, 2, 3]);
+    corrupt[16] = 5;
+    corrupt[INDEX_HEADER_SIZE + 16] = 5;
+    assert!(WalIndex::parse(&corrupt).is_err());
+}
@@ -0,0 +1,474 @@
+use anyhow::{Result, bail};
+use std::cmp::Ordering;
+
+use crate::error::SqliteError;
+use crate::expr::Expr;
+use crate::record::ColType;
+
+// Window is a window function call together with its OVER clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub func: WindowFunction,
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<OrderTerm>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderTerm {
+    pub expr: Expr,
+    pub desc: bool,
+}
+
+// NAMES are the functions that only exist with an OVER clause.
+pub const NAMES: [&str; 8] = [
+    "lag",
+    "lead",
+    "first_value",
+    "last_value",
+    "nth_value",
+    "ntile",
+    "cume_dist",
+    "percent_rank",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFunction {
+    // the value `offset` rows before the current one in its partition
+    Lag {
+        expr: Box<Expr>,
+        offset: Option<Box<Expr>>,
+        default: Option<Box<Expr>>,
+    },
+    // the value `offset` rows after the current one in its partition
+    Lead {
+        expr: Box<Expr>,
+        offset: Option<Box<Expr>>,
+        default: Option<Box<Expr>>,
+    },
+    // the value of the first row of the partition
+    FirstValue(Box<Expr>),
+    // the value of the last row of the frame
+    LastValue(Box<Expr>),
+    // the value of the n-th row of the frame, counting from 1
+    NthValue(Box<Expr>, Box<Expr>),
+    // which of n buckets of the partition the row falls in, counting from 1
+    Ntile(Box<Expr>),
+    // the fraction of the partition sorted at or before the row's peers
+    CumeDist,
+    // (rank - 1) / (partition rows - 1), 0 for a partition of one row
+    PercentRank,
+}
+
+impl WindowFunction {
+    pub fn new(name: &str, args: Vec<Expr>) -> Result<Self, String> {
+        match name {
+            "lag" | "lead" => {
+                if !(1..=3).contains(&args.len()) {
+                    return Err(format!("wrong number of arguments to function {name}()"));
+                }
+                let mut args = args.into_iter().map(Box::new);
+                let expr = args.next().unwrap();
+                let (offset, default) = (args.next(), args.next());
+                Ok(if name == "lag" {
+                    WindowFunction::Lag {
+                        expr,
+                        offset,
+                        default,
+                    }
+                } else {
+                    WindowFunction::Lead {
+                        expr,
+                        offset,
+                        default,
+                    }
+                })
+            }
+            "first_value" | "last_value" | "nth_value" => {
+                let want = if name == "nth_value" { 2 } else { 1 };
+                if args.len() != want {
+                    return Err(format!("wrong number of arguments to function {name}()"));
+                }
+                let mut args = args.into_iter().map(Box::new);
+                let expr = args.next().unwrap();
+                Ok(match name {
+                    "first_value" => WindowFunction::FirstValue(expr),
+                    "last_value" => WindowFunction::LastValue(expr),
+                    _ => WindowFunction::NthValue(expr, args.next().unwrap()),
+                })
+            }
+            "ntile" => match <[Expr; 1]>::try_from(args) {
+                Ok([n]) => Ok(WindowFunction::Ntile(Box::new(n))),
+                Err(_) => Err("wrong number of arguments to function ntile()".to_string()),
+            },
+            "cume_dist" | "percent_rank" => {
+                if !args.is_empty() {
+                    return Err(format!("wrong number of arguments to function {name}()"));
+                }
+                Ok(if name == "cume_dist" {
+                    WindowFunction::CumeDist
+                } else {
+                    WindowFunction::PercentRank
+                })
+            }
+            _ => Err(format!("no such window function: {name}")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowFunction::Lag { .. } => "lag",
+            WindowFunction::Lead { .. } => "lead",
+            WindowFunction::FirstValue(_) => "first_value",
+            WindowFunction::LastValue(_) => "last_value",
+            WindowFunction::NthValue(..) => "nth_value",
+            WindowFunction::Ntile(_) => "ntile",
+            WindowFunction::CumeDist => "cume_dist",
+            WindowFunction::PercentRank => "percent_rank",
+        }
+    }
+
+    pub fn args(&self) -> Vec<&Expr> {
+        match self {
+            WindowFunction::Lag {
+                expr,
+                offset,
+                default,
+            }
+            | WindowFunction::Lead {
+                expr,
+                offset,
+                default,
+            } => std::iter::once(expr)
+                .chain(offset)
+                .chain(default)
+                .map(|e| &**e)
+                .collect(),
+            WindowFunction::FirstValue(expr)
+            | WindowFunction::LastValue(expr)
+            | WindowFunction::Ntile(expr) => vec![expr],
+            WindowFunction::NthValue(expr, n) => vec![expr, n],
+            WindowFunction::CumeDist | WindowFunction::PercentRank => Vec::new(),
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            WindowFunction::Lag {
+                expr,
+                offset,
+                default,
+            }
+            | WindowFunction::Lead {
+                expr,
+                offset,
+                default,
+            } => std::iter::once(expr)
+                .chain(offset)
+                .chain(default)
+                .map(|e| &mut **e)
+                .collect(),
+            WindowFunction::FirstValue(expr)
+            | WindowFunction::LastValue(expr)
+            | WindowFunction::Ntile(expr) => vec![&mut **expr],
+            WindowFunction::NthValue(expr, n) => vec![&mut **expr, &mut **n],
+            WindowFunction::CumeDist | WindowFunction::PercentRank => Vec::new(),
+        }
+    }
+
+    // value computes the function for the row at `pos` of a sorted partition,
+    // `eval` evaluates an expression on one of the buffered rows. The frame is
+    // the default RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW: the
+    // partition up to `frame_end`, the last peer of the current row.
+    // `peer_start` is the first peer, the row's rank is one more.
+    fn value(
+        &self,
+        partition: &[usize],
+        pos: usize,
+        peer_start: usize,
+        frame_end: usize,
+        eval: &dyn Fn(&Expr, usize) -> Result<ColType>,
+    ) -> Result<ColType> {
+        let row = partition[pos];
+        match self {
+            WindowFunction::Lag {
+                expr,
+                offset,
+                default,
+            }
+            | WindowFunction::Lead {
+                expr,
+                offset,
+                default,
+            } => {
+                let offset = match offset {
+                    Some(e) => match eval(e, row)? {
+                        ColType::Integer(i) => i,
+                        _ => bail!(SqliteError::SqlParse(format!(
+                            "argument 2 of {}() must be an integer",
+                            self.name()
+                        ))),
+                    },
+                    None => 1,
+                };
+                let offset = if matches!(self, WindowFunction::Lag { .. }) {
+                    -offset
+                } else {
+                    offset
+                };
+                let source = (pos as i64)
+                    .checked_add(offset)
+                    .filter(|&i| i >= 0 && i < partition.len() as i64);
+                match (source, default) {
+                    (Some(i), _) => eval(expr, partition[i as usize]),
+                    (None, Some(default)) => eval(default, row),
+                    (None, None) => Ok(ColType::Null),
+                }
+            }
+            WindowFunction::FirstValue(expr) => eval(expr, partition[0]),
+            WindowFunction::LastValue(expr) => eval(expr, partition[frame_end]),
+            WindowFunction::NthValue(expr, n) => match eval(n, row)? {
+                ColType::Integer(n) if n > 0 => match usize::try_from(n - 1) {
+                    Ok(i) if i <= frame_end => eval(expr, partition[i]),
+                    _ => Ok(ColType::Null),
+                },
+                _ => bail!(SqliteError::SqlParse(
+                    "second argument to nth_value must be a positive integer".to_string()
+                )),
+            },
+            WindowFunction::Ntile(n) => {
+                let n = match eval(n, row)? {
+                    ColType::Integer(n) if n > 0 => n as usize,
+                    _ => bail!(SqliteError::SqlParse(
+                        "argument of ntile must be a positive integer".to_string()
+                    )),
+                };
+                // the first `extra` buckets get one more row than the rest
+                let (size, extra) = (partition.len() / n, partition.len() % n);
+                let big = extra * (size + 1);
+                let bucket = if pos < big {
+                    pos / (size + 1)
+                } else {
+                    extra + (pos - big) / size
+                };
+                Ok(ColType::Integer(bucket as i64 + 1))
+            }
+            WindowFunction::CumeDist => Ok(ColType::Float(
+                (frame_end + 1) as f64 / partition.len() as f64,
+            )),
+            WindowFunction::PercentRank => Ok(ColType::Float(if partition.len() == 1 {
+                0.0
+            } else {
+                peer_start as f64 / (partition.len() - 1) as f64
+            })),
+        }
+    }
+}
+
+impl Window {
+    // evaluate runs the window over `n` buffered rows: it returns the row order
+    // the window sorted them into, and the function's value for each row.
+    pub fn evaluate(
+        &self,
+        n: usize,
+        eval: &dyn Fn(&Expr, usize) -> Result<ColType>,
+    ) -> Result<(Vec<usize>, Vec<ColType>)> {
+        let keys = (0..n)
+            .map(|i| {
+                let partition = self
+                    .partition_by
+                    .iter()
+                    .map(|e| eval(e, i))
+                    .collect::<Result<Vec<_>>>()?;
+                let order = self
+                    .order_by
+                    .iter()
+                    .map(|t| eval(&t.expr, i))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((partition, order))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let same_partition =
+            |a: usize, b: usize| keys[a].0.iter().zip(&keys[b].0).all(|(x, y)| x == y);
+        let mut order = (0..n).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            let partition = keys[a].0.iter().zip(&keys[b].0).map(|(x, y)| x.cmp(y));
+            let terms = keys[a]
+                .1
+                .iter()
+                .zip(&keys[b].1)
+                .zip(&self.order_by)
+                .map(|((x, y), t)| {
+                    let ord = x.cmp(y);
+                    if t.desc { ord.reverse() } else { ord }
+                });
+            partition
+                .chain(terms)
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        // peers are rows of a partition that ORDER BY doesn't tell apart
+        let peers = |a: usize, b: usize| keys[a].1.iter().zip(&keys[b].1).all(|(x, y)| x == y);
+        let mut values = vec![ColType::Null; n];
+        for partition in order.chunk_by(|&a, &b| same_partition(a, b)) {
+            let mut pos = 0;
+            for group in partition.chunk_by(|&a, &b| peers(a, b)) {
+                let (peer_start, frame_end) = (pos, pos + group.len() - 1);
+                for _ in group {
+                    values[partition[pos]] = self
+                        .func
+                        .value(partition, pos, peer_start, frame_end, eval)?;
+                    pos += 1;
+                }
+            }
+        }
+        Ok((order, values))
+    }
+}
+
+#[test]
+fn test_lag_lead() {
+    // (day, price) rows, deliberately out of order
+    let rows = [(3, 30), (1, 10), (4, 40), (2, 20)];
+    let eval = |e: &Expr, i: usize| {
+        let (day, price) = rows[i];
+        e.eval(&|name| match name {
+            "day" => Some(ColType::Integer(day)),
+            "price" => Some(ColType::Integer(price)),
+            "grp" => Some(ColType::Integer(day % 2)),
+            _ => None,
+        })
+    };
+    let run = |sql: &str| {
+        let e = crate::expr::parse_expr(sql).unwrap();
+        let (order, values) = e.windows()[0].evaluate(rows.len(), &eval).unwrap();
+        let values = order
+            .iter()
+            .map(|&i| values[i].to_string())
+            .collect::<Vec<_>>();
+        (order, values.join(","))
+    };
+    assert_eq!(
+        run("lag(price) over (order by day)"),
+        (vec![1, 3, 0, 2], ",10,20,30".to_string())
+    );
+    assert_eq!(
+        run("lead(price, 1, -1) over (order by day)").1,
+        "20,30,40,-1"
+    );
+    assert_eq!(
+        run("lag(price, 2, 0) over (order by day desc)").1,
+        "0,0,40,30"
+    );
+    assert_eq!(
+        run("lag(price) over (partition by grp order by day)"),
+        (vec![3, 2, 1, 0], ",20,,10".to_string())
+    );
+    assert!(crate::expr::parse_expr("lag() over ()").is_err());
+    assert!(crate::expr::parse_expr("nth_value(price) over ()").is_err());
+    assert!(crate::expr::parse_expr("nosuch(price) over ()").is_err());
+}
+
+#[test]
+fn test_frame_values() {
+    // (day, price), days 2 and 3 tie on week
+    let rows = [(1, 10), (3, 30), (2, 20), (4, 40)];
+    let eval = |e: &Expr, i: usize| {
+        let (day, price) = rows[i];
+        e.eval(&|name| match name {
+            "day" => Some(ColType::Integer(day)),
+            "week" => Some(ColType::Integer(day / 2)),
+            "price" => Some(ColType::Integer(price)),
+            _ => None,
+        })
+    };
+    let run = |sql: &str| {
+        let e = crate::expr::parse_expr(sql).unwrap();
+        let (order, values) = e.windows()[0].evaluate(rows.len(), &eval).unwrap();
+        order
+            .iter()
+            .map(|&i| values[i].to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    assert_eq!(
+        run("first_value(price) over (order by day desc)"),
+        "40,40,40,40"
+    );
+    assert_eq!(run("last_value(price) over (order by day)"), "10,20,30,40");
+    // the frame ends at the last peer, not the current row
+    assert_eq!(run("last_value(price) over (order by week)"), "10,20,20,40");
+    assert_eq!(run("last_value(price) over ()"), "40,40,40,40");
+    assert_eq!(run("nth_value(price, 2) over (order by day)"), ",20,20,20");
+    assert_eq!(run("nth_value(price, 3) over (order by week)"), ",20,20,20");
+    let e = crate::expr::parse_expr("nth_value(price, 0) over ()").unwrap();
+    assert!(e.windows()[0].evaluate(rows.len(), &eval).is_err());
+}
+
+#[test]
+fn test_ntile() {
+    let n = 10;
+    let eval = |e: &Expr, i: usize| {
+        e.eval(&|name| match name {
+            "score" => Some(ColType::Integer(i as i64 % 3)),
+            "i" => Some(ColType::Integer(i as i64)),
+            _ => None,
+        })
+    };
+    let run = |sql: &str| {
+        let e = crate::expr::parse_expr(sql).unwrap();
+        let (order, values) = e.windows()[0].evaluate(n, &eval).unwrap();
+        order
+            .iter()
+            .map(|&i| values[i].to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    assert_eq!(run("ntile(4) over (order by i)"), "1,1,1,2,2,2,3,3,4,4");
+    assert_eq!(run("ntile(5) over (order by i)"), "1,1,2,2,3,3,4,4,5,5");
+    assert_eq!(run("ntile(20) over (order by i)"), "1,2,3,4,5,6,7,8,9,10");
+    // partitions of 4, 3 and 3 rows
+    assert_eq!(
+        run("ntile(2) over (partition by score order by i)"),
+        "1,1,2,2,1,1,2,1,1,2"
+    );
+    let e = crate::expr::parse_expr("ntile(0) over ()").unwrap();
+    assert!(e.windows()[0].evaluate(n, &eval).is_err());
+}
+
+#[test]
+fn test_distribution() {
+    // scores with a tie on 20, the last row alone in its group
+    let rows = [(1, 10), (1, 20), (1, 20), (1, 40), (2, 5)];
+    let eval = |e: &Expr, i: usize| {
+        let (grp, score) = rows[i];
+        e.eval(&|name| match name {
+            "grp" => Some(ColType::Integer(grp)),
+            "score" => Some(ColType::Integer(score)),
+            _ => None,
+        })
+    };
+    let run = |sql: &str| {
+        let e = crate::expr::parse_expr(sql).unwrap();
+        let (order, values) = e.windows()[0].evaluate(rows.len(), &eval).unwrap();
+        order
+            .iter()
+            .map(|&i| values[i].to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    assert_eq!(
+        run("cume_dist() over (partition by grp order by score)"),
+        "0.25,0.75,0.75,1.0,1.0"
+    );
+    assert_eq!(
+        run("percent_rank() over (partition by grp order by score)"),
+        "0.0,0.333333333333333,0.333333333333333,1.0,0.0"
+    );
+    assert_eq!(
+        run("percent_rank() over (order by score desc)"),
+        "0.0,0.25,0.25,0.75,1.0"
+    );
+    assert_eq!(run("cume_dist() over ()"), "1.0,1.0,1.0,1.0,1.0");
+    assert!(crate::expr::parse_expr("cume_dist(score) over ()").is_err());
+}
@@ -0,0 +1,911 @@
+use anyhow::{Result, bail};
+
+use crate::btree::{self, TableCursor, local_size};
+use crate::error::SqliteError;
+use crate::expr::{self, Expr};
+use crate::pager::{Page, Pager};
+use crate::parser::{
+    ColumnDef, ConflictAction, CreateTableStmt, InsertStmt, NewTable, OnConflict, UpdateStmt,
+};
+use crate::record::{ColType, decode_varint, encode_record, encode_varint};
+use crate::schema::{Create, SchemaKind, Tables, column_affinity, column_index, is_rowid_alias};
+
+// insert runs an INSERT: every row of VALUES is evaluated and checked
+// against the table's constraints before any is stored, each under the
+// rowid its INTEGER PRIMARY KEY gives it or the one after the largest. A
+// row whose rowid is taken fails the statement, unless an UPSERT clause
+// drops it or updates the row it conflicts with instead. The rows stored
+// are returned with their rowids, as SQL sees them.
+pub fn insert(
+    pager: &Pager,
+    tables: &Tables,
+    stmt: &InsertStmt,
+    params: &[ColType],
+) -> Result<Vec<(i64, Vec<ColType>)>> {
+    let t = writable(tables, &stmt.table, "insert into")?;
+    let targets = insert_targets(t, &stmt.columns)?;
+    // None without ON CONFLICT, Some(None) for DO NOTHING
+    let upsert = match &stmt.on_conflict {
+        None => None,
+        Some(on_conflict) => Some(upsert(t, on_conflict, params)?),
+    };
+    let rows = stmt
+        .values
+        .iter()
+        .map(|row| insert_row(t, &targets, row, params))
+        .collect::<Result<Vec<_>>>()?;
+
+    let rootpage = tables.pos[&t.table];
+    let mut stored = Vec::with_capacity(rows.len());
+    for (rowid, values) in &rows {
+        let existing = match rowid {
+            Some(rowid) => stored_row(pager, rootpage, *rowid)?,
+            None => None,
+        };
+        if let (Some(rowid), Some(mut existing)) = (*rowid, existing) {
+            let set = match &upsert {
+                Some(set) => set,
+                None => {
+                    let column = t.rowid_column().unwrap_or("rowid");
+                    bail!(SqliteError::Constraint(format!(
+                        "UNIQUE constraint failed: {}.{column}",
+                        t.table
+                    )));
+                }
+            };
+            let Some(set) = set else {
+                continue;
+            };
+            add_defaults(t, &mut existing, params)?;
+            let seen = as_seen(t, rowid, &existing);
+            let excluded = as_seen(t, rowid, values);
+            let lookup = |name: &str| match name.split_once('.') {
+                Some((q, name)) if q.eq_ignore_ascii_case("excluded") => {
+                    lookup_column(t, rowid, &excluded, name)
+                }
+                Some((q, name)) if q.eq_ignore_ascii_case(&t.table) => {
+                    lookup_column(t, rowid, &seen, name)
+                }
+                Some(_) => None,
+                None => lookup_column(t, rowid, &seen, name),
+            };
+            if set.apply(t, &mut existing, &lookup)? {
+                let record = encode_record(
+                    &existing,
+                    pager.dbinfo().text_encoding,
+                    pager.dbinfo().schema_format,
+                );
+                rewrite_record(pager, &t.table, rootpage, rowid, &record)?;
+                stored.push((rowid, as_seen(t, rowid, &existing)));
+            }
+            continue;
+        }
+        let record = encode_record(
+            values,
+            pager.dbinfo().text_encoding,
+            pager.dbinfo().schema_format,
+        );
+        let seq = match t.autoincrement {
+            true => sequence(pager, tables, &t.table)?,
+            false => None,
+        };
+        let rowid = match rowid {
+            Some(rowid) => *rowid,
+            None => {
+                let last = btree::last_rowid(pager, rootpage)?;
+                let last = last.max(seq.map_or(0, |(_, seq)| seq));
+                // sqlite3 would look for an unused one at random
+                last.checked_add(1).ok_or_else(|| {
+                    SqliteError::Unsupported(format!("{} has no rowid left", t.table))
+                })?
+            }
+        };
+        put_record(pager, rootpage, rowid, &record)?;
+        if t.autoincrement && seq.is_none_or(|(_, seq)| rowid > seq) {
+            set_sequence(pager, tables, &t.table, seq.map(|(r, _)| r), rowid)?;
+        }
+        stored.push((rowid, as_seen(t, rowid, values)));
+    }
+    Ok(stored)
+}
+
+// upsert compiles an UPSERT clause: Some with the SET of DO UPDATE, None for
+// DO NOTHING. Only the rowid can conflict in the tables that are written,
+// so a target has to be their INTEGER PRIMARY KEY.
+fn upsert(
+    t: &CreateTableStmt,
+    on_conflict: &OnConflict,
+    params: &[ColType],
+) -> Result<Option<Assignments>> {
+    let is_key = |c: &String| t.rowid_column().is_some_and(|r| r.eq_ignore_ascii_case(c));
+    match on_conflict.target.as_slice() {
+        [] => {}
+        [c] if is_key(c) => {}
+        _ => bail!(SqliteError::SqlParse(
+            "ON CONFLICT clause does not match any PRIMARY KEY or UNIQUE constraint".to_string()
+        )),
+    }
+    Ok(match &on_conflict.action {
+        ConflictAction::Nothing => None,
+        ConflictAction::Update {
+            assignments,
+            filter,
+        } => Some(Assignments::compile(
+            t,
+            assignments,
+            filter.as_ref(),
+            params,
+            true,
+        )?),
+    })
+}
+
+// update runs an UPDATE of columns other than the rowid: the SET values are
+// evaluated on each row the WHERE clause holds for as it was, and checked
+// against the table's constraints before any row is stored. A record that
+// doesn't grow is rewritten where its cell is, one that does only when its
+// leaf has room for it. The rows changed are returned as SQL sees them.
+pub fn update(
+    pager: &Pager,
+    tables: &Tables,
+    stmt: &UpdateStmt,
+    params: &[ColType],
+) -> Result<Vec<(i64, Vec<ColType>)>> {
+    let t = writable(tables, &stmt.table, "update")?;
+    let set = Assignments::compile(t, &stmt.assignments, stmt.filter.as_ref(), params, false)?;
+
+    let rootpage = tables.pos[&t.table];
+    let mut rows = Vec::new();
+    for row in TableCursor::new(pager, rootpage) {
+        let (rowid, mut values) = row?;
+        add_defaults(t, &mut values, params)?;
+        let seen = as_seen(t, rowid, &values);
+        if set.apply(t, &mut values, &|name| lookup_column(t, rowid, &seen, name))? {
+            rows.push((rowid, values));
+        }
+    }
+
+    rows.iter().try_for_each(|(rowid, values)| {
+        let record = encode_record(
+            values,
+            pager.dbinfo().text_encoding,
+            pager.dbinfo().schema_format,
+        );
+        rewrite_record(pager, &t.table, rootpage, *rowid, &record)
+    })?;
+    Ok(rows
+        .into_iter()
+        .map(|(rowid, values)| (rowid, as_seen(t, rowid, &values)))
+        .collect())
+}
+
+// Assignments is a compiled SET clause, of an UPDATE or an UPSERT's DO
+// UPDATE: the column each value goes to with its expression, and the WHERE
+// clause a row has to pass to be changed.
+struct Assignments {
+    set: Vec<(usize, Expr)>,
+    filter: Option<Expr>,
+}
+
+impl Assignments {
+    // compile checks the SET clause and its WHERE against the table, before
+    // any row is read, so unknown columns fail even on an empty table.
+    // `excluded` lets them read `excluded.col`, the row an UPSERT inserts.
+    fn compile(
+        t: &CreateTableStmt,
+        assignments: &[(String, String)],
+        filter: Option<&Expr>,
+        params: &[ColType],
+        excluded: bool,
+    ) -> Result<Self> {
+        let rowid_column = t.rowid_column();
+        let mut set = Vec::with_capacity(assignments.len());
+        for (name, sql) in assignments {
+            let i = match column_index(&t.columns, name) {
+                Some(i) if rowid_column != Some(t.columns[i].name.as_str()) => i,
+                None if !is_rowid_alias(name) => bail!(SqliteError::NoSuchColumn(name.clone())),
+                _ => bail!(SqliteError::Unsupported(format!(
+                    "cannot update {}: changing the rowid is not implemented",
+                    t.table
+                ))),
+            };
+            let mut e = expr::parse_expr(sql).map_err(SqliteError::SqlParse)?;
+            e.bind(params)?;
+            set.push((i, e));
+        }
+        let mut filter = filter.cloned();
+        if let Some(filter) = &mut filter {
+            filter.bind(params)?;
+        }
+        for e in set.iter_mut().map(|(_, e)| e).chain(&mut filter) {
+            for col in e.columns() {
+                let name = unqualified(t, excluded, col);
+                if column_index(&t.columns, name).is_none() && !is_rowid_alias(name) {
+                    bail!(SqliteError::NoSuchColumn(col.to_string()));
+                }
+            }
+            e.resolve_affinity(&|col| column_affinity(t, unqualified(t, excluded, col)));
+        }
+        Ok(Assignments { set, filter })
+    }
+
+    // apply evaluates the SET values on a row, its columns read through
+    // `lookup`, into `values`, and tells whether it did: not when the WHERE
+    // clause doesn't hold for the row.
+    fn apply(
+        &self,
+        t: &CreateTableStmt,
+        values: &mut [ColType],
+        lookup: &dyn Fn(&str) -> Option<ColType>,
+    ) -> Result<bool> {
+        if let Some(filter) = &self.filter
+            && !expr::truthy(&filter.eval(lookup)?)
+        {
+            return Ok(false);
+        }
+        let mut changed = Vec::with_capacity(self.set.len());
+        for (i, e) in &self.set {
+            let c = &t.columns[*i];
+            let v = with_affinity(c, e.eval(lookup)?);
+            if c.not_null && v == ColType::Null {
+                bail!(SqliteError::Constraint(format!(
+                    "NOT NULL constraint failed: {}.{}",
+                    t.table, c.name
+                )));
+            }
+            changed.push((*i, v));
+        }
+        // every value is computed from the row as it was
+        for (i, v) in changed {
+            values[i] = v;
+        }
+        Ok(true)
+    }
+}
+
+// unqualified is the column an UPSERT's `excluded.col` or `<table>.col`
+// names, when `excluded` says the expression is one's.
+fn unqualified<'a>(t: &CreateTableStmt, excluded: bool, col: &'a str) -> &'a str {
+    match col.split_once('.') {
+        Some((q, name)) if excluded && q.eq_ignore_ascii_case("excluded") => name,
+        Some((q, name)) if excluded && q.eq_ignore_ascii_case(&t.table) => name,
+        _ => col,
+    }
+}
+
+// add_defaults completes a stored row with the DEFAULT of the columns ALTER
+// TABLE added after it was written, which its record is missing.
+fn add_defaults(t: &CreateTableStmt, values: &mut Vec<ColType>, params: &[ColType]) -> Result<()> {
+    for c in &t.columns[values.len().min(t.columns.len())..] {
+        values.push(match &c.default {
+            Some(default) => constant(default, params)?,
+            None => ColType::Null,
+        });
+    }
+    Ok(())
+}
+
+// lookup_column reads the column `name` of a row as SQL sees it.
+fn lookup_column(t: &CreateTableStmt, rowid: i64, seen: &[ColType], name: &str) -> Option<ColType> {
+    match column_index(&t.columns, name) {
+        Some(i) => seen.get(i).cloned(),
+        None if is_rowid_alias(name) => Some(ColType::Integer(rowid)),
+        None => None,
+    }
+}
+
+// as_seen is what SQL sees of a stored row: the rowid in its INTEGER
+// PRIMARY KEY, and integers as reals in REAL columns.
+fn as_seen(t: &CreateTableStmt, rowid: i64, values: &[ColType]) -> Vec<ColType> {
+    let rowid_column = t.rowid_column();
+    t.columns
+        .iter()
+        .zip(values)
+        .map(|(c, v)| match v {
+            _ if rowid_column == Some(c.name.as_str()) => ColType::Integer(rowid),
+            ColType::Integer(i) if c.real_affinity() => ColType::Float(*i as f64),
+            v => v.clone(),
+        })
+        .collect()
+}
+
+// create_table runs a CREATE TABLE: a page off the freelist, or a new one
+// at the end of the file, becomes the empty root of the table's b-tree, and
+// sqlite_schema gets a row for it. AUTOINCREMENT makes sqlite_sequence too
+// the first time. Tables that need an index sqlite3 makes itself, for a
+// UNIQUE or PRIMARY KEY constraint, aren't supported.
+pub fn create_table(pager: &Pager, tables: &Tables, create: &NewTable) -> Result<()> {
+    let t = &create.stmt;
+    let unsupported =
+        |why: &str| SqliteError::Unsupported(format!("cannot create {}: {why}", t.table));
+    if create.temp {
+        bail!(unsupported("TEMP tables are not implemented"));
+    }
+    if let Some(e) = tables
+        .entries
+        .iter()
+        .find(|e| e.name.eq_ignore_ascii_case(&t.table))
+    {
+        match e.kind {
+            SchemaKind::Index => bail!(SqliteError::SqlParse(format!(
+                "there is already an index named {}",
+                t.table
+            ))),
+            _ if create.if_not_exists => return Ok(()),
+            SchemaKind::View => bail!(SqliteError::SqlParse(format!(
+                "view {} already exists",
+                t.table
+            ))),
+            _ => bail!(SqliteError::SqlParse(format!(
+                "table {} already exists",
+                t.table
+            ))),
+        }
+    }
+    if t.table.to_ascii_lowercase().starts_with("sqlite_") {
+        bail!(SqliteError::SqlParse(format!(
+            "object name reserved for internal use: {}",
+            t.table
+        )));
+    }
+    for (i, c) in t.columns.iter().enumerate() {
+        if column_index(&t.columns[..i], &c.name).is_some() {
+            bail!(SqliteError::SqlParse(format!(
+                "duplicate column name: {}",
+                c.name
+            )));
+        }
+    }
+    let primary_key = t.columns.iter().any(|c| c.primary_key);
+    if t.without_rowid && !primary_key {
+        bail!(SqliteError::SqlParse(format!(
+            "PRIMARY KEY missing on table {}",
+            t.table
+        )));
+    }
+    if t.autoincrement && t.rowid_column().is_none() {
+        bail!(SqliteError::SqlParse(
+            "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY".to_string()
+        ));
+    }
+    if create.unique || (primary_key && !t.without_rowid && t.rowid_column().is_none()) {
+        bail!(unsupported(
+            "the indexes of its UNIQUE and PRIMARY KEY constraints are not implemented"
+        ));
+    }
+    // every page of an auto-vacuum database has a pointer map entry
+    if pager.dbinfo().header.largest_root_page != 0 {
+        bail!(unsupported("pointer map pages are not implemented"));
+    }
+
+    new_btree(pager, &t.table, &create.sql, t.without_rowid)?;
+    if t.autoincrement && !tables.pos.contains_key("sqlite_sequence") {
+        new_btree(
+            pager,
+            "sqlite_sequence",
+            "CREATE TABLE sqlite_sequence(name,seq)",
+            false,
+        )?;
+    }
+    // the schema cookie tells every connection to read the schema again
+    let header = pager.page(0, true)?;
+    let cookie = u32::from_be_bytes(header.page[40..44].try_into().unwrap());
+    pager.write_header(40, cookie.wrapping_add(1))
+}
+
+// new_btree makes the empty b-tree of the table `name`, a rowid table's or
+// with `without_rowid` an index one's, and adds its row to sqlite_schema.
+fn new_btree(pager: &Pager, name: &str, sql: &str, without_rowid: bool) -> Result<()> {
+    let u = pager.dbinfo().page_size as usize;
+    let rootpage = allocate_page(pager, false)?;
+    let text = |s: &str| ColType::Text(s.to_string());
+    let record = encode_record(
+        &[
+            text("table"),
+            text(name),
+            text(name),
+            ColType::Integer(rootpage as i64),
+            text(sql),
+        ],
+        pager.dbinfo().text_encoding,
+        pager.dbinfo().schema_format,
+    );
+    let rowid = btree::last_rowid(pager, 1)? + 1;
+    // sqlite_schema's leaf may be full, before anything is written
+    put_record(pager, 1, rowid, &record)?;
+
+    allocate_page(pager, true)?;
+    let mut page = vec![0; u];
+    page[0] = if without_rowid { 0x0a } else { 0x0d };
+    // 0 stands for 65536, an empty page of a 64KiB database
+    page[5..7].copy_from_slice(&(u as u16).to_be_bytes());
+    pager.write_page(rootpage - 1, &page)
+}
+
+// allocate_page picks the page a new b-tree takes: the last leaf of the
+// first freelist trunk, the trunk itself once it lists none, or else a new
+// page at the end of the file. Returns its number, and with `take` takes a
+// freelist page off the list; the caller writes the page.
+fn allocate_page(pager: &Pager, take: bool) -> Result<usize> {
+    let u32_at = |b: &[u8], at: usize| u32::from_be_bytes(b[at..at + 4].try_into().unwrap());
+    let header = pager.page(0, true)?;
+    let trunk = u32_at(&header.page, 32) as usize;
+    let free = u32_at(&header.page, 36);
+    let pages = pager.page_count()?;
+    let corrupt = |what: String| SqliteError::CorruptDatabase(format!("freelist {what}"));
+    if trunk == 0 {
+        let mut number = pages + 1;
+        // the page holding the lock bytes at 1GiB is never used
+        if number == (1 << 30) / pager.dbinfo().page_size as usize + 1 {
+            number += 1;
+        }
+        return Ok(number);
+    }
+    if trunk > pages {
+        bail!(corrupt(format!(
+            "trunk page {trunk} is past the end of the file"
+        )));
+    }
+    let mut page = pager.page(trunk - 1, true)?.page.into_owned();
+    let leaves = u32_at(&page, 4) as usize;
+    let number = if leaves == 0 {
+        if take {
+            pager.write_header(32, u32_at(&page, 0))?;
+        }
+        trunk
+    } else {
+        let Some(leaf) = page.get(4 + 4 * leaves..8 + 4 * leaves) else {
+            bail!(corrupt(format!("trunk page {trunk} lists {leaves} leaves")));
+        };
+        let leaf = u32::from_be_bytes(leaf.try_into().unwrap()) as usize;
+        if leaf < 2 || leaf > pages {
+            bail!(corrupt(format!("leaf page {leaf} is out of range")));
+        }
+        if take {
+            page[4..8].copy_from_slice(&(leaves as u32 - 1).to_be_bytes());
+            pager.write_page(trunk - 1, &page)?;
+        }
+        leaf
+    };
+    if take {
+        pager.write_header(36, free.saturating_sub(1))?;
+    }
+    Ok(number)
+}
+
+// writable is the table a statement that `verb`s `table` can write: a plain
+// rowid table, without an index or a trigger to keep up to date.
+fn writable<'t>(tables: &'t Tables, table: &str, verb: &str) -> Result<&'t CreateTableStmt> {
+    let t = match tables.content.get(table) {
+        Some(Create::Table(t)) => t,
+        _ => bail!(SqliteError::NoSuchTable(table.to_string())),
+    };
+    let unsupported =
+        |why: &str| SqliteError::Unsupported(format!("cannot {verb} {}: {why}", t.table));
+    if t.without_rowid || t.strict || t.fts5.is_some() || t.rtree.is_some() {
+        bail!(unsupported("only plain rowid tables can be written"));
+    }
+    if tables
+        .objects_of_type(SchemaKind::Index)
+        .any(|e| e.table == t.table)
+    {
+        bail!(unsupported("updating its indexes is not implemented"));
+    }
+    if tables.triggers.contains_key(&t.table) {
+        bail!(unsupported("running its triggers is not implemented"));
+    }
+    Ok(t)
+}
+
+// constant evaluates the SQL of an expression that reads no column.
+fn constant(sql: &str, params: &[ColType]) -> Result<ColType> {
+    let mut e = expr::parse_expr(sql).map_err(SqliteError::SqlParse)?;
+    e.bind(params)?;
+    e.eval(&|_| None)
+}
+
+// insert_targets maps the column list of an INSERT to the table's columns,
+// None standing for the rowid by one of its own names. No list means every
+// column in order.
+fn insert_targets(t: &CreateTableStmt, columns: &[String]) -> Result<Vec<Option<usize>>> {
+    if columns.is_empty() {
+        return Ok((0..t.columns.len()).map(Some).collect());
+    }
+    columns
+        .iter()
+        .map(|name| match column_index(&t.columns, name) {
+            Some(i) => Ok(Some(i)),
+            None if is_rowid_alias(name) => Ok(None),
+            None => Err(SqliteError::SqlParse(format!(
+                "table {} has no column named {name}",
+                t.table
+            ))
+            .into()),
+        })
+        .collect()
+}
+
+// insert_row evaluates one row of VALUES into the record to store and the
+// rowid it asks for, if any. Columns left out get their DEFAULT, and every
+// value the affinity of its column; an INTEGER PRIMARY KEY is stored as
+// NULL, its value being the rowid.
+fn insert_row(
+    t: &CreateTableStmt,
+    targets: &[Option<usize>],
+    row: &[String],
+    params: &[ColType],
+) -> Result<(Option<i64>, Vec<ColType>)> {
+    if row.len() != targets.len() {
+        bail!(SqliteError::SqlParse(
+            match targets.len() == t.columns.len() {
+                true => format!(
+                    "table {} has {} columns but {} values were supplied",
+                    t.table,
+                    t.columns.len(),
+                    row.len()
+                ),
+                false => format!("{} values for {} columns", row.len(), targets.len()),
+            }
+        ));
+    }
+    let eval = |sql: &str| constant(sql, params);
+    let mut given = vec![None; t.columns.len()];
+    let mut rowid = None;
+    for (target, sql) in targets.iter().zip(row) {
+        match target {
+            Some(i) => given[*i] = Some(eval(sql)?),
+            None => rowid = Some(numeric(eval(sql)?)),
+        }
+    }
+    let rowid_column = t.rowid_column();
+    let mut values = Vec::with_capacity(t.columns.len());
+    for (c, v) in t.columns.iter().zip(given) {
+        let v = match (v, &c.default) {
+            (Some(v), _) => v,
+            (None, Some(default)) => eval(default)?,
+            (None, None) => ColType::Null,
+        };
+        let v = with_affinity(c, v);
+        if rowid_column == Some(c.name.as_str()) {
+            if v != ColType::Null {
+                rowid = Some(v);
+            }
+            values.push(ColType::Null);
+            continue;
+        }
+        if c.not_null && v == ColType::Null {
+            bail!(SqliteError::Constraint(format!(
+                "NOT NULL constraint failed: {}.{}",
+                t.table, c.name
+            )));
+        }
+        values.push(v);
+    }
+    let rowid = match rowid {
+        None | Some(ColType::Null) => None,
+        Some(ColType::Integer(i)) => Some(i),
+        Some(_) => bail!(SqliteError::Constraint("datatype mismatch".to_string())),
+    };
+    Ok((rowid, values))
+}
+
+// with_affinity converts a value the way its column's declared type asks,
+// by the rules of https://www.sqlite.org/datatype3.html#type_affinity:
+// numbers become text in a TEXT column, text that reads as a number becomes
+// one in a numeric column, and BLOB columns take values as they are.
+fn with_affinity(c: &ColumnDef, v: ColType) -> ColType {
+    let ty = c.ty.as_deref().unwrap_or_default().to_ascii_uppercase();
+    if ty.contains("INT") {
+        numeric(v)
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| ty.contains(t)) {
+        match v {
+            ColType::Integer(_) | ColType::Float(_) => ColType::Text(v.to_string()),
+            v => v,
+        }
+    } else if ty.is_empty() || ty.contains("BLOB") {
+        v
+    } else if c.real_affinity() {
+        match numeric(v) {
+            ColType::Integer(i) => ColType::Float(i as f64),
+            v => v,
+        }
+    } else {
+        numeric(v)
+    }
+}
+
+// numeric is NUMERIC affinity: text that reads as a number becomes it, and
+// a REAL that's a whole number small enough becomes an INTEGER.
+fn numeric(v: ColType) -> ColType {
+    match v {
+        ColType::Text(s) => {
+            let t = s.trim();
+            let looks_numeric = t.bytes().any(|b| b.is_ascii_digit())
+                && t.bytes()
+                    .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+            match (t.parse::<i64>(), t.parse::<f64>()) {
+                (Ok(i), _) if looks_numeric => ColType::Integer(i),
+                (_, Ok(f)) if looks_numeric => numeric(ColType::Float(f)),
+                _ => ColType::Text(s),
+            }
+        }
+        ColType::Float(f) if f.fract() == 0.0 && f.abs() < 9.2e18 => ColType::Integer(f as i64),
+        v => v,
+    }
+}
+
+// stored_row reads the row `rowid` of the table rooted at `rootpage`, if it
+// has one.
+fn stored_row(pager: &Pager, rootpage: usize, rowid: i64) -> Result<Option<Vec<ColType>>> {
+    let mut cursor = TableCursor::new(pager, rootpage);
+    cursor.seek(rowid)?;
+    Ok(match cursor.next().transpose()? {
+        Some((r, values)) if r == rowid => Some(values),
+        _ => None,
+    })
+}
+
+// sequence reads the sqlite_sequence row of an AUTOINCREMENT table: the
+// row's rowid and the largest rowid the table ever had.
+fn sequence(pager: &Pager, tables: &Tables, table: &str) -> Result<Option<(i64, i64)>> {
+    for row in tables.scan_table(pager, "sqlite_sequence")? {
+        let (rowid, values) = row?;
+        if let [ColType::Text(name), ColType::Integer(seq), ..] = values.as_slice()
+            && name == table
+        {
+            return Ok(Some((rowid, *seq)));
+        }
+    }
+    Ok(None)
+}
+
+// set_sequence stores `seq` as the largest rowid `table` ever had, in its
+// sqlite_sequence row `rowid` or a new one.
+fn set_sequence(
+    pager: &Pager,
+    tables: &Tables,
+    table: &str,
+    rowid: Option<i64>,
+    seq: i64,
+) -> Result<()> {
+    let Some(&rootpage) = tables.pos.get("sqlite_sequence") else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "{table} is AUTOINCREMENT but there's no sqlite_sequence"
+        )));
+    };
+    let rowid = match rowid {
+        Some(rowid) => rowid,
+        None => btree::last_rowid(pager, rootpage)? + 1,
+    };
+    let record = encode_record(
+        &[ColType::Text(table.to_string()), ColType::Integer(seq)],
+        pager.dbinfo().text_encoding,
+        pager.dbinfo().schema_format,
+    );
+    put_record(pager, rootpage, rowid, &record)
+}
+
+// put_record stores `record` as the row `rowid` of the table rooted at
+// `rootpage`, replacing the row with that rowid if there is one. The leaf it
+// belongs in is written back whole with its cells packed at the end of the
+// page, so it has no freeblocks or fragments left. A leaf without room for
+// the cell fails: splitting pages isn't implemented, nor are overflow pages.
+pub fn put_record(pager: &Pager, rootpage: usize, rowid: i64, record: &[u8]) -> Result<()> {
+    let u = pager.dbinfo().page_size as usize;
+    if record.len() > u - 35 {
+        bail!(SqliteError::Unsupported(format!(
+            "a {} byte record, too big for a page of its own, needs overflow pages",
+            record.len()
+        )));
+    }
+    let mut cell = encode_varint(record.len() as i64);
+    cell.extend(encode_varint(rowid));
+    cell.extend_from_slice(record);
+
+    let leaf = btree::leaf_for(pager, rootpage, rowid)?;
+    let mut cells = leaf
+        .cell_offsets
+        .iter()
+        .map(|&offset| leaf_cell(&leaf, offset as usize, u))
+        .collect::<Result<Vec<_>>>()?;
+    match cells.binary_search_by_key(&rowid, |&(r, _)| r) {
+        Ok(i) => cells[i].1 = &cell,
+        Err(i) => cells.insert(i, (rowid, &cell)),
+    }
+    let page = pack_leaf(&leaf, &cells, u)?;
+    pager.write_page(leaf.number - 1, &page)
+}
+
+// rewrite_record replaces the record of the existing row `rowid` of
+// `table`. A cell that doesn't grow is overwritten where it is, the bytes it
+// no longer uses given back to the page; one that grows goes through
+// put_record, which repacks its leaf.
+fn rewrite_record(
+    pager: &Pager,
+    table: &str,
+    rootpage: usize,
+    rowid: i64,
+    record: &[u8],
+) -> Result<()> {
+    let u = pager.dbinfo().page_size as usize;
+    let leaf = btree::leaf_for(pager, rootpage, rowid)?;
+    let mut old = None;
+    for &offset in &leaf.cell_offsets {
+        let (r, cell) = leaf_cell(&leaf, offset as usize, u)?;
+        if r == rowid {
+            old = Some((offset as usize, cell));
+            break;
+        }
+    }
+    let Some((offset, old)) = old else {
+        bail!(SqliteError::CorruptDatabase(format!(
+            "row {rowid} of {table} is missing from page {}",
+            leaf.number
+        )));
+    };
+    // the overflow pages would have to go on the freelist
+    let (size, _) = decode_varint(old)?;
+    if local_size(size as usize, u - 35, u) < size as usize {
+        bail!(SqliteError::Unsupported(format!(
+            "cannot update {table}: row {rowid} spills onto overflow pages, \
+             and freeing them is not implemented"
+        )));
+    }
+    let mut cell = encode_varint(record.len() as i64);
+    cell.extend(encode_varint(rowid));
+    cell.extend_from_slice(record);
+    if cell.len() > old.len() {
+        return put_record(pager, rootpage, rowid, record);
+    }
+    let mut page = leaf.page.to_vec();
+    page[offset..offset + cell.len()].copy_from_slice(&cell);
+    let start = if leaf.number == 1 { 100 } else { 0 };
+    if !free_range(
+        &mut page,
+        start,
+        offset + cell.len(),
+        offset + old.len(),
+        leaf.number,
+    )? {
+        return put_record(pager, rootpage, rowid, record);
+    }
+    pager.write_page(leaf.number - 1, &page)
+}
+
+// free_range gives the bytes from..to of the leaf page `page`, its b-tree
+// header at `start`, back to it the way sqlite3's freeSpace() does: merged
+// with the freeblocks and fragments right before and after them into a
+// freeblock of the chain, kept in increasing order, or counted as fragmented
+// when fewer than 4. false when that would take the page past the 60
+// fragmented bytes sqlite3 allows.
+fn free_range(
+    page: &mut [u8],
+    start: usize,
+    from: usize,
+    to: usize,
+    number: usize,
+) -> Result<bool> {
+    if from == to {
+        return Ok(true);
+    }
+    let get = |page: &[u8], at: usize| u16::from_be_bytes([page[at], page[at + 1]]) as usize;
+    let mut blocks = Vec::new();
+    let mut next = get(page, start + 1);
+    while next != 0 {
+        if next + 4 > page.len() || blocks.last().is_some_and(|&(_, end)| next < end) {
+            bail!(SqliteError::CorruptDatabase(format!(
+                "freeblock at offset {next} of page {number} is out of order"
+            )));
+        }
+        blocks.push((next, next + get(page, next + 2)));
+        next = get(page, next);
+    }
+    let (mut from, mut to) = (from, to);
+    let mut fragmented = page[start + 7] as usize;
+    // fewer than 4 bytes between two free ranges can only be fragments
+    blocks.retain(|&(s, e)| {
+        if e <= from && from - e <= 3 {
+            fragmented = fragmented.saturating_sub(from - e);
+            from = s;
+            false
+        } else if s >= to && s - to <= 3 {
+            fragmented = fragmented.saturating_sub(s - to);
+            to = e;
+            false
+        } else {
+            true
+        }
+    });
+    if to - from < 4 {
+        fragmented += to - from;
+        if fragmented > 60 {
+            return Ok(false);
+        }
+        page[start + 7] = fragmented as u8;
+        return Ok(true);
+    }
+    page[start + 7] = fragmented as u8;
+    let i = blocks.partition_point(|&(s, _)| s < from);
+    blocks.insert(i, (from, to));
+    let mut pointer = start + 1;
+    for &(s, e) in &blocks {
+        page[pointer..pointer + 2].copy_from_slice(&(s as u16).to_be_bytes());
+        page[s + 2..s + 4].copy_from_slice(&((e - s) as u16).to_be_bytes());
+        pointer = s;
+    }
+    page[pointer..pointer + 2].copy_from_slice(&[0, 0]);
+    Ok(true)
+}
+
+// leaf_cell is the rowid and the bytes of the table leaf cell at `offset`:
+// its two varints, the payload kept on the page, and the number of the first
+// overflow page when the rest spills over.
+fn leaf_cell<'a>(p: &'a Page, offset: usize, u: usize) -> Result<(i64, &'a [u8])> {
+    let corrupt = || {
+        SqliteError::CorruptDatabase(format!(
+            "cell at offset {offset} runs past the end of page {}",
+            p.number
+        ))
+    };
+    let buf = p.page.get(offset..).ok_or_else(corrupt)?;
+    let (size, n1) = decode_varint(buf)?;
+    let (rowid, n2) = decode_varint(&buf[n1..])?;
+    let size = usize::try_from(size).map_err(|_| corrupt())?;
+    let local = local_size(size, u - 35, u);
+    let overflow = if local < size { 4 } else { 0 };
+    let cell = buf.get(..n1 + n2 + local + overflow).ok_or_else(corrupt)?;
+    Ok((rowid, cell))
+}
+
+// pack_leaf lays `cells` out as the table leaf page `p`: the header and the
+// cell pointers in rowid order at the start, the cells from the end down.
+// Page 1 keeps the file header in front of its b-tree header.
+fn pack_leaf(p: &Page, cells: &[(i64, &[u8])], u: usize) -> Result<Vec<u8>> {
+    let start = if p.number == 1 { 100 } else { 0 };
+    let content = cells.iter().map(|(_, c)| c.len()).sum::<usize>();
+    if start + 8 + 2 * cells.len() + content > u {
+        bail!(SqliteError::Unsupported(format!(
+            "page {} is full, and splitting pages is not implemented",
+            p.number
+        )));
+    }
+    let mut page = vec![0; u];
+    page[..start].copy_from_slice(&p.page[..start]);
+    let mut end = u;
+    for (i, (_, cell)) in cells.iter().enumerate() {
+        end -= cell.len();
+        page[end..end + cell.len()].copy_from_slice(cell);
+        let pointer = start + 8 + 2 * i;
+        page[pointer..pointer + 2].copy_from_slice(&(end as u16).to_be_bytes());
+    }
+    page[start] = 0x0d;
+    page[start + 3..start + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    // 0 stands for 65536, an empty page of a 64KiB database
+    page[start + 5..start + 7].copy_from_slice(&(end as u16).to_be_bytes());
+    Ok(page)
+}
+
+#[test]
+fn test_free_range() {
+    let get = |page: &[u8], at: usize| u16::from_be_bytes([page[at], page[at + 1]]);
+    let mut page = vec![0; 512];
+    page[0] = 0x0d;
+    // 4 fragmented bytes, 2 on either side of 110..112 and 198..200
+    page[7] = 4;
+    assert!(free_range(&mut page, 0, 103, 110, 2).unwrap());
+    assert!(free_range(&mut page, 0, 200, 210, 2).unwrap());
+    assert_eq!(get(&page, 1), 103);
+    assert_eq!((get(&page, 103), get(&page, 105)), (200, 7));
+    assert_eq!((get(&page, 200), get(&page, 202)), (0, 10));
+    // the fragments between them are taken into one freeblock
+    assert!(free_range(&mut page, 0, 112, 198, 2).unwrap());
+    assert_eq!(get(&page, 1), 103);
+    assert_eq!((get(&page, 103), get(&page, 105)), (0, 107));
+    assert_eq!(page[7], 0);
+    // fewer than 4 bytes are fragmented, up to 60 of them
+    assert!(free_range(&mut page, 0, 300, 303, 2).unwrap());
+    assert_eq!((get(&page, 1), page[7]), (103, 3));
+    page[7] = 59;
+    assert!(!free_range(&mut page, 0, 400, 402, 2).unwrap());
+    assert_eq!(page[7], 59);
+}
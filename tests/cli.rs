@@ -0,0 +1,1245 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+mod fixtures;
+
+fn run_with_stdin(args: &[&str], stdin: impl AsRef<[u8]>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-sqlite"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_ref())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+const SCRIPT: &str = "\
+.tables
+select name from apples;
+select name from oranges where name = 'Tangelo';
+select name from nosuchtable;
+select color from apples;
+";
+
+#[test]
+fn script_from_stdin_stops_at_first_error() {
+    for args in [&["sample.db"][..], &["sample.db", "-"][..]] {
+        let out = run_with_stdin(args, SCRIPT);
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert_eq!(
+            stdout,
+            "apples sqlite_sequence oranges\n\
+             Granny Smith\nFuji\nHoneycrisp\nGolden Delicious\n\
+             Tangelo\n"
+        );
+        assert_eq!(out.status.code(), Some(6));
+        assert!(
+            stderr.contains("near line 4: no such table: nosuchtable"),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn init_script_runs_before_command() {
+    let path = std::env::temp_dir().join(format!("init-{}.sql", std::process::id()));
+    std::fs::write(&path, "select name from apples where color = 'Red';\n").unwrap();
+    let out = run_with_stdin(
+        &[
+            "--init",
+            path.to_str().unwrap(),
+            "sample.db",
+            &format!(".read {}", path.display()),
+        ],
+        "",
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Fuji\nFuji\n");
+}
+
+#[test]
+fn read_cycle_stops_at_the_nesting_limit() {
+    let path = std::env::temp_dir().join(format!("cycle-{}.sql", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            "select name from apples where color = 'Red';\n.read {}\n",
+            path.display()
+        ),
+    )
+    .unwrap();
+    let out = run_with_stdin(&["sample.db", &format!(".read {}", path.display())], "");
+    std::fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert_eq!(out.status.code(), Some(2), "{stderr}");
+    assert!(
+        stderr.contains("near line 2: input nesting limit (25) reached"),
+        "{stderr}"
+    );
+    // only the innermost script locates the error
+    assert_eq!(stderr.matches("near line").count(), 1, "{stderr}");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Fuji\n".repeat(25));
+}
+
+#[test]
+fn hot_journal_is_reported() {
+    let dir = std::env::temp_dir().join(format!("hot-journal-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("sample.db");
+    std::fs::copy("sample.db", &db).unwrap();
+    let mut journal = vec![0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+    for v in [1u32, 7, 4, 512, 4096] {
+        journal.extend(v.to_be_bytes());
+    }
+    // a header torn before its record was written isn't hot
+    std::fs::write(dir.join("sample.db-journal"), &journal).unwrap();
+    let out = run_with_stdin(&[db.to_str().unwrap(), ".tables"], "");
+    assert!(out.status.success());
+
+    journal.resize(512 + 4 + 4096 + 4, 0);
+    std::fs::write(dir.join("sample.db-journal"), journal).unwrap();
+    let out = run_with_stdin(&[db.to_str().unwrap(), ".tables"], "");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stdout).unwrap().is_empty());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("hot journal"), "{stderr}");
+}
+
+#[test]
+fn immutable_reads_the_base_file_past_a_hot_journal() {
+    let dir = std::env::temp_dir().join(format!("immutable-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("sample.db");
+    std::fs::copy("sample.db", &db).unwrap();
+    // a journal saving a zeroed page 2, which rolling back would put in
+    // place of the apples
+    let mut journal = vec![0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+    for v in [1u32, 7, 4, 512, 4096] {
+        journal.extend(v.to_be_bytes());
+    }
+    journal.resize(512, 0);
+    journal.extend(2u32.to_be_bytes());
+    journal.extend([0; 4096]);
+    journal.extend(7u32.to_be_bytes());
+    std::fs::write(dir.join("sample.db-journal"), journal).unwrap();
+    let db = db.to_str().unwrap();
+
+    let out = run_with_stdin(&["--immutable", db, "select name from apples"], "");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "Granny Smith\nFuji\nHoneycrisp\nGolden Delicious\n"
+    );
+    let insert = "insert into apples (name) values ('Gala')";
+    let out = run_with_stdin(&["--immutable", "--write", db, insert], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("readonly database"));
+    let out = run_with_stdin(&[db, "select name from apples"], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("open it immutable"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_reads_the_database_before_a_hot_journal_transaction() {
+    let dir = std::env::temp_dir().join(format!("rollback-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("sample.db");
+    std::fs::copy("sample.db", &db).unwrap();
+    let db = db.to_str().unwrap();
+    // a writer that saved pages 1 and 2 and changed both before it crashed
+    let before = std::fs::read("sample.db").unwrap();
+    let insert = "insert into apples (name, color) values ('Gala', 'Red')";
+    assert!(
+        run_with_stdin(&["--write", db, insert], "")
+            .status
+            .success()
+    );
+    let nonce = 0x5eed_u32;
+    let mut journal = vec![0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+    for v in [2u32, nonce, 4, 512, 4096] {
+        journal.extend(v.to_be_bytes());
+    }
+    journal.resize(512, 0);
+    for (pgno, page) in before.chunks(4096).enumerate().take(2) {
+        let checksum = (1..4096 / 200)
+            .map(|k| page[4096 - 200 * k] as u32)
+            .fold(nonce, u32::wrapping_add);
+        journal.extend((pgno as u32 + 1).to_be_bytes());
+        journal.extend(page);
+        journal.extend(checksum.to_be_bytes());
+    }
+    std::fs::write(format!("{db}-journal"), journal).unwrap();
+
+    let select = "select count(*) from apples";
+    let out = run_with_stdin(&[db, select], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("hot journal"));
+    let out = run_with_stdin(&["--rollback", db, select], "");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "4\n");
+    let out = run_with_stdin(&["--immutable", db, select], "");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "5\n");
+    // the file and the journal are left for sqlite3 to roll back
+    assert!(std::path::Path::new(&format!("{db}-journal")).exists());
+    let out = run_with_stdin(&["--rollback", "--write", db, insert], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("readonly database"));
+    // sqlite3 rolls the journal back to the same rows
+    assert_eq!(
+        fixtures::sqlite_rows(std::path::Path::new(db), "", select),
+        "4\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn multiple_commands_run_in_order() {
+    let out = run_with_stdin(
+        &[
+            "-cmd",
+            "select name from apples where color = 'Red'",
+            "sample.db",
+            ".dbinfo",
+            ".tables",
+            "select name from oranges where name = 'Tangelo'",
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "Fuji\n\
+         database page size: 4096\n\
+         write format: 1\n\
+         read format: 1\n\
+         reserved bytes: 0\n\
+         file change counter: 5\n\
+         database page count: 4\n\
+         freelist page count: 0\n\
+         schema cookie: 2\n\
+         schema format: 4\n\
+         default cache size: 0\n\
+         autovacuum top root: 0\n\
+         incremental vacuum: 0\n\
+         text encoding: 1 (utf8)\n\
+         user version: 0\n\
+         application id: 0\n\
+         software version: 3034000\n\
+         number of tables: 3\n\
+         number of indexes: 0\n\
+         number of triggers: 0\n\
+         number of views: 0\n\
+         schema size: 217\n\
+         apples sqlite_sequence oranges\n\
+         Tangelo\n"
+    );
+
+    let out = run_with_stdin(&["sample.db", ".tables", "select x from nosuchtable"], "");
+    assert_eq!(out.status.code(), Some(6));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("argument 3 (select x from nosuchtable): no such table: nosuchtable"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn utf16_database_text_is_decoded() {
+    let out = run_with_stdin(
+        &["tests/fixtures/utf16.db", "select name from greetings"],
+        "",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "hello\n😀 grin\n𝄞 clef\n"
+    );
+}
+
+#[test]
+fn timer_reports_on_stderr_only() {
+    let line = regex::Regex::new(
+        r"(?m)^Run Time: real \d+\.\d{3} parse \d+\.\d{3} plan \d+\.\d{3} execute \d+\.\d{3}$",
+    )
+    .unwrap();
+
+    let out = run_with_stdin(
+        &[
+            "--timer",
+            "sample.db",
+            "select name from apples where color = 'Red'",
+        ],
+        "",
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Fuji\n");
+    assert!(line.is_match(&String::from_utf8(out.stderr).unwrap()));
+
+    let out = run_with_stdin(
+        &["sample.db"],
+        ".timer on\nselect name from apples where color = 'Red';\n.timer off\n",
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Fuji\n");
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert_eq!(line.find_iter(&stderr).count(), 1, "{stderr}");
+}
+
+#[test]
+fn mismatched_schema_names_warn_instead_of_panicking() {
+    // the CREATE statements in this fixture name "old_fruits",
+    // while sqlite_master's tbl_name is "fruits"
+    let out = run_with_stdin(
+        &[
+            "tests/fixtures/renamed.db",
+            ".tables",
+            "select name from fruits",
+            "select id from fruits where name = 'plum'",
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "fruits\nkiwi\nplum\n2\n"
+    );
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "warning: table fruits refers to table old_fruits but the schema says fruits"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn explain_query_plan_reports_access_path() {
+    let indexed = fixtures::write_temp("indexed.db", &fixtures::create_indexed_table());
+    let multipage = fixtures::write_temp("multipage.db", &fixtures::create_multipage_table(1000));
+    let cases = [
+        (
+            "sample.db",
+            "explain query plan select name from apples where color = 'Red'",
+            "SCAN apples",
+        ),
+        (
+            indexed.to_str().unwrap(),
+            "EXPLAIN QUERY PLAN select id from companies where country = 'chad'",
+            "SEARCH companies USING INDEX idx_companies_country (country=?)",
+        ),
+        (
+            multipage.to_str().unwrap(),
+            "explain query plan select name from numbers where id = 777",
+            "SEARCH numbers USING INTEGER PRIMARY KEY (rowid=?)",
+        ),
+    ];
+    for (db, sql, plan) in cases {
+        let out = run_with_stdin(&[db, sql], "");
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            format!("QUERY PLAN\n`--{plan}\n")
+        );
+    }
+
+    let out = run_with_stdin(
+        &[
+            multipage.to_str().unwrap(),
+            "select name from numbers where id = 777",
+            "select name from numbers where rowid = 3",
+        ],
+        "",
+    );
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "number 777\nnumber 3\n"
+    );
+    std::fs::remove_file(&indexed).unwrap();
+    std::fs::remove_file(&multipage).unwrap();
+}
+
+#[test]
+fn expression_indexes_are_listed_but_not_used() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table people (id integer primary key, name text, email text unique)")
+        .sql("create index idx_people_lower on people (lower(name))")
+        .sql("create index idx_people_name on people (name)")
+        .sql("insert into people (name, email) values ('Ann', 'a@x'), ('bob', 'b@x')");
+    let image = builder.build();
+    let db = fixtures::write_temp("expression_index.db", &image);
+    let db_str = db.to_str().unwrap();
+
+    let out = run_with_stdin(&[db_str, ".indexes people"], "");
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "idx_people_lower idx_people_name sqlite_autoindex_people_1\n"
+    );
+
+    let out = run_with_stdin(&[db_str, "select email from people where name = 'bob'"], "");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "b@x\n");
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn max_rows_truncates_output() {
+    let multipage = fixtures::write_temp("max_rows.db", &fixtures::create_multipage_table(1000));
+    let db = multipage.to_str().unwrap();
+
+    let out = run_with_stdin(&["--max-rows", "3", db, "select name from numbers"], "");
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "number 1\nnumber 2\nnumber 3\n... (truncated)\n"
+    );
+
+    // exactly at the cap is not truncated
+    let out = run_with_stdin(
+        &[
+            "--max-rows",
+            "1",
+            db,
+            "select name from numbers where id = 5",
+        ],
+        "",
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "number 5\n");
+
+    let out = run_with_stdin(&["--max-rows", "lots", db, ".tables"], "");
+    assert!(!out.status.success());
+    std::fs::remove_file(&multipage).unwrap();
+}
+
+// totals from the last "trace: N pages read, ..." line
+fn trace_totals(stderr: &[u8]) -> Vec<usize> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let line = stderr
+        .lines()
+        .rfind(|l| l.starts_with("trace: ") && l.ends_with("rows matched"))
+        .expect("no trace totals");
+    line.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse().ok())
+        .collect()
+}
+
+#[test]
+fn trace_logs_pages_and_totals() {
+    let multipage = fixtures::write_temp("trace.db", &fixtures::create_multipage_table(1000));
+    let db = multipage.to_str().unwrap();
+
+    let seek = run_with_stdin(
+        &["--trace", db, "select name from numbers where id = 777"],
+        "",
+    );
+    assert_eq!(String::from_utf8(seek.stdout).unwrap(), "number 777\n");
+    let stderr = String::from_utf8_lossy(&seek.stderr);
+    assert!(stderr.contains("(table interior,"), "{stderr}");
+    assert!(stderr.contains("key"), "{stderr}");
+    let seek_totals = trace_totals(&seek.stderr);
+    assert_eq!(seek_totals[2], 1);
+
+    let scan = run_with_stdin(
+        &[
+            "--trace",
+            db,
+            "select name from numbers where parity = 'odd'",
+        ],
+        "",
+    );
+    let scan_totals = trace_totals(&scan.stderr);
+    assert_eq!(scan_totals[2], 500);
+    // a rowid seek reads a path through the tree, a scan reads every page
+    assert!(
+        seek_totals[0] < scan_totals[0],
+        "{seek_totals:?} {scan_totals:?}"
+    );
+    assert!(seek_totals[1] < scan_totals[1]);
+    std::fs::remove_file(&multipage).unwrap();
+}
+
+#[test]
+fn failures_exit_with_distinct_codes() {
+    let garbage = fixtures::write_temp("not_a_database.db", &[b'x'; 512]);
+    let mut corrupt = fixtures::create_simple_table();
+    // turn the fruits root page (page 2) into an unknown page type
+    let page_size = u16::from_be_bytes([corrupt[16], corrupt[17]]) as usize;
+    corrupt[page_size] = 0x07;
+    let corrupt = fixtures::write_temp("corrupt.db", &corrupt);
+
+    let cases = [
+        (vec![], 2, "Missing <database path> and <command>"),
+        (
+            vec!["sample.db", ".nosuchcommand"],
+            2,
+            "Missing or invalid command passed",
+        ),
+        (
+            vec![garbage.to_str().unwrap(), ".tables"],
+            3,
+            "file is not a database",
+        ),
+        (
+            vec![corrupt.to_str().unwrap(), "select name from fruits"],
+            4,
+            "database disk image is malformed: page 2 has invalid type 0x07",
+        ),
+        (
+            vec!["sample.db", "selec name from apples"],
+            5,
+            "Invalid SELECT statement",
+        ),
+        (
+            vec!["sample.db", "select name from foo"],
+            6,
+            "no such table: foo",
+        ),
+        (
+            vec!["sample.db", "select bar from apples"],
+            7,
+            "no such column: bar",
+        ),
+        (
+            vec!["sample.db", "select name from apples where bar = 1"],
+            7,
+            "no such column: bar",
+        ),
+        (
+            vec!["sample.db", "delete from apples"],
+            8,
+            "attempt to write a readonly database",
+        ),
+    ];
+    for (args, code, message) in cases {
+        let out = run_with_stdin(&args, "");
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert_eq!(out.status.code(), Some(code), "{args:?}: {stderr}");
+        let last = stderr.lines().last().unwrap_or_default();
+        assert!(last.starts_with("Error: "), "{args:?}: {stderr}");
+        assert!(last.contains(message), "{args:?}: {stderr}");
+        assert!(!stderr.contains("panicked"), "{stderr}");
+    }
+    std::fs::remove_file(&garbage).unwrap();
+    std::fs::remove_file(&corrupt).unwrap();
+}
+
+#[test]
+fn null_rendering_follows_the_output_mode() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, name text, note text, score real)")
+        .sql(
+            "insert into t (name, note, score) values \
+             ('a,b', NULL, 1.5), ('say \"hi\"', 'x', NULL), ('plain', 'line\nbreak', 2)",
+        );
+    let image = builder.build();
+    let db = fixtures::write_temp("nullable.db", &image);
+    let db = db.to_str().unwrap();
+
+    let cases: [(&[&str], &str); 5] = [
+        (
+            &[],
+            "1|a,b||1.5\n2|say \"hi\"|x|\n3|plain|line\nbreak|2.0\n",
+        ),
+        (
+            &["-nullvalue", "NULL"],
+            "1|a,b|NULL|1.5\n2|say \"hi\"|x|NULL\n3|plain|line\nbreak|2.0\n",
+        ),
+        (
+            &["-csv"],
+            "1,\"a,b\",,1.5\n2,\"say \"\"hi\"\"\",x,\n3,plain,\"line\nbreak\",2.0\n",
+        ),
+        (
+            &["-json"],
+            "[{\"id\":1,\"name\":\"a,b\",\"note\":null,\"score\":1.5},\n\
+             {\"id\":2,\"name\":\"say \\\"hi\\\"\",\"note\":\"x\",\"score\":null},\n\
+             {\"id\":3,\"name\":\"plain\",\"note\":\"line\\nbreak\",\"score\":2.0}]\n",
+        ),
+        // json keeps null whatever the nullvalue
+        (
+            &["-json", "-nullvalue", "NIL"],
+            "[{\"id\":1,\"name\":\"a,b\",\"note\":null,\"score\":1.5},\n\
+             {\"id\":2,\"name\":\"say \\\"hi\\\"\",\"note\":\"x\",\"score\":null},\n\
+             {\"id\":3,\"name\":\"plain\",\"note\":\"line\\nbreak\",\"score\":2.0}]\n",
+        ),
+    ];
+    for (flags, expected) in cases {
+        // json mode is written with serde
+        if cfg!(not(feature = "serde")) && flags.contains(&"-json") {
+            continue;
+        }
+        let mut args = flags.to_vec();
+        args.extend([db, "select * from t"]);
+        let out = run_with_stdin(&args, "");
+        assert!(out.status.success(), "{flags:?}");
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            expected,
+            "{flags:?}"
+        );
+    }
+
+    // the dot commands do the same as the flags
+    let out = run_with_stdin(&[db], ".mode csv\n.nullvalue NIL\nselect note from t;\n");
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "NIL\nx\n\"line\nbreak\"\n"
+    );
+    std::fs::remove_file(db).unwrap();
+}
+
+#[test]
+fn quote_mode_matches_sqlite() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table q (id integer primary key, s text, n, r real, b blob)")
+        .sql(
+            "insert into q (s, n, r, b) values \
+             ('', NULL, 1.5, x'00ff10'), ('it''s', 42, 2, NULL), ('plain', -7, -0.25, x'')",
+        );
+    let image = builder.build();
+    let db = fixtures::write_temp("quote.db", &image);
+    let sql = "select * from q";
+    let expected = fixtures::sqlite3_output(&db, &["-quote"], sql);
+    assert_eq!(
+        String::from_utf8_lossy(&expected),
+        "1,'',NULL,1.5,X'00ff10'\n2,'it''s',42,2.0,NULL\n3,'plain',-7,-0.25,X''\n"
+    );
+
+    let out = run_with_stdin(&[db.to_str().unwrap(), ".mode quote", sql], "");
+    assert!(out.status.success());
+    assert_eq!(out.stdout, expected);
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_reads_the_same_rows() {
+    let sql = "select id, name from apples where color = 'Yellow'";
+    let read = run_with_stdin(&["sample.db", sql], "");
+    let mapped = run_with_stdin(&["--mmap", "sample.db", sql], "");
+    assert!(mapped.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&mapped.stdout),
+        "4|Golden Delicious\n"
+    );
+    assert_eq!(mapped.stdout, read.stdout);
+}
+
+#[test]
+fn verbose_enables_debug_logging() {
+    let sql = "select name from apples where id = 2";
+    let quiet = run_with_stdin(&["sample.db", sql], "");
+    assert_eq!(String::from_utf8_lossy(&quiet.stdout), "Fuji\n");
+    assert!(quiet.stderr.is_empty());
+
+    let verbose = run_with_stdin(&["--verbose", "sample.db", sql], "");
+    assert_eq!(verbose.stdout, quiet.stdout);
+    let stderr = String::from_utf8_lossy(&verbose.stderr);
+    assert!(stderr.contains("DEBUG codecrafters_sqlite::pager] read page 1"));
+    assert!(!stderr.contains("TRACE"));
+}
+
+#[test]
+fn output_is_binary_safe() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table raw (t text, b blob)")
+        .sql("insert into raw values ('a' || char(0) || 'b', x'00ff'), (char(27) || '[31mred', x'41')");
+    let image = builder.build();
+    let db = fixtures::write_temp("raw.db", &image);
+    let redirected = fixtures::temp_path("raw.out");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_codecrafters-sqlite"))
+        .args([db.to_str().unwrap(), "select t, b from raw"])
+        .stdout(std::fs::File::create(&redirected).unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        std::fs::read(&redirected).unwrap(),
+        b"a\0b|\0\xff\n\x1b[31mred|A\n"
+    );
+
+    let out = run_with_stdin(
+        &["--escape", db.to_str().unwrap(), "select t, b from raw"],
+        "",
+    );
+    assert_eq!(out.stdout, b"a\\x00b|\\x00\xff\n\\x1b[31mred|A\n");
+    std::fs::remove_file(&redirected).unwrap();
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn uri_filenames_open_read_only() {
+    let dir = fixtures::temp_path("uri dir");
+    std::fs::create_dir(&dir).unwrap();
+    let db = dir.join("my sample.db");
+    std::fs::copy("sample.db", &db).unwrap();
+    let encoded = db.to_str().unwrap().replace(' ', "%20");
+
+    for uri in [
+        format!("file:{encoded}"),
+        format!("file://{encoded}?immutable=1&mode=ro"),
+    ] {
+        let out = run_with_stdin(&[&uri, ".tables"], "");
+        assert!(out.status.success(), "{uri}");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "apples sqlite_sequence oranges\n"
+        );
+    }
+
+    let uri = format!("file:{encoded}?cache=shared");
+    let out = run_with_stdin(&[&uri, ".tables"], "");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown URI parameter: cache"));
+
+    // writes need --write, and a read-only URI wins over it
+    let delete = "delete from apples";
+    let out = run_with_stdin(
+        &["--write", &format!("file:{encoded}?immutable=1"), delete],
+        "",
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("readonly database"));
+    let out = run_with_stdin(&["--write", &format!("file:{encoded}"), delete], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not implemented"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn write_inserts_rows_sqlite3_can_read() {
+    let path = fixtures::write_temp("insert.db", &std::fs::read("sample.db").unwrap());
+    let db = path.to_str().unwrap();
+    let insert = "insert into apples (name, color) values ('Fuji', 'Red')";
+    let out = run_with_stdin(&[db, insert], "");
+    assert_eq!(out.status.code(), Some(8));
+    let out = run_with_stdin(&["--write", db, insert], "");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(out.stdout.is_empty());
+
+    let out = run_with_stdin(&[db, "select id, name from apples where color = 'Red'"], "");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "2|Fuji\n5|Fuji\n");
+    let out = fixtures::sqlite_rows(
+        &path,
+        "",
+        "pragma integrity_check; select name, color from apples where id = 5; \
+         select seq from sqlite_sequence where name = 'apples'",
+    );
+    assert_eq!(out, "ok\nFuji|Red\n5\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failed_insert_leaves_the_file_as_it_was() {
+    let sample = std::fs::read("sample.db").unwrap();
+    let path = fixtures::write_temp("failed-insert.db", &sample);
+    let db = path.to_str().unwrap();
+    // the second row fails on the rowid the first one took
+    let insert =
+        "insert into apples (id, name, color) values (7, 'Fuji', 'Red'), (7, 'Gala', 'Red')";
+    let out = run_with_stdin(&["--write", db, insert], "");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("UNIQUE constraint failed: apples.id"),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(std::fs::read(&path).unwrap() == sample);
+    let out = run_with_stdin(&[db, "select count(*) from apples where id = 7"], "");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "0\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn create_table_adds_a_table_sqlite3_can_use() {
+    let path = fixtures::write_temp("create.db", &std::fs::read("sample.db").unwrap());
+    let db = path.to_str().unwrap();
+    let create = "create table notes (id integer primary key, body text)";
+    let out = run_with_stdin(
+        &[
+            "--write",
+            db,
+            create,
+            "insert into notes (body) values ('first')",
+            ".tables",
+        ],
+        "",
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "apples sqlite_sequence oranges notes\n"
+    );
+    let out = run_with_stdin(&["--write", db, create], "");
+    assert_eq!(out.status.code(), Some(5));
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("table notes already exists"),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let out = fixtures::sqlite_rows(
+        &path,
+        "",
+        "select sql from sqlite_schema where name = 'notes';\
+         insert into notes (body) values ('second');\
+         select * from notes; pragma integrity_check;",
+    );
+    assert_eq!(
+        out,
+        "CREATE TABLE notes (id integer primary key, body text)\n1|first\n2|second\nok\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recover_prints_a_script_sqlite3_rebuilds_the_tables_from() {
+    let out = run_with_stdin(&["sample.db", ".recover"], "");
+    assert!(out.status.success());
+    let script = String::from_utf8(out.stdout).unwrap();
+    assert!(script.contains(
+        "INSERT INTO \"apples\"(rowid, \"name\", \"color\") VALUES(2, 'Fuji', 'Red');\n"
+    ));
+    let path = fixtures::temp_path("recovered.db");
+    let rebuilt = fixtures::sqlite_rows(
+        &path,
+        "",
+        &format!("{script} select * from oranges; select * from sqlite_sequence;"),
+    );
+    let expected = run_with_stdin(
+        &[
+            "sample.db",
+            "select * from oranges",
+            "select * from sqlite_sequence",
+        ],
+        "",
+    );
+    assert_eq!(rebuilt, String::from_utf8(expected.stdout).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn import_reads_quoted_multiline_csv_fields() {
+    let csv = fixtures::temp_path("notes.csv");
+    std::fs::write(
+        &csv,
+        "title,body\r\nplain,one line\r\nquoted,\"a, b\"\r\n\
+         multi,\"first\nsecond \"\"quoted\"\"\"\r\nempty,\r\n",
+    )
+    .unwrap();
+    let csv = csv.to_str().unwrap();
+    let path = fixtures::write_temp("import.db", &std::fs::read("sample.db").unwrap());
+    let db = path.to_str().unwrap();
+    let select = "select title, body, typeof(body) from notes";
+    let out = run_with_stdin(
+        &[
+            "--write",
+            db,
+            &format!(".import --csv {csv} notes"),
+            &format!(".import {csv} notes"),
+            "select count(*) from notes",
+        ],
+        "",
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    // the header names the new table's columns, and is a row once it exists
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "9\n");
+    let ours = run_with_stdin(&[db, select], "").stdout;
+    assert!(String::from_utf8_lossy(&ours).contains("multi|first\nsecond \"quoted\"|text\n"));
+    assert_eq!(
+        String::from_utf8(ours).unwrap(),
+        fixtures::sqlite_rows(&path, "", select)
+    );
+
+    std::fs::write(csv, "a,b\n\"x\ny\",1,2\n").unwrap();
+    let out = run_with_stdin(&["--write", db, &format!(".import {csv} notes")], "");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("line 2: expected 2 columns but found 3"),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let count = run_with_stdin(&[db, "select count(*) from notes"], "").stdout;
+    assert_eq!(count, b"9\n");
+    std::fs::remove_file(csv).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn database_can_be_piped_through_stdin() {
+    let image = std::fs::read("sample.db").unwrap();
+    let from_file = run_with_stdin(&["sample.db", ".tables", "select count(*) from apples"], "");
+    let piped = run_with_stdin(&["-", ".tables", "select count(*) from apples"], &image);
+    assert!(piped.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&piped.stdout),
+        "apples sqlite_sequence oranges\n4\n"
+    );
+    assert_eq!(piped.stdout, from_file.stdout);
+
+    let empty = run_with_stdin(&["-", ".tables"], "");
+    assert_eq!(empty.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&empty.stderr).contains("into memory"));
+    let garbage = run_with_stdin(&["-", ".tables"], "not a database at all");
+    assert_eq!(garbage.status.code(), Some(3));
+    let truncated = run_with_stdin(&["-", "select * from apples"], &image[..4096]);
+    assert_eq!(truncated.status.code(), Some(4));
+}
+
+#[test]
+fn schema_lists_tables_indexes_and_triggers() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table items (id integer primary key, name text, price real)")
+        .sql("create table log (msg)")
+        .sql("create index items_name on items (name)")
+        .sql(
+            "create trigger items_ins after insert on items when new.price > 0 \
+             begin insert into log values ('added'); end",
+        )
+        .sql("create trigger log_del before delete on log begin select 1; end")
+        .sql(
+            "create trigger items_upd update of price, name on items \
+             begin insert into log values ('changed'); end",
+        )
+        .sql("insert into items (name, price) values ('pen', 2)");
+    let image = builder.build();
+    let db = fixtures::write_temp("triggers.db", &image);
+    let db_str = db.to_str().unwrap();
+    for command in [".schema", ".schema items", ".schema LOG"] {
+        let expected = fixtures::sqlite3_output(&db, &[], command);
+        let out = run_with_stdin(&[db_str, command], "");
+        assert!(out.status.success(), "{command}");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&expected),
+            "{command}"
+        );
+    }
+
+    // triggers don't get in the way of queries
+    let out = run_with_stdin(&[db_str, ".tables", "select name from items"], "");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "items log\npen\n");
+
+    let out = run_with_stdin(&[db_str, "pragma trigger_info(items)"], "");
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "items_ins|AFTER INSERT\nitems_upd|BEFORE UPDATE OF price, name\n"
+    );
+    let out = run_with_stdin(&[db_str, "PRAGMA trigger_info('Log');"], "");
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "log_del|BEFORE DELETE\n"
+    );
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_mode_matches_serialized_rows() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t (i integer, r real, s text, n text, b blob)")
+        .sql("insert into t values (-7, 2.5, 'tab\there \"q\" caf\u{e9}', NULL, x'00ff41')");
+    let image = builder.build();
+    let path = fixtures::write_temp("serde.db", &image);
+    let path = path.to_str().unwrap();
+    let db = codecrafters_sqlite::Database::open(path).unwrap();
+    let row = |sql| db.query(sql).unwrap().next().unwrap().unwrap();
+
+    // json mode writes blobs as text like sqlite3, so they're left out here
+    let sql = "select i, r, s, n from t";
+    let out = run_with_stdin(&["-json", path, sql], "");
+    assert!(out.status.success());
+    let shown: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let serialized = serde_json::to_value([row(sql).as_map()]).unwrap();
+    assert_eq!(shown, serialized);
+
+    // blobs are base64 in json, and raw bytes in a binary format
+    let row = row("select * from t");
+    let json = serde_json::to_string(&row).unwrap();
+    assert_eq!(json, r#"[-7,2.5,"tab\there \"q\" café",null,"AP9B"]"#);
+    let back: codecrafters_sqlite::Row = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.values()[..4], row.values()[..4]);
+    let packed = rmp_serde::to_vec(&row).unwrap();
+    let back: codecrafters_sqlite::Row = rmp_serde::from_slice(&packed).unwrap();
+    assert_eq!(back.values(), row.values());
+    assert_eq!(back.column_name(4), "column5");
+    let (i, r, s, n, b): (i64, f64, String, Option<String>, Vec<u8>) =
+        rmp_serde::from_slice(&packed).unwrap();
+    assert_eq!((i, r, n), (-7, 2.5, None));
+    assert_eq!(s, row.get::<String>(2).unwrap());
+    assert_eq!(b, [0x00, 0xff, 0x41]);
+}
+
+#[test]
+fn pagestats_reports_leaves_and_depth() {
+    let image = fixtures::create_multipage_table(1000);
+    let path = fixtures::write_temp("pagestats.db", &image);
+    let db = path.to_str().unwrap();
+    let out = run_with_stdin(&[db, ".pagestats numbers"], "");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("depth: 2"));
+    assert_eq!(lines.next(), Some("leaf pages: 6"));
+    let leaves = lines.collect::<Vec<_>>();
+    assert_eq!(leaves.len(), 6);
+
+    // the same cells and free bytes as sqlite3's dbstat
+    let sql = "select 'page ' || pageno || ': ' || ncell || ' cells, ' || unused || ' bytes free' \
+               from dbstat where name = 'numbers' and pagetype = 'leaf' order by path";
+    let dbstat = fixtures::sqlite_rows(&path, "", sql);
+    assert_eq!(dbstat.lines().collect::<Vec<_>>(), leaves);
+
+    let out = run_with_stdin(&[db, ".pagestats nosuch"], "");
+    assert_eq!(out.status.code(), Some(6));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn trim_nul_strips_padding_from_text() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table legacy (code text)")
+        .sql("insert into legacy values (cast(x'41420000' as text)), ('C' || char(0) || 'D')");
+    let image = builder.build();
+    let db = fixtures::write_temp("legacy.db", &image);
+    let query = "select code, length(code) from legacy";
+    let out = run_with_stdin(&["--escape", db.to_str().unwrap(), query], "");
+    assert_eq!(out.stdout, b"AB\\x00\\x00|4\nC\\x00D|3\n");
+
+    let out = run_with_stdin(&["--trim-nul", db.to_str().unwrap(), query], "");
+    assert_eq!(out.stdout, b"AB|2\nC\0D|3\n");
+    let out = run_with_stdin(
+        &[
+            "--trim-nul",
+            db.to_str().unwrap(),
+            "select rowid from legacy where code = 'AB'",
+        ],
+        "",
+    );
+    assert_eq!(out.stdout, b"1\n");
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn dbinfo_matches_sqlite3() {
+    let builder = fixtures::FixtureBuilder::new()
+        .page_size(1024)
+        .sql("PRAGMA auto_vacuum = incremental")
+        .sql("PRAGMA user_version = 7")
+        .sql("create table t (x)")
+        .sql("create index t_x on t (x)")
+        .sql("create view v as select x from t")
+        .sql("create trigger tr after insert on t begin select 1; end")
+        .sql("insert into t values (zeroblob(3000))")
+        .sql("delete from t");
+    let image = builder.build();
+    let db = fixtures::write_temp("dbinfo.db", &image);
+    let db_str = db.to_str().unwrap();
+    let expected = fixtures::sqlite3_output(&db, &[], ".dbinfo");
+    // sqlite3 pads the values into a column and ends with the data version,
+    // which isn't in the file
+    let expected = String::from_utf8(expected).unwrap();
+    let expected: String = expected
+        .lines()
+        .filter(|l| !l.starts_with("data version"))
+        .map(|l| {
+            let (label, value) = l.split_once(':').unwrap();
+            format!("{label}: {}\n", value.trim())
+        })
+        .collect();
+
+    let out = run_with_stdin(&[db_str, ".dbinfo"], "");
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), expected);
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn reads_a_database_of_64k_pages() {
+    let image = fixtures::create_large_page_table();
+    // the header stores 65536 as 1
+    assert_eq!(&image[16..18], &[0, 1]);
+    let db = fixtures::write_temp("large_page.db", &image);
+    let db_str = db.to_str().unwrap();
+
+    let out = run_with_stdin(&[db_str, ".dbinfo"], "");
+    assert!(out.status.success());
+    let dbinfo = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        dbinfo.starts_with("database page size: 65536\n"),
+        "{dbinfo}"
+    );
+    let pages = image.len() / 65536;
+    assert!(dbinfo.contains(&format!("database page count: {pages}\n")));
+
+    let out = run_with_stdin(&[db_str, ".tables"], "");
+    assert_eq!(out.stdout, b"notes\n");
+
+    // rows far into the file, through the index, the rowid and a full scan
+    for sql in [
+        "select id, length(body) from notes where title = 'note 2900'",
+        "select title, length(body) from notes where id = 2999",
+        "select count(*), sum(length(body)) from notes where length(body) > 3990",
+        "select id, substr(body, 99990) from notes where id = 3000",
+    ] {
+        let expected = fixtures::sqlite_rows(&db, "", sql);
+        let out = run_with_stdin(&[db_str, sql], "");
+        assert!(out.status.success(), "{sql}");
+        assert_eq!(String::from_utf8_lossy(&out.stdout), expected, "{sql}");
+    }
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn show_rowid_prefixes_each_row() {
+    let out = run_with_stdin(
+        &[
+            "--show-rowid",
+            "sample.db",
+            "select name from apples where id > 2 order by name",
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    assert_eq!(out.stdout, b"4|Golden Delicious\n3|Honeycrisp\n");
+
+    // aggregates have no rowid to show, and csv mode uses its own separator
+    let out = run_with_stdin(
+        &[
+            "--show-rowid",
+            "--csv",
+            "sample.db",
+            "select count(*) from apples",
+        ],
+        "",
+    );
+    assert_eq!(out.stdout, b",4\n");
+
+    let out = run_with_stdin(&["sample.db", "select name from apples where id = 3"], "");
+    assert_eq!(out.stdout, b"Honeycrisp\n");
+}
+
+#[test]
+fn reals_print_like_sqlite3() {
+    let values = [
+        "0.0",
+        "-0.0",
+        "1.0",
+        "0.1",
+        "0.1 + 0.2",
+        "1e15",
+        "1e16",
+        "123456789012345.6",
+        "999999999999999.0",
+        "1e-4",
+        "1.5e-5",
+        "100.0 / 3",
+        "2.5e-300",
+        "4.9e-324",
+        "1.7976931348623157e308",
+        "12345678901234567890.0",
+        "93.20001220703125",
+        "1e308 * 10",
+        "-1e308 * 10",
+        "-0.000123456789012345678",
+        "1e19 / 3",
+        "-9223372036854775808.0",
+    ];
+    let rows = values.map(|v| format!("({v})")).join(", ");
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table reals (id integer primary key, v real)")
+        .sql(&format!("insert into reals (v) values {rows}"));
+    let image = builder.build();
+    let db = fixtures::write_temp("reals.db", &image);
+    let db_str = db.to_str().unwrap();
+    let sql = "select v, -v, v || '', v * 2 from reals";
+    for mode in [&[][..], &["-csv"][..], &["-cmd", ".mode quote"][..]] {
+        let expected = fixtures::sqlite3_output(&db, mode, sql);
+        let out = run_with_stdin(&[mode, &[db_str, sql]].concat(), "");
+        assert!(out.status.success());
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            String::from_utf8(expected).unwrap(),
+            "{mode:?}"
+        );
+    }
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn regexp_filters_rows_like_sqlite3() {
+    for sql in [
+        "select name from apples where name regexp '^[GH].*s'",
+        "select name from apples where name not regexp 'e.*e'",
+        "select name, color regexp 'Red$' from apples",
+    ] {
+        let expected = fixtures::sqlite3_output("sample.db".as_ref(), &[], sql);
+        let out = run_with_stdin(&["sample.db", sql], "");
+        assert!(out.status.success(), "{sql}");
+        assert_eq!(out.stdout, expected, "{sql}");
+    }
+
+    let out = run_with_stdin(
+        &["sample.db", "select name from apples where name regexp '('"],
+        "",
+    );
+    assert!(!out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "Error: argument 2 (select name from apples where name regexp '('): \
+         invalid regular expression \"(\": unclosed group\n"
+    );
+}
+
+#[test]
+fn check_reports_rowids_out_of_order() {
+    let image = fixtures::create_multipage_table(1000);
+    let path = fixtures::write_temp("check.db", &image);
+    let db = path.to_str().unwrap();
+    let out = run_with_stdin(&[db, ".check"], "");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "ok\n");
+
+    // the last rowid of the table's last leaf, 1000 as a two-byte varint,
+    // is made 1
+    let page_size = u16::from_be_bytes([image[16], image[17]]) as usize;
+    let mut corrupt = image;
+    let at = corrupt.len() - page_size;
+    let page = &corrupt[at..];
+    assert_eq!(page[0], 0x0d);
+    let pointers = 8 + 2 * (u16::from_be_bytes([page[3], page[4]]) as usize - 1);
+    let cell = at + u16::from_be_bytes([page[pointers], page[pointers + 1]]) as usize;
+    assert_eq!(corrupt[cell + 1..cell + 3], [0x87, 0x68]);
+    corrupt[cell + 1..cell + 3].copy_from_slice(&[0x80, 0x01]);
+    std::fs::write(&path, &corrupt).unwrap();
+    let out = run_with_stdin(&[db, ".check numbers"], "");
+    assert!(out.status.success());
+    let pageno = at / page_size + 1;
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!(
+            "numbers: rowid 1 in cell {} of page {pageno} is not above rowid 999\n",
+            (pointers - 8) / 2
+        )
+    );
+    std::fs::remove_file(&path).unwrap();
+}
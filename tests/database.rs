@@ -0,0 +1,2610 @@
+use codecrafters_sqlite::{
+    ColType, Database, DatabasePool, Mode, Row, RowFormatter, RowidViolation, Rows, SchemaKind,
+    SqliteError, TableValuedFunction, Value, array_param, compare_index_keys,
+};
+use std::rc::Rc;
+
+mod fixtures;
+
+// strings renders every value the way list mode would, NULL as empty.
+fn strings(rows: Rows) -> Vec<Vec<String>> {
+    rows.map(|row| {
+        row.unwrap()
+            .values()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    })
+    .collect()
+}
+
+fn collect(rows: Rows) -> Vec<Row> {
+    rows.collect::<Result<_, _>>().unwrap()
+}
+
+// list renders rows like `sqlite3 -nullvalue NULL` lists them, to compare
+// with the reference shell's output.
+fn list(rows: &[Vec<Value>]) -> String {
+    rows.iter()
+        .map(|row| {
+            let values = row.iter().map(|v| match v {
+                Value::Null => "NULL".to_string(),
+                v => v.to_string(),
+            });
+            values.collect::<Vec<_>>().join("|") + "\n"
+        })
+        .collect()
+}
+
+// matches_sqlite checks that every query gives the rows sqlite3 gives.
+fn matches_sqlite(db: &std::path::Path, queries: &[&str]) {
+    let ours = Database::open(db.to_str().unwrap()).unwrap();
+    for sql in queries {
+        let expected = fixtures::sqlite_rows(db, "NULL", sql);
+        let rows = ours.query_to_vec(sql).unwrap();
+        assert_eq!(list(&rows), expected, "{sql}");
+    }
+}
+
+// assert_query_matches_csv checks that `sql` gives the rows of
+// `expected_csv`, as `sqlite3 -csv -header` prints them: a header line of the
+// column names, then a line per row. Rows are written as they're read, and
+// the first line that differs is named.
+fn assert_query_matches_csv(db: &Database, sql: &str, expected_csv: &str) {
+    let rows = db.query(sql).unwrap_or_else(|e| panic!("{sql}: {e}"));
+    let columns = rows.columns().to_vec();
+    let header = columns.iter().map(|c| text(c)).collect::<Vec<_>>();
+    let mut formatter = RowFormatter::new(Mode::Csv, "", &columns);
+    let mut out = Vec::new();
+    formatter.write_row(&mut out, &header).unwrap();
+    for row in rows {
+        let row = row.unwrap_or_else(|e| panic!("{sql}: {e}"));
+        formatter.write_row(&mut out, row.values()).unwrap();
+    }
+    formatter.finish(&mut out).unwrap();
+    let ours = String::from_utf8(out).unwrap();
+    let mut expected = expected_csv.lines();
+    for (i, line) in ours.lines().enumerate() {
+        assert_eq!(Some(line), expected.next(), "{sql}: line {}", i + 1);
+    }
+    assert_eq!(expected.next(), None, "{sql}: rows missing");
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+// parse_error tells the error of `sql`, which must fail as SQL sqlite rejects.
+fn parse_error(db: &Database, sql: &str) -> String {
+    let err = db.query_to_vec(sql).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::SqlParse(_))),
+        "{sql}: {err}"
+    );
+    err.to_string()
+}
+
+#[test]
+fn generated_fixtures_are_readable() {
+    let simple = fixtures::write_temp("simple.db", &fixtures::create_simple_table());
+    let db = Database::open(simple.to_str().unwrap()).unwrap();
+    assert_eq!(db.table_names(), ["fruits"]);
+    let result = db
+        .query("select name from fruits where color = 'red'")
+        .unwrap();
+    assert_eq!(strings(result), [["apple"], ["cherry"]]);
+    std::fs::remove_file(&simple).unwrap();
+
+    let multipage = fixtures::write_temp("multipage.db", &fixtures::create_multipage_table(1000));
+    let db = Database::open(multipage.to_str().unwrap()).unwrap();
+    let result = db
+        .query("select id, name from numbers where parity = 'even'")
+        .unwrap();
+    assert_eq!(result.columns(), ["id", "name"]);
+    let rows = collect(result);
+    assert_eq!(rows.len(), 500);
+    assert_eq!(rows[0].get::<i64>(0).unwrap(), 2);
+    assert_eq!(rows[499].column_name(1), "name");
+    assert_eq!(rows[499].value(1).to_string(), "number 1000");
+    std::fs::remove_file(&multipage).unwrap();
+
+    let indexed = fixtures::write_temp("indexed.db", &fixtures::create_indexed_table());
+    let db = Database::open(indexed.to_str().unwrap()).unwrap();
+    assert_eq!(db.table_names(), ["companies"]);
+    std::fs::remove_file(&indexed).unwrap();
+}
+
+#[test]
+fn empty_tables_have_no_rows() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table empty (id integer primary key, name text, color text)")
+        .sql("create index idx_empty_color on empty (color)")
+        .sql("create table other (x text)")
+        .sql("insert into other values ('a')");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    assert_eq!(db.table_names(), ["empty", "other"]);
+    let indexes = &db.table("empty").unwrap().indexes;
+    assert_eq!(indexes.len(), 1);
+    assert_eq!(indexes[0].name, "idx_empty_color");
+    let count = db.query("select count(*) from empty").unwrap();
+    assert!(matches!(&collect(count)[..], [row] if matches!(row.values(), [ColType::Integer(0)])));
+
+    for sql in [
+        "select name from empty",
+        "select name from empty where id = 3",
+        "select name from empty where color = 'red'",
+        "select upper(name) from empty where length(name) > 1",
+    ] {
+        let result = db.query(sql).unwrap();
+        assert_eq!(result.columns().len(), 1, "{sql}");
+        assert_eq!(result.count(), 0, "{sql}");
+    }
+}
+
+#[test]
+fn explain_yields_the_plan_without_reading_rows() {
+    let db = Database::open("sample.db").unwrap();
+    let stmt = db
+        .prepare("explain query plan select name from apples where id = 2")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SEARCH apples USING INTEGER PRIMARY KEY (rowid=?)\n"
+    );
+    let result = db.execute(&stmt).unwrap();
+    assert_eq!(result.columns(), ["QUERY PLAN"]);
+    assert_eq!(
+        strings(result),
+        [["`--SEARCH apples USING INTEGER PRIMARY KEY (rowid=?)"]]
+    );
+    assert_eq!(db.stats().pages_read, 0);
+}
+
+#[test]
+fn writes_are_rejected() {
+    let mut db = Database::open("file:sample.db?mode=ro").unwrap();
+    let err = db.query("delete from apples").unwrap_err();
+    assert!(err.to_string().contains("readonly database"), "{err}");
+    db.set_writable(true);
+    let err = db.query("delete from apples").unwrap_err();
+    assert!(err.to_string().contains("readonly database"), "{err}");
+
+    let mut db = Database::open("sample.db").unwrap();
+    db.set_writable(true);
+    let err = db.query("delete from apples").unwrap_err();
+    assert!(err.to_string().contains("not implemented"), "{err}");
+}
+
+#[test]
+fn returning_gives_the_rows_written() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, name text not null, n integer, r real)")
+        .sql("insert into t (name, n, r) values ('a', 1, 0.5)")
+        .build();
+    let ours = fixtures::write_temp("returning.db", &image);
+    let theirs = fixtures::write_temp("returning-ref.db", &image);
+    let mut db = Database::open(ours.to_str().unwrap()).unwrap();
+    db.set_writable(true);
+    for sql in [
+        "insert into t (name, n, r) values ('b', '7', 2), ('x returning y', NULL, '1.5') \
+         returning id, name, n * 2 as doubled, r, rowid",
+        "insert into t values (10, 'c', 3, 4) returning *",
+        "update t set n = n + 1, r = 8 where n is not null returning name, n, id",
+        "update t set name = 'none' where id = 99 returning id",
+    ] {
+        let rows = db
+            .query_to_vec(sql)
+            .unwrap_or_else(|e| panic!("{sql}: {e}"));
+        assert_eq!(
+            list(&rows),
+            fixtures::sqlite_rows(&theirs, "NULL", sql),
+            "{sql}"
+        );
+    }
+    let result = db
+        .query("update t set r = 1 where id = 1 returning r as ratio, upper(name)")
+        .unwrap();
+    assert_eq!(result.columns(), ["ratio", "upper(name)"]);
+    // a bad RETURNING clause writes nothing
+    let err = db
+        .query("insert into t (name) values ('d') returning nosuch")
+        .map(drop)
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NoSuchColumn(_))),
+        "{err}"
+    );
+    assert_eq!(
+        strings(db.query("select count(*) from t").unwrap()),
+        [["4"]]
+    );
+    std::fs::remove_file(&ours).unwrap();
+    std::fs::remove_file(&theirs).unwrap();
+}
+
+#[test]
+fn upserts_skip_or_update_the_conflicting_row() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, name text not null, n integer)")
+        .sql("insert into t values (1, 'a', 1), (2, 'b', 2)")
+        .build();
+    let ours = fixtures::write_temp("upsert.db", &image);
+    let theirs = fixtures::write_temp("upsert-ref.db", &image);
+    let mut db = Database::open(ours.to_str().unwrap()).unwrap();
+    db.set_writable(true);
+    for sql in [
+        "insert into t values (1, 'x', 10), (3, 'c', 3) on conflict do nothing returning *",
+        "insert into t values (2, 'on conflict do nothing', 20) on conflict (id) \
+         do update set name = excluded.name, n = t.n + excluded.n returning id, name, n",
+        "insert into t values (1, 'y', 5), (2, 'z', 6) on conflict (ID) \
+         do update set n = excluded.n where excluded.n > n returning id, n",
+        "insert into t (name, n) values ('d', 4) on conflict do update set n = 0 returning id",
+        "select * from t",
+    ] {
+        let rows = db
+            .query_to_vec(sql)
+            .unwrap_or_else(|e| panic!("{sql}: {e}"));
+        assert_eq!(
+            list(&rows),
+            fixtures::sqlite_rows(&theirs, "NULL", sql),
+            "{sql}"
+        );
+    }
+    // a NOT NULL violation in DO UPDATE writes nothing
+    let err = db
+        .query(
+            "insert into t values (5, 'e', 5), (1, 'a', 1) on conflict do update set name = null",
+        )
+        .map(drop)
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Constraint(_))),
+        "{err}"
+    );
+    for sql in [
+        "insert into t values (1, 'a', 1) on conflict (name) do nothing",
+        "insert into t values (1, 'a', 1) on conflict do update set nosuch = 1",
+        "insert into t values (1, 'a', 1) on conflict do update set n = other.n",
+    ] {
+        let err = db.query(sql).map(drop).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref(),
+                Some(SqliteError::SqlParse(_) | SqliteError::NoSuchColumn(_))
+            ),
+            "{sql}: {err}"
+        );
+    }
+    assert_eq!(
+        strings(db.query("select count(*) from t").unwrap()),
+        [["4"]]
+    );
+    std::fs::remove_file(&ours).unwrap();
+    std::fs::remove_file(&theirs).unwrap();
+}
+
+#[test]
+fn inserts_fill_leaves_with_room() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key autoincrement, name text not null, n int)")
+        .sql("insert into t (name, n) values ('a', 1)")
+        .build();
+    let path = fixtures::write_temp("insert.db", &image);
+    let mut db = Database::open(path.to_str().unwrap()).unwrap();
+    db.set_writable(true);
+    // nothing is written while a value is missing
+    let err = db
+        .query("insert into t (name, n) values ('b', '2'), (?1, ?2)")
+        .map(drop)
+        .unwrap_err();
+    assert!(err.to_string().contains("2 parameters"), "{err}");
+    let stmt = db
+        .prepare("insert into t (name, n) values ('b', '2'), (?1, ?2)")
+        .unwrap();
+    stmt.query(&[text("c"), Value::Float(3.0)]).unwrap();
+    db.query("insert into t values (10, 'd', NULL)").unwrap();
+    assert_eq!(
+        strings(db.query("select id, name, typeof(n) from t").unwrap()),
+        [
+            ["1", "a", "integer"],
+            ["2", "b", "integer"],
+            ["3", "c", "integer"],
+            ["10", "d", "null"],
+        ]
+    );
+
+    let constraint = |sql: &str| {
+        let err = db.query(sql).map(drop).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::Constraint(_))),
+            "{err}"
+        );
+        err.to_string()
+    };
+    assert_eq!(
+        constraint("insert into t (n) values (1)"),
+        "NOT NULL constraint failed: t.name"
+    );
+    assert_eq!(
+        constraint("insert into t (id, name) values (2, 'again')"),
+        "UNIQUE constraint failed: t.id"
+    );
+    assert_eq!(
+        constraint("insert into t (id, name) values ('two', 'x')"),
+        "datatype mismatch"
+    );
+
+    // the 512 byte leaf runs out of room well before 100 rows
+    let err = (0..100)
+        .try_for_each(|_| {
+            db.query("insert into t (name) values ('filler row')")
+                .map(drop)
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("is full"), "{err}");
+    let out = fixtures::sqlite_rows(
+        &path,
+        "",
+        "pragma integrity_check; select max(id) = seq from t, sqlite_sequence",
+    );
+    assert_eq!(out, "ok\n1\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn updates_rewrite_cells_in_place() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, name text not null, n int, r real)")
+        .sql("insert into t values (1, 'apple', 1, 0.5), (2, 'banana', 2, 1.5), (3, 'cherry', 3, NULL)")
+        .build();
+    let path = fixtures::write_temp("update.db", &image);
+    let mut db = Database::open(path.to_str().unwrap()).unwrap();
+    let err = db.query("update t set n = 0").map(drop).unwrap_err();
+    assert!(err.to_string().contains("readonly"), "{err}");
+    db.set_writable(true);
+    let free = || db.page_stats("t").unwrap().leaves[0].free_bytes;
+    let before = free();
+    // 'banana' to 'fig' frees 3 bytes of its cell, a fragment
+    db.query("update t set name = 'fig' where id = 2").unwrap();
+    assert_eq!(free(), before + 3);
+    // the values come from the row as it was, and a longer name moves its cell
+    let stmt = db
+        .prepare("update t set n = n * 10, r = ?1 where name <> ?2")
+        .unwrap();
+    stmt.query(&[Value::Integer(7), text("fig")]).unwrap();
+    db.query("update t set name = 'elderberry' where r = 7")
+        .unwrap();
+    assert_eq!(
+        strings(db.query("select id, name, n, r from t").unwrap()),
+        [
+            ["1", "elderberry", "10", "7.0"],
+            ["2", "fig", "2", "1.5"],
+            ["3", "elderberry", "30", "7.0"],
+        ]
+    );
+    let err = db
+        .query("update t set name = NULL where id = 3")
+        .map(drop)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "NOT NULL constraint failed: t.name");
+    let err = db
+        .query("update t set id = 4 where id = 3")
+        .map(drop)
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+        "{err}"
+    );
+    // a WHERE inside the new value is part of it
+    db.query("update t set name = 'a where b' where id = 2")
+        .unwrap();
+    let out = fixtures::sqlite_rows(
+        &path,
+        "",
+        "pragma integrity_check; select group_concat(name) from t",
+    );
+    assert_eq!(out, "ok\nelderberry,a where b,elderberry\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sqlite_schema_is_queryable() {
+    let db = Database::open("sample.db").unwrap();
+    let result = db
+        .query("select type, name, tbl_name, rootpage from sqlite_schema")
+        .unwrap();
+    assert_eq!(
+        strings(result),
+        [
+            ["table", "apples", "apples", "2"],
+            ["table", "sqlite_sequence", "sqlite_sequence", "3"],
+            ["table", "oranges", "oranges", "4"],
+        ]
+    );
+    let result = db
+        .query("select sql from sqlite_master where name = 'oranges'")
+        .unwrap();
+    assert!(
+        matches!(collect(result)[0].value(0), ColType::Text(sql) if sql.starts_with("CREATE TABLE oranges"))
+    );
+    assert!(!db.table_names().contains(&"sqlite_schema"));
+
+    // with small pages, a hundred tables push the schema past page 1: its
+    // root becomes an interior page right after the file header
+    let mut builder = fixtures::FixtureBuilder::new().page_size(512);
+    for i in 0..100 {
+        builder = builder.sql(&format!(
+            "create table t{i} (id integer primary key, v text)"
+        ));
+    }
+    let image = builder.build();
+    let path = fixtures::write_temp("big_schema.db", &image);
+    let sql = "select name, rootpage from sqlite_schema where type = 'table'";
+    let expected = fixtures::sqlite_rows(&path, "", sql);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image[100], 0x05);
+
+    let db = Database::from_bytes(image).unwrap();
+    assert_eq!(db.table_names().len(), 100);
+    assert_eq!(db.table_names()[99], "t99");
+    let result = db.query(sql).unwrap();
+    let rows = strings(result)
+        .iter()
+        .map(|row| format!("{}\n", row.join("|")))
+        .collect::<String>();
+    assert_eq!(rows, expected);
+}
+
+#[test]
+fn order_by_sorts_on_every_key() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table staff (name text, dept text, age integer)")
+        .sql(
+            "insert into staff values ('ann', 'ops', 31), ('bob', 'dev', 25), \
+             ('cy', 'ops', 45), ('dee', 'dev', 38), ('eve', 'dev', 25), ('fay', NULL, 50)",
+        );
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let names = |sql: &str| {
+        strings(db.query(sql).unwrap())
+            .into_iter()
+            .map(|row| row.join("|"))
+            .collect::<Vec<_>>()
+    };
+    // ties on dept fall through to age, ties on both keep their stored order
+    assert_eq!(
+        names("select name from staff order by dept ASC, age desc"),
+        ["fay", "dee", "bob", "eve", "cy", "ann"]
+    );
+    assert_eq!(
+        names("select name, age from staff where age < 40 order by 2, name desc"),
+        ["eve|25", "bob|25", "ann|31", "dee|38"]
+    );
+    assert_eq!(
+        names("select name from staff order by length(name) desc, dept desc, name"),
+        ["ann", "bob", "dee", "eve", "fay", "cy"]
+    );
+    assert_eq!(
+        names(
+            "select name from staff except select name from staff where dept = 'dev' order by name desc"
+        ),
+        ["fay", "cy", "ann"]
+    );
+    let err = db.query("select name from staff order by 3").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "1st ORDER BY term out of range - should be between 1 and 1"
+    );
+}
+
+#[test]
+fn rows_know_the_rowid_they_were_selected_from() {
+    let indexed = fixtures::write_temp("rowids.db", &fixtures::create_indexed_table());
+    let db = Database::open(indexed.to_str().unwrap()).unwrap();
+    let rowids = |sql: &str| {
+        collect(db.query(sql).unwrap())
+            .iter()
+            .map(Row::rowid)
+            .collect::<Vec<_>>()
+    };
+    // a scan, an index lookup, a sort, and rows that are no one row
+    assert_eq!(
+        rowids("select name from companies where id < 3"),
+        [Some(1), Some(2)]
+    );
+    assert_eq!(
+        rowids("select name from companies where country = 'chad' and id < 16"),
+        [Some(5), Some(10), Some(15)]
+    );
+    assert_eq!(
+        rowids("select name from companies where id > 1997 order by country desc"),
+        [Some(1998), Some(1999), Some(2000)]
+    );
+    assert_eq!(
+        rowids("select count(*), country from companies group by country"),
+        [None; 5]
+    );
+    assert_eq!(
+        rowids(
+            "select id from companies where id < 3 intersect select id from companies where id > 0"
+        ),
+        [None, None]
+    );
+    std::fs::remove_file(&indexed).unwrap();
+}
+
+#[test]
+fn rows_are_read_as_they_are_iterated() {
+    let image = fixtures::create_multipage_table(1000);
+    let db = Database::from_bytes(image).unwrap();
+    let sql = "select id, name from numbers";
+    let all = db.query(sql).unwrap().count();
+    assert_eq!(all, 1000);
+    let full = db.stats();
+
+    let mut rows = db.query(sql).unwrap();
+    let first = rows
+        .by_ref()
+        .take(3)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(first[2].column_count(), 2);
+    assert_eq!(first[2].get::<i64>(0).unwrap(), 3);
+    let partial = db.stats();
+    drop(rows);
+    // the root and the first leaf, against every page of the table
+    assert!(partial.pages_read <= 3, "{partial}");
+    assert!(full.pages_read > partial.pages_read + 2, "{full}");
+    assert_eq!(partial.rows_matched, 3);
+    assert_eq!(db.stats(), partial);
+
+    // sorting has to see every row before the first one
+    let mut rows = db.query("select id from numbers order by id desc").unwrap();
+    assert_eq!(rows.next().unwrap().unwrap().get::<i64>(0).unwrap(), 1000);
+    assert_eq!(db.stats().pages_read, full.pages_read);
+}
+
+#[test]
+fn typeof_names_each_storage_class() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table things (id integer primary key, a, r real, t text)")
+        .sql(
+            "insert into things (a, r, t) values (1, 2, 3), ('x', 2.5, 'y'), \
+             (1.5, NULL, NULL), (x'00ff', 7, x'01')",
+        );
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query("select typeof(id), typeof(a), typeof(r), typeof(t) from things")
+        .unwrap();
+    assert_eq!(
+        strings(rows),
+        [
+            ["integer", "integer", "real", "text"],
+            ["integer", "text", "real", "text"],
+            ["integer", "real", "null", "null"],
+            ["integer", "blob", "real", "blob"],
+        ]
+    );
+    let rows = db
+        .query("select id from things where typeof(a) = 'text'")
+        .unwrap();
+    assert_eq!(strings(rows), [["2"]]);
+}
+
+#[test]
+fn rows_convert_to_rust_types() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table items (id integer primary key, name text, price real, tag, flag)")
+        .sql("insert into items values (1, 'pen', 2, NULL, 1), (2, 'ink', 3.5, x'01', 0)");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query("select id, name AS Label, price, tag, flag as in_stock from items")
+        .unwrap();
+    let rows = collect(rows);
+    assert_eq!(rows[0].get::<i64>(0).unwrap(), 1);
+    assert_eq!(rows[0].get_named::<String>("label").unwrap(), "pen");
+    // price has REAL affinity, so 2 reads back as 2.0
+    assert_eq!(rows[0].get_named::<f64>("PRICE").unwrap(), 2.0);
+    assert_eq!(rows[0].get_named::<Option<Vec<u8>>>("tag").unwrap(), None);
+    assert_eq!(rows[1].get::<Option<Vec<u8>>>(3).unwrap(), Some(vec![1]));
+    assert!(rows[0].get_named::<bool>("in_stock").unwrap());
+    assert!(!rows[1].get_named::<bool>("In_Stock").unwrap());
+
+    let err = rows[0].get_named::<i64>("label").unwrap_err();
+    assert!(matches!(
+        err,
+        SqliteError::WrongType {
+            expected: "integer",
+            actual: "text",
+            ..
+        }
+    ));
+    assert_eq!(err.to_string(), "column Label is text, not integer");
+    assert!(matches!(
+        rows[0].get::<Vec<u8>>(3),
+        Err(SqliteError::WrongType { actual: "null", .. })
+    ));
+    assert!(matches!(
+        rows[0].get_named::<i64>("name"),
+        Err(SqliteError::NoSuchColumn(name)) if name == "name"
+    ));
+
+    // ORDER BY can name an alias
+    let rows = db.query("select name as n from items order by n").unwrap();
+    assert_eq!(strings(rows), [["ink"], ["pen"]]);
+}
+
+#[test]
+fn index_lookups_find_keys_interior_cells_hold() {
+    // a thousand copies of each key: their entries fill many leaves, and the
+    // interior cells between those leaves hold some of them
+    let builder = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table c (id integer primary key, country text)")
+        .sql("create index c_country on c (country)")
+        .sql(
+            "with recursive x(i) as (select 1 union all select i + 1 from x where i < 3000) \
+             insert into c (country) \
+             select case i % 3 when 0 then 'chad' when 1 then 'fiji' else 'peru' end from x",
+        );
+    let image = builder.build();
+    let db = fixtures::write_temp("interior_keys.db", &image);
+    matches_sqlite(
+        &db,
+        &[
+            "select count(*), sum(id) from c where country = 'chad'",
+            "select count(*), sum(id) from c where country = 'fiji'",
+            "select count(*), sum(id) from c where country = 'peru'",
+            "select count(*) from c where country = 'oman'",
+        ],
+    );
+    std::fs::remove_file(&db).unwrap();
+}
+
+#[test]
+fn payloads_spill_to_overflow_pages() {
+    let builder = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table docs (id integer primary key, body text)")
+        .sql("create index docs_body on docs (body)")
+        .sql(
+            "insert into docs values (1, 'short'), \
+             (2, substr(hex(zeroblob(3000)), 1, 5000)), (3, 'tail')",
+        );
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db.query("select id, length(body) from docs").unwrap();
+    assert_eq!(strings(rows), [["1", "5"], ["2", "5000"], ["3", "4"]]);
+    // the index keeps the long key on overflow pages too
+    let sql = format!("select id from docs where body = '{}'", "0".repeat(5000));
+    assert!(db.prepare(&sql).unwrap().query_plan().is_none());
+    assert_eq!(strings(db.query(&sql).unwrap()), [["2"]]);
+}
+
+#[test]
+fn payloads_split_where_the_file_format_says() {
+    // with 512-byte pages a table cell keeps payloads up to X = 477 bytes
+    // whole, and of a longer one M = 39 bytes, or K = M + (P - M) % 508 when
+    // that fits in X. An index cell's X is 102. A record of one text of n
+    // bytes (n >= 57) has a 3-byte header, so its payload is n + 3.
+    let lengths = [474, 475, 476, 553, 554, 597, 1055, 1056, 99, 100, 5000];
+    let values = lengths
+        .iter()
+        .map(|n| format!("(printf('%.*c', {}, 'a') || 'z')", n - 1))
+        .collect::<Vec<_>>();
+    let builder = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (body text)")
+        .sql("create index t_body on t (body)")
+        .sql(&format!("insert into t values {}", values.join(", ")));
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query("select rowid, length(body), substr(body, -1) from t")
+        .unwrap();
+    let want = lengths
+        .iter()
+        .enumerate()
+        .map(|(i, n)| vec![(i + 1).to_string(), n.to_string(), "z".to_string()])
+        .collect::<Vec<_>>();
+    assert_eq!(strings(rows), want);
+    // every key is found through the index, however it was split
+    for (i, n) in lengths.iter().enumerate() {
+        let sql = format!("select rowid from t where body = '{}z'", "a".repeat(n - 1));
+        assert!(db.prepare(&sql).unwrap().query_plan().is_none());
+        assert_eq!(strings(db.query(&sql).unwrap()), [[(i + 1).to_string()]]);
+    }
+}
+
+#[test]
+fn payload_size_must_match_the_record() {
+    let image = fixtures::create_simple_table();
+    let page_size = u16::from_be_bytes([image[16], image[17]]) as usize;
+    // cells fill the fruits root page (page 2) from its end, so the last one
+    // is followed by another: a larger size makes them overlap, which reading
+    // the page already catches, and a smaller one leaves the record longer
+    let pointer = page_size + 8 + 2 * 2;
+    let cell = page_size + u16::from_be_bytes([image[pointer], image[pointer + 1]]) as usize;
+    for (delta, message) in [
+        (1, "cells 2 and 1 of page 2 overlap"),
+        (-1, "record on page 2"),
+    ] {
+        let mut corrupt = image.clone();
+        corrupt[cell] = corrupt[cell].wrapping_add_signed(delta);
+        let db = Database::from_bytes(corrupt).unwrap();
+        let err = db
+            .query("select name from fruits")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<SqliteError>(),
+                Some(SqliteError::CorruptDatabase(msg)) if msg.starts_with(message)
+            ),
+            "{err}"
+        );
+    }
+}
+
+// random_literal makes a pseudo-random SQL value of any storage class, with
+// few distinct values so there are ties, integral reals and numeric text.
+fn random_literal(seed: &mut u64) -> String {
+    let mut next = |n: u64| {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*seed >> 33) % n
+    };
+    let n = next(21) as i64 - 10;
+    match next(6) {
+        0 => "NULL".to_string(),
+        1 => n.to_string(),
+        2 => format!("{}.{}", n, [0, 25, 5][next(3) as usize]),
+        3 => format!("'{n}'"),
+        4 => {
+            let letters = (0..next(3)).map(|_| ['a', 'B', 'b', ' '][next(4) as usize]);
+            format!("'{}'", letters.collect::<String>())
+        }
+        _ => format!("x'{:02x}'", n + 10),
+    }
+}
+
+#[test]
+fn values_sort_and_match_like_sqlite() {
+    let mut seed = 7;
+    let values = (0..300)
+        .map(|_| format!("({})", random_literal(&mut seed)))
+        .collect::<Vec<_>>();
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t (v)")
+        .sql(&format!("insert into t values {}", values.join(", ")));
+    let image = builder.build();
+    let path = fixtures::write_temp("values.db", &image);
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let queries = [
+        "select v from t order by v",
+        "select v, rowid from t order by v desc, rowid",
+        "select v from t except select v from t where rowid % 3 = 0 order by 1",
+        "select v from t intersect select v from t where rowid % 2 = 0 order by 1 desc",
+        "select min(v), max(v) from t",
+    ];
+    for sql in queries {
+        let expected = fixtures::sqlite3_output(&path, &["-quote"], sql);
+        let rows = db.query(sql).unwrap();
+        let columns = rows.columns().to_vec();
+        let mut out = Vec::new();
+        let mut formatter = RowFormatter::new(Mode::Quote, "", &columns);
+        for row in rows {
+            formatter
+                .write_row(&mut out, row.unwrap().values())
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(expected).unwrap(),
+            "{sql}"
+        );
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn databases_open_at_an_offset_into_a_larger_file() {
+    let sample = std::fs::read("sample.db").unwrap();
+    let mut container = b"container header\n".repeat(41); // 697 bytes, not page aligned
+    let offset = container.len() as u64;
+    container.extend(&sample);
+    container.extend(b"trailing data");
+    let path = fixtures::write_temp("embedded.bin", &container);
+    let path = path.to_str().unwrap();
+
+    let sql = "select id, name, color from apples order by name";
+    let want = strings(Database::open("sample.db").unwrap().query(sql).unwrap());
+    #[allow(unused_mut)]
+    let mut db = Database::open_at_offset(path, offset).unwrap();
+    assert_eq!(db.table_names(), ["apples", "sqlite_sequence", "oranges"]);
+    assert_eq!(strings(db.query(sql).unwrap()), want);
+    #[cfg(feature = "mmap")]
+    {
+        db.map_file().unwrap();
+        assert_eq!(strings(db.query(sql).unwrap()), want);
+    }
+    db.set_writable(true);
+    let err = db.query("delete from apples").unwrap_err();
+    assert!(err.to_string().contains("readonly database"), "{err}");
+
+    // the magic string has to be right where the offset says
+    for offset in [0, offset - 1, offset + 1, container.len() as u64] {
+        let err = Database::open_at_offset(path, offset).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::NotADatabase)),
+            "{offset}: {err}"
+        );
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn schema_format_is_read_and_checked() {
+    let mut image = std::fs::read("sample.db").unwrap();
+    assert_eq!(Database::open("sample.db").unwrap().info().schema_format, 4);
+    for (format, read) in [(0, 1), (1, 1), (3, 3)] {
+        image[44..48].copy_from_slice(&u32::to_be_bytes(format));
+        let db = Database::from_bytes(image.clone()).unwrap();
+        assert_eq!(db.info().schema_format, read);
+        assert_eq!(
+            strings(db.query("select count(*) from apples").unwrap()),
+            [["4"]]
+        );
+    }
+    image[44..48].copy_from_slice(&u32::to_be_bytes(5));
+    let err = Database::from_bytes(image).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+        "{err}"
+    );
+    assert!(err.to_string().contains("schema format 5"), "{err}");
+}
+
+#[test]
+fn descending_indexes_answer_lookups() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, x integer)")
+        .sql("create index t_x on t (x desc)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 2000) \
+             insert into t (x) select i * 7 % 1999 from n",
+        );
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    assert_eq!(db.info().schema_format, 4);
+    let rows = db.query("select id from t where x = 500").unwrap();
+    assert_eq!(strings(rows), [["357"]]);
+}
+
+#[test]
+fn pooled_databases_serve_concurrent_readers() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = DatabasePool::new("sample.db", 2).unwrap();
+    assert_eq!(pool.idle(), 2);
+    let (in_use, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    std::thread::scope(|s| {
+        for i in 0..8 {
+            let (pool, in_use, most) = (&pool, &in_use, &most);
+            s.spawn(move || {
+                let db = pool.acquire();
+                most.fetch_max(in_use.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                let id = i % 4 + 1;
+                let rows = db
+                    .query(&format!("select id from apples where id = {id}"))
+                    .unwrap();
+                assert_eq!(strings(rows), [[id.to_string()]]);
+                in_use.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    assert!(most.load(Ordering::SeqCst) <= 2);
+    assert_eq!(pool.idle(), 2);
+
+    let mut db = pool.acquire();
+    db.set_writable(true);
+    assert_eq!(pool.idle(), 1);
+    drop(db);
+    assert_eq!(pool.idle(), 2);
+
+    let err = DatabasePool::new("sample.db", 0).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(SqliteError::Usage(_))));
+    assert!(DatabasePool::new("no/such.db", 2).is_err());
+}
+
+#[test]
+fn schema_is_introspectable() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql(
+            "create table orders (customer text not null, day integer, \
+             status text default 'open', qty integer default (1 + 1), \
+             primary key (customer, day))",
+        )
+        .sql("create unique index orders_status on orders (status, qty)")
+        .sql("create table tags (name text primary key, n) without rowid")
+        .sql("create view open_orders as select * from orders where status = 'open'")
+        .sql("insert into orders (customer, day) values ('ann', 1)");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    assert_eq!(db.table_names(), ["orders", "tags"]);
+
+    let orders = db.table("ORDERS").unwrap();
+    assert_eq!(orders.name, "orders");
+    assert!(!orders.without_rowid);
+    let columns = orders
+        .columns
+        .iter()
+        .map(|c| {
+            (
+                c.name.as_str(),
+                c.ty.as_deref(),
+                c.primary_key,
+                c.not_null,
+                c.default.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        [
+            ("customer", Some("text"), true, true, None),
+            ("day", Some("integer"), true, false, None),
+            ("status", Some("text"), false, false, Some("'open'")),
+            ("qty", Some("integer"), false, false, Some("1 + 1")),
+        ]
+    );
+    // the composite primary key gets an automatic index, then comes ours
+    let indexes = orders
+        .indexes
+        .iter()
+        .map(|i| {
+            (
+                i.name.as_str(),
+                i.columns.clone(),
+                i.unique,
+                i.sql.is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        indexes,
+        [
+            ("sqlite_autoindex_orders_1", vec![], true, false),
+            (
+                "orders_status",
+                vec!["status".to_string(), "qty".to_string()],
+                true,
+                true
+            ),
+        ]
+    );
+    // the primary key isn't a rowid alias, so rows are read by rowid as usual
+    let rows = db
+        .query("select customer, day, status, qty from orders")
+        .unwrap();
+    assert_eq!(strings(rows), [["ann", "1", "open", "2"]]);
+
+    let tags = db.table("tags").unwrap();
+    assert!(tags.without_rowid);
+    assert!(tags.columns[0].primary_key);
+    let err = db.query("select * from tags").unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+        "{err}"
+    );
+
+    let kinds = db
+        .schema()
+        .iter()
+        .map(|e| (e.kind, e.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (SchemaKind::Table, "orders"),
+            (SchemaKind::Index, "sqlite_autoindex_orders_1"),
+            (SchemaKind::Index, "orders_status"),
+            (SchemaKind::Table, "tags"),
+            (SchemaKind::View, "open_orders"),
+        ]
+    );
+    assert!(db.table("open_orders").is_none());
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&orders.columns[3]).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"qty","ty":"integer","primary_key":false,"not_null":false,"default":"1 + 1","collation":null}"#
+        );
+        let info: codecrafters_sqlite::TableInfo =
+            serde_json::from_str(&serde_json::to_string(orders).unwrap()).unwrap();
+        assert_eq!(&info, orders);
+    }
+}
+
+#[test]
+fn prepared_statements_bind_parameters() {
+    let db = Database::open("sample.db").unwrap();
+    let stmt = db.prepare("select name from apples where id = ?").unwrap();
+    assert_eq!(stmt.parameter_count(), 1);
+    assert_eq!(stmt.query_plan(), None);
+    for (id, name) in [(2, "Fuji"), (4, "Golden Delicious")] {
+        let rows = stmt.query(&[ColType::Integer(id)]).unwrap();
+        assert_eq!(strings(rows), [[name]]);
+    }
+    assert!(collect(stmt.query(&[ColType::Null]).unwrap()).is_empty());
+
+    let stmt = db
+        .prepare("select id, :suffix || name from apples where color = :color or id = ?3 + :suffix")
+        .unwrap();
+    assert_eq!(stmt.parameter_count(), 3);
+    assert_eq!(stmt.parameter_index(":color"), Some(1));
+    let rows = stmt
+        .query_named(&[
+            (":color", ColType::Text("Red".to_string())),
+            (":suffix", ColType::Integer(1)),
+        ])
+        .unwrap_err();
+    assert!(
+        matches!(rows.downcast_ref(), Some(SqliteError::Usage(_))),
+        "{rows}"
+    );
+    let rows = stmt
+        .query(&[
+            ColType::Text("x".to_string()),
+            ColType::Text("Red".to_string()),
+            ColType::Integer(-1),
+        ])
+        .unwrap();
+    assert_eq!(strings(rows), [["2", "xFuji"]]);
+
+    // the wrong number of values fails before any row is read
+    for params in [&[][..], &[ColType::Integer(1), ColType::Integer(2)]] {
+        let stmt = db.prepare("select name from apples where id = ?1").unwrap();
+        let err = stmt.query(params).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::Usage(_))),
+            "{err}"
+        );
+    }
+    assert!(db.query("select name from apples where id = ?").is_err());
+
+    // values are data, whatever SQL they look like
+    let stmt = db
+        .prepare("select name from apples where name = ? or color = ?")
+        .unwrap();
+    for text in [
+        "' or 1=1 --",
+        "x' or 'a'='a",
+        "Fuji\"; drop table apples; --",
+        "?1",
+    ] {
+        let rows = stmt
+            .query(&[
+                ColType::Text(text.to_string()),
+                ColType::Text(text.to_string()),
+            ])
+            .unwrap();
+        assert!(collect(rows).is_empty(), "{text}");
+    }
+    let stmt = db
+        .prepare("select ? = 'it''s', length(?1) from apples")
+        .unwrap();
+    let rows = stmt.query(&[ColType::Text("it's".to_string())]).unwrap();
+    assert_eq!(strings(rows)[0], ["1", "4"]);
+
+    // a placeholder compared with an indexed column is searched for
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, x text)")
+        .sql("create index t_x on t (x)")
+        .sql("insert into t (x) values ('a''b'), ('c'), ('a''b')");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let stmt = db
+        .prepare("explain query plan select id from t where x = ?")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_x (x=?)\n"
+    );
+    let stmt = db.prepare("select id from t where x = ?").unwrap();
+    let rows = stmt.query(&[ColType::Text("a'b".to_string())]).unwrap();
+    assert_eq!(strings(rows), [["1"], ["3"]]);
+}
+
+#[test]
+fn tree_depth_follows_the_leftmost_path() {
+    let db = Database::from_bytes(fixtures::create_simple_table()).unwrap();
+    assert_eq!(db.tree_depth("fruits").unwrap(), 1);
+    let err = db.tree_depth("nosuchtable").unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NoSuchTable(_))),
+        "{err}"
+    );
+
+    let db = Database::from_bytes(fixtures::create_multipage_table(1000)).unwrap();
+    let depth = db.tree_depth("numbers").unwrap();
+    assert!(depth > 1, "{depth}");
+    // a page per level, not the whole tree
+    assert_eq!(db.stats().pages_read, depth);
+    assert_eq!(depth, db.page_stats("numbers").unwrap().depth);
+}
+
+#[test]
+fn table_cursors_seek_across_leaf_boundaries() {
+    let db = Database::from_bytes(fixtures::create_multipage_table(1000)).unwrap();
+    let rows: Vec<_> = db
+        .scan_table("numbers")
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(rows.len(), 1000);
+    assert!(
+        rows.iter()
+            .enumerate()
+            .all(|(i, (rowid, _))| *rowid == i as i64 + 1)
+    );
+    // the alias of the rowid is NULL in the record, as stored
+    assert_eq!(rows[41].1, [Value::Null, text("number 42"), text("even")]);
+
+    // the first leaf ends at the rowid of its last cell
+    let last = db.page_stats("numbers").unwrap().leaves[0].cells as i64;
+    let mut cursor = db.scan_table("numbers").unwrap();
+    cursor.seek(last).unwrap();
+    let rowids: Vec<i64> = cursor.by_ref().take(3).map(|r| r.unwrap().0).collect();
+    assert_eq!(rowids, [last, last + 1, last + 2]);
+    // seeking again starts over from the root
+    cursor.seek(last + 1).unwrap();
+    assert_eq!(cursor.next().unwrap().unwrap().0, last + 1);
+    cursor.seek(0).unwrap();
+    assert_eq!(cursor.next().unwrap().unwrap().0, 1);
+    cursor.seek(1000).unwrap();
+    assert_eq!(cursor.next().unwrap().unwrap().0, 1000);
+    assert!(cursor.next().is_none());
+    cursor.seek(1001).unwrap();
+    assert!(cursor.next().is_none());
+
+    let err = db.scan_table("nosuchtable").unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NoSuchTable(_))),
+        "{err}"
+    );
+    let bytes = fixtures::FixtureBuilder::new()
+        .sql("create table kv (k text primary key, v) without rowid")
+        .build();
+    let err = Database::from_bytes(bytes)
+        .unwrap()
+        .scan_table("kv")
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans_cover_open_paging_and_queries() {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+    use tracing_core::span::Current;
+
+    // Spans keeps every span as its name and fields, `name a=1 b=2`, with
+    // the stack of entered ones for Span::current
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<SpanLog>>);
+
+    #[derive(Default)]
+    struct SpanLog {
+        spans: Vec<(String, &'static Metadata<'static>)>,
+        entered: Vec<Id>,
+    }
+
+    struct Fields<'a>(&'a mut String);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            *self.0 += &format!(" {}={value:?}", field.name());
+        }
+    }
+
+    impl tracing::Subscriber for Spans {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut line = span.metadata().name().to_string();
+            span.record(&mut Fields(&mut line));
+            let mut log = self.0.lock().unwrap();
+            log.spans.push((line, span.metadata()));
+            Id::from_u64(log.spans.len() as u64)
+        }
+
+        fn record(&self, id: &Id, values: &Record) {
+            let mut log = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut log.spans[id.into_u64() as usize - 1].0));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, id: &Id) {
+            self.0.lock().unwrap().entered.push(id.clone());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.0.lock().unwrap().entered.pop();
+        }
+
+        fn current_span(&self) -> Current {
+            let log = self.0.lock().unwrap();
+            match log.entered.last() {
+                Some(id) => Current::new(id.clone(), log.spans[id.into_u64() as usize - 1].1),
+                None => Current::none(),
+            }
+        }
+    }
+
+    let spans = Spans::default();
+    tracing::subscriber::with_default(spans.clone(), || {
+        let db = Database::open("sample.db").unwrap();
+        let rows = db.query("select name from apples where id = 2").unwrap();
+        assert_eq!(strings(rows), [["Fuji"]]);
+    });
+    let log = spans.0.lock().unwrap();
+    let has = |prefix: &str| log.spans.iter().any(|(s, _)| s.starts_with(prefix));
+    let names = log.spans.iter().map(|(s, _)| s).collect::<Vec<_>>();
+    assert!(has("open path=\"sample.db\""), "{names:?}");
+    assert!(has("schema"), "{names:?}");
+    assert!(has("parse_page page_idx=0 cell_count=3"), "{names:?}");
+    assert!(
+        has("query sql=\"select name from apples where id = 2\""),
+        "{names:?}"
+    );
+    assert!(has("select table_name=apples"), "{names:?}");
+    assert!(has("scan_btree page_idx=1 cell_count=4"), "{names:?}");
+    assert!(
+        has("parse_one_cell ic=1 offset=4054 page_idx=1"),
+        "{names:?}"
+    );
+    assert!(has("decode_varint"), "{names:?}");
+}
+
+#[test]
+fn string_functions_match_sqlite() {
+    let db = Database::open("sample.db").unwrap();
+    let rows = db
+        .query_to_vec("select name, length(name), upper(color), lower(name) from apples")
+        .unwrap();
+    let row = |name: &str, color: &str| {
+        [
+            text(name),
+            Value::Integer(name.len() as i64),
+            text(color),
+            text(&name.to_lowercase()),
+        ]
+    };
+    assert_eq!(
+        rows,
+        [
+            row("Granny Smith", "LIGHT GREEN"),
+            row("Fuji", "RED"),
+            row("Honeycrisp", "BLUSH RED"),
+            row("Golden Delicious", "YELLOW"),
+        ]
+    );
+    let rows = db
+        .query_to_vec(
+            "select substr(name, 2, 3), substr(name, -3), substr(name, 0, 2), \
+             substr(name, 20), substr(name, 3, -2) from apples where length(name) > 5",
+        )
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            ["ran", "ith", "G", "", "Gr"].map(text),
+            ["one", "isp", "H", "", "Ho"].map(text),
+            ["old", "ous", "G", "", "Go"].map(text),
+        ]
+    );
+    let id = db
+        .query_one("select id from apples where lower(color) = 'red' and substr(name, 1, 1) = 'F'")
+        .unwrap();
+    assert_eq!(id, Some(Value::Integer(2)));
+    parse_error(&db, "select nosuch(name) from apples");
+}
+
+#[test]
+fn intersect_keeps_rows_on_both_sides() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table t1 (a, b)")
+        .sql("create table t2 (a, b)")
+        .sql("insert into t1 values (NULL, 'n'), (1, 'x'), (NULL, 'n'), (2, 'y'), (3, NULL)")
+        .sql("insert into t2 values (3, NULL), (NULL, 'n'), (2, 'z'), (1, 'x')");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query_to_vec("select a, b from t1 intersect select a, b from t2")
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            [Value::Null, text("n")],
+            [Value::Integer(1), text("x")],
+            [Value::Integer(3), Value::Null],
+        ]
+    );
+    let err = parse_error(&db, "select a from t1 intersect select a, b from t2");
+    assert!(err.contains("same number of result columns"), "{err}");
+}
+
+#[test]
+fn except_removes_right_rows_and_duplicates() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table all_users (name)")
+        .sql("create table admin_users (name)")
+        .sql("insert into all_users values ('ann'), (NULL), ('ann'), ('bob'), ('cy'), (NULL)")
+        .sql("insert into admin_users values (NULL), ('bob')");
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query_to_vec("select name from all_users except select name from admin_users")
+        .unwrap();
+    assert_eq!(rows, [[text("ann")], [text("cy")]]);
+}
+
+#[test]
+fn window_functions_match_sqlite() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table prices (day, ticker, price)")
+        .sql(
+            "insert into prices values (3, 'b', 30), (1, 'a', 10), (2, 'b', 20), \
+             (4, 'a', 40), (5, 'a', NULL), (6, 'b', 60)",
+        );
+    let image = builder.build();
+    let path = fixtures::write_temp("window.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select day, price - lag(price) over (order by day) from prices",
+            "select day, lead(price, 2, -1) over (partition by ticker order by day desc) \
+             from prices",
+            "select ticker, lag(price, 1, 0) over (partition by ticker order by day), \
+             lead(day) over (order by day) from prices where day > 1",
+            "select day, first_value(price) over (partition by ticker order by day desc), \
+             last_value(day) over (order by ticker), nth_value(price, 2) over (order by day) \
+             from prices",
+            "select day, ntile(4) over (order by price desc), \
+             ntile(2) over (partition by ticker order by day) from prices",
+            "select day, cume_dist() over (order by price), \
+             percent_rank() over (partition by ticker order by price desc) from prices",
+        ],
+    );
+    let db = Database::from_bytes(image).unwrap();
+    let err = parse_error(&db, "select lag(price) from prices");
+    assert!(err.contains("misuse of window function lag()"), "{err}");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn aggregate_filter_matches_sqlite() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table users (id integer primary key, name text, active, score)")
+        .sql(
+            "insert into users (name, active, score) values ('ann', 1, 10), ('bob', 0, 20), \
+             ('cy', 1, NULL), ('di', NULL, 2.5), ('ed', 1, 7)",
+        );
+    let image = builder.build();
+    let path = fixtures::write_temp("aggregate.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select count(*) filter (where active = 1) from users",
+            "select count(*), count(score), count(*) filter (where active) from users",
+            "select sum(score) filter (where active = 1), total(score) filter (where active = 0), \
+             avg(score) filter (where score > 5) from users",
+            "select min(name) filter (where active = 1), \
+             max(score) filter (where active is null) from users",
+            "select sum(score) filter (where id > 100), count(score) filter (where id > 100) \
+             from users",
+            "select count(*) filter (where active = 1) * 100 / count(*) from users where id > 1",
+        ],
+    );
+    let db = Database::from_bytes(image).unwrap();
+    let err = parse_error(
+        &db,
+        "select name from users where count(*) filter (where active) > 1",
+    );
+    assert!(
+        err.contains("misuse of aggregate function count()"),
+        "{err}"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_databases_stream_rows() {
+    use codecrafters_sqlite::AsyncDatabase;
+    use tokio_stream::StreamExt;
+
+    let db = AsyncDatabase::open("sample.db").await.unwrap();
+    assert_eq!(db.table_names(), ["apples", "sqlite_sequence", "oranges"]);
+    let names = db
+        .query("select name from apples where color = 'Red' or id = 4")
+        .map(|row| row.unwrap().get::<String>(0).unwrap())
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(names, ["Fuji", "Golden Delicious"]);
+
+    let mut rows = db.query("select name from nosuchtable");
+    let err = rows.next().await.unwrap().unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NoSuchTable(_))),
+        "{err}"
+    );
+    assert!(rows.next().await.is_none());
+
+    let garbage = fixtures::write_temp("async-garbage.db", &[0x42; 512]);
+    let err = AsyncDatabase::open(garbage.to_str().unwrap())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NotADatabase)),
+        "{err}"
+    );
+    std::fs::remove_file(&garbage).unwrap();
+}
+
+#[test]
+fn mixed_numeric_comparisons_match_sqlite() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table counts (id integer primary key, n integer, r real)")
+        .sql("create table levels (id integer primary key, r real)")
+        .sql("create index idx_counts_n on counts (n)")
+        .sql("create index idx_levels_r on levels (r)")
+        .sql(
+            "insert into counts (n, r) values (1, 1.5), (2, 2.0), (3, 2.5), (-1, -0.5), \
+             (9007199254740993, 9007199254740992.0)",
+        )
+        .sql("insert into levels (r) values (0.5), (2.0), (2.5), (-1.0), (1e3)");
+    let image = builder.build();
+    let path = fixtures::write_temp("mixed_numeric.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select id from counts where n > 1.5 order by id",
+            "select id from counts where 1.5 < n order by id",
+            "select id from counts where n = 2.0",
+            "select id from counts where n = 2.5",
+            "select id from counts where n = 1e0",
+            "select id from counts where n <= -0.5 order by id",
+            "select id from counts where n = 9007199254740992.0",
+            "select id from counts where r = 2",
+            "select id from counts where 2 = r",
+            "select id from counts where r > n",
+            "select id, n = r, n < r from counts",
+            "select id from levels where r = 2",
+            "select id from levels where r = -1",
+            "select id from levels where r = 1000",
+            "select id from levels where r >= 1 order by id",
+            "select id from levels where 3 > r order by id",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let stmt = db.prepare("select id from counts where n = ?").unwrap();
+    let rows = stmt.query(&[Value::Float(3.0)]).unwrap();
+    assert_eq!(strings(rows), [["3"]]);
+    let stmt = db.prepare("select id from levels where r = ?").unwrap();
+    let rows = stmt.query(&[Value::Integer(-1)]).unwrap();
+    assert_eq!(strings(rows), [["4"]]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn bare_columns_beside_aggregates_match_sqlite() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table scores (player text, points integer)")
+        .sql(
+            "insert into scores values ('ann', 1), ('bob', 3), ('cy', 3), ('dee', NULL), \
+             ('eve', 1)",
+        );
+    let image = builder.build();
+    let path = fixtures::write_temp("bare_columns.db", &image);
+    // without GROUP BY a bare column comes from the row of the only min() or
+    // max(), its first one on ties, and otherwise from the first row
+    matches_sqlite(
+        &path,
+        &[
+            "select player, max(points) from scores",
+            "select player, min(points) from scores",
+            "select max(points) + 1, upper(player), count(*) from scores",
+            "select player, max(points) filter (where player != 'bob') from scores",
+            "select player, count(*) from scores",
+            "select player, sum(points) from scores where points > 1",
+            "select player, max(points) from scores where points > 5",
+        ],
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn header_fields_follow_their_pragmas() {
+    let builder = fixtures::FixtureBuilder::new()
+        .page_size(1024)
+        .sql("PRAGMA auto_vacuum = incremental")
+        .sql("PRAGMA encoding = 'UTF-16le'")
+        .sql("PRAGMA journal_mode = wal")
+        .sql("PRAGMA user_version = 42")
+        .sql("PRAGMA application_id = -1234567")
+        .sql("PRAGMA default_cache_size = 500")
+        .sql("create table kept (x)")
+        .sql("create table gone (x)")
+        .sql("insert into gone values (zeroblob(5000))")
+        .sql("drop table gone");
+    let image = builder.build();
+    let len = image.len();
+    let h = Database::from_bytes(image).unwrap().header();
+    assert_eq!(h.page_size, 1024);
+    assert_eq!(h.page_count as usize * 1024, len);
+    // WAL mode shows in both format versions
+    assert_eq!((h.write_version, h.read_version), (2, 2));
+    assert_eq!(h.reserved_bytes, 0);
+    // the dropped table's pages wait for PRAGMA incremental_vacuum
+    assert!(h.incremental_vacuum);
+    assert_eq!(h.freelist_page_count, 5);
+    assert!(h.freelist_trunk_page > 1);
+    // kept's root, after the schema and a pointer map page
+    assert_eq!(h.largest_root_page, 3);
+    // creating, creating and dropping
+    assert_eq!(h.schema_cookie, 3);
+    assert_eq!(h.schema_format, 4);
+    assert_eq!(h.default_cache_size, 500);
+    assert_eq!(h.text_encoding, 2);
+    assert_eq!(h.text_encoding_name(), "utf16le");
+    assert_eq!(h.user_version, 42);
+    assert_eq!(h.application_id, -1234567);
+    assert!(h.file_change_counter > 0);
+    assert_eq!(h.version_valid_for, h.file_change_counter);
+    assert!(h.sqlite_version_number >= 3_007_000, "{h:?}");
+}
+
+#[test]
+fn strict_tables_check_stored_types() {
+    let builder = fixtures::FixtureBuilder::new()
+        .sql("create table typed (id integer primary key, n int, r real, s text, b blob, x any) strict")
+        .sql("insert into typed (n, r, s, b, x) values (1, 2.0, 'a', x'00', 'q'), (2, 2.5, NULL, NULL, 3)")
+        .sql("create table loose (id integer primary key, n any) strict")
+        .sql("insert into loose (n) values (1), ('bad'), (3)");
+    let mut image = builder.build();
+    // sqlite3 never stores a value of the wrong type, so narrow the declared
+    // type after the fact: ANY and INT are the same length
+    let sql = b"n any) strict";
+    let at = image.windows(sql.len()).position(|w| w == sql).unwrap();
+    image[at..at + 5].copy_from_slice(b"n int");
+    let db = Database::from_bytes(image).unwrap();
+    assert!(db.table("typed").unwrap().strict);
+
+    // a real with no fraction is stored as an integer
+    let rows = db.query_to_vec("select r, x from typed").unwrap();
+    assert_eq!(
+        rows,
+        [
+            [Value::Float(2.0), text("q")],
+            [Value::Float(2.5), Value::Integer(3)]
+        ]
+    );
+
+    let mut rows = db.query("select id from loose").unwrap();
+    assert_eq!(rows.next().unwrap().unwrap().get::<i64>(0).unwrap(), 1);
+    let err = rows.next().unwrap().unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref(),
+            Some(SqliteError::StrictTypeViolation { column, stored: "text", .. }) if column == "n"
+        ),
+        "{err}"
+    );
+    assert_eq!(
+        err.to_string(),
+        "loose.n is a STRICT INT column but holds a text value"
+    );
+}
+
+#[test]
+fn foreign_files_are_named_in_the_error() {
+    let mut zipvfs = vec![0; 4096];
+    zipvfs[..8].copy_from_slice(b"ZV-zlib\0");
+    let path = fixtures::write_temp("foreign.db", &zipvfs);
+    let err = Database::open(path.to_str().unwrap()).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+        "{err}"
+    );
+    assert!(
+        err.to_string()
+            .starts_with("unsupported: file appears to be a ZipVFS compressed database"),
+        "{err}"
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    // a JPEG, random bytes after its signature, is no database at all, and
+    // neither is plain garbage
+    let mut x = 0x9e37_79b9_7f4a_7c15u64;
+    let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0];
+    jpeg.extend((0..4092).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        (x >> 32) as u8
+    }));
+    for image in [jpeg, vec![0x42; 4096]] {
+        let path = fixtures::write_temp("foreign.db", &image);
+        let err = Database::open(path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::NotADatabase)),
+            "{err}"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+// Split is split(text, sep): a row per piece of text, with its position.
+struct Split;
+
+impl TableValuedFunction for Split {
+    fn name(&self) -> &str {
+        "split"
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec!["n".to_string(), "piece".to_string()]
+    }
+
+    fn call(&self, args: &[Value]) -> anyhow::Result<Box<dyn Iterator<Item = Row>>> {
+        let [text, sep] = args else {
+            anyhow::bail!("split() takes two arguments");
+        };
+        let columns: Rc<[String]> = self.columns().into();
+        let pieces = text
+            .to_string()
+            .split(&sep.to_string())
+            .enumerate()
+            .map(|(i, p)| {
+                let values = vec![Value::Integer(i as i64), Value::Text(p.to_string())];
+                Row::new(columns.clone(), values)
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(pieces.into_iter()))
+    }
+}
+
+#[test]
+fn table_valued_functions_are_row_sources() {
+    let mut db = Database::open("sample.db").unwrap();
+    let err = db.query("select * from split('a,b', ',')").unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(SqliteError::NoSuchTable(t)) if t == "split"),
+        "{err}"
+    );
+    db.register_function(Split);
+    assert!(db.functions().get("SPLIT").is_some());
+
+    let rows = db.query("select * from split('a,bb,c', ',')").unwrap();
+    assert_eq!(rows.columns(), ["n", "piece"]);
+    assert_eq!(strings(rows), [["0", "a"], ["1", "bb"], ["2", "c"]]);
+    // filters, sorting, aggregates and parameters work as on a table
+    let stmt = db
+        .prepare("select piece from split(?1, ?2) where length(piece) > 1 order by piece desc")
+        .unwrap();
+    let rows = stmt
+        .query(&[
+            Value::Text("x;yy;zzz".to_string()),
+            Value::Text(";".to_string()),
+        ])
+        .unwrap();
+    assert_eq!(strings(rows), [["zzz"], ["yy"]]);
+    let rows = db
+        .query("select count(*), max(n) from split('1-2-3-4', '-')")
+        .unwrap();
+    assert_eq!(strings(rows), [["4", "3"]]);
+    let rows = db
+        .query("select value from generate_series(1, 6) except select id from apples")
+        .unwrap();
+    assert_eq!(strings(rows), [["5"], ["6"]]);
+
+    let stmt = db
+        .prepare("explain query plan select value from generate_series(1)")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SCAN generate_series VIRTUAL TABLE\n"
+    );
+    // rows stream, so a series of four billion values can stop early
+    let first = db
+        .query("select value from generate_series(1)")
+        .unwrap()
+        .take(3)
+        .map(|row| row.unwrap().get::<i64>(0).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(first, [1, 2, 3]);
+
+    let err = db.query("select * from json_each('[1,')").unwrap_err();
+    assert_eq!(err.to_string(), "malformed JSON");
+    let err = db.query("select * from split('a')").unwrap_err();
+    assert_eq!(err.to_string(), "split() takes two arguments");
+}
+
+#[test]
+fn group_concat_joins_values_per_group() {
+    let builder = fixtures::FixtureBuilder::new().sql(
+        "create table staff (name text, dept text, floor int);
+             insert into staff values
+               ('ann', 'eng', 2), ('bob', 'ops', 1), ('cy', 'eng', NULL),
+               ('dee', NULL, 3), ('eve', 'ops', 1);",
+    );
+    let image = builder.build();
+    let db = Database::from_bytes(image).unwrap();
+    let run = |sql: &str| strings(db.query(sql).unwrap());
+    assert_eq!(
+        run("select group_concat(name), group_concat(floor, '; ') from staff"),
+        [["ann,bob,cy,dee,eve", "2; 1; 3; 1"]]
+    );
+    assert_eq!(
+        run("select dept, group_concat(name), count(floor) from staff group by dept"),
+        [
+            ["", "dee", "1"],
+            ["eng", "ann,cy", "1"],
+            ["ops", "bob,eve", "2"]
+        ]
+    );
+    // NULL values are left out, and a group with nothing left is NULL
+    assert_eq!(
+        run("select floor, group_concat(dept, '+') from staff group by floor order by floor desc"),
+        [["3", ""], ["2", "eng"], ["1", "ops+ops"], ["", "eng"]]
+    );
+    assert!(
+        run("select dept, group_concat(name) from staff where floor > 5 group by dept").is_empty()
+    );
+    let err = db
+        .query("select count(*) from staff group by count(*)")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "aggregate functions are not allowed in the GROUP BY clause"
+    );
+}
+
+#[test]
+fn damaged_interior_pages_are_errors() {
+    let image = fixtures::create_multipage_table(1000);
+    let page_size = u16::from_be_bytes([image[16], image[17]]) as usize;
+    // page 2 is the interior root of numbers: its header holds the cell count
+    // at 3 and the right child at 8, and the cell pointers follow at 12
+    assert_eq!(image[page_size], 0x05);
+    let damage: [(usize, &[u8]); 3] = [
+        (page_size + 8, &[0, 0, 0, 0]),
+        (page_size + 3, &[0xff, 0xff]),
+        (page_size + 12, &(page_size as u16 - 2).to_be_bytes()),
+    ];
+    for (at, bytes) in damage {
+        let mut corrupt = image.clone();
+        corrupt[at..at + bytes.len()].copy_from_slice(bytes);
+        let db = Database::from_bytes(corrupt).unwrap();
+        let err = db
+            .query("select name from numbers")
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<SqliteError>(),
+                Some(SqliteError::CorruptDatabase(_))
+            ),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn carray_reads_a_bound_array() {
+    let db = Database::open("sample.db").unwrap();
+    let stmt = db
+        .prepare("select value from carray(?) where value > 1 order by value desc")
+        .unwrap();
+    let ids = array_param(&[1, 5, 3].map(Value::Integer)).unwrap();
+    assert_eq!(strings(stmt.query(&[ids]).unwrap()), [["5"], ["3"]]);
+    // the same statement runs again with another array
+    let names = ["it's", "b"].map(|s| Value::Text(s.to_string()));
+    let rows = stmt.query(&[array_param(&names).unwrap()]).unwrap();
+    assert_eq!(strings(rows), [["it's"], ["b"]]);
+    let rows = db
+        .query("select group_concat(value, '|') from carray('[1, 2.5, \"x\"]', 2)")
+        .unwrap();
+    assert_eq!(strings(rows), [["1|2.5"]]);
+    let err = db.query("select value from carray('1')").unwrap_err();
+    assert_eq!(err.to_string(), "carray() argument is not a JSON array");
+}
+
+#[test]
+fn match_searches_fts5_tables() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create virtual table notes using fts5(title, body)")
+        .sql("insert into notes values ('Groceries', 'milk, eggs and BREAD'), ('Todo', 'bake bread')")
+        .sql("create table plain (body text)")
+        .build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query("select rowid, title from notes where notes match 'bread'")
+        .unwrap();
+    assert_eq!(strings(rows), [["1", "Groceries"], ["2", "Todo"]]);
+    let rows = db
+        .query("select title from notes where notes match 'eggs bread'")
+        .unwrap();
+    assert_eq!(strings(rows), [["Groceries"]]);
+    let rows = db
+        .query("select title from notes where title match 'bread'")
+        .unwrap();
+    assert!(strings(rows).is_empty());
+    let columns = &db.table("notes").unwrap().columns;
+    assert_eq!(columns.len(), 2);
+    let stmt = db
+        .prepare("explain query plan select * from notes where notes match ?")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SCAN notes VIRTUAL TABLE INDEX 0:M2\n"
+    );
+    let err = db
+        .query("select * from plain where body match 'bread'")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unable to use function MATCH in the requested context"
+    );
+}
+
+#[test]
+fn rtree_bounds_skip_nodes_outside_them() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(1024)
+        .sql("create virtual table boxes using rtree(id, min_x, max_x, min_y, max_y, +name)")
+        .sql(
+            "with recursive c(x) as (select 1 union all select x + 1 from c where x < 2000)
+             insert into boxes select x, x, x + 0.5, x % 10, x % 10 + 1, 'b' || x from c",
+        )
+        .build();
+    let db = Database::from_bytes(image).unwrap();
+    assert_eq!(db.query("select * from boxes").unwrap().count(), 2000);
+    let full = db.stats();
+    let sql = "select id, name from boxes where min_x >= 100 and max_x <= 103.5 and 2 <= min_y";
+    let rows = db.query(sql).unwrap();
+    assert_eq!(strings(rows), [["102", "b102"], ["103", "b103"]]);
+    let bounded = db.stats();
+    assert!(full.pages_read > bounded.pages_read * 4, "{full} {bounded}");
+    let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SCAN boxes VIRTUAL TABLE INDEX 2:D0B1D2\n"
+    );
+    let rows = db.query("select max_y from boxes where id = 7").unwrap();
+    assert_eq!(strings(rows), [["8.0"]]);
+    let stmt = db
+        .prepare("explain query plan select * from boxes where id = 7")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SCAN boxes VIRTUAL TABLE INDEX 1:\n"
+    );
+}
+
+#[test]
+fn interior_pages_that_loop_back_are_corrupt() {
+    let image = fixtures::create_indexed_table();
+    let page_size = u16::from_be_bytes([image[16], image[17]]) as usize;
+    // every interior page's right child becomes the page itself
+    let mut corrupt = image.clone();
+    for (i, page) in corrupt.chunks_mut(page_size).enumerate() {
+        let header = if i == 0 { 100 } else { 0 };
+        if matches!(page[header], 0x02 | 0x05) {
+            page[header + 8..header + 12].copy_from_slice(&(i as u32 + 1).to_be_bytes());
+        }
+    }
+    let db = Database::from_bytes(corrupt).unwrap();
+    // a key past every country takes the right child all the way down
+    let lookup = db
+        .query("select name from companies where country = 'zambia'")
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map(|_| ());
+    for err in [
+        lookup.unwrap_err(),
+        db.page_stats("companies").unwrap_err(),
+        db.page_stats("idx_companies_country").unwrap_err(),
+    ] {
+        assert!(
+            matches!(
+                err.downcast_ref::<SqliteError>(),
+                Some(SqliteError::CorruptDatabase(msg)) if msg.ends_with("levels deep")
+            ),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn distinct_aggregates_count_each_group_apart() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create table apples (id integer primary key, color text, size int)")
+        .sql(
+            "insert into apples (color, size) values ('red', 1), ('red', 1), ('red', 2),
+             ('green', 3), ('green', 3), ('green', 1), ('red', 2.0), ('yellow', NULL)",
+        )
+        .build();
+    let db = Database::from_bytes(image).unwrap();
+    let rows = db
+        .query(
+            "select color, count(distinct size), count(size), group_concat(distinct size) \
+             from apples group by color",
+        )
+        .unwrap();
+    assert_eq!(
+        strings(rows),
+        [
+            ["green", "2", "3", "3,1"],
+            ["red", "2", "4", "1,2"],
+            ["yellow", "0", "0", ""],
+        ]
+    );
+}
+
+#[test]
+fn objects_of_type_lists_every_index() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create table a (x unique, y)")
+        .sql("create index a_y on a (y)")
+        .sql("create table b (z)")
+        .sql("create index b_z on b (z desc)")
+        .sql("create view v as select x from a")
+        .sql("create trigger b_log after insert on b begin select 1; end")
+        .build();
+    let db = Database::from_bytes(image).unwrap();
+    let indexes = db
+        .objects_of_type(SchemaKind::Index)
+        .map(|e| (e.name.as_str(), e.table.as_str(), e.sql.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        indexes,
+        [
+            ("sqlite_autoindex_a_1", "a", false),
+            ("a_y", "a", true),
+            ("b_z", "b", true),
+        ]
+    );
+    let names = |kind| {
+        db.objects_of_type(kind)
+            .map(|e| e.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(SchemaKind::Table), ["a", "b"]);
+    assert_eq!(names(SchemaKind::View), ["v"]);
+    assert_eq!(names(SchemaKind::Trigger), ["b_log"]);
+}
+
+#[test]
+fn check_unique_reports_duplicate_keys() {
+    // sqlite3 won't build a UNIQUE index over duplicates, so the index is
+    // built plain and its sql patched to UNIQUE, padded to keep its length;
+    // enough rows for interior pages to hold entries too
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, code unique, a, b)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 300) \
+             insert into t select i, i, i % 150, printf('%040d', i % 150) from n",
+        )
+        .sql("insert into t values (301, 301, null, 'x'), (302, 302, null, 'x')")
+        .sql("create index t_ab on t        (a, b)")
+        .sql("create index t_a on t (a)")
+        .build();
+    let plain = b"CREATE INDEX t_ab on t        (";
+    let at = image.windows(plain.len()).position(|w| w == plain).unwrap();
+    let mut image = image;
+    image[at..at + plain.len()].copy_from_slice(b"CREATE UNIQUE INDEX t_ab on t (");
+    let db = Database::from_bytes(image).unwrap();
+    let violations = db.check_unique("T").unwrap();
+    assert_eq!(violations.len(), 150);
+    assert!(violations.iter().all(|v| v.index_name == "t_ab"));
+    assert_eq!(
+        violations[7].key_value,
+        [Value::Integer(7), Value::Text(format!("{:040}", 7))]
+    );
+    assert_eq!(violations[7].rowids, [7, 157]);
+    assert!(matches!(
+        db.check_unique("nosuch").unwrap_err().downcast_ref(),
+        Some(SqliteError::NoSuchTable(_))
+    ));
+}
+
+#[test]
+fn recover_reads_the_leaves_a_corrupt_page_cuts_off() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, v text)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 400) \
+             insert into t select i, printf('row %d', i) from n",
+        )
+        .sql("create table u (x)")
+        .sql("insert into u values (1), (2)")
+        .build();
+    let db = Database::from_bytes(image.clone()).unwrap();
+    let all = db.recover().unwrap();
+    assert_eq!(
+        all.tables
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>(),
+        ["t", "u"]
+    );
+    assert_eq!(all.tables[0].rows.len(), 400);
+    assert_eq!(all.tables[0].rowid_column, Some(0));
+    assert_eq!(
+        all.tables[0].rows[6],
+        (7, vec![Value::Null, Value::Text("row 7".to_string())])
+    );
+
+    // a leaf no longer a b-tree page fails every scan of t, but only loses
+    // its own rows
+    let rootpage = db.table("t").unwrap().rootpage;
+    let mut broken = image.clone();
+    broken[2 * 512] = 0;
+    let db = Database::from_bytes(broken).unwrap();
+    assert!(db.query_to_vec("select count(*) from t").is_err());
+    let recovery = db.recover().unwrap();
+    let t = &recovery.tables[0];
+    assert!(t.rows.len() > 350 && t.rows.len() < 400, "{}", t.rows.len());
+    assert!(
+        t.rows
+            .iter()
+            .all(|(rowid, values)| { values[1] == Value::Text(format!("row {rowid}")) })
+    );
+    assert_eq!(recovery.tables[1].rows.len(), 2);
+
+    // without the root, each leaf's rows are a table of their own
+    let mut broken = image;
+    broken[(rootpage - 1) * 512] = 0;
+    let recovery = Database::from_bytes(broken).unwrap().recover().unwrap();
+    assert!(recovery.tables[0].rows.is_empty());
+    let unknown = &recovery.tables[2..];
+    assert_eq!(unknown[0].name, "unknown_table_3");
+    assert_eq!(unknown[0].columns, ["c0", "c1"]);
+    assert_eq!(unknown.iter().map(|t| t.rows.len()).sum::<usize>(), 400);
+    let script = recovery.to_string();
+    assert!(script.starts_with("BEGIN;\nCREATE TABLE t (id integer primary key, v text);\n"));
+    assert!(script.contains(
+        "CREATE TABLE \"unknown_table_3\"(\"c0\", \"c1\");\n\
+         INSERT INTO \"unknown_table_3\"(rowid, \"c0\", \"c1\") VALUES(1, NULL, 'row 1');\n"
+    ));
+    assert!(script.ends_with("COMMIT;\n"));
+}
+
+#[test]
+fn transactions_replace_the_file_only_on_commit() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, name text)")
+        .sql("insert into t values (1, 'apple')")
+        .build();
+    let path = fixtures::write_temp("transaction.db", &image);
+    let mut db = Database::open(path.to_str().unwrap()).unwrap();
+    db.set_writable(true);
+    let names = |db: &Database| strings(db.query("select name from t").unwrap());
+
+    db.begin().unwrap();
+    assert!(db.begin().is_err());
+    db.query("insert into t (name) values ('banana')").unwrap();
+    db.query("create table u (x)").unwrap();
+    db.reload_schema().unwrap();
+    db.query("insert into u values (1)").unwrap();
+    // later statements read the transaction's pages, the file is untouched
+    assert_eq!(names(&db), [["apple"], ["banana"]]);
+    assert_eq!(std::fs::read(&path).unwrap(), image);
+    db.rollback().unwrap();
+    assert_eq!(names(&db), [["apple"]]);
+    assert!(db.table("u").is_none());
+    assert!(db.rollback().is_err());
+    assert!(db.commit().is_err());
+
+    // a commit that fails before the rename, here because the temp file
+    // can't be created, leaves the file as it was and the transaction open
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    db.begin().unwrap();
+    db.query("insert into t (name) values ('cherry')").unwrap();
+    assert!(db.commit().is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), image);
+    std::fs::remove_dir(&tmp).unwrap();
+    db.commit().unwrap();
+    assert_eq!(names(&db), [["apple"], ["cherry"]]);
+    // a statement on its own commits at once, once
+    db.query("insert into t (name) values ('date')").unwrap();
+    let reopened = Database::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(names(&reopened), [["apple"], ["cherry"], ["date"]]);
+    assert_eq!(
+        reopened.header().file_change_counter,
+        Database::from_bytes(image)
+            .unwrap()
+            .header()
+            .file_change_counter
+            + 2
+    );
+    assert_eq!(
+        fixtures::sqlite_rows(&path, "", "pragma integrity_check"),
+        "ok\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn like_prefixes_seek_the_index() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table c (id integer primary key, code text)")
+        .sql("create index c_code on c (code)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 3000)
+             insert into c select i, ((i * 7) % 1000) || '-' || i from n",
+        )
+        .sql("insert into c values (3001, '12a'), (3002, 12), (3003, x'3132'), (3004, NULL)")
+        .build();
+    let path = fixtures::write_temp("like_prefix.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select id, code from c where code like '12%' order by id",
+            "select id from c where code like '12-%' order by id",
+            "select id from c where code like '%12' order by id",
+            "select id from c where code like '_2%' order by id",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    let prefix = "select id from c where code like '12-%'";
+    assert_eq!(
+        plan(prefix),
+        "QUERY PLAN\n`--SEARCH c USING INDEX c_code (code>? AND code<?)\n"
+    );
+    for sql in [
+        "select id from c where code like '%12'",
+        "select id from c where code like '_2%'",
+    ] {
+        assert_eq!(plan(sql), "QUERY PLAN\n`--SCAN c\n", "{sql}");
+    }
+    assert_eq!(db.query(prefix).unwrap().count(), 3);
+    let searched = db.stats();
+    assert_eq!(
+        db.query("select id from c where code like '%12-%'")
+            .unwrap()
+            .count(),
+        30
+    );
+    let scanned = db.stats();
+    assert!(
+        scanned.cells_decoded > searched.cells_decoded * 20,
+        "{scanned} {searched}"
+    );
+}
+
+#[test]
+fn check_rowids_reports_rows_out_of_order() {
+    let image = fixtures::create_multipage_table(1000);
+    let db = Database::from_bytes(image.clone()).unwrap();
+    assert_eq!(db.check_rowids("numbers").unwrap(), []);
+    let leaves = db.page_stats("numbers").unwrap().leaves;
+    let last = leaves[0].cells as i64;
+
+    // the first cell of the second leaf: a one-byte payload size, then its
+    // rowid as two bytes, made 1 without changing the cell's length
+    let page_size = u16::from_be_bytes([image[16], image[17]]) as usize;
+    let start = (leaves[1].page - 1) * page_size;
+    let cell = start + u16::from_be_bytes([image[start + 8], image[start + 9]]) as usize;
+    let next = last as usize + 1;
+    assert_eq!(
+        image[cell + 1..cell + 3],
+        [0x80 | (next >> 7) as u8, next as u8 & 0x7f]
+    );
+    let mut corrupt = image;
+    corrupt[cell + 1..cell + 3].copy_from_slice(&[0x80, 0x01]);
+    let db = Database::from_bytes(corrupt).unwrap();
+    assert_eq!(
+        db.check_rowids("numbers").unwrap(),
+        [RowidViolation {
+            page: leaves[1].page,
+            cell: 0,
+            rowid: 1,
+            previous: last,
+        }]
+    );
+    // debug builds stop a scan at the row too
+    if cfg!(debug_assertions) {
+        let err = db
+            .scan_table("numbers")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::CorruptDatabase(_))),
+            "{err}"
+        );
+    }
+    assert!(matches!(
+        db.check_rowids("nosuch").unwrap_err().downcast_ref(),
+        Some(SqliteError::NoSuchTable(_))
+    ));
+}
+
+#[test]
+fn desc_indexes_serve_seeks_and_order_by() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, score int)")
+        .sql("create index t_score on t (score desc)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 1000)
+             insert into t select i, i % 10 from n",
+        )
+        .build();
+    let db = Database::from_bytes(image).unwrap();
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    assert_eq!(
+        plan("select id from t where score = 3"),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_score (score=?)\n"
+    );
+    let rows = db.query("select id from t where score = 3").unwrap();
+    assert_eq!(rows.count(), 100);
+    for sql in [
+        "select id from t order by score desc",
+        "select id from t order by score",
+    ] {
+        assert_eq!(plan(sql), "QUERY PLAN\n`--SCAN t USING INDEX t_score\n");
+    }
+    // forward, ties in rowid order, and backward
+    let ids = |sql| strings(db.query(sql).unwrap());
+    let desc = ids("select id, score from t order by score desc");
+    assert_eq!(desc[..2], [["9", "9"], ["19", "9"]]);
+    let asc = ids("select id, score from t order by score");
+    assert_eq!(asc[..2], [["1000", "0"], ["990", "0"]]);
+    // a result column named score is what ORDER BY sorts by
+    assert_eq!(
+        plan("select id as score from t order by score"),
+        "QUERY PLAN\n`--SCAN t\n"
+    );
+}
+
+#[test]
+fn indexes_with_another_collation_are_not_searched() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, name text)")
+        .sql("create index t_name on t (name collate nocase)")
+        .sql("create table u (id integer primary key, name text collate nocase)")
+        .sql("create index u_name on u (name)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 3000)
+             insert into t select i, case i % 2 when 0 then 'ANN' else 'ann' end || (i % 100)
+             from n",
+        )
+        .build();
+    let path = fixtures::write_temp("collated_index.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select count(*) from t where name = 'ANN2'",
+            "select count(*) from t where name = 'ann2'",
+            "select id from t where name like '1%'",
+            "select id from t where name = 'ann7' and id < 500",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        strings(
+            db.query("select count(*) from t where name = 'ANN2'")
+                .unwrap()
+        ),
+        [["30"]]
+    );
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    for (sql, table) in [
+        ("select id from t where name = 'ANN2'", "t"),
+        ("select id from t order by name", "t"),
+        ("select id from u where name = 'x'", "u"),
+    ] {
+        assert_eq!(plan(sql), format!("QUERY PLAN\n`--SCAN {table}\n"), "{sql}");
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn compare_index_keys_orders_column_by_column() {
+    use std::cmp::Ordering::*;
+    let key = |v: &[Value]| v.to_vec();
+    let a = key(&[Value::Integer(1), text("b")]);
+    assert_eq!(
+        compare_index_keys(&a, &key(&[Value::Integer(1), text("a")])),
+        Greater
+    );
+    assert_eq!(
+        compare_index_keys(&a, &key(&[Value::Float(1.0), text("b")])),
+        Equal
+    );
+    assert_eq!(
+        compare_index_keys(&a, &key(&[Value::Integer(2), Value::Null])),
+        Less
+    );
+    // NULL first, then numbers, text and blobs
+    assert_eq!(
+        compare_index_keys(&[Value::Null], &[Value::Integer(-5)]),
+        Less
+    );
+    assert_eq!(
+        compare_index_keys(&[text("1")], &[Value::Integer(2)]),
+        Greater
+    );
+    assert_eq!(
+        compare_index_keys(&[Value::Blob(vec![])], &[text("z")]),
+        Greater
+    );
+    // a prefix finds every key that starts with it
+    assert_eq!(compare_index_keys(&a, &[Value::Integer(1)]), Equal);
+}
+
+#[test]
+fn multi_column_indexes_search_their_leading_column() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, a, b text, c)")
+        .sql("create index t_ab on t (a, b)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 2000)
+             insert into t select i, i % 50, printf('%020d', i % 7), i from n",
+        )
+        .sql(
+            "insert into t values (2001, NULL, 'x', 0), (2002, 'a', NULL, 0), (2003, 7.0, NULL, 0)",
+        )
+        .build();
+    let path = fixtures::write_temp("multi_column_index.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select id, b from t where a = 7 order by id",
+            "select id from t where a = 'a'",
+            "select count(*) from t where a = 49",
+            "select id from t where a = 50",
+            "select a, b, id from t order by a",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let stmt = db
+        .prepare("explain query plan select c from t where a = 7")
+        .unwrap();
+    assert_eq!(
+        stmt.query_plan().unwrap(),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_ab (a=?)\n"
+    );
+    assert_eq!(db.query("select c from t where a = 7").unwrap().count(), 41);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn multi_column_indexes_seek_every_bound_column() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table t (id integer primary key, a, b int, c text)")
+        .sql("create index t_ab on t (a, b desc)")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 20000)
+             insert into t select i, i % 3, i, 'row ' || i from n",
+        )
+        .sql("insert into t values (20001, '1', 4000, 'text a'), (20002, 1, '17', 'text b')")
+        .build();
+    let path = fixtures::write_temp("seek_every_column.db", &image);
+    let sql = "select id, c from t where a = 1 and b = 4000";
+    matches_sqlite(
+        &path,
+        &[
+            sql,
+            "select id from t where b = 4000 and a = 1",
+            "select id from t where a = 1 and b = '4000'",
+            "select id from t where a = '1'",
+            "select id from t where a = '1' and b = 4000",
+            "select id from t where a = 1 and b = 17.0",
+            "select id from t where a = 2 and b = 4000",
+            "select count(*) from t where a = 0 and b > 19990",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    assert_eq!(
+        plan(sql),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_ab (a=? AND b=?)\n"
+    );
+    assert_eq!(
+        plan("select id from t where a = 0 and b > 19990"),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_ab (a=?)\n"
+    );
+    // one path down the index and one down the table
+    let depth = db.tree_depth("t_ab").unwrap() + db.tree_depth("t").unwrap();
+    assert_eq!(db.query(sql).unwrap().count(), 1);
+    let stats = db.stats();
+    assert!(stats.pages_read <= depth + 1, "{stats}");
+    // an untyped column compares the text '1' with no number
+    assert_eq!(
+        db.query("select id from t where a = '1'").unwrap().count(),
+        1
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn partial_indexes_serve_only_queries_implying_their_predicate() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("create table orders (id integer primary key, customer int, status text)")
+        .sql("create index open_orders on orders (customer) where status = 'open'")
+        .sql(
+            "with recursive n(i) as (select 1 union all select i + 1 from n where i < 1000)
+             insert into orders select i, i % 20, case i % 3 when 0 then 'open' else 'closed' end
+             from n",
+        )
+        .build();
+    let path = fixtures::write_temp("partial_index.db", &image);
+    let all = "select id from orders where customer = 7 order by id";
+    let open = "select id from orders where customer = 7 and status = 'open' order by id";
+    matches_sqlite(
+        &path,
+        &[
+            all,
+            open,
+            "select id from orders where status = 'open' and customer = 7 order by id",
+            "select id from orders where customer = 7 and status = 'closed' order by id",
+            "select customer, id from orders order by customer",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    // the index holds a third of the rows, the others only a scan finds
+    assert_eq!(plan(all), "QUERY PLAN\n`--SCAN orders\n");
+    assert_eq!(db.query(all).unwrap().count(), 50);
+    assert_eq!(
+        plan(open),
+        "QUERY PLAN\n`--SEARCH orders USING INDEX open_orders (customer=?)\n"
+    );
+    assert_eq!(db.query(open).unwrap().count(), 17);
+    assert_eq!(
+        plan("select id from orders order by customer"),
+        "QUERY PLAN\n`--SCAN orders\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn comparisons_of_two_columns_scan_the_table() {
+    let image = fixtures::FixtureBuilder::new()
+        .sql("create table t (id integer primary key, name text, country text)")
+        .sql("create index t_country on t (country)")
+        .sql(
+            "insert into t values (1, 'chad', 'fiji'), (2, 'peru', 'peru'), \
+             (3, 'oman', 'oman'), (4, 'laos', 'chad'), (5, 'fiji', 'fiji'), (6, 'name', 'x')",
+        )
+        .build();
+    let path = fixtures::write_temp("column_comparisons.db", &image);
+    matches_sqlite(
+        &path,
+        &[
+            "select id from t where country = name",
+            "select id from t where name = country and id > 2",
+            "select id from t where country = \"name\"",
+            "select id from t where country = 'fiji' and name = country",
+        ],
+    );
+    let db = Database::open(path.to_str().unwrap()).unwrap();
+    let plan = |sql: &str| {
+        let stmt = db.prepare(&format!("explain query plan {sql}")).unwrap();
+        stmt.query_plan().unwrap()
+    };
+    assert_eq!(
+        plan("select id from t where country = name"),
+        "QUERY PLAN\n`--SCAN t\n"
+    );
+    assert_eq!(
+        db.query("select id from t where country = name")
+            .unwrap()
+            .count(),
+        3
+    );
+    assert_eq!(
+        plan("select id from t where name = 'x' and country = 'fiji'"),
+        "QUERY PLAN\n`--SEARCH t USING INDEX t_country (country=?)\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn auto_vacuum_roots_stay_below_the_largest_root_page() {
+    let image = fixtures::FixtureBuilder::new()
+        .page_size(512)
+        .sql("PRAGMA auto_vacuum = full")
+        .sql("create table a (x)")
+        .sql("insert into a values (randomblob(2000))")
+        .sql("create table b (y)")
+        .sql("create index b_y on b (y)")
+        .build();
+    let db = Database::from_bytes(image.clone()).unwrap();
+    let largest = db.header().largest_root_page as usize;
+    let roots = db.schema().iter().map(|e| e.rootpage).collect::<Vec<_>>();
+    // page 2 is the pointer map, the roots follow it before a's overflow
+    assert_eq!(roots, [3, 4, 5]);
+    assert_eq!(largest, 5);
+    assert_eq!(
+        strings(db.query("select count(*) from a").unwrap()),
+        [["1"]]
+    );
+
+    let mut corrupt = image;
+    corrupt[52..56].copy_from_slice(&4u32.to_be_bytes());
+    let err = Database::from_bytes(corrupt).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "database disk image is malformed: malformed database schema (b_y) - \
+         root page 5 is past the largest root page 4"
+    );
+}
+
+#[test]
+fn queries_match_their_csv_fixtures() {
+    let db = Database::from_bytes(fixtures::create_indexed_table()).unwrap();
+    assert_query_matches_csv(
+        &db,
+        "select country, count(*) as companies, min(name) from companies \
+         group by country order by country",
+        include_str!("fixtures/companies_by_country.csv"),
+    );
+    assert_query_matches_csv(
+        &db,
+        "select id, name, country, id / 3.0 as third from companies \
+         where country = 'peru' and id < 30",
+        include_str!("fixtures/peru_companies.csv"),
+    );
+}
@@ -0,0 +1,472 @@
+//! Differential tests: every query in `CASES` runs through rusqlite and
+//! through our executor on the same database file, and the rows must agree
+//! value by value. A new SQL feature gets a line in `CASES` per query.
+
+use codecrafters_sqlite::{Database, SqliteError, Value};
+use rusqlite::types::ValueRef;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+mod fixtures;
+
+// CASES are (fixture, query) pairs. Rows are compared in order when the
+// query has an ORDER BY, and as sorted sets otherwise.
+const CASES: &[(&str, &str)] = &[
+    ("people", "select * from people"),
+    ("people", "select name, age from people where age > 30"),
+    ("people", "select name from people where score is null"),
+    (
+        "people",
+        "select id, photo from people where photo is not null",
+    ),
+    (
+        "people",
+        "select name, score * 2, age + 0.5 from people order by name",
+    ),
+    (
+        "people",
+        "select upper(name), length(name), typeof(score) from people",
+    ),
+    (
+        "people",
+        "select substr(name, 2, 3), name || '!' from people where id = 3",
+    ),
+    ("people", "select count(*) from people"),
+    (
+        "people",
+        "select count(score), sum(age), total(score), min(name), max(age) from people",
+    ),
+    ("people", "select avg(score) from people where age >= 20"),
+    ("people", "select name from people order by age desc, name"),
+    ("people", "select rowid, name from people where rowid = 2"),
+    ("numbers", "select count(*) from numbers"),
+    (
+        "numbers",
+        "select count(*) from numbers where parity = 'even'",
+    ),
+    ("numbers", "select id, name from numbers where id = 1777"),
+    (
+        "numbers",
+        "select id, parity from numbers where name = 'number 2500'",
+    ),
+    ("numbers", "select name, sq from numbers where id > 2990"),
+    (
+        "numbers",
+        "select sum(id), max(sq) from numbers where parity = 'odd'",
+    ),
+    (
+        "numbers",
+        "select id from numbers where sq < 100 order by id desc",
+    ),
+    ("docs", "select id, length(body), length(data) from docs"),
+    ("docs", "select body from docs where id = 2"),
+    ("docs", "select data from docs where id = 3"),
+    (
+        "docs",
+        "select substr(body, 4990, 20) from docs where id = 2",
+    ),
+    ("docs", "select id from docs where body is null"),
+    (
+        "utf16le",
+        "select word, length(word), upper(word) from words",
+    ),
+    ("utf16le", "select id from words where word = 'naïve'"),
+    (
+        "utf16be",
+        "select word, length(word) from words order by id desc",
+    ),
+    ("utf16be", "select count(*) from words"),
+    (
+        "wide",
+        "select id, name from items where id >= 40 and id <= 45",
+    ),
+    ("wide", "select count(*), min(id), max(id) from items"),
+    (
+        "people",
+        "select name, min(age, 40), max(score, age, 50), min(name, age) from people",
+    ),
+    ("people", "select name from people where name glob '?e?'"),
+    (
+        "people",
+        "select name from people where name not glob '*[a-c]*'",
+    ),
+    ("people", "select name, age glob '[13]*' from people"),
+    (
+        "numbers",
+        "select id from numbers where name glob 'number 29[0-9]?'",
+    ),
+    ("people", "select group_concat(name) from people"),
+    (
+        "people",
+        "select group_concat(name, ' | '), group_concat(score) from people where age > 20",
+    ),
+    (
+        "people",
+        "select age > 30, count(*), group_concat(name) from people group by 1",
+    ),
+    (
+        "people",
+        "select name from people group by name order by 1 desc",
+    ),
+    (
+        "numbers",
+        "select parity, count(*), sum(id), group_concat(id, '-') from numbers where id < 12 group by parity",
+    ),
+    (
+        "numbers",
+        "select id % 3 as r, min(id), max(sq) from numbers group by r order by r desc",
+    ),
+    (
+        "numbers",
+        "select parity, count(distinct id % 5), sum(distinct id % 7), group_concat(distinct id % 4) from numbers where id < 40 group by parity",
+    ),
+    ("people", "select value from generate_series(1, 5)"),
+    (
+        "people",
+        "select value * 2 from generate_series(1, 20, 3) where value > 2 order by value desc",
+    ),
+    (
+        "people",
+        "select sum(value), count(*), max(value) from generate_series(1, 100, 7)",
+    ),
+    (
+        "people",
+        r#"select key, value, type, atom, fullkey, path from json_each('{"a": [1, 2.5], "b c": null, "d": "x"}')"#,
+    ),
+    (
+        "people",
+        r#"select key, value, type from json_each('[1, [2, 3e1], {"k": true}]', '$[2]')"#,
+    ),
+    ("people", "select key, value from json_each(42)"),
+    (
+        "articles",
+        "select rowid, * from articles where articles match 'rust programming'",
+    ),
+    (
+        "articles",
+        "select title from articles where articles match 'Rust' order by rowid",
+    ),
+    (
+        "articles",
+        "select count(*) from articles where body match 'programming' and title match 'go'",
+    ),
+    (
+        "articles",
+        "select * from articles where articles match 'tags'",
+    ),
+    (
+        "articles",
+        "select title, tags from articles where rowid = 3",
+    ),
+    ("places", "select * from places"),
+    (
+        "places",
+        "select id, label from places where min_x >= 10 and max_x <= 12.5 and min_y > 80",
+    ),
+    ("places", "select * from places where id = 1234"),
+    ("places", "select count(*) from places where 50 < min_x"),
+    (
+        "places",
+        "select max(max_x), sum(min_y) from places where max_y <= 3",
+    ),
+    ("places", "select * from plots where lo < 0 or owner = 'b'"),
+    ("mixed", "select id, v from mixed order by v, id"),
+    ("mixed", "select id, v from mixed order by v desc, id"),
+    (
+        "mixed",
+        "select min(v), max(v), count(distinct v) from mixed",
+    ),
+    (
+        "mixed",
+        "select typeof(v), min(v), max(v) from mixed group by typeof(v)",
+    ),
+    ("mixed", "select id from mixed where v = 'z'"),
+    ("mixed", "select id from mixed where v = 2"),
+    ("mixed", "select id from mixed where v = ''"),
+    ("ranked", "select id from ranked where score = 17"),
+    ("ranked", "select id from ranked where score = 'x'"),
+    ("ranked", "select id from ranked where score = 1000"),
+    ("ranked", "select id, score from ranked order by score desc"),
+    ("ranked", "select id, score from ranked order by score"),
+    (
+        "ranked",
+        "select score, id from ranked where id % 100 = 7 order by score desc",
+    ),
+    ("ranked", "select id from codes where code = '12-716'"),
+    ("ranked", "select id, code from codes where code like '12%'"),
+    ("ranked", "select id from codes where code like '99-%'"),
+    (
+        "ranked",
+        "select code from codes where code like '1%' order by code desc",
+    ),
+    ("affinity", "select id from t where s = 1"),
+    ("affinity", "select id from t where b = 1"),
+    ("affinity", "select id from t where b > 1"),
+    ("affinity", "select id from t where '1' = 1"),
+    ("affinity", "select id from t where n = '2' or n > '2.5'"),
+    ("affinity", "select id from t where n = s"),
+    ("affinity", "select id from t where s = b"),
+    ("affinity", "select id from t where s < 2"),
+    ("affinity", "select id from t where rowid = '2'"),
+    (
+        "affinity",
+        "select id, s = 1, b = '1', n = '1.0', 1 = b, s >= n + 0 from t",
+    ),
+];
+
+// UNSUPPORTED are queries sqlite3 answers that we reject with
+// SqliteError::Unsupported. A line moves to CASES once it's implemented.
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("without_rowid", "select * from kv"),
+    ("without_rowid", "select v from kv where k = 'b'"),
+];
+
+// Fixture is a database built through rusqlite, so it's always available.
+struct Fixture {
+    name: &'static str,
+    page_size: u32,
+    encoding: &'static str,
+    sql: &'static str,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "people",
+        page_size: 4096,
+        encoding: "UTF-8",
+        sql: "create table people (id integer primary key, name text, age int, score real, photo blob);
+              insert into people (name, age, score, photo) values
+                ('ann', 31, 9.5, x'00ff'), ('bob', 17, NULL, NULL), ('cy', 45, 3.0, x''),
+                ('dee', NULL, -1.25, NULL), ('eve', 30, 0.0, x'cafe');",
+    },
+    // small pages make a b-tree of several levels
+    Fixture {
+        name: "numbers",
+        page_size: 512,
+        encoding: "UTF-8",
+        sql: "create table numbers (id integer primary key, name text, parity text, sq real);
+              create index idx_numbers_name on numbers (name);
+              with recursive c(x) as (select 1 union all select x + 1 from c where x < 3000)
+              insert into numbers (name, parity, sq)
+              select 'number ' || x, case x % 2 when 0 then 'even' else 'odd' end, x * x / 7.0
+              from c;",
+    },
+    // values bigger than a page spill onto overflow pages
+    Fixture {
+        name: "docs",
+        page_size: 1024,
+        encoding: "UTF-8",
+        sql: "create table docs (id integer primary key, body text, data blob);
+              insert into docs (body, data) values
+                ('short', x'01'),
+                (printf('%.5000c', 'x') || 'tail', NULL),
+                (NULL, randomblob(20000)),
+                (printf('%.1500c', 'é'), zeroblob(3000));",
+    },
+    Fixture {
+        name: "utf16le",
+        page_size: 4096,
+        encoding: "UTF-16le",
+        sql: "create table words (id integer primary key, word text);
+              insert into words (word) values ('hello'), ('naïve'), ('日本語'), ('😀 grin'), (NULL);",
+    },
+    Fixture {
+        name: "utf16be",
+        page_size: 4096,
+        encoding: "UTF-16be",
+        sql: "create table words (id integer primary key, word text);
+              insert into words (word) values ('hello'), ('naïve'), ('日本語'), ('😀 grin');",
+    },
+    Fixture {
+        name: "wide",
+        page_size: 32768,
+        encoding: "UTF-8",
+        sql: "create table items (id integer primary key, name text);
+              with recursive c(x) as (select 1 union all select x + 1 from c where x < 5000)
+              insert into items (name) select printf('item %05d', x) from c;",
+    },
+    // MATCH reads the text back from the articles_content shadow table
+    Fixture {
+        name: "articles",
+        page_size: 4096,
+        encoding: "UTF-8",
+        sql: "create virtual table articles using fts5(title, body, tags unindexed);
+              insert into articles values
+                ('Rust book', 'Learning Rust programming, step by step.', 'tags'),
+                ('Go', 'go programming: simply', 'lang'),
+                ('Cooking', 'RUST removal from pans', NULL),
+                ('Empty', NULL, 'rust');",
+    },
+    // the rtree nodes are blobs of places_node, three levels deep
+    Fixture {
+        name: "places",
+        page_size: 1024,
+        encoding: "UTF-8",
+        sql: "create virtual table places using rtree(id, min_x, max_x, min_y, max_y, +label);
+              with recursive c(x) as (select 1 union all select x + 1 from c where x < 3000)
+              insert into places select x, x % 97, x % 97 + (x % 7) * 0.3, x % 89,
+                x % 89 + 1.1, 'p' || x from c;
+              create virtual table plots using rtree_i32(id, lo, hi, +owner);
+              insert into plots values (1, -5, 5, 'a'), (2, 7, 9, 'b'), (3, 1, 2, NULL);",
+    },
+    // a column of every storage class, with ties across integer and real
+    // and text whose byte order isn't its length order
+    Fixture {
+        name: "mixed",
+        page_size: 512,
+        encoding: "UTF-8",
+        sql: "create table mixed (id integer primary key, v);
+              create index idx_mixed_v on mixed (v);
+              insert into mixed (v) values
+                (2), (2.0), (-1.5), (NULL), ('z'), ('é'), (''), ('Z'), ('10'), ('2'),
+                (x'00'), (x''), (x'ff'), (9223372036854775807), (9.3e18), (NULL),
+                (-9223372036854775808), (0.0), (-0.0), ('z '), (x'0001'), (1e-300);",
+    },
+    // DESC indexes keep their keys largest first, ties in rowid order
+    Fixture {
+        name: "ranked",
+        page_size: 512,
+        encoding: "UTF-8",
+        sql: "create table ranked (id integer primary key, score int);
+              create index idx_ranked_score on ranked (score desc);
+              with recursive c(x) as (select 1 union all select x + 1 from c where x < 3000)
+              insert into ranked (score) select (x * 37) % 500 from c;
+              insert into ranked (score) values (NULL), ('x'), (17.0), (x'11');
+              create table codes (id integer primary key, code text);
+              create index idx_codes_code on codes (code collate binary desc);
+              with recursive c(x) as (select 1 union all select x + 1 from c where x < 3000)
+              insert into codes (code) select ((x * 7) % 1000) || '-' || x from c;
+              insert into codes (code) values ('12a'), (12), (x'3132'), (NULL), ('12');",
+    },
+    // text that reads as a number, in columns of every affinity
+    Fixture {
+        name: "affinity",
+        page_size: 4096,
+        encoding: "UTF-8",
+        sql: "create table t (id integer primary key, s text, n integer, b);
+              insert into t values (1, '01', 1, '1'), (2, '1', 2, 'x'), (3, 'abc', 3, 2);",
+    },
+    Fixture {
+        name: "without_rowid",
+        page_size: 4096,
+        encoding: "UTF-8",
+        sql: "create table kv (k text primary key, v) without rowid;
+              insert into kv values ('a', 1), ('b', NULL), ('c', 'three');",
+    },
+];
+
+// fixture_path writes the named fixture, built on first use, to a fresh
+// temp file for one case to read.
+fn fixture_path(name: &str) -> PathBuf {
+    static BUILT: OnceLock<HashMap<&'static str, Vec<u8>>> = OnceLock::new();
+    let built = BUILT.get_or_init(|| FIXTURES.iter().map(|f| (f.name, build(f))).collect());
+    let image = built
+        .get(name)
+        .unwrap_or_else(|| panic!("no fixture named {name}"));
+    fixtures::write_temp(&format!("{name}.db"), image)
+}
+
+fn build(fixture: &Fixture) -> Vec<u8> {
+    let path = fixtures::temp_path(&format!("{}.db", fixture.name));
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(&format!(
+        "PRAGMA page_size = {}; PRAGMA encoding = '{}';",
+        fixture.page_size, fixture.encoding
+    ))
+    .unwrap();
+    conn.execute_batch(fixture.sql)
+        .unwrap_or_else(|e| panic!("building {}: {e}", fixture.name));
+    conn.close().unwrap();
+    let image = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    image
+}
+
+// sqlite_rows runs `sql` through rusqlite.
+fn sqlite_rows(path: &PathBuf, sql: &str) -> Vec<Vec<Value>> {
+    let conn = rusqlite::Connection::open(path).unwrap();
+    // the library leaves generate_series to the sqlite3 shell
+    rusqlite::vtab::series::load_module(&conn).unwrap();
+    let mut stmt = conn
+        .prepare(sql)
+        .unwrap_or_else(|e| panic!("sqlite rejects {sql}: {e}"));
+    let columns = stmt.column_count();
+    let mut rows = stmt.query([]).unwrap();
+    let mut out = Vec::new();
+    while let Some(row) = rows.next().unwrap() {
+        let values = (0..columns).map(|i| match row.get_ref(i).unwrap() {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(i) => Value::Integer(i),
+            ValueRef::Real(f) => Value::Float(f),
+            ValueRef::Text(t) => Value::Text(String::from_utf8_lossy(t).into_owned()),
+            ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+        });
+        out.push(values.collect());
+    }
+    out
+}
+
+// same compares values strictly: an integer doesn't match the equal real,
+// which `==` allows.
+fn same(a: &[Value], b: &[Value]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(x, y)| x.type_name() == y.type_name() && x == y)
+}
+
+// differ describes how our rows differ from sqlite's, None when they agree.
+fn differ(sql: &str, mut ours: Vec<Vec<Value>>, mut theirs: Vec<Vec<Value>>) -> Option<String> {
+    if !sql.to_ascii_lowercase().contains("order by") {
+        ours.sort();
+        theirs.sort();
+    }
+    let first = (0..ours.len().max(theirs.len()))
+        .find(|&i| !matches!((ours.get(i), theirs.get(i)), (Some(a), Some(b)) if same(a, b)))?;
+    Some(format!(
+        "{sql}\n  {} rows, sqlite {}; first difference at row {first}:\n  ours:   {:?}\n  sqlite: {:?}",
+        ours.len(),
+        theirs.len(),
+        ours.get(first),
+        theirs.get(first),
+    ))
+}
+
+#[test]
+fn queries_match_sqlite() {
+    let mut failures = Vec::new();
+    for &(fixture, sql) in CASES {
+        let path = fixture_path(fixture);
+        let theirs = sqlite_rows(&path, sql);
+        let db = Database::open(path.to_str().unwrap()).unwrap();
+        match db.query_to_vec(sql) {
+            Ok(ours) => failures.extend(differ(sql, ours, theirs)),
+            Err(e) => failures.push(format!("{sql}\n  fails: {e}")),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} queries differ from sqlite:\n{}",
+        failures.len(),
+        CASES.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn unsupported_queries_are_rejected() {
+    for &(fixture, sql) in UNSUPPORTED {
+        let path = fixture_path(fixture);
+        // sqlite answers them, so they're a gap of ours rather than bad SQL
+        sqlite_rows(&path, sql);
+        let db = Database::open(path.to_str().unwrap()).unwrap();
+        let err = db.query_to_vec(sql).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(SqliteError::Unsupported(_))),
+            "{sql}: {err}"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
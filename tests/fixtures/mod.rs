@@ -0,0 +1,169 @@
+//! Programmatically generated test databases.
+//!
+//! Fixtures are built through the bundled SQLite of the `rusqlite`
+//! dev-dependency, so every test has them whether or not `sqlite3` is installed.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a path in the temp dir that no other test (or test process) uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!("sqlite-fixture-{}-{n}-{name}", std::process::id()))
+}
+
+/// Writes a database image to a fresh temp file and returns its path.
+pub fn write_temp(name: &str, image: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, image).unwrap();
+    path
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FixtureBuilder {
+    page_size: Option<u32>,
+    statements: Vec<String>,
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn sql(mut self, statement: &str) -> Self {
+        self.statements.push(statement.to_string());
+        self
+    }
+
+    /// Runs the statements through rusqlite and returns the database image.
+    pub fn build(&self) -> Vec<u8> {
+        let path = temp_path("build.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        if let Some(page_size) = self.page_size {
+            conn.pragma_update(None, "page_size", page_size).unwrap();
+        }
+        for statement in &self.statements {
+            conn.execute_batch(statement)
+                .unwrap_or_else(|e| panic!("{statement}: {e}"));
+        }
+        conn.close().unwrap();
+        let image = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        image
+    }
+}
+
+/// Runs `sqlite3 [args] db sql` and returns its stdout, so tests can
+/// byte-compare against the reference shell. Only what needs the shell itself,
+/// its dot commands and output modes, goes through it: it panics when
+/// `sqlite3` can't be run rather than letting the comparison pass unchecked.
+pub fn sqlite3_output(db: &Path, args: &[&str], sql: &str) -> Vec<u8> {
+    let out = Command::new("sqlite3")
+        .args(args)
+        .arg(db)
+        .arg(sql)
+        .output()
+        .unwrap_or_else(|e| panic!("cannot run sqlite3 to compare with: {e}"));
+    assert!(
+        out.status.success(),
+        "sqlite3 failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out.stdout
+}
+
+/// Runs the statements of `sql` through rusqlite on the database at `db` and
+/// returns their rows as `sqlite3` lists them: a line per row, values joined
+/// by '|' and NULL shown as `null`.
+pub fn sqlite_rows(db: &Path, null: &str, sql: &str) -> String {
+    use rusqlite::fallible_iterator::FallibleIterator;
+    use rusqlite::types::ValueRef;
+
+    let conn = rusqlite::Connection::open(db).unwrap();
+    let mut out = String::new();
+    let mut batch = rusqlite::Batch::new(&conn, sql);
+    while let Some(mut stmt) = batch.next().unwrap_or_else(|e| panic!("{sql}: {e}")) {
+        let n = stmt.column_count();
+        let mut rows = stmt.raw_query();
+        while let Some(row) = rows.next().unwrap_or_else(|e| panic!("{sql}: {e}")) {
+            let values = (0..n).map(|i| match row.get_ref(i).unwrap() {
+                ValueRef::Null => null.to_string(),
+                ValueRef::Integer(v) => v.to_string(),
+                // the text sqlite3 makes of a real, which the shell prints
+                ValueRef::Real(v) => conn
+                    .query_row("select cast(?1 as text)", [v], |r| r.get(0))
+                    .unwrap(),
+                ValueRef::Text(b) | ValueRef::Blob(b) => String::from_utf8_lossy(b).into_owned(),
+            });
+            out += &values.collect::<Vec<_>>().join("|");
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// `fruits(id integer primary key, name text, color text)` with three rows.
+pub fn create_simple_table() -> Vec<u8> {
+    let builder = FixtureBuilder::new()
+        .sql("create table fruits (id integer primary key, name text, color text)")
+        .sql(
+            "insert into fruits (name, color) values \
+             ('apple', 'red'), ('banana', 'yellow'), ('cherry', 'red')",
+        );
+    builder.build()
+}
+
+/// `numbers(id integer primary key, name text, parity text)` holding
+/// `rows` rows named "number <id>", spread over many pages.
+pub fn create_multipage_table(rows: usize) -> Vec<u8> {
+    let builder = FixtureBuilder::new()
+        .sql("create table numbers (id integer primary key, name text, parity text)")
+        .sql(&format!(
+            "with recursive c(x) as (select 1 union all select x + 1 from c where x < {rows}) \
+             insert into numbers (name, parity) \
+             select 'number ' || x, case x % 2 when 0 then 'even' else 'odd' end from c"
+        ));
+    builder.build()
+}
+
+/// `companies(id integer primary key, name text, country text)` with 2000 rows
+/// over five countries, indexed by `idx_companies_country`.
+pub fn create_indexed_table() -> Vec<u8> {
+    let builder = FixtureBuilder::new()
+        .sql("create table companies (id integer primary key, name text, country text)")
+        .sql("create index idx_companies_country on companies (country)")
+        .sql(
+            "with recursive c(x) as (select 1 union all select x + 1 from c where x < 2000) \
+             insert into companies (name, country) \
+             select 'company ' || x, \
+             case x % 5 when 0 then 'chad' when 1 then 'fiji' when 2 then 'peru' \
+             when 3 then 'oman' else 'laos' end from c",
+        );
+    builder.build()
+}
+
+/// `notes(id integer primary key, title text, body text)` on 64KiB pages:
+/// 3000 rows whose bodies run up to 4000 bytes, every hundredth one 100000
+/// bytes long so it spills onto overflow pages, indexed by `idx_notes_title`.
+pub fn create_large_page_table() -> Vec<u8> {
+    FixtureBuilder::new()
+        .page_size(65536)
+        .sql("create table notes (id integer primary key, title text, body text)")
+        .sql("create index idx_notes_title on notes (title)")
+        .sql(
+            "with recursive c(x) as (select 1 union all select x + 1 from c where x < 3000) \
+             insert into notes (title, body) \
+             select 'note ' || x, \
+             printf('%.*c', case x % 100 when 0 then 100000 else x % 4000 end, 'x') from c",
+        )
+        .build()
+}